@@ -0,0 +1,111 @@
+use std::{fs, path::PathBuf, time::Duration};
+
+use chess::{
+  analysis::format_score,
+  board::Color,
+  pgn,
+  review::{self, Judgement},
+  san,
+  search::Limits,
+  tournament::EngineConfig,
+};
+
+const HELP: &str = "\
+Reviews games: evaluates every position, marks inaccuracies, mistakes
+and blunders and reports each player's accuracy.
+
+USAGE:
+  chess-review FILE [OPTIONS]
+
+OPTIONS:
+  --engine SPEC     cmd=builtin|PATH[,name=NAME][,option.NAME=VALUE]...
+                    [default: cmd=builtin]
+  --depth N         search depth per position [default: 10]
+  --time SECONDS    time per position instead
+  --output FILE     write the games with the review as PGN annotations
+";
+
+fn main() -> anyhow::Result<()> {
+  env_logger::Builder::from_env(
+    env_logger::Env::default().default_filter_or("warn"),
+  )
+  .init();
+  let mut args = pico_args::Arguments::from_env();
+  if args.contains(["-h", "--help"]) {
+    print!("{HELP}");
+    return Ok(());
+  }
+  let engine: EngineConfig = args
+    .opt_value_from_str("--engine")?
+    .unwrap_or_else(|| "cmd=builtin".parse().unwrap());
+  let mut limits =
+    Limits { depth: args.opt_value_from_str("--depth")?, ..Default::default() };
+  let seconds: Option<f64> = args.opt_value_from_str("--time")?;
+  if let Some(seconds) = seconds {
+    limits.movetime = Some(Duration::try_from_secs_f64(seconds)?);
+  } else if limits.depth.is_none() {
+    limits.depth = Some(10);
+  }
+  let output: Option<PathBuf> = args.opt_value_from_str("--output")?;
+  let path: PathBuf = args.free_from_str()?;
+  let rest = args.finish();
+  if !rest.is_empty() {
+    anyhow::bail!("unexpected arguments {rest:?}, see --help");
+  }
+
+  let games = pgn::parse_trees(&fs::read_to_string(&path)?)?;
+  let mut analyzer = engine.launch()?;
+  let mut annotated = String::new();
+  for (game, mut tree) in games {
+    println!(
+      "{} - {} {}",
+      game.tag("White").unwrap_or("?"),
+      game.tag("Black").unwrap_or("?"),
+      game.outcome
+    );
+    let review = review::review(&mut analyzer, &tree, &limits, |_, _| {})?;
+    let mainline = tree.mainline();
+    for (ply, mv) in review.moves.iter().enumerate() {
+      let Some(judgement) = mv.judgement else {
+        continue;
+      };
+      let position =
+        &tree.node(tree.node(mainline[ply]).parent.unwrap()).position;
+      let number = position.fullmove_number();
+      let dots = mv.color.fold(".", "...");
+      let after = review.evals[ply + 1].score.map_or("-".into(), format_score);
+      let best = review.evals[ply]
+        .best_line
+        .first()
+        .map_or("-".into(), |&best| san::to_san(position, best));
+      println!(
+        "  {number}{dots} {:<8} {:<10} {after:>6}  best {best}",
+        san::to_san(position, mv.mv),
+        judgement.name(),
+      );
+    }
+    for color in [Color::White, Color::Black] {
+      let counts: Vec<String> = Judgement::ALL
+        .iter()
+        .map(|&judgement| {
+          format!("{} {}", review.count(color, judgement), judgement.name())
+        })
+        .collect();
+      println!(
+        "  {:<5} accuracy {:>5.1}%  acpl {:>4.0}  {}",
+        color.fold("White", "Black"),
+        review.accuracy(color).unwrap_or(100.0),
+        review.average_cp_loss(color).unwrap_or(0.0),
+        counts.join(", ")
+      );
+    }
+    println!();
+    review.annotate(&mut tree)?;
+    annotated.push_str(&game.to_pgn_with(&tree));
+    annotated.push('\n');
+  }
+  if let Some(output) = output {
+    fs::write(output, annotated)?;
+  }
+  Ok(())
+}
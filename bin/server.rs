@@ -0,0 +1,59 @@
+use std::{net::TcpListener, path::PathBuf};
+
+use chess::{chat::WordList, server::Server};
+
+const HELP: &str = "\
+Hosts games between players: a lobby to seek and challenge in, games
+with clocks kept here and anyone watching them, and chat beside them.
+With --games the games that end are stored and the players rated.
+
+USAGE:
+  chess-server [OPTIONS]
+
+OPTIONS:
+  --address ADDR    where to listen [default: 0.0.0.0:7879]
+  --name NAME       name told to the players [default: chess-server]
+  --words FILE      words masked in the chat, one a line
+  --games FILE      database to store the games in
+  --ratings FILE    ratings of the stored games [default: ratings.txt]
+";
+
+fn main() -> anyhow::Result<()> {
+  env_logger::Builder::from_env(
+    env_logger::Env::default().default_filter_or("info"),
+  )
+  .init();
+  let mut args = pico_args::Arguments::from_env();
+  if args.contains(["-h", "--help"]) {
+    print!("{HELP}");
+    return Ok(());
+  }
+  let address: String = args
+    .opt_value_from_str("--address")?
+    .unwrap_or_else(|| "0.0.0.0:7879".into());
+  let name: String =
+    args.opt_value_from_str("--name")?.unwrap_or_else(|| "chess-server".into());
+  let words: Option<PathBuf> = args.opt_value_from_str("--words")?;
+  let games: Option<PathBuf> = args.opt_value_from_str("--games")?;
+  let ratings: PathBuf = args
+    .opt_value_from_str("--ratings")?
+    .unwrap_or_else(|| "ratings.txt".into());
+  let rest = args.finish();
+  if !rest.is_empty() {
+    anyhow::bail!("unexpected arguments {rest:?}, see --help");
+  }
+  if name.is_empty() || name.contains(char::is_whitespace) {
+    anyhow::bail!("the name must be one word");
+  }
+
+  let listener = TcpListener::bind(&address)?;
+  log::info!("listening on {}", listener.local_addr()?);
+  let mut server = Server::new(&name);
+  if let Some(words) = words {
+    server.add_filter(WordList::load(&words)?);
+  }
+  if let Some(games) = games {
+    server.keep_games(&games, &ratings)?;
+  }
+  server.run(listener)
+}
@@ -0,0 +1,100 @@
+use std::{fs, path::PathBuf, time::Duration};
+
+use chess::{
+  epd, san,
+  search::Limits,
+  suite::{self, Goal},
+  tournament::EngineConfig,
+};
+
+const HELP: &str = "\
+Runs an EPD test suite (bm/am records) and reports how many positions
+the engine solves.
+
+USAGE:
+  chess-suite FILE [OPTIONS]
+
+OPTIONS:
+  --engine SPEC     cmd=builtin|PATH[,name=NAME][,option.NAME=VALUE]...
+                    [default: cmd=builtin]
+  --time SECONDS    time per position [default: 1]
+  --depth N         search depth per position instead
+  --nodes N         nodes per position instead
+";
+
+fn main() -> anyhow::Result<()> {
+  env_logger::Builder::from_env(
+    env_logger::Env::default().default_filter_or("warn"),
+  )
+  .init();
+  let mut args = pico_args::Arguments::from_env();
+  if args.contains(["-h", "--help"]) {
+    print!("{HELP}");
+    return Ok(());
+  }
+  let engine: EngineConfig = args
+    .opt_value_from_str("--engine")?
+    .unwrap_or_else(|| "cmd=builtin".parse().unwrap());
+  let mut limits = Limits {
+    depth: args.opt_value_from_str("--depth")?,
+    nodes: args.opt_value_from_str("--nodes")?,
+    ..Default::default()
+  };
+  let seconds: Option<f64> = args.opt_value_from_str("--time")?;
+  if seconds.is_some() || (limits.depth.is_none() && limits.nodes.is_none()) {
+    limits.movetime =
+      Some(Duration::try_from_secs_f64(seconds.unwrap_or(1.0))?);
+  }
+  let path: PathBuf = args.free_from_str()?;
+  let rest = args.finish();
+  if !rest.is_empty() {
+    anyhow::bail!("unexpected arguments {rest:?}, see --help");
+  }
+
+  let records = epd::parse(&fs::read_to_string(&path)?);
+  let mut analyzer = engine.launch()?;
+  let results = suite::run(&mut analyzer, &records, &limits, |epd, result| {
+    let expected = Goal::of(epd)
+      .map(|goal| {
+        let sans = |moves: &[_]| {
+          let sans: Vec<String> =
+            moves.iter().map(|&mv| san::to_san(&epd.position, mv)).collect();
+          sans.join(" ")
+        };
+        if goal.best.is_empty() {
+          format!("am {}", sans(&goal.avoid))
+        } else {
+          format!("bm {}", sans(&goal.best))
+        }
+      })
+      .unwrap_or_default();
+    let played =
+      result.best_move.map_or("-".into(), |mv| san::to_san(&epd.position, mv));
+    let solved_after = result
+      .solved_after
+      .map_or("-".into(), |time| format!("{:.2}s", time.as_secs_f64()));
+    println!(
+      "{:<16} {:<6} {:<8} {:<16} depth {:>3} time {:>6.2}s solved after {:>7}  {}",
+      result.id,
+      if result.solved { "solved" } else { "FAILED" },
+      played,
+      expected,
+      result.depth,
+      result.time.as_secs_f64(),
+      solved_after,
+      epd.op("c0").and_then(|c0| c0.first()).map_or("", String::as_str),
+    );
+  })?;
+
+  let solved = results.iter().filter(|result| result.solved).count();
+  let time: Duration = results.iter().map(|result| result.time).sum();
+  println!();
+  println!(
+    "{}: solved {solved}/{} in {:.1}s with {}",
+    path.display(),
+    results.len(),
+    time.as_secs_f64(),
+    engine.name,
+  );
+  Ok(())
+}
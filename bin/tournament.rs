@@ -0,0 +1,170 @@
+use std::{fs::File, io::Write, ops::ControlFlow, path::PathBuf, sync::Arc};
+
+use chess::{
+  sprt::{self, Verdict},
+  syzygy::Tablebase,
+  tournament::{self, Adjudication, EngineConfig, Settings},
+};
+
+const HELP: &str = "\
+Plays engine tournaments without the GUI.
+
+USAGE:
+  chess-tournament --engine SPEC --engine SPEC [OPTIONS]
+
+ENGINES:
+  cmd=builtin|PATH[,name=NAME][,option.NAME=VALUE]...
+
+OPTIONS:
+  --format round-robin|gauntlet  gauntlet plays the first engine against the rest
+  --rounds N                     game pairs per pairing [default: 1, 10000 with --sprt]
+  --openings FILE                EPD or PGN opening suite
+  --plies N                      opening moves taken from PGN games [default: 16]
+  --tc TC                        10+0.1, st=0.5, depth=8 or nodes=20000 [default: 10+0.1]
+  --concurrency N                games played at once [default: 1]
+  --pgn FILE                     where to write the games
+  --resign CP:MOVES              resign adjudication
+  --draw CP:MOVES:FROM_MOVE      draw adjudication
+  --syzygy PATHS                 tablebase adjudication
+  --event NAME                   PGN event name
+  --sprt elo0=0,elo1=5,alpha=0.05,beta=0.05
+                                 test the first engine against the second,
+                                 stopping once a bound is reached
+";
+
+/// `a:b:c` with every part parsed as a number.
+fn numbers<const N: usize>(s: &str) -> anyhow::Result<[i64; N]> {
+  let parts: Vec<i64> =
+    s.split(':').map(str::parse).collect::<Result<_, _>>()?;
+  parts
+    .try_into()
+    .map_err(|_| anyhow::anyhow!("expected {N} numbers separated by ':'"))
+}
+
+fn main() -> anyhow::Result<()> {
+  env_logger::Builder::from_env(
+    env_logger::Env::default().default_filter_or("warn"),
+  )
+  .init();
+  let mut args = pico_args::Arguments::from_env();
+  if args.contains(["-h", "--help"]) {
+    print!("{HELP}");
+    return Ok(());
+  }
+
+  let engines: Vec<EngineConfig> = args.values_from_str("--engine")?;
+  let mut settings = Settings::default();
+  if let Some(format) = args.opt_value_from_str("--format")? {
+    settings.format = format;
+  }
+  let sprt: Option<sprt::Params> = args.opt_value_from_str("--sprt")?;
+  if sprt.is_some() {
+    if engines.len() != 2 {
+      anyhow::bail!("--sprt needs exactly two engines");
+    }
+    settings.rounds = 10_000;
+  }
+  if let Some(rounds) = args.opt_value_from_str("--rounds")? {
+    settings.rounds = rounds;
+  }
+  let plies = args.opt_value_from_str("--plies")?.unwrap_or(16);
+  if let Some(path) = args.opt_value_from_str::<_, PathBuf>("--openings")? {
+    settings.openings = tournament::load_openings(&path, plies)?;
+  }
+  if let Some(time_control) = args.opt_value_from_str("--tc")? {
+    settings.time_control = time_control;
+  }
+  if let Some(concurrency) = args.opt_value_from_str("--concurrency")? {
+    settings.concurrency = concurrency;
+  }
+  if let Some(event) = args.opt_value_from_str("--event")? {
+    settings.event = event;
+  }
+  let mut adjudication = Adjudication::default();
+  if let Some(resign) = args.opt_value_from_fn("--resign", numbers::<2>)? {
+    adjudication.resign_score = Some(resign[0] as i32);
+    adjudication.resign_moves = resign[1] as usize;
+  }
+  if let Some(draw) = args.opt_value_from_fn("--draw", numbers::<3>)? {
+    adjudication.draw_score = Some(draw[0] as i32);
+    adjudication.draw_moves = draw[1] as usize;
+    adjudication.draw_after = draw[2] as u32;
+  }
+  if let Some(paths) = args.opt_value_from_str::<_, String>("--syzygy")? {
+    adjudication.tablebase = Some(Arc::new(Tablebase::open(&paths)?));
+  }
+  settings.adjudication = adjudication;
+  let mut pgn: Option<File> =
+    match args.opt_value_from_str::<_, PathBuf>("--pgn")? {
+      Some(path) => Some(File::create(path)?),
+      None => None,
+    };
+  let rest = args.finish();
+  if !rest.is_empty() {
+    anyhow::bail!("unexpected arguments {rest:?}, see --help");
+  }
+
+  let total = tournament::schedule(engines.len(), &settings).len();
+  let mut played = 0;
+  let mut stats = sprt::Stats::default();
+  let records = tournament::run(&engines, &settings, |record| {
+    played += 1;
+    let game = &record.game;
+    println!(
+      "game {played}/{total}: {} - {} {} ({})",
+      game.tag("White").unwrap_or_default(),
+      game.tag("Black").unwrap_or_default(),
+      game.outcome,
+      game.tag("Termination").unwrap_or_default(),
+    );
+    if let Some(file) = &mut pgn {
+      // written as they finish, so an interrupted run keeps its games
+      if let Err(err) = writeln!(file, "{}", game.to_pgn()) {
+        log::error!("failed to write pgn: {err}");
+      }
+    }
+
+    let Some(params) = &sprt else {
+      return ControlFlow::Continue(());
+    };
+    stats.add(record, 0);
+    let (lower, upper) = params.bounds();
+    println!(
+      "{stats}, LLR {:.2} ({lower:.2}, {upper:.2}) [{}, {}]",
+      stats.llr(params),
+      params.elo0,
+      params.elo1
+    );
+    match stats.verdict(params) {
+      Some(Verdict::H1) => {
+        println!("H1 accepted, the first engine is stronger")
+      }
+      Some(Verdict::H0) => {
+        println!("H0 accepted, the first engine is no stronger")
+      }
+      None => return ControlFlow::Continue(()),
+    }
+    ControlFlow::Break(())
+  })?;
+
+  println!();
+  println!(
+    "{:>4} {:<24} {:>7} {:>6} {:>5} {:>5} {:>5}",
+    "rank", "name", "points", "games", "wins", "draws", "losses"
+  );
+  for (rank, standing) in
+    tournament::standings(&engines, &records).iter().enumerate()
+  {
+    println!(
+      "{:>4} {:<24} {:>7.1} {:>6} {:>5} {:>5} {:>5}",
+      rank + 1,
+      standing.name,
+      standing.points(),
+      standing.games(),
+      standing.wins,
+      standing.draws,
+      standing.losses,
+    );
+  }
+  Ok(())
+}
@@ -0,0 +1,12 @@
+use std::io;
+
+fn main() {
+  // logs go to stderr, stdout belongs to the protocol
+  env_logger::Builder::from_env(
+    env_logger::Env::default().default_filter_or("warn"),
+  )
+  .init();
+  if let Err(err) = chess::uci::run(io::stdin().lock(), io::stdout()) {
+    log::error!("{:#?}", err);
+  }
+}
@@ -0,0 +1,374 @@
+//! Analysis mode: an engine keeps analysing the shown position and the
+//! best lines it found so far are kept for the panel, the evaluation bar
+//! and the arrows on the board.
+
+use std::{
+  path::Path,
+  sync::{mpsc, Arc},
+};
+
+use anyhow::bail;
+
+use crate::{
+  board::{Color, Move, Position},
+  search::{Engine, Limits, Score, SearchEvent, SearchInfo},
+  syzygy::Tablebase,
+  uci,
+};
+
+pub enum Analyzer {
+  BuiltIn(Engine),
+  Uci(uci::Client),
+}
+
+impl Analyzer {
+  pub fn uci(path: &Path) -> anyhow::Result<Analyzer> {
+    uci::Client::spawn(path).map(Analyzer::Uci)
+  }
+
+  pub fn name(&self) -> String {
+    match self {
+      Analyzer::BuiltIn(_) => "built-in".into(),
+      Analyzer::Uci(client) => {
+        client.name().unwrap_or_else(|| "uci engine".into())
+      }
+    }
+  }
+
+  pub fn set_multi_pv(
+    &mut self,
+    lines: usize,
+  ) -> anyhow::Result<()> {
+    match self {
+      Analyzer::BuiltIn(engine) => engine.set_multi_pv(lines),
+      Analyzer::Uci(client) => {
+        client.set_option("MultiPV", &lines.to_string())?
+      }
+    }
+    Ok(())
+  }
+
+  pub fn set_threads(
+    &mut self,
+    threads: usize,
+  ) -> anyhow::Result<()> {
+    match self {
+      Analyzer::BuiltIn(engine) => engine.set_threads(threads),
+      Analyzer::Uci(client) => {
+        client.set_option("Threads", &threads.to_string())?
+      }
+    }
+    Ok(())
+  }
+
+  /// Sets an option by its UCI name. The built-in engine knows the ones
+  /// its UCI front-end offers.
+  pub fn set_option(
+    &mut self,
+    name: &str,
+    value: &str,
+  ) -> anyhow::Result<()> {
+    match self {
+      Analyzer::BuiltIn(engine) => match name.to_ascii_lowercase().as_str() {
+        "threads" => engine.set_threads(value.parse()?),
+        "hash" => engine.set_hash_size(value.parse()?),
+        "multipv" => engine.set_multi_pv(value.parse()?),
+        "syzygypath" => engine.set_tablebase(uci::tablebase(value)?),
+        _ => bail!("unknown option {name:?}"),
+      },
+      Analyzer::Uci(client) => client.set_option(name, value)?,
+    }
+    Ok(())
+  }
+
+  /// The built-in engine shares the opened tables, a UCI engine gets
+  /// their directories as its `SyzygyPath`.
+  pub fn set_tablebase(
+    &mut self,
+    tablebase: Option<Arc<Tablebase>>,
+  ) -> anyhow::Result<()> {
+    match self {
+      Analyzer::BuiltIn(engine) => engine.set_tablebase(tablebase),
+      Analyzer::Uci(client) => {
+        let paths = tablebase.as_ref().map_or("<empty>", |tb| tb.paths());
+        client.set_option("SyzygyPath", paths)?
+      }
+    }
+    Ok(())
+  }
+
+  pub fn new_game(&mut self) -> anyhow::Result<()> {
+    match self {
+      Analyzer::BuiltIn(engine) => engine.new_game(),
+      Analyzer::Uci(client) => client.new_game()?,
+    }
+    Ok(())
+  }
+
+  /// Searches the position reached by `moves` from `start` in the
+  /// background, replacing the running search.
+  pub fn start(
+    &mut self,
+    start: &Position,
+    moves: &[Move],
+    limits: Limits,
+  ) -> anyhow::Result<mpsc::Receiver<SearchEvent>> {
+    match self {
+      Analyzer::BuiltIn(engine) => {
+        let mut position = start.clone();
+        let mut history = Vec::with_capacity(moves.len());
+        for &mv in moves {
+          history.push(position.hash());
+          position.play(mv);
+        }
+        Ok(engine.start(position, history, limits))
+      }
+      Analyzer::Uci(client) => client.start(start, moves, &limits),
+    }
+  }
+
+  pub fn stop(&mut self) -> anyhow::Result<()> {
+    match self {
+      Analyzer::BuiltIn(engine) => engine.stop(),
+      Analyzer::Uci(client) => client.stop()?,
+    }
+    Ok(())
+  }
+}
+
+pub struct Analysis {
+  pub analyzer: Analyzer,
+  tablebase: Option<Arc<Tablebase>>,
+  enabled: bool,
+  multi_pv: usize,
+  threads: usize,
+  events: Option<mpsc::Receiver<SearchEvent>>,
+  /// The position the lines belong to.
+  position: Position,
+  /// Best lines by rank, `lines[0]` is the principal variation.
+  lines: Vec<SearchInfo>,
+}
+
+impl Default for Analysis {
+  fn default() -> Self {
+    Self::new(Analyzer::BuiltIn(Engine::new()))
+  }
+}
+
+impl Analysis {
+  pub fn new(analyzer: Analyzer) -> Self {
+    Self {
+      analyzer,
+      tablebase: None,
+      enabled: false,
+      multi_pv: 1,
+      threads: 1,
+      events: None,
+      position: Position::startpos(),
+      lines: Vec::new(),
+    }
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.enabled
+  }
+
+  pub fn multi_pv(&self) -> usize {
+    self.multi_pv
+  }
+
+  pub fn threads(&self) -> usize {
+    self.threads
+  }
+
+  pub fn position(&self) -> &Position {
+    &self.position
+  }
+
+  pub fn lines(&self) -> &[SearchInfo] {
+    &self.lines
+  }
+
+  pub fn is_searching(&self) -> bool {
+    self.events.is_some()
+  }
+
+  pub fn tablebase(&self) -> Option<&Tablebase> {
+    self.tablebase.as_deref()
+  }
+
+  /// Switches engines, picking up the analysis with the new one.
+  pub fn set_analyzer(
+    &mut self,
+    analyzer: Analyzer,
+    start: &Position,
+    moves: &[Move],
+  ) -> anyhow::Result<()> {
+    self.stop()?;
+    self.analyzer = analyzer;
+    self.analyzer.set_multi_pv(self.multi_pv)?;
+    self.analyzer.set_threads(self.threads)?;
+    if self.tablebase.is_some() {
+      self.analyzer.set_tablebase(self.tablebase.clone())?;
+    }
+    self.restart(start, moves)
+  }
+
+  /// Opens the tables in `paths` for the panel and the analyzer.
+  pub fn set_tablebase(
+    &mut self,
+    paths: &str,
+    start: &Position,
+    moves: &[Move],
+  ) -> anyhow::Result<()> {
+    self.stop()?;
+    self.tablebase = uci::tablebase(paths)?;
+    self.analyzer.set_tablebase(self.tablebase.clone())?;
+    self.restart(start, moves)
+  }
+
+  pub fn set_multi_pv(
+    &mut self,
+    lines: usize,
+    start: &Position,
+    moves: &[Move],
+  ) -> anyhow::Result<()> {
+    self.multi_pv = lines.max(1);
+    self.analyzer.set_multi_pv(self.multi_pv)?;
+    self.restart(start, moves)
+  }
+
+  pub fn set_threads(
+    &mut self,
+    threads: usize,
+    start: &Position,
+    moves: &[Move],
+  ) -> anyhow::Result<()> {
+    self.threads = threads.max(1);
+    self.analyzer.set_threads(self.threads)?;
+    self.restart(start, moves)
+  }
+
+  pub fn set_enabled(
+    &mut self,
+    enabled: bool,
+    start: &Position,
+    moves: &[Move],
+  ) -> anyhow::Result<()> {
+    self.enabled = enabled;
+    if enabled {
+      self.restart(start, moves)
+    } else {
+      self.lines.clear();
+      self.stop()
+    }
+  }
+
+  pub fn stop(&mut self) -> anyhow::Result<()> {
+    self.events = None;
+    self.analyzer.stop()
+  }
+
+  /// Starts over on the position reached by `moves`, if analysing.
+  pub fn restart(
+    &mut self,
+    start: &Position,
+    moves: &[Move],
+  ) -> anyhow::Result<()> {
+    let mut position = start.clone();
+    for &mv in moves {
+      position.play(mv);
+    }
+    self.position = position;
+    self.lines.clear();
+    if !self.enabled {
+      return Ok(());
+    }
+    let limits = Limits { infinite: true, ..Default::default() };
+    self.events = Some(self.analyzer.start(start, moves, limits)?);
+    Ok(())
+  }
+
+  /// Follows the shown position, restarting when it changed.
+  pub fn follow(
+    &mut self,
+    start: &Position,
+    moves: &[Move],
+  ) -> anyhow::Result<()> {
+    let mut position = start.clone();
+    for &mv in moves {
+      position.play(mv);
+    }
+    if position != self.position {
+      self.restart(start, moves)?;
+    }
+    Ok(())
+  }
+
+  /// Collects what the engine reported since the last frame.
+  pub fn poll(&mut self) {
+    let Some(events) = &self.events else {
+      return;
+    };
+    loop {
+      match events.try_recv() {
+        Ok(SearchEvent::Info(info)) => {
+          let rank = info.multipv.max(1);
+          if self.lines.len() < rank {
+            self.lines.resize(rank, info.clone());
+          }
+          self.lines[rank - 1] = info;
+        }
+        Ok(SearchEvent::Done(_)) | Err(mpsc::TryRecvError::Disconnected) => {
+          self.events = None;
+          break;
+        }
+        Err(mpsc::TryRecvError::Empty) => break,
+      }
+    }
+  }
+
+  /// Evaluation of the best line from white's point of view.
+  pub fn white_score(&self) -> Option<Score> {
+    let score = self.lines.first()?.score;
+    Some(white_pov(score, self.position.side_to_move()))
+  }
+
+  /// First moves of the best lines, best first.
+  pub fn best_moves(&self) -> Vec<Move> {
+    self.lines.iter().filter_map(|line| line.pv.first().copied()).collect()
+  }
+}
+
+/// Turns a score for the side to move into one for white.
+pub fn white_pov(
+  score: Score,
+  side_to_move: Color,
+) -> Score {
+  match (score, side_to_move) {
+    (score, Color::White) => score,
+    (Score::Cp(cp), Color::Black) => Score::Cp(-cp),
+    (Score::Mate(moves), Color::Black) => Score::Mate(-moves),
+  }
+}
+
+/// Expected score for white between 0 and 1, the fill of the
+/// evaluation bar. Uses the usual logistic model with 400 points per
+/// factor of ten in odds.
+pub fn white_share(score: Score) -> f32 {
+  match score {
+    Score::Cp(cp) => 1.0 / (1.0 + 10f32.powf(-cp as f32 / 400.0)),
+    Score::Mate(moves) if moves > 0 => 1.0,
+    Score::Mate(_) => 0.0,
+  }
+}
+
+/// Score from white's point of view as players read it: `+0.35`, `M3`
+/// for white mating, `-M2` for black mating.
+pub fn format_score(score: Score) -> String {
+  match score {
+    Score::Cp(cp) => format!("{:+.2}", cp as f32 / 100.0),
+    Score::Mate(moves) if moves > 0 => format!("M{moves}"),
+    Score::Mate(moves) => format!("-M{}", -moves),
+  }
+}
@@ -0,0 +1,1033 @@
+//! Rules core: position representation, move generation and FEN.
+//! Squares are numbered a1 = 0, b1 = 1, ..., h8 = 63 and every set of
+//! squares is a 64-bit bitboard using the same numbering.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, bail};
+
+use crate::zobrist;
+
+pub const STARTING_FEN: &str =
+  "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Color {
+  White,
+  Black,
+}
+
+impl Color {
+  pub fn index(self) -> usize {
+    self as usize
+  }
+
+  pub fn fold<T>(
+    self,
+    white: T,
+    black: T,
+  ) -> T {
+    match self {
+      Color::White => white,
+      Color::Black => black,
+    }
+  }
+}
+
+impl std::ops::Not for Color {
+  type Output = Color;
+
+  fn not(self) -> Color {
+    self.fold(Color::Black, Color::White)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PieceKind {
+  Pawn,
+  Knight,
+  Bishop,
+  Rook,
+  Queen,
+  King,
+}
+
+impl PieceKind {
+  pub const ALL: [PieceKind; 6] = [
+    PieceKind::Pawn,
+    PieceKind::Knight,
+    PieceKind::Bishop,
+    PieceKind::Rook,
+    PieceKind::Queen,
+    PieceKind::King,
+  ];
+
+  pub fn index(self) -> usize {
+    self as usize
+  }
+
+  /// Uppercase letter used by FEN and SAN.
+  pub fn char(self) -> char {
+    match self {
+      PieceKind::Pawn => 'P',
+      PieceKind::Knight => 'N',
+      PieceKind::Bishop => 'B',
+      PieceKind::Rook => 'R',
+      PieceKind::Queen => 'Q',
+      PieceKind::King => 'K',
+    }
+  }
+
+  pub fn from_char(c: char) -> Option<PieceKind> {
+    let kind = match c.to_ascii_uppercase() {
+      'P' => PieceKind::Pawn,
+      'N' => PieceKind::Knight,
+      'B' => PieceKind::Bishop,
+      'R' => PieceKind::Rook,
+      'Q' => PieceKind::Queen,
+      'K' => PieceKind::King,
+      _ => return None,
+    };
+    Some(kind)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Piece {
+  pub color: Color,
+  pub kind: PieceKind,
+}
+
+impl Piece {
+  pub fn new(
+    color: Color,
+    kind: PieceKind,
+  ) -> Self {
+    Self { color, kind }
+  }
+
+  pub fn fen_char(self) -> char {
+    let c = self.kind.char();
+    self.color.fold(c, c.to_ascii_lowercase())
+  }
+
+  pub fn from_fen_char(c: char) -> Option<Piece> {
+    let kind = PieceKind::from_char(c)?;
+    let color =
+      if c.is_ascii_uppercase() { Color::White } else { Color::Black };
+    Some(Piece { color, kind })
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Square(u8);
+
+impl Square {
+  pub const fn new(
+    file: u8,
+    rank: u8,
+  ) -> Self {
+    Square(rank * 8 + file)
+  }
+
+  pub const fn from_index(index: u8) -> Self {
+    Square(index & 63)
+  }
+
+  pub fn index(self) -> usize {
+    self.0 as usize
+  }
+
+  /// 0 for the a-file, 7 for the h-file.
+  pub fn file(self) -> u8 {
+    self.0 & 7
+  }
+
+  /// 0 for the first rank, 7 for the eighth.
+  pub fn rank(self) -> u8 {
+    self.0 >> 3
+  }
+
+  /// The same square as seen from the black side of the board.
+  pub fn flip(self) -> Square {
+    Square(self.0 ^ 56)
+  }
+
+  pub fn all() -> impl Iterator<Item = Square> {
+    (0..64).map(Square)
+  }
+
+  fn bb(self) -> u64 {
+    1 << self.0
+  }
+}
+
+impl fmt::Display for Square {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    let file = (b'a' + self.file()) as char;
+    let rank = (b'1' + self.rank()) as char;
+    write!(f, "{file}{rank}")
+  }
+}
+
+impl FromStr for Square {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> anyhow::Result<Square> {
+    match s.as_bytes() {
+      &[file @ b'a'..=b'h', rank @ b'1'..=b'8'] => {
+        Ok(Square::new(file - b'a', rank - b'1'))
+      }
+      _ => Err(anyhow!("invalid square: {s:?}")),
+    }
+  }
+}
+
+/// Castling is encoded as the king moving two squares, as in UCI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Move {
+  pub from: Square,
+  pub to: Square,
+  pub promotion: Option<PieceKind>,
+}
+
+impl Move {
+  pub fn new(
+    from: Square,
+    to: Square,
+  ) -> Self {
+    Self { from, to, promotion: None }
+  }
+
+  /// Parses long algebraic notation (`e2e4`, `e7e8q`) without checking
+  /// that the move is legal anywhere.
+  pub fn from_uci(s: &str) -> anyhow::Result<Move> {
+    if !(4..=5).contains(&s.len()) || !s.is_ascii() {
+      bail!("invalid uci move: {s:?}");
+    }
+    let from = s[0..2].parse()?;
+    let to = s[2..4].parse()?;
+    let promotion = match s[4..].chars().next() {
+      None => None,
+      Some(c) => match PieceKind::from_char(c) {
+        Some(PieceKind::Pawn | PieceKind::King) | None => {
+          bail!("invalid promotion in uci move: {s:?}")
+        }
+        kind => kind,
+      },
+    };
+    Ok(Move { from, to, promotion })
+  }
+}
+
+impl fmt::Display for Move {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    write!(f, "{}{}", self.from, self.to)?;
+    if let Some(kind) = self.promotion {
+      write!(f, "{}", kind.char().to_ascii_lowercase())?;
+    }
+    Ok(())
+  }
+}
+
+// castling right bits
+const WHITE_SHORT: u8 = 1;
+const WHITE_LONG: u8 = 2;
+const BLACK_SHORT: u8 = 4;
+const BLACK_LONG: u8 = 8;
+
+/// Rights lost when a piece moves from or to the square.
+const fn castling_mask(sq: usize) -> u8 {
+  match sq {
+    0 => WHITE_LONG,
+    4 => WHITE_SHORT | WHITE_LONG,
+    7 => WHITE_SHORT,
+    56 => BLACK_LONG,
+    60 => BLACK_SHORT | BLACK_LONG,
+    63 => BLACK_SHORT,
+    _ => 0,
+  }
+}
+
+fn castling_hash(rights: u8) -> u64 {
+  (0..4)
+    .filter(|bit| rights & (1 << bit) != 0)
+    .fold(0, |hash, bit| hash ^ zobrist::CASTLING[bit])
+}
+
+/// Iterates over the squares of a bitboard, lowest first.
+pub(crate) struct Squares(pub u64);
+
+impl Iterator for Squares {
+  type Item = Square;
+
+  fn next(&mut self) -> Option<Square> {
+    if self.0 == 0 {
+      return None;
+    }
+    let sq = self.0.trailing_zeros() as u8;
+    self.0 &= self.0 - 1;
+    Some(Square(sq))
+  }
+}
+
+const fn leaper_attacks(deltas: &[(i8, i8)]) -> [u64; 64] {
+  let mut table = [0; 64];
+  let mut sq = 0;
+  while sq < 64 {
+    let (file, rank) = ((sq % 8) as i8, (sq / 8) as i8);
+    let mut i = 0;
+    while i < deltas.len() {
+      let (f, r) = (file + deltas[i].0, rank + deltas[i].1);
+      if f >= 0 && f < 8 && r >= 0 && r < 8 {
+        table[sq] |= 1 << (r * 8 + f);
+      }
+      i += 1;
+    }
+    sq += 1;
+  }
+  table
+}
+
+static KNIGHT_ATTACKS: [u64; 64] = leaper_attacks(&[
+  (1, 2),
+  (2, 1),
+  (2, -1),
+  (1, -2),
+  (-1, -2),
+  (-2, -1),
+  (-2, 1),
+  (-1, 2),
+]);
+
+static KING_ATTACKS: [u64; 64] = leaper_attacks(&[
+  (0, 1),
+  (1, 1),
+  (1, 0),
+  (1, -1),
+  (0, -1),
+  (-1, -1),
+  (-1, 0),
+  (-1, 1),
+]);
+
+static PAWN_ATTACKS: [[u64; 64]; 2] =
+  [leaper_attacks(&[(-1, 1), (1, 1)]), leaper_attacks(&[(-1, -1), (1, -1)])];
+
+// ray directions, the first four go towards higher square indices
+const DIRECTIONS: [(i8, i8); 8] =
+  [(0, 1), (1, 0), (1, 1), (-1, 1), (0, -1), (-1, 0), (-1, -1), (1, -1)];
+
+const fn rays() -> [[u64; 64]; 8] {
+  let mut table = [[0; 64]; 8];
+  let mut dir = 0;
+  while dir < 8 {
+    let mut sq = 0;
+    while sq < 64 {
+      let (mut f, mut r) = ((sq % 8) as i8, (sq / 8) as i8);
+      loop {
+        f += DIRECTIONS[dir].0;
+        r += DIRECTIONS[dir].1;
+        if f < 0 || f >= 8 || r < 0 || r >= 8 {
+          break;
+        }
+        table[dir][sq] |= 1 << (r * 8 + f);
+      }
+      sq += 1;
+    }
+    dir += 1;
+  }
+  table
+}
+
+static RAYS: [[u64; 64]; 8] = rays();
+
+fn ray_attacks(
+  sq: Square,
+  occupied: u64,
+  dir: usize,
+) -> u64 {
+  let ray = RAYS[dir][sq.index()];
+  let blockers = ray & occupied;
+  if blockers == 0 {
+    return ray;
+  }
+  let blocker = if dir < 4 {
+    blockers.trailing_zeros()
+  } else {
+    63 - blockers.leading_zeros()
+  };
+  ray ^ RAYS[dir][blocker as usize]
+}
+
+pub fn rook_attacks(
+  sq: Square,
+  occupied: u64,
+) -> u64 {
+  [0, 1, 4, 5].iter().fold(0, |bb, &dir| bb | ray_attacks(sq, occupied, dir))
+}
+
+pub fn bishop_attacks(
+  sq: Square,
+  occupied: u64,
+) -> u64 {
+  [2, 3, 6, 7].iter().fold(0, |bb, &dir| bb | ray_attacks(sq, occupied, dir))
+}
+
+pub fn knight_attacks(sq: Square) -> u64 {
+  KNIGHT_ATTACKS[sq.index()]
+}
+
+pub fn king_attacks(sq: Square) -> u64 {
+  KING_ATTACKS[sq.index()]
+}
+
+/// Squares a pawn of `color` standing on `sq` attacks.
+pub fn pawn_attacks(
+  color: Color,
+  sq: Square,
+) -> u64 {
+  PAWN_ATTACKS[color.index()][sq.index()]
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct Position {
+  by_kind: [u64; 6],
+  by_color: [u64; 2],
+  side_to_move: Color,
+  castling: u8,
+  /// Only set when a pawn of the side to move can capture en passant,
+  /// which keeps equal positions equal and matches the Polyglot hash.
+  en_passant: Option<Square>,
+  halfmove_clock: u32,
+  fullmove_number: u32,
+  hash: u64,
+}
+
+impl Default for Position {
+  fn default() -> Self {
+    Position::from_fen(STARTING_FEN).unwrap()
+  }
+}
+
+impl fmt::Debug for Position {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    write!(f, "Position({})", self.to_fen())
+  }
+}
+
+impl Position {
+  pub fn startpos() -> Self {
+    Self::default()
+  }
+
+  pub fn from_fen(fen: &str) -> anyhow::Result<Self> {
+    let mut fields = fen.split_whitespace();
+    let placement =
+      fields.next().ok_or_else(|| anyhow!("empty fen: {fen:?}"))?;
+    let mut position = Position {
+      by_kind: [0; 6],
+      by_color: [0; 2],
+      side_to_move: Color::White,
+      castling: 0,
+      en_passant: None,
+      halfmove_clock: 0,
+      fullmove_number: 1,
+      hash: 0,
+    };
+
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != 8 {
+      bail!("fen must describe 8 ranks: {fen:?}");
+    }
+    for (i, row) in ranks.iter().enumerate() {
+      let rank = 7 - i as u8;
+      let mut file = 0;
+      for c in row.chars() {
+        if let Some(skip) = c.to_digit(10) {
+          file += skip as u8;
+        } else if let Some(piece) = Piece::from_fen_char(c) {
+          if file >= 8 {
+            bail!("too many squares on rank {}: {fen:?}", rank + 1);
+          }
+          position.put(Square::new(file, rank), piece);
+          file += 1;
+        } else {
+          bail!("unexpected character {c:?} in fen: {fen:?}");
+        }
+      }
+      if file != 8 {
+        bail!("rank {} doesn't have 8 squares: {fen:?}", rank + 1);
+      }
+    }
+
+    position.side_to_move = match fields.next() {
+      None | Some("w") => Color::White,
+      Some("b") => Color::Black,
+      Some(other) => bail!("invalid side to move {other:?}: {fen:?}"),
+    };
+
+    match fields.next() {
+      None | Some("-") => {}
+      Some(castling) => {
+        for c in castling.chars() {
+          position.castling |= match c {
+            'K' => WHITE_SHORT,
+            'Q' => WHITE_LONG,
+            'k' => BLACK_SHORT,
+            'q' => BLACK_LONG,
+            _ => bail!("invalid castling rights {castling:?}: {fen:?}"),
+          };
+        }
+      }
+    }
+    // drop rights the placement can't back up
+    for (right, king, rook) in [
+      (WHITE_SHORT, 4, 7),
+      (WHITE_LONG, 4, 0),
+      (BLACK_SHORT, 60, 63),
+      (BLACK_LONG, 60, 56),
+    ] {
+      let color = if right & (WHITE_SHORT | WHITE_LONG) != 0 {
+        Color::White
+      } else {
+        Color::Black
+      };
+      let king_ok = position.piece_at(Square(king))
+        == Some(Piece::new(color, PieceKind::King));
+      let rook_ok = position.piece_at(Square(rook))
+        == Some(Piece::new(color, PieceKind::Rook));
+      if !(king_ok && rook_ok) {
+        position.castling &= !right;
+      }
+    }
+
+    if let Some(ep) = fields.next().filter(|&ep| ep != "-") {
+      let sq: Square = ep.parse()?;
+      position.set_en_passant(sq);
+    }
+    if let Some(clock) = fields.next() {
+      position.halfmove_clock = clock.parse()?;
+    }
+    if let Some(number) = fields.next() {
+      position.fullmove_number = number.parse::<u32>()?.max(1);
+    }
+
+    for color in [Color::White, Color::Black] {
+      let kings = position.pieces(color, PieceKind::King);
+      if kings.count_ones() != 1 {
+        bail!("{color:?} must have exactly one king: {fen:?}");
+      }
+    }
+    let them = !position.side_to_move;
+    if position.is_attacked(position.king(them), position.side_to_move) {
+      bail!("side not to move is in check: {fen:?}");
+    }
+
+    position.hash = position.compute_hash();
+    Ok(position)
+  }
+
+  pub fn to_fen(&self) -> String {
+    let mut fen = String::new();
+    for rank in (0..8).rev() {
+      let mut empty = 0;
+      for file in 0..8 {
+        match self.piece_at(Square::new(file, rank)) {
+          Some(piece) => {
+            if empty > 0 {
+              fen.push_str(&empty.to_string());
+              empty = 0;
+            }
+            fen.push(piece.fen_char());
+          }
+          None => empty += 1,
+        }
+      }
+      if empty > 0 {
+        fen.push_str(&empty.to_string());
+      }
+      if rank > 0 {
+        fen.push('/');
+      }
+    }
+    fen.push(' ');
+    fen.push(self.side_to_move.fold('w', 'b'));
+    fen.push(' ');
+    if self.castling == 0 {
+      fen.push('-');
+    }
+    for (right, c) in [
+      (WHITE_SHORT, 'K'),
+      (WHITE_LONG, 'Q'),
+      (BLACK_SHORT, 'k'),
+      (BLACK_LONG, 'q'),
+    ] {
+      if self.castling & right != 0 {
+        fen.push(c);
+      }
+    }
+    match self.en_passant {
+      Some(sq) => fen.push_str(&format!(" {sq}")),
+      None => fen.push_str(" -"),
+    }
+    fen.push_str(&format!(" {} {}", self.halfmove_clock, self.fullmove_number));
+    fen
+  }
+
+  pub fn side_to_move(&self) -> Color {
+    self.side_to_move
+  }
+
+  pub fn en_passant(&self) -> Option<Square> {
+    self.en_passant
+  }
+
+  pub fn halfmove_clock(&self) -> u32 {
+    self.halfmove_clock
+  }
+
+  pub fn fullmove_number(&self) -> u32 {
+    self.fullmove_number
+  }
+
+  /// Zobrist hash of the position, equal to its Polyglot book key.
+  pub fn hash(&self) -> u64 {
+    self.hash
+  }
+
+  pub fn can_castle(
+    &self,
+    color: Color,
+    short: bool,
+  ) -> bool {
+    let right = match (color, short) {
+      (Color::White, true) => WHITE_SHORT,
+      (Color::White, false) => WHITE_LONG,
+      (Color::Black, true) => BLACK_SHORT,
+      (Color::Black, false) => BLACK_LONG,
+    };
+    self.castling & right != 0
+  }
+
+  pub fn piece_at(
+    &self,
+    sq: Square,
+  ) -> Option<Piece> {
+    let bb = sq.bb();
+    let color = if self.by_color[0] & bb != 0 {
+      Color::White
+    } else if self.by_color[1] & bb != 0 {
+      Color::Black
+    } else {
+      return None;
+    };
+    let kind = PieceKind::ALL
+      .into_iter()
+      .find(|kind| self.by_kind[kind.index()] & bb != 0)?;
+    Some(Piece { color, kind })
+  }
+
+  pub fn pieces(
+    &self,
+    color: Color,
+    kind: PieceKind,
+  ) -> u64 {
+    self.by_kind[kind.index()] & self.by_color[color.index()]
+  }
+
+  pub fn pieces_of_kind(
+    &self,
+    kind: PieceKind,
+  ) -> u64 {
+    self.by_kind[kind.index()]
+  }
+
+  pub fn pieces_of_color(
+    &self,
+    color: Color,
+  ) -> u64 {
+    self.by_color[color.index()]
+  }
+
+  pub fn occupied(&self) -> u64 {
+    self.by_color[0] | self.by_color[1]
+  }
+
+  pub fn king(
+    &self,
+    color: Color,
+  ) -> Square {
+    let kings = self.pieces(color, PieceKind::King);
+    Square(kings.trailing_zeros() as u8)
+  }
+
+  /// All pieces standing on the board with their squares.
+  pub fn board(&self) -> impl Iterator<Item = (Square, Piece)> + '_ {
+    Squares(self.occupied()).map(|sq| (sq, self.piece_at(sq).unwrap()))
+  }
+
+  fn put(
+    &mut self,
+    sq: Square,
+    piece: Piece,
+  ) {
+    self.by_kind[piece.kind.index()] |= sq.bb();
+    self.by_color[piece.color.index()] |= sq.bb();
+    self.hash ^= piece_hash(piece, sq);
+  }
+
+  fn remove(
+    &mut self,
+    sq: Square,
+    piece: Piece,
+  ) {
+    self.by_kind[piece.kind.index()] &= !sq.bb();
+    self.by_color[piece.color.index()] &= !sq.bb();
+    self.hash ^= piece_hash(piece, sq);
+  }
+
+  /// Records `sq` as the en passant square if the side to move has a
+  /// pawn ready to capture there.
+  fn set_en_passant(
+    &mut self,
+    sq: Square,
+  ) {
+    let us = self.side_to_move;
+    let capturers = pawn_attacks(!us, sq) & self.pieces(us, PieceKind::Pawn);
+    if capturers != 0 {
+      self.en_passant = Some(sq);
+    }
+  }
+
+  fn compute_hash(&self) -> u64 {
+    let mut hash =
+      self.board().fold(0, |hash, (sq, piece)| hash ^ piece_hash(piece, sq));
+    hash ^= castling_hash(self.castling);
+    if let Some(sq) = self.en_passant {
+      hash ^= zobrist::EN_PASSANT[sq.file() as usize];
+    }
+    if self.side_to_move == Color::White {
+      hash ^= zobrist::WHITE_TO_MOVE;
+    }
+    hash
+  }
+
+  pub fn attackers_to(
+    &self,
+    sq: Square,
+    occupied: u64,
+  ) -> u64 {
+    let diagonal = self.by_kind[PieceKind::Bishop.index()]
+      | self.by_kind[PieceKind::Queen.index()];
+    let straight = self.by_kind[PieceKind::Rook.index()]
+      | self.by_kind[PieceKind::Queen.index()];
+    (knight_attacks(sq) & self.by_kind[PieceKind::Knight.index()])
+      | (king_attacks(sq) & self.by_kind[PieceKind::King.index()])
+      | (bishop_attacks(sq, occupied) & diagonal)
+      | (rook_attacks(sq, occupied) & straight)
+      | (pawn_attacks(Color::White, sq)
+        & self.pieces(Color::Black, PieceKind::Pawn))
+      | (pawn_attacks(Color::Black, sq)
+        & self.pieces(Color::White, PieceKind::Pawn))
+  }
+
+  pub fn is_attacked(
+    &self,
+    sq: Square,
+    by: Color,
+  ) -> bool {
+    self.attackers_to(sq, self.occupied()) & self.by_color[by.index()] != 0
+  }
+
+  pub fn is_check(&self) -> bool {
+    self.is_attacked(self.king(self.side_to_move), !self.side_to_move)
+  }
+
+  pub fn is_checkmate(&self) -> bool {
+    self.is_check() && self.legal_moves().is_empty()
+  }
+
+  pub fn is_stalemate(&self) -> bool {
+    !self.is_check() && self.legal_moves().is_empty()
+  }
+
+  /// Neither side can possibly mate: bare kings, a single minor piece,
+  /// or bishops that all stand on squares of one color.
+  pub fn is_insufficient_material(&self) -> bool {
+    let heavy = self.by_kind[PieceKind::Pawn.index()]
+      | self.by_kind[PieceKind::Rook.index()]
+      | self.by_kind[PieceKind::Queen.index()];
+    if heavy != 0 {
+      return false;
+    }
+    let knights = self.by_kind[PieceKind::Knight.index()];
+    let bishops = self.by_kind[PieceKind::Bishop.index()];
+    if knights.count_ones() + bishops.count_ones() <= 1 {
+      return true;
+    }
+    const DARK_SQUARES: u64 = 0xAA55_AA55_AA55_AA55;
+    knights == 0
+      && (bishops & DARK_SQUARES == 0 || bishops & !DARK_SQUARES == 0)
+  }
+
+  /// Whether `color` could still mate with the opponent's help, as when
+  /// the opponent's flag falls. A bare king can't, nor a lone knight
+  /// against a king with only queens beside it, nor bishops of one shade
+  /// when no knight, pawn or bishop of the other shade can box the king in.
+  pub fn can_mate(
+    &self,
+    color: Color,
+  ) -> bool {
+    use PieceKind::*;
+    const DARK_SQUARES: u64 = 0xAA55_AA55_AA55_AA55;
+    let own = |kind| self.pieces(color, kind);
+    let theirs = |kind| self.pieces(!color, kind);
+    if own(Pawn) | own(Rook) | own(Queen) != 0 {
+      return true;
+    }
+    let (knights, bishops) = (own(Knight), own(Bishop));
+    if knights != 0 {
+      let helpers =
+        self.pieces_of_color(!color) & !theirs(King) & !theirs(Queen);
+      return knights.count_ones() + bishops.count_ones() > 1 || helpers != 0;
+    }
+    let other_shade = match (bishops & DARK_SQUARES, bishops & !DARK_SQUARES) {
+      (0, 0) => return false,
+      (0, _) => theirs(Bishop) & DARK_SQUARES,
+      (_, 0) => theirs(Bishop) & !DARK_SQUARES,
+      _ => return true,
+    };
+    theirs(Knight) | theirs(Pawn) | other_shade != 0
+  }
+
+  /// Whether the move captures something, en passant included.
+  pub fn is_capture(
+    &self,
+    mv: Move,
+  ) -> bool {
+    self.occupied() & mv.to.bb() != 0
+      || (Some(mv.to) == self.en_passant
+        && self.pieces_of_kind(PieceKind::Pawn) & mv.from.bb() != 0)
+  }
+
+  pub fn legal_moves(&self) -> Vec<Move> {
+    let mut moves = Vec::with_capacity(64);
+    self.pseudo_legal_moves(&mut moves);
+    moves.retain(|&mv| self.played(mv).was_legal());
+    moves
+  }
+
+  pub fn is_legal(
+    &self,
+    mv: Move,
+  ) -> bool {
+    self.legal_moves().contains(&mv)
+  }
+
+  /// After a move was played: whether it left the mover's king safe.
+  pub(crate) fn was_legal(&self) -> bool {
+    let mover = !self.side_to_move;
+    !self.is_attacked(self.king(mover), self.side_to_move)
+  }
+
+  /// Moves that follow piece movement rules but may leave the own king
+  /// in check. Castling through check is already excluded.
+  pub(crate) fn pseudo_legal_moves(
+    &self,
+    moves: &mut Vec<Move>,
+  ) {
+    let us = self.side_to_move;
+    let own = self.by_color[us.index()];
+    let enemies = self.by_color[(!us).index()];
+    let occupied = own | enemies;
+
+    for from in Squares(own & !self.by_kind[PieceKind::Pawn.index()]) {
+      let targets = match self.piece_at(from).unwrap().kind {
+        PieceKind::Knight => knight_attacks(from),
+        PieceKind::Bishop => bishop_attacks(from, occupied),
+        PieceKind::Rook => rook_attacks(from, occupied),
+        PieceKind::Queen => {
+          bishop_attacks(from, occupied) | rook_attacks(from, occupied)
+        }
+        PieceKind::King => king_attacks(from),
+        PieceKind::Pawn => unreachable!(),
+      };
+      for to in Squares(targets & !own) {
+        moves.push(Move::new(from, to));
+      }
+    }
+
+    let (forward, start_rank, last_rank): (i8, u8, u8) =
+      us.fold((8, 1, 7), (-8, 6, 0));
+    for from in Squares(self.pieces(us, PieceKind::Pawn)) {
+      let mut targets = pawn_attacks(us, from) & enemies;
+      if let Some(ep) = self.en_passant {
+        targets |= pawn_attacks(us, from) & ep.bb();
+      }
+      let one = Square((from.0 as i8 + forward) as u8);
+      if occupied & one.bb() == 0 {
+        targets |= one.bb();
+        let two = Square((one.0 as i8 + forward) as u8);
+        if from.rank() == start_rank && occupied & two.bb() == 0 {
+          targets |= two.bb();
+        }
+      }
+      for to in Squares(targets) {
+        if to.rank() == last_rank {
+          for kind in [
+            PieceKind::Queen,
+            PieceKind::Knight,
+            PieceKind::Rook,
+            PieceKind::Bishop,
+          ] {
+            moves.push(Move { from, to, promotion: Some(kind) });
+          }
+        } else {
+          moves.push(Move::new(from, to));
+        }
+      }
+    }
+
+    let king = self.king(us);
+    let back_rank = us.fold(0, 7);
+    // (short, rook file, files that must be empty, files the king crosses)
+    let sides: [(bool, u8, &[u8], [u8; 2]); 2] =
+      [(true, 7, &[5, 6], [4, 5]), (false, 0, &[1, 2, 3], [4, 3])];
+    for (short, rook_file, empty_files, crossed_files) in sides {
+      let rook = Square::new(rook_file, back_rank);
+      if !self.can_castle(us, short)
+        || king != Square::new(4, back_rank)
+        || self.pieces(us, PieceKind::Rook) & rook.bb() == 0
+      {
+        continue;
+      }
+      let blocked = empty_files
+        .iter()
+        .any(|&file| occupied & Square::new(file, back_rank).bb() != 0);
+      // the destination itself is covered by the legality filter
+      let attacked = crossed_files
+        .iter()
+        .any(|&file| self.is_attacked(Square::new(file, back_rank), !us));
+      if !blocked && !attacked {
+        let to = Square::new(if short { 6 } else { 2 }, back_rank);
+        moves.push(Move::new(king, to));
+      }
+    }
+  }
+
+  /// Returns the position after the move. The move must be at least
+  /// pseudo-legal, see [`Position::legal_moves`].
+  pub fn played(
+    &self,
+    mv: Move,
+  ) -> Position {
+    let mut next = self.clone();
+    next.play(mv);
+    next
+  }
+
+  pub fn play(
+    &mut self,
+    mv: Move,
+  ) {
+    let us = self.side_to_move;
+    let piece = self.piece_at(mv.from).expect("no piece on the from square");
+    let captured = self.piece_at(mv.to);
+
+    if let Some(ep) = self.en_passant.take() {
+      self.hash ^= zobrist::EN_PASSANT[ep.file() as usize];
+    }
+    self.halfmove_clock += 1;
+
+    if let Some(captured) = captured {
+      self.remove(mv.to, captured);
+      self.halfmove_clock = 0;
+    }
+    self.remove(mv.from, piece);
+    let kind = mv.promotion.unwrap_or(piece.kind);
+    self.put(mv.to, Piece::new(us, kind));
+
+    let mut double_push = None;
+    if piece.kind == PieceKind::Pawn {
+      self.halfmove_clock = 0;
+      let distance = mv.to.0.abs_diff(mv.from.0);
+      if distance == 16 {
+        double_push = Some(Square((mv.from.0 + mv.to.0) / 2));
+      } else if captured.is_none() && distance != 8 {
+        // en passant, the captured pawn is beside the from square
+        let victim = Square::new(mv.to.file(), mv.from.rank());
+        self.remove(victim, Piece::new(!us, PieceKind::Pawn));
+      }
+    }
+
+    if piece.kind == PieceKind::King
+      && mv.to.file().abs_diff(mv.from.file()) == 2
+    {
+      let rank = mv.from.rank();
+      let (rook_from, rook_to) =
+        if mv.to.file() == 6 { (7, 5) } else { (0, 3) };
+      let rook = Piece::new(us, PieceKind::Rook);
+      self.remove(Square::new(rook_from, rank), rook);
+      self.put(Square::new(rook_to, rank), rook);
+    }
+
+    let rights = self.castling
+      & !castling_mask(mv.from.index())
+      & !castling_mask(mv.to.index());
+    self.hash ^= castling_hash(self.castling ^ rights);
+    self.castling = rights;
+
+    if us == Color::Black {
+      self.fullmove_number += 1;
+    }
+    self.side_to_move = !us;
+    self.hash ^= zobrist::WHITE_TO_MOVE;
+
+    if let Some(sq) = double_push {
+      self.set_en_passant(sq);
+      if let Some(ep) = self.en_passant {
+        self.hash ^= zobrist::EN_PASSANT[ep.file() as usize];
+      }
+    }
+  }
+
+  /// Passes the turn, used by null move pruning.
+  pub(crate) fn play_null(&mut self) {
+    if let Some(ep) = self.en_passant.take() {
+      self.hash ^= zobrist::EN_PASSANT[ep.file() as usize];
+    }
+    self.halfmove_clock += 1;
+    self.side_to_move = !self.side_to_move;
+    self.hash ^= zobrist::WHITE_TO_MOVE;
+  }
+
+  /// Counts leaf nodes of the legal move tree, for testing movegen.
+  pub fn perft(
+    &self,
+    depth: u32,
+  ) -> u64 {
+    let moves = self.legal_moves();
+    if depth <= 1 {
+      return if depth == 0 { 1 } else { moves.len() as u64 };
+    }
+    moves.into_iter().map(|mv| self.played(mv).perft(depth - 1)).sum()
+  }
+}
+
+fn piece_hash(
+  piece: Piece,
+  sq: Square,
+) -> u64 {
+  let index = 2 * piece.kind.index() + (piece.color == Color::White) as usize;
+  zobrist::PIECES[index][sq.index()]
+}
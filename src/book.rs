@@ -0,0 +1,240 @@
+//! Polyglot opening books. A book is a sorted array of 16-byte big-endian
+//! entries: position key, move, weight and a learning field we keep but
+//! never use. http://hgm.nubati.net/book_format.html
+
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::bail;
+
+use crate::{
+  board::{Move, PieceKind, Position, Square},
+  pgn::Game,
+};
+
+const ENTRY_SIZE: usize = 16;
+
+/// Polyglot key of a position. Our Zobrist keys use the Polyglot random
+/// numbers, so this is the position's hash.
+pub fn key(position: &Position) -> u64 {
+  position.hash()
+}
+
+/// Polyglot writes castling as the king taking its own rook.
+pub(crate) fn encode_move(
+  position: &Position,
+  mv: Move,
+) -> u16 {
+  let is_king = position
+    .piece_at(mv.from)
+    .is_some_and(|piece| piece.kind == PieceKind::King);
+  let mut to = mv.to;
+  if is_king && mv.from.file().abs_diff(mv.to.file()) == 2 {
+    let rook_file = if mv.to.file() == 6 { 7 } else { 0 };
+    to = Square::new(rook_file, mv.from.rank());
+  }
+  let promotion = match mv.promotion {
+    None => 0,
+    Some(kind) => kind.index() as u16,
+  };
+  to.index() as u16 | (mv.from.index() as u16) << 6 | promotion << 12
+}
+
+pub(crate) fn decode_move(
+  position: &Position,
+  raw: u16,
+) -> Move {
+  let from = Square::from_index((raw >> 6 & 63) as u8);
+  let mut to = Square::from_index((raw & 63) as u8);
+  let promotion = match raw >> 12 & 7 {
+    0 => None,
+    index => PieceKind::ALL.get(index as usize).copied(),
+  };
+  let castles = position.piece_at(from).zip(position.piece_at(to)).is_some_and(
+    |(king, rook)| {
+      king.kind == PieceKind::King
+        && rook.kind == PieceKind::Rook
+        && king.color == rook.color
+    },
+  );
+  if castles {
+    let file = if to.file() > from.file() { 6 } else { 2 };
+    to = Square::new(file, from.rank());
+  }
+  Move { from, to, promotion }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Entry {
+  key: u64,
+  mv: u16,
+  weight: u16,
+  learn: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Selection {
+  /// Always the move with the highest weight.
+  Best,
+  /// A random move, chosen with probability proportional to its weight.
+  #[default]
+  Weighted,
+}
+
+/// Which games and moves make it into a book built from PGN.
+#[derive(Clone, Debug)]
+pub struct BuildOptions {
+  /// Only the first plies of each game are looked at.
+  pub max_ply: usize,
+  /// Moves played in fewer games are left out.
+  pub min_games: u32,
+  /// Moves scoring fewer points are left out, counting two for a win and
+  /// one for a draw of the side that played the move.
+  pub min_weight: u32,
+}
+
+impl Default for BuildOptions {
+  fn default() -> Self {
+    Self { max_ply: 30, min_games: 1, min_weight: 1 }
+  }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Book {
+  /// Sorted by key, then by descending weight.
+  entries: Vec<Entry>,
+}
+
+impl Book {
+  pub fn open(path: &Path) -> anyhow::Result<Book> {
+    Book::from_bytes(&fs::read(path)?)
+  }
+
+  pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Book> {
+    if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+      bail!("polyglot book size {} is not a multiple of 16", bytes.len());
+    }
+    let mut entries: Vec<Entry> = bytes
+      .chunks_exact(ENTRY_SIZE)
+      .map(|chunk| Entry {
+        key: u64::from_be_bytes(chunk[0..8].try_into().unwrap()),
+        mv: u16::from_be_bytes(chunk[8..10].try_into().unwrap()),
+        weight: u16::from_be_bytes(chunk[10..12].try_into().unwrap()),
+        learn: u32::from_be_bytes(chunk[12..16].try_into().unwrap()),
+      })
+      .collect();
+    // lookups rely on the order, do not trust the file
+    entries.sort_by_key(|entry| (entry.key, u16::MAX - entry.weight));
+    Ok(Book { entries })
+  }
+
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(self.entries.len() * ENTRY_SIZE);
+    for entry in &self.entries {
+      bytes.extend_from_slice(&entry.key.to_be_bytes());
+      bytes.extend_from_slice(&entry.mv.to_be_bytes());
+      bytes.extend_from_slice(&entry.weight.to_be_bytes());
+      bytes.extend_from_slice(&entry.learn.to_be_bytes());
+    }
+    bytes
+  }
+
+  pub fn save(
+    &self,
+    path: &Path,
+  ) -> anyhow::Result<()> {
+    Ok(fs::write(path, self.to_bytes())?)
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  /// Legal book moves of the position with their weights, best first.
+  pub fn moves(
+    &self,
+    position: &Position,
+  ) -> Vec<(Move, u16)> {
+    let key = key(position);
+    let start = self.entries.partition_point(|entry| entry.key < key);
+    self.entries[start..]
+      .iter()
+      .take_while(|entry| entry.key == key)
+      .map(|entry| (decode_move(position, entry.mv), entry.weight))
+      .filter(|&(mv, _)| position.is_legal(mv))
+      .collect()
+  }
+
+  pub fn pick(
+    &self,
+    position: &Position,
+    selection: Selection,
+  ) -> Option<Move> {
+    let moves = self.moves(position);
+    let total: u32 = moves.iter().map(|&(_, weight)| weight as u32).sum();
+    match selection {
+      // a book may list moves it never plays, with weight 0
+      _ if total == 0 => None,
+      Selection::Best => moves.first().map(|&(mv, _)| mv),
+      Selection::Weighted => {
+        let mut choice = fastrand::u32(0..total);
+        moves.into_iter().find_map(|(mv, weight)| {
+          if choice < weight as u32 {
+            Some(mv)
+          } else {
+            choice -= weight as u32;
+            None
+          }
+        })
+      }
+    }
+  }
+
+  /// Builds a book from finished games, weighting each move by how well
+  /// it scored for the side that played it.
+  pub fn build<'a>(
+    games: impl IntoIterator<Item = &'a Game>,
+    options: &BuildOptions,
+  ) -> Book {
+    #[derive(Default)]
+    struct Stats {
+      games: u32,
+      points: u32,
+    }
+    let mut stats: HashMap<(u64, u16), Stats> = HashMap::new();
+    for game in games {
+      let mut position = game.start.clone();
+      for &mv in game.moves.iter().take(options.max_ply) {
+        let Some(score) = game.outcome.score(position.side_to_move()) else {
+          break;
+        };
+        let entry = (key(&position), encode_move(&position, mv));
+        let stats = stats.entry(entry).or_default();
+        stats.games += 1;
+        stats.points += (score * 2.0) as u32;
+        position.play(mv);
+      }
+    }
+
+    stats.retain(|_, stats| {
+      stats.games >= options.min_games && stats.points >= options.min_weight
+    });
+    // weights are 16 bits, scale everything down if needed
+    let max = stats.values().map(|stats| stats.points).max().unwrap_or(0);
+    let scale = (max as f64 / u16::MAX as f64).max(1.0);
+    let mut entries: Vec<Entry> = stats
+      .into_iter()
+      .map(|((key, mv), stats)| Entry {
+        key,
+        mv,
+        weight: ((stats.points as f64 / scale) as u16).max(1),
+        learn: 0,
+      })
+      .collect();
+    entries.sort_by_key(|entry| (entry.key, u16::MAX - entry.weight, entry.mv));
+    Book { entries }
+  }
+}
@@ -0,0 +1,414 @@
+//! Live broadcast of a game to any number of viewers over TCP, for
+//! showing club games on a projector. The host publishes the main line of
+//! its game tree with the annotations, clocks included as `[%clk]`; each
+//! viewer gets the game so far on joining, then the changes. Viewers only
+//! listen, their board and analysis stay their own.
+//!
+//! A viewer's copy is a game tree too, so it can be broadcast on again to
+//! relay the game further.
+
+use std::{
+  io::{BufReader, ErrorKind},
+  net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+  sync::mpsc::{self, TryRecvError},
+  thread,
+  time::Duration,
+};
+
+use anyhow::{anyhow, ensure};
+
+use crate::{
+  board::{Move, Position},
+  gametree::{Annotations, GameTree},
+  pgn::{self, Outcome},
+  protocol::{read, write_message, Relay, VERSION},
+};
+
+/// Viewers that stop reading for this long are dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Tags worth showing to viewers.
+const TAGS: [&str; 7] =
+  ["Event", "Site", "Date", "Round", "White", "Black", "TimeControl"];
+
+/// A move of the main line as sent.
+#[derive(Clone, Debug, PartialEq)]
+struct Sent {
+  mv: Move,
+  nags: Vec<u8>,
+  comment: String,
+}
+
+pub struct Broadcast {
+  listener: TcpListener,
+  pub name: String,
+  viewers: Vec<TcpStream>,
+  tags: Vec<(String, String)>,
+  start: Position,
+  moves: Vec<Sent>,
+  outcome: Outcome,
+}
+
+impl Broadcast {
+  /// Listens for viewers on `addr`.
+  pub fn host(
+    addr: impl ToSocketAddrs,
+    name: &str,
+  ) -> anyhow::Result<Broadcast> {
+    let listener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    Ok(Broadcast {
+      listener,
+      name: name.into(),
+      viewers: Vec::new(),
+      tags: Vec::new(),
+      start: Position::startpos(),
+      moves: Vec::new(),
+      outcome: Outcome::Unknown,
+    })
+  }
+
+  pub fn local_addr(&self) -> Option<SocketAddr> {
+    self.listener.local_addr().ok()
+  }
+
+  pub fn viewers(&self) -> usize {
+    self.viewers.len()
+  }
+
+  /// Sends to every viewer, dropping those who can't take it.
+  fn send(
+    &mut self,
+    message: &Relay,
+  ) {
+    self.viewers.retain_mut(|viewer| {
+      let sent = write_message(viewer, message);
+      if let Err(err) = &sent {
+        log::info!("a viewer left: {err:#}");
+      }
+      sent.is_ok()
+    });
+  }
+
+  /// Everything a newcomer needs.
+  fn history(&self) -> Vec<Relay> {
+    let mut messages =
+      vec![Relay::Hello { version: VERSION, name: self.name.clone() }];
+    messages.extend(self.tags.iter().map(|(name, value)| Relay::Tag {
+      name: name.clone(),
+      value: value.clone(),
+    }));
+    messages.push(Relay::Start { start: self.start.clone() });
+    for (ply, sent) in self.moves.iter().enumerate() {
+      messages.push(Relay::Move {
+        ply,
+        mv: sent.mv,
+        nags: sent.nags.clone(),
+        comment: sent.comment.clone(),
+      });
+    }
+    if self.outcome != Outcome::Unknown {
+      messages.push(Relay::Result { outcome: self.outcome });
+    }
+    messages
+  }
+
+  /// Takes in viewers who came.
+  pub fn poll(&mut self) {
+    loop {
+      let stream = match self.listener.accept() {
+        Ok((stream, addr)) => {
+          log::info!("{addr} is watching");
+          stream
+        }
+        Err(err) if err.kind() == ErrorKind::WouldBlock => return,
+        Err(err) => {
+          log::warn!("can't accept viewers: {err}");
+          return;
+        }
+      };
+      let mut stream = stream;
+      let joined = stream
+        .set_nonblocking(false)
+        .and_then(|()| stream.set_nodelay(true))
+        .and_then(|()| stream.set_write_timeout(Some(WRITE_TIMEOUT)))
+        .map_err(anyhow::Error::from)
+        .and_then(|()| {
+          self
+            .history()
+            .iter()
+            .try_for_each(|message| write_message(&mut stream, message))
+        });
+      match joined {
+        Ok(()) => self.viewers.push(stream),
+        Err(err) => log::warn!("can't take in a viewer: {err:#}"),
+      }
+    }
+  }
+
+  /// Sends what changed in the main line of `tree`, the tags and the
+  /// result of `record` since the last call.
+  pub fn publish(
+    &mut self,
+    tree: &GameTree,
+    record: &pgn::Game,
+  ) {
+    let tags: Vec<(String, String)> = TAGS
+      .iter()
+      .filter_map(|&name| {
+        Some((name.to_string(), record.tag(name)?.to_string()))
+      })
+      .collect();
+    for tag in &tags {
+      if !self.tags.contains(tag) {
+        let (name, value) = tag.clone();
+        self.send(&Relay::Tag { name, value });
+      }
+    }
+    self.tags = tags;
+
+    let moves: Vec<Sent> = tree
+      .mainline()
+      .into_iter()
+      .map(|id| {
+        let node = tree.node(id);
+        Sent {
+          mv: node.mv.unwrap(),
+          nags: node.annotations.nags.clone(),
+          comment: node.annotations.pgn_comment(),
+        }
+      })
+      .collect();
+    if *tree.start() != self.start {
+      self.start = tree.start().clone();
+      self.moves.clear();
+      self.send(&Relay::Start { start: self.start.clone() });
+    }
+    let kept = self
+      .moves
+      .iter()
+      .zip(&moves)
+      .take_while(|(sent, now)| sent.mv == now.mv)
+      .count();
+    if kept < self.moves.len() {
+      self.moves.truncate(kept);
+      self.send(&Relay::Truncate { ply: kept });
+    }
+    for (ply, now) in moves.iter().enumerate() {
+      let message = match self.moves.get(ply) {
+        Some(sent) if sent == now => continue,
+        Some(_) => Relay::Annotate {
+          ply,
+          nags: now.nags.clone(),
+          comment: now.comment.clone(),
+        },
+        None => Relay::Move {
+          ply,
+          mv: now.mv,
+          nags: now.nags.clone(),
+          comment: now.comment.clone(),
+        },
+      };
+      self.send(&message);
+    }
+    self.moves = moves;
+
+    if record.outcome != self.outcome {
+      self.outcome = record.outcome;
+      self.send(&Relay::Result { outcome: self.outcome });
+    }
+  }
+
+  /// Says goodbye to the viewers.
+  pub fn stop(&mut self) {
+    self.send(&Relay::Bye);
+    for viewer in self.viewers.drain(..) {
+      let _ = viewer.shutdown(Shutdown::Both);
+    }
+  }
+}
+
+impl Drop for Broadcast {
+  fn drop(&mut self) {
+    self.stop();
+  }
+}
+
+/// Following a broadcast.
+pub struct Viewer {
+  /// Name of the broadcast, once it said hello.
+  pub name: String,
+  stream: TcpStream,
+  incoming: mpsc::Receiver<anyhow::Result<Option<Relay>>>,
+  connected: bool,
+  tags: Vec<(String, String)>,
+  tree: GameTree,
+  outcome: Outcome,
+}
+
+fn annotations(
+  nags: Vec<u8>,
+  comment: &str,
+) -> Annotations {
+  let mut annotations = Annotations { nags, ..Default::default() };
+  annotations.add_comment(comment);
+  annotations
+}
+
+impl Viewer {
+  /// Connects to the broadcast at `addr`.
+  pub fn connect(addr: impl ToSocketAddrs) -> anyhow::Result<Viewer> {
+    let addr = addr
+      .to_socket_addrs()?
+      .next()
+      .ok_or_else(|| anyhow!("no address to watch"))?;
+    let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let (tx, incoming) = mpsc::channel();
+    thread::spawn(move || loop {
+      let message = read(&mut reader);
+      let done = !matches!(message, Ok(Some(_)));
+      if tx.send(message).is_err() || done {
+        break;
+      }
+    });
+    Ok(Viewer {
+      name: String::new(),
+      stream,
+      incoming,
+      connected: true,
+      tags: Vec::new(),
+      tree: GameTree::new(Position::startpos()),
+      outcome: Outcome::Unknown,
+    })
+  }
+
+  pub fn is_connected(&self) -> bool {
+    self.connected
+  }
+
+  /// The game as broadcast, its main line only.
+  pub fn tree(&self) -> &GameTree {
+    &self.tree
+  }
+
+  pub fn tag(
+    &self,
+    name: &str,
+  ) -> Option<&str> {
+    self
+      .tags
+      .iter()
+      .find(|(tag, _)| tag == name)
+      .map(|(_, value)| value.as_str())
+  }
+
+  pub fn outcome(&self) -> Outcome {
+    self.outcome
+  }
+
+  /// The game as a record, for saving or broadcasting on.
+  pub fn record(&self) -> pgn::Game {
+    pgn::Game {
+      tags: self.tags.clone(),
+      start: self.tree.start().clone(),
+      moves: self.mainline(),
+      outcome: self.outcome,
+    }
+  }
+
+  fn mainline(&self) -> Vec<Move> {
+    let last = self.tree.mainline().last().copied();
+    self.tree.moves_to(last.unwrap_or(self.tree.root()))
+  }
+
+  /// Takes in what came, returning whether the game changed.
+  pub fn poll(&mut self) -> bool {
+    let mut changed = false;
+    while self.connected {
+      let message = match self.incoming.try_recv() {
+        Ok(Ok(Some(Relay::Bye))) | Ok(Ok(None)) => {
+          self.connected = false;
+          break;
+        }
+        Ok(Ok(Some(message))) => message,
+        Ok(Err(err)) => {
+          log::warn!("lost the broadcast: {err:#}");
+          self.connected = false;
+          break;
+        }
+        Err(TryRecvError::Disconnected) => {
+          self.connected = false;
+          break;
+        }
+        Err(TryRecvError::Empty) => break,
+      };
+      match self.receive(message) {
+        Ok(()) => changed = true,
+        Err(err) => {
+          // a viewer can't ask for the game again, better to stop
+          log::warn!("lost track of the broadcast: {err:#}");
+          self.leave();
+        }
+      }
+    }
+    changed
+  }
+
+  fn receive(
+    &mut self,
+    message: Relay,
+  ) -> anyhow::Result<()> {
+    let mainline = self.tree.mainline();
+    match message {
+      Relay::Tag { name, value } => {
+        match self.tags.iter_mut().find(|(tag, _)| *tag == name) {
+          Some(tag) => tag.1 = value,
+          None => self.tags.push((name, value)),
+        }
+      }
+      Relay::Start { start } => self.tree = GameTree::new(start),
+      Relay::Move { ply, mv, nags, comment } => {
+        ensure!(ply == mainline.len(), "move {ply} out of turn");
+        let parent =
+          ply.checked_sub(1).map_or(self.tree.root(), |last| mainline[last]);
+        let id = self.tree.add_move(parent, mv)?;
+        *self.tree.annotations_mut(id) = annotations(nags, &comment);
+        self.tree.go_to(id);
+      }
+      Relay::Annotate { ply, nags, comment } => {
+        let id = *mainline
+          .get(ply)
+          .ok_or_else(|| anyhow!("no move {ply} to annotate"))?;
+        *self.tree.annotations_mut(id) = annotations(nags, &comment);
+      }
+      Relay::Truncate { ply } => {
+        if let Some(&id) = mainline.get(ply) {
+          self.tree.delete(id);
+        }
+      }
+      Relay::Result { outcome } => self.outcome = outcome,
+      Relay::Hello { version, name } => {
+        ensure!(
+          version == VERSION,
+          "the broadcast speaks version {version}, we speak {VERSION}"
+        );
+        self.name = name;
+      }
+      Relay::Bye => {}
+    }
+    Ok(())
+  }
+
+  pub fn leave(&mut self) {
+    let _ = self.stream.shutdown(Shutdown::Both);
+    self.connected = false;
+  }
+}
+
+impl Drop for Viewer {
+  fn drop(&mut self) {
+    self.leave();
+  }
+}
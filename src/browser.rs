@@ -0,0 +1,126 @@
+//! Connections from the wasm build, over the browser's own WebSocket: the
+//! page can't open a TCP socket or wait on one, so messages come in
+//! through callbacks and are queued on a channel, polled like the reading
+//! thread's elsewhere. Each connection keeps the encoding it asked for.
+
+use std::{cell::RefCell, rc::Rc, sync::mpsc};
+
+use anyhow::anyhow;
+use js_sys::{ArrayBuffer, Uint8Array};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use web_sys::{BinaryType, CloseEvent, MessageEvent, WebSocket};
+
+use crate::transport::{decode, encode, Data, Encoding, Wire};
+
+fn js_error(err: JsValue) -> anyhow::Error {
+  anyhow!("{}", err.as_string().unwrap_or_else(|| format!("{err:?}")))
+}
+
+/// The writing half: the socket, and what was sent before it opened.
+pub struct Socket {
+  socket: WebSocket,
+  encoding: Encoding,
+  waiting: Rc<RefCell<Vec<Data>>>,
+  _onopen: Closure<dyn FnMut()>,
+  _onmessage: Closure<dyn FnMut(MessageEvent)>,
+  _onclose: Closure<dyn FnMut(CloseEvent)>,
+}
+
+fn send_data(
+  socket: &WebSocket,
+  data: &Data,
+) -> anyhow::Result<()> {
+  match data {
+    Data::Text(text) => socket.send_with_str(text),
+    Data::Binary(bytes) => socket.send_with_u8_array(bytes),
+  }
+  .map_err(js_error)
+}
+
+impl Socket {
+  pub fn send(
+    &self,
+    message: &impl Wire,
+  ) -> anyhow::Result<()> {
+    let data = encode(message, self.encoding);
+    if self.socket.ready_state() == WebSocket::CONNECTING {
+      self.waiting.borrow_mut().push(data);
+      return Ok(());
+    }
+    send_data(&self.socket, &data)
+  }
+
+  /// Closes the connection; the reading half sees it end.
+  pub fn close(&self) {
+    let _ = self.socket.close();
+  }
+}
+
+impl Drop for Socket {
+  fn drop(&mut self) {
+    self.socket.set_onopen(None);
+    self.socket.set_onmessage(None);
+    self.socket.set_onclose(None);
+    self.close();
+  }
+}
+
+/// Opens a WebSocket to `url` in `encoding`, returning the writing half
+/// and the messages as they arrive, `None` once it closed cleanly.
+pub fn connect<M: Wire + 'static>(
+  url: &str,
+  encoding: Encoding,
+) -> anyhow::Result<(Socket, mpsc::Receiver<anyhow::Result<Option<M>>>)> {
+  let socket =
+    WebSocket::new_with_str(url, encoding.protocol()).map_err(js_error)?;
+  socket.set_binary_type(BinaryType::Arraybuffer);
+  let waiting = Rc::new(RefCell::new(Vec::new()));
+  let (tx, incoming) = mpsc::channel();
+
+  let onopen = {
+    let (socket, waiting, tx) = (socket.clone(), waiting.clone(), tx.clone());
+    Closure::<dyn FnMut()>::new(move || {
+      for data in waiting.borrow_mut().drain(..) {
+        if let Err(err) = send_data(&socket, &data) {
+          let _ = tx.send(Err(err));
+          let _ = socket.close();
+          return;
+        }
+      }
+    })
+  };
+  let onmessage = {
+    let tx = tx.clone();
+    Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+      let data = event.data();
+      let data = match data.as_string() {
+        Some(text) => Data::Text(text),
+        None => match data.dyn_into::<ArrayBuffer>() {
+          Ok(buffer) => Data::Binary(Uint8Array::new(&buffer).to_vec()),
+          Err(_) => return,
+        },
+      };
+      let _ = tx.send(decode(data, encoding).map(Some));
+    })
+  };
+  let onclose =
+    Closure::<dyn FnMut(CloseEvent)>::new(move |event: CloseEvent| {
+      let end = match event.was_clean() {
+        true => Ok(None),
+        false => Err(anyhow!("connection closed ({})", event.code())),
+      };
+      let _ = tx.send(end);
+    });
+  socket.set_onopen(Some(onopen.as_ref().unchecked_ref()));
+  socket.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+  socket.set_onclose(Some(onclose.as_ref().unchecked_ref()));
+  let socket = Socket {
+    socket,
+    encoding,
+    waiting,
+    _onopen: onopen,
+    _onmessage: onmessage,
+    _onclose: onclose,
+  };
+  Ok((socket, incoming))
+}
@@ -0,0 +1,143 @@
+//! Chat beside networked games and the hooks to keep it civil. A server
+//! runs each line through its [`Filter`]s, which may mask words or refuse
+//! the line, and a [`RateLimit`] per player; a word list is the filter
+//! that comes with it. The chat of a game is kept in its PGN as `Chat`
+//! tags, one per line.
+
+use std::{
+  collections::{HashMap, VecDeque},
+  fs,
+  path::Path,
+  time::{Duration, Instant},
+};
+
+use anyhow::ensure;
+
+use crate::{pgn, protocol::ChatLine};
+
+/// Longest chat line, in characters.
+pub const MAX_LEN: usize = 200;
+
+/// The text of a chat line: one line, the spaces squeezed, cut at
+/// [`MAX_LEN`].
+pub fn clean(text: &str) -> String {
+  let words: Vec<&str> = text.split_whitespace().collect();
+  words.join(" ").chars().take(MAX_LEN).collect()
+}
+
+/// Checks chat lines before the server passes them on.
+pub trait Filter: Send {
+  /// The text to pass on from `from`, changed or not, or why it is
+  /// refused.
+  fn check(
+    &self,
+    from: &str,
+    text: &str,
+  ) -> anyhow::Result<String>;
+}
+
+/// Masks the words of a list, whatever their case.
+#[derive(Clone, Debug, Default)]
+pub struct WordList {
+  words: Vec<String>,
+}
+
+impl WordList {
+  pub fn new<S: AsRef<str>>(words: impl IntoIterator<Item = S>) -> WordList {
+    let words = words.into_iter().map(|word| word.as_ref().to_lowercase());
+    WordList { words: words.filter(|word| !word.is_empty()).collect() }
+  }
+
+  /// Reads a list of one word a line, `#` starting a comment.
+  pub fn load(path: &Path) -> anyhow::Result<WordList> {
+    let text = fs::read_to_string(path)?;
+    let lines = text.lines().map(|line| line.split('#').next().unwrap_or(""));
+    Ok(WordList::new(lines.map(str::trim)))
+  }
+}
+
+impl Filter for WordList {
+  fn check(
+    &self,
+    _from: &str,
+    text: &str,
+  ) -> anyhow::Result<String> {
+    let mut checked = String::new();
+    let mut word = String::new();
+    // the punctuation around a word stays
+    for c in text.chars().chain([' ']) {
+      if c.is_alphanumeric() {
+        word.push(c);
+        continue;
+      }
+      if self.words.contains(&word.to_lowercase()) {
+        checked.extend(word.chars().map(|_| '*'));
+      } else {
+        checked.push_str(&word);
+      }
+      word.clear();
+      checked.push(c);
+    }
+    checked.pop();
+    Ok(checked)
+  }
+}
+
+/// Lets each player say so many lines in a while.
+#[derive(Clone, Debug)]
+pub struct RateLimit {
+  lines: usize,
+  per: Duration,
+  said: HashMap<String, VecDeque<Instant>>,
+}
+
+impl Default for RateLimit {
+  fn default() -> Self {
+    RateLimit::new(5, Duration::from_secs(10))
+  }
+}
+
+impl RateLimit {
+  pub fn new(
+    lines: usize,
+    per: Duration,
+  ) -> RateLimit {
+    RateLimit { lines, per, said: HashMap::new() }
+  }
+
+  /// Counts a line of `from` at `now`, refusing it if they said too
+  /// much lately.
+  pub fn check(
+    &mut self,
+    from: &str,
+    now: Instant,
+  ) -> anyhow::Result<()> {
+    let said = self.said.entry(from.into()).or_default();
+    while said.front().is_some_and(|&at| now - at >= self.per) {
+      said.pop_front();
+    }
+    ensure!(
+      said.len() < self.lines,
+      "slow down, {} lines in {} seconds at most",
+      self.lines,
+      self.per.as_secs()
+    );
+    said.push_back(now);
+    Ok(())
+  }
+}
+
+/// The chat kept in a game's tags.
+pub fn load(game: &pgn::Game) -> Vec<ChatLine> {
+  let lines = game.tags.iter().filter(|(name, _)| name == "Chat");
+  lines.filter_map(|(_, line)| line.parse().ok()).collect()
+}
+
+/// Keeps `lines` in a game's tags, in place of the chat there.
+pub fn save(
+  lines: &[ChatLine],
+  game: &mut pgn::Game,
+) {
+  game.tags.retain(|(name, _)| name != "Chat");
+  game.tags.extend(lines.iter().map(|line| ("Chat".into(), line.to_string())));
+}
@@ -0,0 +1,468 @@
+//! The client side of a game server: keeps the lobby and the games played
+//! or watched as the server tells them, and sends what the player does.
+//! Like `net`, the socket is read on a thread and polled once a frame; in
+//! a browser the page's WebSocket fills the same queue.
+
+use std::{
+  collections::BTreeMap,
+  sync::mpsc::{self, TryRecvError},
+  time::Duration,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+  net::ToSocketAddrs, sync::mpsc::RecvTimeoutError, thread, time::Instant,
+};
+
+use anyhow::{bail, ensure};
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+#[cfg(target_arch = "wasm32")]
+use crate::browser;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::transport;
+use crate::{
+  board::{Color, Move, Position},
+  chat,
+  control::Controls,
+  correspondence::Correspondence,
+  pgn::Outcome,
+  protocol::{ChatLine, Control, GameInfo, Request, Seek, Update, VERSION},
+  tournament::TimeControl,
+  transport::{Transport, Writer},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A game as the server last told it.
+#[derive(Clone, Debug)]
+pub struct RemoteGame {
+  pub info: GameInfo,
+  /// Side of the local player, `None` when watching.
+  pub color: Option<Color>,
+  pub start: Position,
+  pub moves: Vec<Move>,
+  position: Position,
+  clocks: [Duration; 2],
+  /// When the clocks came.
+  received: Instant,
+  pub result: Option<(Outcome, String)>,
+  /// Whether each player lost the connection, white first.
+  pub away: [bool; 2],
+  controls: Controls,
+  /// The chat of our room.
+  pub chat: Vec<ChatLine>,
+}
+
+impl RemoteGame {
+  pub fn position(&self) -> &Position {
+    &self.position
+  }
+
+  /// The draw offer or takeback standing, and who made it.
+  pub fn offer(&self) -> Option<(Control, Color)> {
+    self.controls.offer(self.moves.len())
+  }
+
+  /// Whether the local player may do `control`.
+  pub fn allows(
+    &self,
+    control: Control,
+  ) -> bool {
+    self.check(control).is_ok()
+  }
+
+  fn check(
+    &self,
+    control: Control,
+  ) -> anyhow::Result<()> {
+    let Some(color) = self.color else {
+      bail!("we only watch game {}", self.info.id);
+    };
+    ensure!(self.result.is_none(), "the game is over");
+    let away = self.away[(!color).index()];
+    let mut controls = self.controls.clone();
+    controls.apply(color, control, &self.start, &self.moves, away)?;
+    Ok(())
+  }
+
+  pub fn is_our_turn(&self) -> bool {
+    self.result.is_none() && self.color == Some(self.position.side_to_move())
+  }
+
+  /// Time left for `color` at `now`, running for the side to move until
+  /// the game ends.
+  pub fn clock(
+    &self,
+    color: Color,
+    now: Instant,
+  ) -> Duration {
+    let clock = self.clocks[color.index()];
+    if self.result.is_none() && color == self.position.side_to_move() {
+      clock.saturating_sub(now - self.received)
+    } else {
+      clock
+    }
+  }
+}
+
+pub struct ServerClient {
+  /// Name of the local player.
+  pub name: String,
+  /// Name the server gave.
+  pub server: String,
+  writer: Writer,
+  incoming: mpsc::Receiver<anyhow::Result<Option<Update>>>,
+  connected: bool,
+  pub seeks: BTreeMap<u32, Seek>,
+  /// Games going on, for the lobby.
+  pub listed: BTreeMap<u32, GameInfo>,
+  /// Games played or watched, ended ones included until closed.
+  pub games: BTreeMap<u32, RemoteGame>,
+}
+
+impl ServerClient {
+  fn new(
+    name: &str,
+    writer: Writer,
+    incoming: mpsc::Receiver<anyhow::Result<Option<Update>>>,
+  ) -> ServerClient {
+    ServerClient {
+      name: name.into(),
+      server: String::new(),
+      writer,
+      incoming,
+      connected: true,
+      seeks: BTreeMap::new(),
+      listed: BTreeMap::new(),
+      games: BTreeMap::new(),
+    }
+  }
+
+  /// Connects to the server at `addr` and says hello as `name`.
+  #[cfg(not(target_arch = "wasm32"))]
+  pub fn connect(
+    addr: impl ToSocketAddrs,
+    name: &str,
+    transport: Transport,
+  ) -> anyhow::Result<ServerClient> {
+    let (mut reader, writer) =
+      transport::connect(addr, transport, HANDSHAKE_TIMEOUT)?;
+    let (tx, incoming) = mpsc::channel();
+    thread::spawn(move || loop {
+      let update = reader.read();
+      let done = !matches!(update, Ok(Some(_)));
+      if tx.send(update).is_err() || done {
+        break;
+      }
+    });
+    let mut client = ServerClient::new(name, writer, incoming);
+    client.send(&Request::Hello { version: VERSION, name: name.into() })?;
+    let update = match client.incoming.recv_timeout(HANDSHAKE_TIMEOUT) {
+      Ok(update) => update?,
+      Err(RecvTimeoutError::Timeout) => bail!("the server did not answer"),
+      Err(RecvTimeoutError::Disconnected) => None,
+    };
+    match update {
+      Some(Update::Hello { version, name }) => {
+        if version != VERSION {
+          bail!("the server speaks version {version}, we speak {VERSION}");
+        }
+        client.server = name;
+        Ok(client)
+      }
+      Some(Update::Error { message }) => bail!("the server refused: {message}"),
+      Some(update) => bail!("unexpected {update} before hello"),
+      None => bail!("the server closed the connection"),
+    }
+  }
+
+  /// Opens the page's WebSocket to the server at `addr`, a `ws://` or
+  /// `wss://` URL or a host and port, and says hello as `name`. The page
+  /// can't wait for the answer: `poll` takes it and fills in `server`.
+  #[cfg(target_arch = "wasm32")]
+  pub fn connect(
+    addr: &str,
+    name: &str,
+    transport: Transport,
+  ) -> anyhow::Result<ServerClient> {
+    let Transport::WebSocket(encoding) = transport else {
+      bail!("a browser only connects over a WebSocket");
+    };
+    let url = match addr.starts_with("ws://") || addr.starts_with("wss://") {
+      true => addr.to_string(),
+      false => format!("ws://{addr}/"),
+    };
+    let (socket, incoming) = browser::connect(&url, encoding)?;
+    let mut client = ServerClient::new(name, Writer::Browser(socket), incoming);
+    client.send(&Request::Hello { version: VERSION, name: name.into() })?;
+    Ok(client)
+  }
+
+  pub fn is_connected(&self) -> bool {
+    self.connected
+  }
+
+  pub fn send(
+    &mut self,
+    request: &Request,
+  ) -> anyhow::Result<()> {
+    if !self.connected {
+      bail!("not connected");
+    }
+    self.writer.send(request).inspect_err(|_| {
+      self.connected = false;
+    })
+  }
+
+  pub fn seek(
+    &mut self,
+    control: TimeControl,
+    color: Option<Color>,
+  ) -> anyhow::Result<()> {
+    self.send(&Request::Seek { control, color })
+  }
+
+  pub fn challenge(
+    &mut self,
+    to: &str,
+    control: TimeControl,
+    color: Option<Color>,
+  ) -> anyhow::Result<()> {
+    self.send(&Request::Challenge { to: to.into(), control, color })
+  }
+
+  pub fn cancel(
+    &mut self,
+    seek: u32,
+  ) -> anyhow::Result<()> {
+    self.send(&Request::Cancel { seek })
+  }
+
+  pub fn accept(
+    &mut self,
+    seek: u32,
+  ) -> anyhow::Result<()> {
+    self.send(&Request::Accept { seek })
+  }
+
+  /// Sends a move in one of our games. It is played once the server
+  /// sends it back.
+  pub fn play(
+    &mut self,
+    game: u32,
+    mv: Move,
+  ) -> anyhow::Result<()> {
+    let Some(remote) = self.games.get(&game) else {
+      bail!("no game {game}");
+    };
+    if !remote.is_our_turn() {
+      bail!("it is not our turn");
+    }
+    if !remote.position.is_legal(mv) {
+      bail!("illegal move {mv}");
+    }
+    let ply = remote.moves.len();
+    self.send(&Request::Move { game, ply, mv })
+  }
+
+  /// Sends a control in one of our games. The server sends it back with
+  /// what came of it.
+  pub fn control(
+    &mut self,
+    game: u32,
+    control: Control,
+  ) -> anyhow::Result<()> {
+    let Some(remote) = self.games.get(&game) else {
+      bail!("no game {game}");
+    };
+    remote.check(control)?;
+    let ply = remote.moves.len();
+    self.send(&Request::Control { game, ply, control })
+  }
+
+  /// Says something in a game played or watched. The server sends it
+  /// back as it passed it on.
+  pub fn say(
+    &mut self,
+    game: u32,
+    text: &str,
+  ) -> anyhow::Result<()> {
+    ensure!(self.games.contains_key(&game), "no game {game}");
+    let text = chat::clean(text);
+    ensure!(!text.is_empty(), "nothing to say");
+    self.send(&Request::Chat { game, text })
+  }
+
+  /// Sends a correspondence game to its other player.
+  pub fn post(
+    &mut self,
+    game: &Correspondence,
+  ) -> anyhow::Result<()> {
+    self.send(&Request::Post {
+      to: game.opponent().into(),
+      game: game.id.clone(),
+      days: game.days,
+      start: game.start.clone(),
+      moves: game.moves.clone(),
+    })
+  }
+
+  pub fn watch(
+    &mut self,
+    game: u32,
+  ) -> anyhow::Result<()> {
+    self.send(&Request::Watch { game })
+  }
+
+  /// Stops watching a game, or forgets an ended one.
+  pub fn close(
+    &mut self,
+    game: u32,
+  ) -> anyhow::Result<()> {
+    let Some(remote) = self.games.remove(&game) else {
+      return Ok(());
+    };
+    if remote.color.is_none() && remote.result.is_none() {
+      self.send(&Request::Unwatch { game })?;
+    }
+    Ok(())
+  }
+
+  /// Tells the server we're leaving and hangs up. The games go on there,
+  /// so connecting again under the same name picks them back up.
+  pub fn leave(&mut self) {
+    if self.connected {
+      let _ = self.send(&Request::Bye);
+      self.writer.close();
+      self.connected = false;
+    }
+  }
+
+  /// Takes in what the server sent, returning it after keeping track.
+  pub fn poll(&mut self) -> Vec<Update> {
+    let mut updates = Vec::new();
+    while self.connected {
+      let update = match self.incoming.try_recv() {
+        Ok(Ok(Some(update))) => update,
+        Ok(Ok(None)) | Err(TryRecvError::Disconnected) => {
+          self.connected = false;
+          break;
+        }
+        Ok(Err(err)) => {
+          log::warn!("lost the server: {err:#}");
+          self.connected = false;
+          break;
+        }
+        Err(TryRecvError::Empty) => break,
+      };
+      if let Err(err) = self.receive(&update) {
+        log::warn!("can't follow {update}: {err:#}");
+      }
+      updates.push(update);
+    }
+    updates
+  }
+
+  fn receive(
+    &mut self,
+    update: &Update,
+  ) -> anyhow::Result<()> {
+    let now = Instant::now();
+    match update {
+      Update::Seek(seek) => {
+        self.seeks.insert(seek.id, seek.clone());
+      }
+      Update::Unseek { seek } => {
+        self.seeks.remove(seek);
+      }
+      Update::Listed(info) => {
+        self.listed.insert(info.id, info.clone());
+      }
+      Update::Game { info, color, start, moves, clocks } => {
+        let mut position = start.clone();
+        for &mv in moves {
+          if !position.is_legal(mv) {
+            bail!("illegal move {mv} in the game sent");
+          }
+          position.play(mv);
+        }
+        let game = RemoteGame {
+          info: info.clone(),
+          color: *color,
+          start: start.clone(),
+          moves: moves.clone(),
+          position,
+          clocks: *clocks,
+          received: now,
+          result: None,
+          away: self.games.get(&info.id).map_or([false; 2], |game| game.away),
+          controls: Controls::default(),
+          chat: self
+            .games
+            .remove(&info.id)
+            .map_or(Vec::new(), |game| game.chat),
+        };
+        self.games.insert(info.id, game);
+      }
+      Update::Move { game, ply, mv, clocks } => {
+        let Some(remote) = self.games.get_mut(game) else {
+          return Ok(());
+        };
+        if *ply != remote.moves.len() || !remote.position.is_legal(*mv) {
+          // behind, the server has the game
+          return self.watch(*game);
+        }
+        remote.moves.push(*mv);
+        remote.position.play(*mv);
+        (remote.clocks, remote.received) = (*clocks, now);
+      }
+      Update::Control { game, ply, color, control } => {
+        let Some(remote) = self.games.get_mut(game) else {
+          return Ok(());
+        };
+        if *ply != remote.moves.len() {
+          return self.watch(*game);
+        }
+        // the server checked it and sends what comes of it
+        let RemoteGame { controls, start, moves, .. } = remote;
+        controls.apply(*color, *control, start, moves, true)?;
+      }
+      Update::Gone { game, color } | Update::Back { game, color } => {
+        if let Some(remote) = self.games.get_mut(game) {
+          remote.away[color.index()] = matches!(update, Update::Gone { .. });
+        }
+      }
+      Update::Chat { game, line } => {
+        if let Some(remote) = self.games.get_mut(game) {
+          remote.chat.push(line.clone());
+        }
+      }
+      Update::End { game, outcome, reason } => {
+        self.listed.remove(game);
+        if let Some(remote) = self.games.get_mut(game) {
+          remote.clocks =
+            [Color::White, Color::Black].map(|color| remote.clock(color, now));
+          remote.result = Some((*outcome, reason.clone()));
+        }
+      }
+      Update::Hello { version, name } => {
+        // only a browser's hello is answered after connecting
+        if *version != VERSION {
+          self.leave();
+          bail!("the server speaks version {version}, we speak {VERSION}");
+        }
+        self.server = name.clone();
+      }
+      Update::Post { .. } | Update::Error { .. } => {}
+    }
+    Ok(())
+  }
+}
+
+impl Drop for ServerClient {
+  fn drop(&mut self) {
+    self.leave();
+  }
+}
@@ -0,0 +1,205 @@
+//! The rules of what players do in a game besides moving: draw offers,
+//! takebacks, resigning, aborting and claims. Local, LAN and server games
+//! each keep [`Controls`] and run the controls of both sides through it,
+//! so they all play by the same rules.
+//!
+//! An offer stands until it is answered or a move is played; offering a
+//! draw while the opponent's offer stands accepts it. A game can be
+//! aborted until both sides moved. Between people threefold repetition
+//! and the fifty-move rule are claimed, the game only ends by itself at
+//! fivefold repetition and seventy-five moves.
+
+use anyhow::{bail, ensure};
+
+use crate::{
+  board::{Color, Move, Position},
+  pgn::Outcome,
+  protocol::Control,
+  tournament,
+};
+
+/// Moves after which a game can't be aborted.
+const ABORT_BEFORE: usize = 2;
+
+/// The position after `moves` and the hash of every position on the way.
+pub fn replay(
+  start: &Position,
+  moves: &[Move],
+) -> (Position, Vec<u64>) {
+  let mut position = start.clone();
+  let mut history = vec![position.hash()];
+  for &mv in moves {
+    position.play(mv);
+    history.push(position.hash());
+  }
+  (position, history)
+}
+
+/// How a game between people ends by itself after the last move.
+pub fn game_over(
+  position: &Position,
+  history: &[u64],
+) -> Option<(Outcome, &'static str)> {
+  match tournament::game_over(position, history) {
+    Some((_, "fifty-move rule" | "threefold repetition")) => {}
+    end => return end,
+  }
+  if position.halfmove_clock() >= 150 {
+    return Some((Outcome::Draw, "seventy-five-move rule"));
+  }
+  let hash = position.hash();
+  if history.iter().filter(|&&seen| seen == hash).count() >= 5 {
+    return Some((Outcome::Draw, "fivefold repetition"));
+  }
+  None
+}
+
+/// The draw a player may claim in `position`, if any.
+pub fn claimable(
+  position: &Position,
+  history: &[u64],
+) -> Option<&'static str> {
+  if position.halfmove_clock() >= 100 {
+    return Some("fifty-move rule");
+  }
+  let hash = position.hash();
+  (history.iter().filter(|&&seen| seen == hash).count() >= 3)
+    .then_some("threefold repetition")
+}
+
+/// What a control did.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Effect {
+  /// An offer waits for the opponent's answer.
+  Offered,
+  Declined,
+  /// The last moves are taken back, this many.
+  TakeBack(usize),
+  End(Outcome, &'static str),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Controls {
+  /// The offer standing: the draw offer or takeback asked, who made it
+  /// and after how many moves.
+  offer: Option<(Control, Color, usize)>,
+}
+
+impl Controls {
+  /// The offer standing after `ply` moves, and who made it.
+  pub fn offer(
+    &self,
+    ply: usize,
+  ) -> Option<(Control, Color)> {
+    let (control, color, at) = self.offer?;
+    (at == ply).then_some((control, color))
+  }
+
+  pub fn clear(&mut self) {
+    self.offer = None;
+  }
+
+  /// Whether `color` may do `control` now; see [`Controls::apply`].
+  pub fn allows(
+    &self,
+    color: Color,
+    control: Control,
+    start: &Position,
+    moves: &[Move],
+    away: bool,
+  ) -> bool {
+    self.clone().apply(color, control, start, moves, away).is_ok()
+  }
+
+  /// Does `control` for `color` in the game of `moves` from `start`,
+  /// `away` telling whether the opponent left. The caller carries out
+  /// the effect; a game that ended takes no more controls.
+  pub fn apply(
+    &mut self,
+    color: Color,
+    control: Control,
+    start: &Position,
+    moves: &[Move],
+    away: bool,
+  ) -> anyhow::Result<Effect> {
+    let ply = moves.len();
+    let theirs = self.offer(ply).filter(|&(_, by)| by != color);
+    let answers = |offer, what| {
+      ensure!(
+        theirs.is_some_and(|(standing, _)| standing == offer),
+        "there is no {what} to answer"
+      );
+      Ok(())
+    };
+    let (wins, loses) = color.fold(
+      (Outcome::WhiteWins, Outcome::BlackWins),
+      (Outcome::BlackWins, Outcome::WhiteWins),
+    );
+    let effect = match control {
+      Control::OfferDraw
+        if theirs.is_some_and(|(offer, _)| offer == control) =>
+      {
+        Effect::End(Outcome::Draw, "agreement")
+      }
+      Control::OfferDraw | Control::AskTakeback => {
+        ensure!(self.offer(ply).is_none(), "an offer already stands");
+        if control == Control::AskTakeback {
+          ensure!(takeback(start, moves, color) > 0, "no move to take back");
+        }
+        self.offer = Some((control, color, ply));
+        return Ok(Effect::Offered);
+      }
+      Control::AcceptDraw => {
+        answers(Control::OfferDraw, "draw offer")?;
+        Effect::End(Outcome::Draw, "agreement")
+      }
+      Control::AcceptTakeback => {
+        answers(Control::AskTakeback, "takeback")?;
+        Effect::TakeBack(takeback(start, moves, !color))
+      }
+      Control::DeclineDraw => {
+        answers(Control::OfferDraw, "draw offer")?;
+        Effect::Declined
+      }
+      Control::DeclineTakeback => {
+        answers(Control::AskTakeback, "takeback")?;
+        Effect::Declined
+      }
+      Control::Resign => Effect::End(loses, "resignation"),
+      Control::Abort => {
+        ensure!(ply < ABORT_BEFORE, "too late to abort");
+        Effect::End(Outcome::Unknown, "aborted")
+      }
+      Control::ClaimDraw => {
+        let (position, history) = replay(start, moves);
+        let Some(reason) = claimable(&position, &history) else {
+          bail!("no draw to claim");
+        };
+        Effect::End(Outcome::Draw, reason)
+      }
+      Control::ClaimWin => {
+        ensure!(away, "the opponent is still here");
+        Effect::End(wins, "abandonment")
+      }
+    };
+    self.offer = None;
+    Ok(effect)
+  }
+}
+
+/// Moves to take back for `color`: theirs and, on their move, the
+/// opponent's reply.
+fn takeback(
+  start: &Position,
+  moves: &[Move],
+  color: Color,
+) -> usize {
+  let first = start.side_to_move();
+  let to_move = if moves.len().is_multiple_of(2) { first } else { !first };
+  let count = if to_move == color { 2 } else { 1 };
+  if count > moves.len() {
+    0
+  } else {
+    count
+  }
+}
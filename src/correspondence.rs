@@ -0,0 +1,398 @@
+//! Correspondence chess: games of days per move that outlive the app.
+//! Each game is a PGN file in a directory, its state in tags next to the
+//! players; ours are saved after every change and read back on start.
+//!
+//! Moves travel as move files, the game's PGN without our private tags,
+//! or through a game server that passes the whole game on (see
+//! `ServerClient::post`). Either way the other side sends the game after
+//! their move, so whoever receives a new one has the move.
+//!
+//! While waiting, a player can leave conditional moves: lines of the
+//! opponent's move, our reply, their next and so on. When a move comes
+//! that a line expects, the reply is played at once.
+
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, ensure};
+
+use crate::{
+  board::{Color, Move, Position},
+  pgn::{self, Outcome},
+  tournament::game_over,
+};
+
+const DAY: u64 = 86400;
+
+/// Seconds since the Unix epoch.
+pub fn now() -> u64 {
+  let since = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+  since.as_secs()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Correspondence {
+  /// One word naming the game for both players, and its file.
+  pub id: String,
+  pub white: String,
+  pub black: String,
+  /// Our side.
+  pub color: Color,
+  pub days: u32,
+  pub start: Position,
+  pub moves: Vec<Move>,
+  /// When the side to move got the move, in seconds since the epoch.
+  pub since: u64,
+  pub outcome: Outcome,
+  /// How the game ended, when it did.
+  pub reason: String,
+  /// Conditional moves, each line starting with the opponent's move.
+  conditionals: Vec<Vec<Move>>,
+}
+
+impl Correspondence {
+  /// A new game between us and `opponent`, white to move.
+  pub fn new(
+    name: &str,
+    opponent: &str,
+    color: Color,
+    days: u32,
+    now: u64,
+  ) -> Correspondence {
+    let (white, black) = color.fold((name, opponent), (opponent, name));
+    Correspondence {
+      id: format!("{white}-{black}-{now}"),
+      white: white.into(),
+      black: black.into(),
+      color,
+      days,
+      start: Position::startpos(),
+      moves: Vec::new(),
+      since: now,
+      outcome: Outcome::Unknown,
+      reason: String::new(),
+      conditionals: Vec::new(),
+    }
+  }
+
+  /// A game `from` sent us for the first time.
+  pub fn received(
+    id: &str,
+    from: &str,
+    name: &str,
+    days: u32,
+    start: Position,
+    moves: &[Move],
+    now: u64,
+  ) -> anyhow::Result<Correspondence> {
+    let mut game = Correspondence::new(name, from, Color::White, days, now);
+    game.start = start;
+    for &mv in moves {
+      game.push(mv)?;
+    }
+    let color = game.position().side_to_move();
+    let (white, black) = color.fold((name, from), (from, name));
+    Ok(Correspondence {
+      id: id.into(),
+      white: white.into(),
+      black: black.into(),
+      color,
+      ..game
+    })
+  }
+
+  pub fn opponent(&self) -> &str {
+    self.color.fold(&self.black, &self.white)
+  }
+
+  /// Position after the moves.
+  pub fn position(&self) -> Position {
+    let mut position = self.start.clone();
+    for &mv in &self.moves {
+      position.play(mv);
+    }
+    position
+  }
+
+  pub fn is_over(&self) -> bool {
+    self.outcome != Outcome::Unknown
+  }
+
+  pub fn is_our_turn(&self) -> bool {
+    !self.is_over() && self.position().side_to_move() == self.color
+  }
+
+  /// When the side to move runs out of time, in seconds since the epoch.
+  pub fn deadline(&self) -> u64 {
+    self.since + self.days as u64 * DAY
+  }
+
+  pub fn time_left(
+    &self,
+    now: u64,
+  ) -> Duration {
+    Duration::from_secs(self.deadline().saturating_sub(now))
+  }
+
+  /// Ends the game if the side to move let the deadline pass, returning
+  /// whether it did.
+  pub fn check_time(
+    &mut self,
+    now: u64,
+  ) -> bool {
+    if self.is_over() || now <= self.deadline() {
+      return false;
+    }
+    let loser = self.position().side_to_move();
+    self.outcome = loser.fold(Outcome::BlackWins, Outcome::WhiteWins);
+    self.reason = "time".into();
+    true
+  }
+
+  /// Plays a move, ending the game by the rules after it.
+  fn push(
+    &mut self,
+    mv: Move,
+  ) -> anyhow::Result<()> {
+    ensure!(!self.is_over(), "the game is over");
+    let mut position = self.start.clone();
+    let mut history = vec![position.hash()];
+    for &mv in &self.moves {
+      position.play(mv);
+      history.push(position.hash());
+    }
+    ensure!(position.is_legal(mv), "illegal move {mv}");
+    position.play(mv);
+    history.push(position.hash());
+    self.moves.push(mv);
+    if let Some((outcome, reason)) = game_over(&position, &history) {
+      (self.outcome, self.reason) = (outcome, reason.into());
+    }
+    Ok(())
+  }
+
+  /// Plays our move. The game then goes to the opponent.
+  pub fn play(
+    &mut self,
+    mv: Move,
+    now: u64,
+  ) -> anyhow::Result<()> {
+    ensure!(self.is_our_turn(), "it is not our move");
+    self.push(mv)?;
+    self.since = now;
+    self.conditionals.clear();
+    Ok(())
+  }
+
+  /// Takes in this game as the opponent sent it, with their move or
+  /// their result. Returns the conditional reply played, if any.
+  pub fn receive(
+    &mut self,
+    sent: &Correspondence,
+    now: u64,
+  ) -> anyhow::Result<Option<Move>> {
+    ensure!(sent.id == self.id, "{} is another game", sent.id);
+    ensure!(sent.start == self.start, "the game starts elsewhere");
+    let ours = self.moves.len();
+    ensure!(sent.moves.starts_with(&self.moves), "the moves do not match ours");
+    let mv = match &sent.moves[ours..] {
+      [] => None,
+      &[mv] if !self.is_our_turn() => Some(mv),
+      _ => bail!("expected one move of {}", self.opponent()),
+    };
+    if let Some(mv) = mv {
+      self.push(mv)?;
+      self.since = now;
+    }
+    if !self.is_over() && sent.is_over() {
+      self.outcome = sent.outcome;
+      self.reason = sent.reason.clone();
+    }
+    let conditionals = std::mem::take(&mut self.conditionals);
+    let Some(mv) = mv.filter(|_| !self.is_over()) else {
+      return Ok(None);
+    };
+    let Some(reply) =
+      conditionals.iter().find(|line| line[0] == mv).map(|line| line[1])
+    else {
+      return Ok(None);
+    };
+    self.play(reply, now)?;
+    self.conditionals = conditionals
+      .into_iter()
+      .filter(|line| line.len() > 2 && line[..2] == [mv, reply])
+      .map(|line| line[2..].to_vec())
+      .collect();
+    Ok(Some(reply))
+  }
+
+  pub fn conditionals(&self) -> &[Vec<Move>] {
+    &self.conditionals
+  }
+
+  /// Adds a line of conditional moves, starting with the opponent's
+  /// move. It may not answer a move otherwise than the lines already
+  /// there.
+  pub fn add_conditional(
+    &mut self,
+    line: Vec<Move>,
+  ) -> anyhow::Result<()> {
+    ensure!(
+      !self.is_over() && !self.is_our_turn(),
+      "conditional moves wait for the opponent's move"
+    );
+    ensure!(line.len() >= 2, "a conditional line needs our reply");
+    let mut position = self.position();
+    for &mv in &line {
+      ensure!(position.is_legal(mv), "illegal move {mv} in the line");
+      position.play(mv);
+    }
+    for other in &self.conditionals {
+      let shared = line.iter().zip(other).take_while(|(a, b)| a == b).count();
+      // where the lines part, the opponent's moves must differ
+      if shared < line.len().min(other.len()) && shared % 2 == 1 {
+        bail!(
+          "{} is already answered with {}",
+          line[shared - 1],
+          other[shared]
+        );
+      }
+    }
+    self.conditionals.push(line);
+    Ok(())
+  }
+
+  pub fn clear_conditionals(&mut self) {
+    self.conditionals.clear();
+  }
+
+  fn record(&self) -> pgn::Game {
+    let mut game = pgn::Game {
+      start: self.start.clone(),
+      moves: self.moves.clone(),
+      outcome: self.outcome,
+      ..Default::default()
+    };
+    game.set_tag("Event", "Correspondence");
+    game.set_tag("White", &self.white);
+    game.set_tag("Black", &self.black);
+    game.set_tag("GameId", &self.id);
+    game.set_tag("DaysPerMove", self.days.to_string());
+    if !self.reason.is_empty() {
+      game.set_tag("Termination", &self.reason);
+    }
+    game
+  }
+
+  /// The game for the opponent.
+  pub fn to_move_file(&self) -> String {
+    self.record().to_pgn()
+  }
+
+  /// The game for our own files: the move file with our side, when the
+  /// move came and the conditional moves.
+  pub fn to_pgn(&self) -> String {
+    let mut game = self.record();
+    game.set_tag("Player", self.color.fold("White", "Black"));
+    game.set_tag("Since", self.since.to_string());
+    for line in &self.conditionals {
+      let moves: Vec<String> = line.iter().map(Move::to_string).collect();
+      game.tags.push(("Conditional".into(), moves.join(" ")));
+    }
+    game.to_pgn()
+  }
+
+  fn from_record(
+    game: &pgn::Game,
+    color: Color,
+    since: u64,
+  ) -> anyhow::Result<Correspondence> {
+    let tag = |name| game.tag(name).ok_or_else(|| anyhow!("no {name} tag"));
+    let mut read = Correspondence {
+      id: tag("GameId")?.into(),
+      white: tag("White")?.into(),
+      black: tag("Black")?.into(),
+      color,
+      days: tag("DaysPerMove")?.parse()?,
+      start: game.start.clone(),
+      moves: Vec::new(),
+      since,
+      outcome: Outcome::Unknown,
+      reason: String::new(),
+      conditionals: Vec::new(),
+    };
+    for &mv in &game.moves {
+      read.push(mv)?;
+    }
+    if !read.is_over() {
+      read.outcome = game.outcome;
+      read.reason = game.tag("Termination").unwrap_or_default().into();
+    }
+    Ok(read)
+  }
+
+  pub fn from_pgn(text: &str) -> anyhow::Result<Correspondence> {
+    let game = pgn::parse(text)?.pop().ok_or_else(|| anyhow!("no game"))?;
+    let color = match game.tag("Player") {
+      Some("White") => Color::White,
+      Some("Black") => Color::Black,
+      _ => bail!("whose game is it?"),
+    };
+    let since = game.tag("Since").ok_or_else(|| anyhow!("no Since tag"))?;
+    let mut read = Correspondence::from_record(&game, color, since.parse()?)?;
+    for (_, line) in game.tags.iter().filter(|(name, _)| name == "Conditional")
+    {
+      let line = line.split_whitespace().map(Move::from_uci);
+      read.add_conditional(line.collect::<anyhow::Result<_>>()?)?;
+    }
+    Ok(read)
+  }
+
+  /// A game from a move file the opponent sent first.
+  pub fn from_move_file(
+    text: &str,
+    now: u64,
+  ) -> anyhow::Result<Correspondence> {
+    let game = pgn::parse(text)?.pop().ok_or_else(|| anyhow!("no game"))?;
+    Correspondence::from_record(&game, game.end().side_to_move(), now)
+  }
+
+  pub fn path(
+    &self,
+    dir: &Path,
+  ) -> PathBuf {
+    dir.join(format!("{}.pgn", self.id))
+  }
+
+  pub fn save(
+    &self,
+    dir: &Path,
+  ) -> anyhow::Result<()> {
+    fs::create_dir_all(dir)?;
+    Ok(fs::write(self.path(dir), self.to_pgn())?)
+  }
+}
+
+/// Reads the games saved in `dir`, those to move first and then by
+/// deadline.
+pub fn load(dir: &Path) -> anyhow::Result<Vec<Correspondence>> {
+  let mut games = Vec::new();
+  if !dir.exists() {
+    return Ok(games);
+  }
+  for entry in fs::read_dir(dir)? {
+    let path = entry?.path();
+    if path.extension().is_some_and(|ext| ext == "pgn") {
+      let text = fs::read_to_string(&path)?;
+      let game = Correspondence::from_pgn(&text)
+        .map_err(|err| anyhow!("{}: {err:#}", path.display()))?;
+      games.push(game);
+    }
+  }
+  games
+    .sort_by_key(|game| (game.is_over(), !game.is_our_turn(), game.deadline()));
+  Ok(games)
+}
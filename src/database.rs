@@ -0,0 +1,605 @@
+//! A database of games in one file, imported from PGN and searched by
+//! tags, by position and by material. A game is stored as its tags and
+//! its moves, two bytes each, and nothing per position: the positions and
+//! material the games went through are indexed when the database is
+//! opened, each key once with the games that reached it, so searching
+//! never replays a game.
+//!
+//! The file is big-endian: a magic, then the games as length-prefixed
+//! records, appended as they are imported.
+
+use std::{
+  fmt,
+  fs::{self, File, OpenOptions},
+  io::{self, Write},
+  path::Path,
+  str::FromStr,
+};
+
+use anyhow::{anyhow, bail};
+
+use crate::{
+  board::{Color, Move, PieceKind, Position},
+  book::{decode_move, encode_move, key},
+  explorer,
+  pgn::{Game, Outcome},
+};
+
+const MAGIC: &[u8; 8] = b"CHGDB002";
+
+/// Material on the board, counting pawns to queens for each side.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Material([[u8; 5]; 2]);
+
+impl Material {
+  pub fn of(position: &Position) -> Material {
+    let mut counts = [[0; 5]; 2];
+    for color in [Color::White, Color::Black] {
+      for kind in &PieceKind::ALL[..5] {
+        counts[color.index()][kind.index()] =
+          position.pieces(color, *kind).count_ones() as u8;
+      }
+    }
+    Material(counts)
+  }
+
+  pub fn count(
+    &self,
+    color: Color,
+    kind: PieceKind,
+  ) -> u8 {
+    self.0[color.index()][kind.index()]
+  }
+
+  /// Four bits per count: promotions past 15 pieces of a kind alias.
+  pub fn key(&self) -> u64 {
+    self
+      .0
+      .iter()
+      .flatten()
+      .fold(0, |key, &count| key << 4 | (count & 15) as u64)
+  }
+}
+
+/// Written like `KRPkr`, white in uppercase; the kings are optional.
+impl FromStr for Material {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> anyhow::Result<Material> {
+    let mut counts = [[0; 5]; 2];
+    for c in s.trim().chars() {
+      let color =
+        if c.is_ascii_uppercase() { Color::White } else { Color::Black };
+      match PieceKind::from_char(c.to_ascii_uppercase()) {
+        Some(PieceKind::King) => {}
+        Some(kind) => counts[color.index()][kind.index()] += 1,
+        None => bail!("invalid piece {c:?} in material {s:?}"),
+      }
+    }
+    Ok(Material(counts))
+  }
+}
+
+/// A range of material, each count between the bounds. Written `KRkr`
+/// for exactly that material, `KRkr+` for at least it, or `KRkr..KRPPkr`
+/// for anything from the first to the second, here up to two white pawns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MaterialRange {
+  pub min: Material,
+  pub max: Material,
+}
+
+impl MaterialRange {
+  pub fn at_least(min: Material) -> MaterialRange {
+    MaterialRange { min, max: Material([[15; 5]; 2]) }
+  }
+
+  /// Whether the material with this key is in the range.
+  pub fn contains(
+    &self,
+    key: u64,
+  ) -> bool {
+    let bounds = self.min.0.iter().flatten().zip(self.max.0.iter().flatten());
+    bounds.enumerate().all(|(i, (&min, &max))| {
+      let count = (key >> (4 * (9 - i)) & 15) as u8;
+      (min & 15) <= count && count <= (max & 15)
+    })
+  }
+}
+
+impl From<Material> for MaterialRange {
+  fn from(material: Material) -> MaterialRange {
+    MaterialRange { min: material, max: material }
+  }
+}
+
+impl FromStr for MaterialRange {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> anyhow::Result<MaterialRange> {
+    let s = s.trim();
+    if let Some(min) = s.strip_suffix('+') {
+      return Ok(MaterialRange::at_least(min.parse()?));
+    }
+    let Some((min, max)) = s.split_once("..") else {
+      return Ok(s.parse::<Material>()?.into());
+    };
+    let range = MaterialRange { min: min.parse()?, max: max.parse()? };
+    let bounds = range.min.0.iter().flatten().zip(range.max.0.iter().flatten());
+    if bounds.clone().any(|(min, max)| min > max) {
+      bail!("{min} is not less material than {max}");
+    }
+    Ok(range)
+  }
+}
+
+impl fmt::Display for Material {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter,
+  ) -> fmt::Result {
+    for color in [Color::White, Color::Black] {
+      let mut side = String::from("K");
+      for kind in PieceKind::ALL[..5].iter().rev() {
+        for _ in 0..self.count(color, *kind) {
+          side.push(kind.char());
+        }
+      }
+      match color {
+        Color::White => write!(f, "{side}")?,
+        Color::Black => write!(f, "{}", side.to_lowercase())?,
+      }
+    }
+    Ok(())
+  }
+}
+
+/// What to look for. Every field set must match; text matches ignore case
+/// and may be part of the value.
+#[derive(Clone, Debug, Default)]
+pub struct Query {
+  /// Either player.
+  pub player: Option<String>,
+  pub white: Option<String>,
+  pub black: Option<String>,
+  pub event: Option<String>,
+  /// First date, as in PGN: `2021.11.26`, or a prefix like `2021`.
+  pub from_date: Option<String>,
+  /// Last date, a prefix includes the whole year or month.
+  pub to_date: Option<String>,
+  pub outcome: Option<Outcome>,
+  /// ECO code or its start, `B` or `B9`.
+  pub eco: Option<String>,
+  /// Key of a position the game went through.
+  pub position: Option<u64>,
+  /// Material the game had at some point.
+  pub material: Option<MaterialRange>,
+}
+
+/// A stored game.
+#[derive(Clone, Debug)]
+struct Record {
+  tags: Vec<(String, String)>,
+  /// FEN of the start, empty for the usual one.
+  start: String,
+  moves: Vec<u16>,
+  outcome: Outcome,
+}
+
+fn contains(
+  value: Option<&str>,
+  part: &str,
+) -> bool {
+  let value = value.unwrap_or("").to_lowercase();
+  value.contains(&part.to_lowercase())
+}
+
+impl Record {
+  fn of(game: &Game) -> Record {
+    let start = match game.start == Position::startpos() {
+      true => String::new(),
+      false => game.start.to_fen(),
+    };
+    let mut position = game.start.clone();
+    let mut moves = Vec::with_capacity(game.moves.len());
+    for &mv in &game.moves {
+      moves.push(encode_move(&position, mv));
+      position.play(mv);
+    }
+    Record { tags: game.tags.clone(), start, moves, outcome: game.outcome }
+  }
+
+  /// The start and the moves, checking each is legal.
+  fn replay(&self) -> anyhow::Result<(Position, Vec<Move>)> {
+    let start = match self.start.is_empty() {
+      true => Position::startpos(),
+      false => Position::from_fen(&self.start)?,
+    };
+    let mut position = start.clone();
+    let mut moves = Vec::with_capacity(self.moves.len());
+    for &raw in &self.moves {
+      let mv = decode_move(&position, raw);
+      if !position.is_legal(mv) {
+        bail!("an illegal move {mv}");
+      }
+      position.play(mv);
+      moves.push(mv);
+    }
+    Ok((start, moves))
+  }
+
+  fn tag(
+    &self,
+    name: &str,
+  ) -> Option<&str> {
+    let (_, value) = self.tags.iter().find(|(tag, _)| tag == name)?;
+    Some(value)
+  }
+
+  fn matches(
+    &self,
+    query: &Query,
+  ) -> bool {
+    let has = |name, part: &Option<String>| {
+      part.as_ref().is_none_or(|part| contains(self.tag(name), part))
+    };
+    let date = self.tag("Date").unwrap_or("????");
+    let known = date.get(..4).is_some_and(|year| year.parse::<u32>().is_ok());
+    let after = query
+      .from_date
+      .as_ref()
+      .is_none_or(|from| known && date >= from.as_str());
+    let before = query.to_date.as_ref().is_none_or(|to| {
+      known && (date <= to.as_str() || date.starts_with(to.as_str()))
+    });
+    query.player.as_ref().is_none_or(|player| {
+      contains(self.tag("White"), player) || contains(self.tag("Black"), player)
+    }) && has("White", &query.white)
+      && has("Black", &query.black)
+      && has("Event", &query.event)
+      && after
+      && before
+      && query.outcome.is_none_or(|outcome| outcome == self.outcome)
+      && query.eco.as_ref().is_none_or(|eco| {
+        let own = self.tag("ECO").unwrap_or("");
+        own.to_uppercase().starts_with(&eco.trim().to_uppercase())
+      })
+  }
+
+  /// Checks every length fits the two bytes `to_bytes` gives it.
+  fn check(&self) -> anyhow::Result<()> {
+    let too_long = |len: usize| len > u16::MAX as usize;
+    if too_long(self.moves.len()) {
+      bail!("a game of {} plies is too long", self.moves.len());
+    }
+    if too_long(self.tags.len()) {
+      bail!("a game with {} tags has too many", self.tags.len());
+    }
+    let texts = self.tags.iter().flat_map(|(name, value)| [name, value]);
+    if let Some(text) = texts.chain([&self.start]).find(|t| too_long(t.len())) {
+      bail!("a tag of {} bytes is too long", text.len());
+    }
+    Ok(())
+  }
+
+  fn to_bytes(&self) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.push(match self.outcome {
+      Outcome::Unknown => 0,
+      Outcome::WhiteWins => 1,
+      Outcome::BlackWins => 2,
+      Outcome::Draw => 3,
+    });
+    let text = |bytes: &mut Vec<u8>, text: &str| {
+      bytes.extend((text.len() as u16).to_be_bytes());
+      bytes.extend(text.as_bytes());
+    };
+    bytes.extend((self.tags.len() as u16).to_be_bytes());
+    for (name, value) in &self.tags {
+      text(&mut bytes, name);
+      text(&mut bytes, value);
+    }
+    text(&mut bytes, &self.start);
+    bytes.extend((self.moves.len() as u16).to_be_bytes());
+    for mv in &self.moves {
+      bytes.extend(mv.to_be_bytes());
+    }
+    bytes
+  }
+
+  fn from_bytes(bytes: &[u8]) -> anyhow::Result<Record> {
+    let mut reader = Reader { bytes };
+    let outcome = match reader.take::<1>()?[0] {
+      0 => Outcome::Unknown,
+      1 => Outcome::WhiteWins,
+      2 => Outcome::BlackWins,
+      3 => Outcome::Draw,
+      code => bail!("invalid result {code}"),
+    };
+    let tags = (0..reader.u16()?)
+      .map(|_| Ok((reader.text()?, reader.text()?)))
+      .collect::<anyhow::Result<_>>()?;
+    let start = reader.text()?;
+    let moves = (0..reader.u16()?)
+      .map(|_| reader.u16())
+      .collect::<anyhow::Result<_>>()?;
+    Ok(Record { tags, start, moves, outcome })
+  }
+}
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+  fn take<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+    if self.bytes.len() < N {
+      bail!("truncated game record");
+    }
+    let (taken, rest) = self.bytes.split_at(N);
+    self.bytes = rest;
+    Ok(taken.try_into().unwrap())
+  }
+
+  fn u16(&mut self) -> anyhow::Result<u16> {
+    Ok(u16::from_be_bytes(self.take()?))
+  }
+
+  fn text(&mut self) -> anyhow::Result<String> {
+    let len = self.u16()? as usize;
+    if self.bytes.len() < len {
+      bail!("truncated game record");
+    }
+    let (text, rest) = self.bytes.split_at(len);
+    self.bytes = rest;
+    Ok(String::from_utf8(text.to_vec())?)
+  }
+}
+
+/// Games by the keys they went through, each key once: its games are
+/// the run of `games` from its start to the next key's.
+#[derive(Default)]
+struct Index {
+  /// Sorted, without repeats.
+  keys: Vec<u64>,
+  starts: Vec<u32>,
+  /// The games of each key in turn, in the order they were imported.
+  games: Vec<u32>,
+}
+
+impl Index {
+  fn push(
+    &mut self,
+    key: u64,
+    game: u32,
+  ) {
+    if self.keys.last() != Some(&key) {
+      self.keys.push(key);
+      self.starts.push(self.games.len() as u32);
+    }
+    self.games.push(game);
+  }
+
+  /// Adds pairs of key and game, every game newer than those indexed.
+  fn extend(
+    &mut self,
+    mut added: Vec<(u64, u32)>,
+  ) {
+    added.sort_unstable();
+    added.dedup();
+    let old = std::mem::take(self);
+    let mut added = added.into_iter().peekable();
+    for (i, &key) in old.keys.iter().enumerate() {
+      while let Some((new, game)) = added.next_if(|&(new, _)| new < key) {
+        self.push(new, game);
+      }
+      for &game in old.run(i) {
+        self.push(key, game);
+      }
+      while let Some((_, game)) = added.next_if(|&(new, _)| new == key) {
+        self.push(key, game);
+      }
+    }
+    for (key, game) in added {
+      self.push(key, game);
+    }
+  }
+
+  fn run(
+    &self,
+    i: usize,
+  ) -> &[u32] {
+    let end =
+      self.starts.get(i + 1).map_or(self.games.len(), |&end| end as usize);
+    &self.games[self.starts[i] as usize..end]
+  }
+
+  /// The games through `key`, in order.
+  fn games(
+    &self,
+    key: u64,
+  ) -> &[u32] {
+    match self.keys.binary_search(&key) {
+      Ok(i) => self.run(i),
+      Err(_) => &[],
+    }
+  }
+
+  /// The games through any key `wanted`, in order.
+  fn games_where(
+    &self,
+    wanted: impl Fn(u64) -> bool,
+  ) -> Vec<u32> {
+    let runs = (0..self.keys.len()).filter(|&i| wanted(self.keys[i]));
+    let mut games: Vec<u32> = runs.flat_map(|i| self.run(i)).copied().collect();
+    games.sort_unstable();
+    games.dedup();
+    games
+  }
+}
+
+/// An open database: the games are read into memory and indexed, imports
+/// are appended to the file.
+pub struct Database {
+  file: File,
+  records: Vec<Record>,
+  positions: Index,
+  materials: Index,
+}
+
+impl Database {
+  /// Opens the database at `path`, creating an empty one if there is none.
+  pub fn open(path: &Path) -> anyhow::Result<Database> {
+    let bytes = match fs::read(path) {
+      Ok(bytes) => bytes,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => {
+        fs::write(path, MAGIC)?;
+        MAGIC.to_vec()
+      }
+      Err(err) => return Err(err.into()),
+    };
+    if !bytes.starts_with(MAGIC) {
+      bail!("{} is not a game database", path.display());
+    }
+    let mut records = Vec::new();
+    let mut rest = &bytes[MAGIC.len()..];
+    while !rest.is_empty() {
+      let Some((len, tail)) = rest.split_first_chunk::<4>() else {
+        bail!("{} is truncated", path.display());
+      };
+      let len = u32::from_be_bytes(*len) as usize;
+      if tail.len() < len {
+        bail!("{} is truncated", path.display());
+      }
+      let record = Record::from_bytes(&tail[..len]).map_err(|err| {
+        anyhow!("game {} of {}: {err}", records.len(), path.display())
+      })?;
+      records.push(record);
+      rest = &tail[len..];
+    }
+    let mut games = Vec::with_capacity(records.len());
+    for (id, record) in records.iter().enumerate() {
+      let (start, moves) = record
+        .replay()
+        .map_err(|err| anyhow!("game {id} of {}: {err}", path.display()))?;
+      games.push((start, moves));
+    }
+    let file = OpenOptions::new().append(true).open(path)?;
+    let mut database = Database {
+      file,
+      records,
+      positions: Index::default(),
+      materials: Index::default(),
+    };
+    database.index(0, games.iter().map(|(start, moves)| (start, &moves[..])));
+    Ok(database)
+  }
+
+  /// Indexes the positions and material of games from id `first` on.
+  fn index<'a>(
+    &mut self,
+    first: u32,
+    games: impl Iterator<Item = (&'a Position, &'a [Move])>,
+  ) {
+    let (mut positions, mut materials) = (Vec::new(), Vec::new());
+    for ((start, moves), id) in games.zip(first..) {
+      let mut position = start.clone();
+      positions.push((key(&position), id));
+      materials.push((Material::of(&position).key(), id));
+      for &mv in moves {
+        position.play(mv);
+        positions.push((key(&position), id));
+        materials.push((Material::of(&position).key(), id));
+      }
+    }
+    self.positions.extend(positions);
+    self.materials.extend(materials);
+  }
+
+  pub fn len(&self) -> usize {
+    self.records.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.records.is_empty()
+  }
+
+  /// Adds games at the end, returning the id of the first.
+  pub fn import(
+    &mut self,
+    games: &[Game],
+  ) -> anyhow::Result<u32> {
+    let first = self.records.len() as u32;
+    let records: Vec<Record> = games.iter().map(Record::of).collect();
+    let mut bytes = Vec::new();
+    for (i, record) in records.iter().enumerate() {
+      record.check().map_err(|err| anyhow!("game {i}: {err}"))?;
+      let record = record.to_bytes();
+      bytes.extend((record.len() as u32).to_be_bytes());
+      bytes.extend(record);
+    }
+    self.file.write_all(&bytes)?;
+    self.file.flush()?;
+    self.records.extend(records);
+    let games = games.iter().map(|game| (&game.start, &game.moves[..]));
+    self.index(first, games);
+    Ok(first)
+  }
+
+  /// Ids of the games matching `query`, in the order they were imported.
+  pub fn search(
+    &self,
+    query: &Query,
+  ) -> Vec<u32> {
+    let mut ids: Vec<u32> = match query.position {
+      Some(key) => self.positions.games(key).to_vec(),
+      None => (0..self.records.len() as u32).collect(),
+    };
+    if let Some(range) = query.material {
+      let games = match range.min == range.max {
+        true => self.materials.games(range.min.key()).to_vec(),
+        false => self.materials.games_where(|key| range.contains(key)),
+      };
+      ids.retain(|id| games.binary_search(id).is_ok());
+    }
+    ids.retain(|&id| self.records[id as usize].matches(query));
+    ids
+  }
+
+  fn record(
+    &self,
+    id: u32,
+  ) -> anyhow::Result<&Record> {
+    let record = self.records.get(id as usize);
+    record.ok_or_else(|| anyhow!("no game {id} in the database"))
+  }
+
+  pub fn game(
+    &self,
+    id: u32,
+  ) -> anyhow::Result<Game> {
+    let record = self.record(id)?;
+    let (start, moves) =
+      record.replay().map_err(|err| anyhow!("game {id}: {err}"))?;
+    Ok(Game {
+      tags: record.tags.clone(),
+      start,
+      moves,
+      outcome: record.outcome,
+    })
+  }
+
+  /// One line naming a game, without reading its moves.
+  pub fn summary(
+    &self,
+    id: u32,
+  ) -> anyhow::Result<String> {
+    let record = self.record(id)?;
+    let game = Game {
+      tags: record.tags.clone(),
+      outcome: record.outcome,
+      ..Default::default()
+    };
+    Ok(explorer::summary(&game))
+  }
+}
@@ -0,0 +1,81 @@
+//! Opening classification by ECO code and name. The table, in `eco.tsv`,
+//! lists the moves of each opening; it is looked up by the key of the
+//! position they reach, so transpositions get the same name.
+
+use std::{collections::HashMap, sync::OnceLock};
+
+use crate::{
+  board::{Move, Position},
+  pgn::Game,
+  san,
+};
+
+const TABLE: &str = include_str!("eco.tsv");
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Opening {
+  pub eco: &'static str,
+  pub name: &'static str,
+}
+
+/// Reads the table once. It ships with the program, so a line that does
+/// not parse is a bug.
+fn table() -> &'static HashMap<u64, Opening> {
+  static TABLE_BY_KEY: OnceLock<HashMap<u64, Opening>> = OnceLock::new();
+  TABLE_BY_KEY.get_or_init(|| {
+    let mut openings = HashMap::new();
+    for line in TABLE.lines().skip(1) {
+      let mut fields = line.split('\t');
+      let (Some(eco), Some(name), Some(moves)) =
+        (fields.next(), fields.next(), fields.next())
+      else {
+        panic!("invalid ECO line {line:?}");
+      };
+      let mut position = Position::startpos();
+      for word in moves.split_whitespace() {
+        if word.ends_with('.') {
+          continue;
+        }
+        let mv = san::parse_san(&position, word)
+          .unwrap_or_else(|err| panic!("{eco} {name}: {err}"));
+        position.play(mv);
+      }
+      // the first name of a position is its usual one
+      openings.entry(position.hash()).or_insert(Opening { eco, name });
+    }
+    openings
+  })
+}
+
+/// Number of positions in the table.
+pub fn len() -> usize {
+  table().len()
+}
+
+/// The opening of a position in the table.
+pub fn lookup(position: &Position) -> Option<Opening> {
+  table().get(&position.hash()).copied()
+}
+
+/// The opening of a game that went through `moves`: the last position of
+/// the table it reached.
+pub fn classify(
+  start: &Position,
+  moves: &[Move],
+) -> Option<Opening> {
+  let mut position = start.clone();
+  let mut opening = lookup(&position);
+  for &mv in moves {
+    position.play(mv);
+    opening = lookup(&position).or(opening);
+  }
+  opening
+}
+
+/// Sets the `ECO` and `Opening` tags of a game that can be classified.
+pub fn tag(game: &mut Game) {
+  if let Some(opening) = classify(&game.start, &game.moves) {
+    game.set_tag("ECO", opening.eco);
+    game.set_tag("Opening", opening.name);
+  }
+}
@@ -0,0 +1,119 @@
+//! Extended Position Description: the first four FEN fields followed by
+//! `opcode operands;` pairs, one record per line.
+
+use anyhow::{anyhow, bail};
+
+use crate::board::Position;
+
+#[derive(Clone, Debug)]
+pub struct Epd {
+  pub position: Position,
+  /// Operations in the order they appear, string operands unquoted.
+  pub ops: Vec<(String, Vec<String>)>,
+}
+
+impl Epd {
+  pub fn op(
+    &self,
+    name: &str,
+  ) -> Option<&[String]> {
+    self.ops.iter().find(|(op, _)| op == name).map(|(_, args)| args.as_slice())
+  }
+
+  /// The `id` of the record, usually the suite name and a number.
+  pub fn id(&self) -> Option<&str> {
+    self.op("id")?.first().map(String::as_str)
+  }
+}
+
+/// Splits operands on whitespace, keeping quoted strings together.
+fn operands(text: &str) -> anyhow::Result<Vec<String>> {
+  let mut operands = Vec::new();
+  let mut rest = text.trim();
+  while !rest.is_empty() {
+    if let Some(quoted) = rest.strip_prefix('"') {
+      let end =
+        quoted.find('"').ok_or_else(|| anyhow!("unterminated string"))?;
+      operands.push(quoted[..end].to_string());
+      rest = quoted[end + 1..].trim_start();
+    } else {
+      let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+      operands.push(rest[..end].to_string());
+      rest = rest[end..].trim_start();
+    }
+  }
+  Ok(operands)
+}
+
+/// Splits operations on `;` outside quoted strings.
+fn operations(text: &str) -> anyhow::Result<Vec<(String, Vec<String>)>> {
+  let mut ops = Vec::new();
+  let mut start = 0;
+  let mut quoted = false;
+  for (i, c) in text.char_indices() {
+    match c {
+      '"' => quoted = !quoted,
+      ';' if !quoted => {
+        let op = text[start..i].trim();
+        let (name, args) =
+          op.split_once(char::is_whitespace).unwrap_or((op, ""));
+        if !name.is_empty() {
+          ops.push((name.to_string(), operands(args)?));
+        }
+        start = i + 1;
+      }
+      _ => {}
+    }
+  }
+  if !text[start..].trim().is_empty() {
+    bail!("operation without ';': {:?}", text[start..].trim());
+  }
+  Ok(ops)
+}
+
+pub fn parse_record(line: &str) -> anyhow::Result<Epd> {
+  let mut rest = line.trim();
+  let mut fields = Vec::new();
+  for _ in 0..4 {
+    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    fields.push(&rest[..end]);
+    rest = rest[end..].trim_start();
+  }
+  // some files carry the full FEN, move counters included
+  let mut counters = ["0", "1"];
+  for counter in &mut counters {
+    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    if end == 0 || !rest[..end].bytes().all(|c| c.is_ascii_digit()) {
+      break;
+    }
+    *counter = &rest[..end];
+    rest = rest[end..].trim_start();
+  }
+  let ops = operations(rest)?;
+  let op = |name: &str| {
+    ops.iter().find(|(op, _)| op == name).and_then(|(_, args)| args.first())
+  };
+  let halfmove = op("hmvc").map_or(counters[0], String::as_str);
+  let fullmove = op("fmvn").map_or(counters[1], String::as_str);
+  let fen = format!("{} {halfmove} {fullmove}", fields.join(" "));
+  let position = Position::from_fen(&fen)?;
+  Ok(Epd { position, ops })
+}
+
+/// Reads every record of an EPD file, skipping blank lines and `#`
+/// comments. Records that don't parse are skipped with a warning, so one
+/// broken line doesn't lose the rest of the file.
+pub fn parse(text: &str) -> Vec<Epd> {
+  text
+    .lines()
+    .enumerate()
+    .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+    .filter_map(|(i, line)| match parse_record(line) {
+      Ok(epd) => Some(epd),
+      Err(err) => {
+        log::warn!("skipping EPD line {}: {err}", i + 1);
+        None
+      }
+    })
+    .collect()
+}
@@ -0,0 +1,141 @@
+//! Static evaluation: material plus piece-square tables, with the king
+//! table blended between middlegame and endgame by remaining material.
+//! Tables follow the "Simplified Evaluation Function",
+//! https://www.chessprogramming.org/Simplified_Evaluation_Function
+
+use crate::board::{Color, PieceKind, Position, Squares};
+
+pub const PIECE_VALUES: [i32; 6] = [100, 320, 330, 500, 900, 0];
+
+// tables are laid out as the board is printed, a8 first, from white's side
+#[rustfmt::skip]
+const PAWN: [i32; 64] = [
+   0,  0,   0,   0,   0,   0,  0,  0,
+  50, 50,  50,  50,  50,  50, 50, 50,
+  10, 10,  20,  30,  30,  20, 10, 10,
+   5,  5,  10,  25,  25,  10,  5,  5,
+   0,  0,   0,  20,  20,   0,  0,  0,
+   5, -5, -10,   0,   0, -10, -5,  5,
+   5, 10,  10, -20, -20,  10, 10,  5,
+   0,  0,   0,   0,   0,   0,  0,  0,
+];
+
+#[rustfmt::skip]
+const KNIGHT: [i32; 64] = [
+  -50, -40, -30, -30, -30, -30, -40, -50,
+  -40, -20,   0,   0,   0,   0, -20, -40,
+  -30,   0,  10,  15,  15,  10,   0, -30,
+  -30,   5,  15,  20,  20,  15,   5, -30,
+  -30,   0,  15,  20,  20,  15,   0, -30,
+  -30,   5,  10,  15,  15,  10,   5, -30,
+  -40, -20,   0,   5,   5,   0, -20, -40,
+  -50, -40, -30, -30, -30, -30, -40, -50,
+];
+
+#[rustfmt::skip]
+const BISHOP: [i32; 64] = [
+  -20, -10, -10, -10, -10, -10, -10, -20,
+  -10,   0,   0,   0,   0,   0,   0, -10,
+  -10,   0,   5,  10,  10,   5,   0, -10,
+  -10,   5,   5,  10,  10,   5,   5, -10,
+  -10,   0,  10,  10,  10,  10,   0, -10,
+  -10,  10,  10,  10,  10,  10,  10, -10,
+  -10,   5,   0,   0,   0,   0,   5, -10,
+  -20, -10, -10, -10, -10, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const ROOK: [i32; 64] = [
+   0,  0,  0,  0,  0,  0,  0,  0,
+   5, 10, 10, 10, 10, 10, 10,  5,
+  -5,  0,  0,  0,  0,  0,  0, -5,
+  -5,  0,  0,  0,  0,  0,  0, -5,
+  -5,  0,  0,  0,  0,  0,  0, -5,
+  -5,  0,  0,  0,  0,  0,  0, -5,
+  -5,  0,  0,  0,  0,  0,  0, -5,
+   0,  0,  0,  5,  5,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const QUEEN: [i32; 64] = [
+  -20, -10, -10, -5, -5, -10, -10, -20,
+  -10,   0,   0,  0,  0,   0,   0, -10,
+  -10,   0,   5,  5,  5,   5,   0, -10,
+   -5,   0,   5,  5,  5,   5,   0,  -5,
+    0,   0,   5,  5,  5,   5,   0,  -5,
+  -10,   5,   5,  5,  5,   5,   0, -10,
+  -10,   0,   5,  0,  0,   0,   0, -10,
+  -20, -10, -10, -5, -5, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const KING_MIDDLEGAME: [i32; 64] = [
+  -30, -40, -40, -50, -50, -40, -40, -30,
+  -30, -40, -40, -50, -50, -40, -40, -30,
+  -30, -40, -40, -50, -50, -40, -40, -30,
+  -30, -40, -40, -50, -50, -40, -40, -30,
+  -20, -30, -30, -40, -40, -30, -30, -20,
+  -10, -20, -20, -20, -20, -20, -20, -10,
+   20,  20,   0,   0,   0,   0,  20,  20,
+   20,  30,  10,   0,   0,  10,  30,  20,
+];
+
+#[rustfmt::skip]
+const KING_ENDGAME: [i32; 64] = [
+  -50, -40, -30, -20, -20, -30, -40, -50,
+  -30, -20, -10,   0,   0, -10, -20, -30,
+  -30, -10,  20,  30,  30,  20, -10, -30,
+  -30, -10,  30,  40,  40,  30, -10, -30,
+  -30, -10,  30,  40,  40,  30, -10, -30,
+  -30, -10,  20,  30,  30,  20, -10, -30,
+  -30, -30,   0,   0,   0,   0, -30, -30,
+  -50, -30, -30, -30, -30, -30, -30, -50,
+];
+
+// game phase weights of knight, bishop, rook and queen
+const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+const MAX_PHASE: i32 = 24;
+const BISHOP_PAIR: i32 = 30;
+const TEMPO: i32 = 10;
+
+/// 0 with only kings and pawns left, `MAX_PHASE` with all pieces.
+fn phase(position: &Position) -> i32 {
+  let phase = PieceKind::ALL.iter().fold(0, |phase, &kind| {
+    let count = position.pieces_of_kind(kind).count_ones() as i32;
+    phase + count * PHASE_WEIGHTS[kind.index()]
+  });
+  phase.min(MAX_PHASE)
+}
+
+/// Evaluation in centipawns from the point of view of the side to move.
+pub fn evaluate(position: &Position) -> i32 {
+  let phase = phase(position);
+  let mut score = 0;
+  for color in [Color::White, Color::Black] {
+    let sign = color.fold(1, -1);
+    for kind in PieceKind::ALL {
+      for sq in Squares(position.pieces(color, kind)) {
+        // table index of the square from this color's side of the board
+        let index = color.fold(sq.flip(), sq).index();
+        let bonus = match kind {
+          PieceKind::Pawn => PAWN[index],
+          PieceKind::Knight => KNIGHT[index],
+          PieceKind::Bishop => BISHOP[index],
+          PieceKind::Rook => ROOK[index],
+          PieceKind::Queen => QUEEN[index],
+          PieceKind::King => {
+            (KING_MIDDLEGAME[index] * phase
+              + KING_ENDGAME[index] * (MAX_PHASE - phase))
+              / MAX_PHASE
+          }
+        };
+        score += sign * (PIECE_VALUES[kind.index()] + bonus);
+      }
+    }
+    if position.pieces(color, PieceKind::Bishop).count_ones() >= 2 {
+      score += sign * BISHOP_PAIR;
+    }
+  }
+  let side = position.side_to_move();
+  side.fold(score, -score) + TEMPO
+}
@@ -0,0 +1,318 @@
+//! Opening explorer over a local collection of games: for each position of
+//! the first plies, the moves played with their results and the ratings of
+//! the players. The index lives in a file and is searched on disk, so it
+//! works offline however many games went in.
+//!
+//! The file is big-endian: a header, entries sorted by position key, then
+//! a table of offsets and the games themselves as PGN.
+
+use std::{
+  collections::HashMap,
+  fs::File,
+  io::{BufWriter, Read, Seek, SeekFrom, Write},
+  path::Path,
+};
+
+use anyhow::{anyhow, bail};
+
+use crate::{
+  board::{Move, Position},
+  book::{decode_move, encode_move, key},
+  pgn::{self, Game, Outcome},
+};
+
+const MAGIC: &[u8; 8] = b"CHEXPL01";
+const HEADER_SIZE: u64 = 16;
+/// Games kept per move to jump to, the best rated ones.
+const TOP_GAMES: usize = 4;
+const ENTRY_SIZE: u64 = 8 + 2 + 3 * 4 + 8 + 4 + TOP_GAMES as u64 * 4;
+const NO_GAME: u32 = u32::MAX;
+
+#[derive(Clone, Copy, Debug)]
+pub struct BuildOptions {
+  /// Plies of each game that go into the index.
+  pub max_ply: usize,
+}
+
+impl Default for BuildOptions {
+  fn default() -> Self {
+    Self { max_ply: 40 }
+  }
+}
+
+/// How a move did in the games of the index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MoveStats {
+  pub mv: Move,
+  pub white: u32,
+  pub draws: u32,
+  pub black: u32,
+  /// Sum of the players' average ratings, over the games rated.
+  pub rating_sum: u64,
+  pub rated: u32,
+  /// Best rated games with the move, best first.
+  pub games: Vec<u32>,
+}
+
+impl MoveStats {
+  fn new(mv: Move) -> Self {
+    Self {
+      mv,
+      white: 0,
+      draws: 0,
+      black: 0,
+      rating_sum: 0,
+      rated: 0,
+      games: Vec::new(),
+    }
+  }
+
+  pub fn total(&self) -> u32 {
+    self.white + self.draws + self.black
+  }
+
+  /// Shares of white wins, draws and black wins in percent.
+  pub fn percentages(&self) -> [f64; 3] {
+    let total = self.total().max(1) as f64;
+    [self.white, self.draws, self.black].map(|n| n as f64 * 100.0 / total)
+  }
+
+  pub fn average_rating(&self) -> Option<u32> {
+    (self.rated > 0).then(|| (self.rating_sum / self.rated as u64) as u32)
+  }
+}
+
+/// Average rating of the players, when both are known.
+fn game_rating(game: &Game) -> Option<u32> {
+  let elo = |tag| game.tag(tag)?.parse::<u32>().ok();
+  Some((elo("WhiteElo")? + elo("BlackElo")?) / 2)
+}
+
+/// One line naming a game: `Carlsen (2850) - Nepo (2790) 1-0, WCh 2021`.
+pub fn summary(game: &Game) -> String {
+  let player = |name, elo| match game.tag(elo) {
+    Some(elo) => format!("{} ({elo})", game.tag(name).unwrap_or("?")),
+    None => game.tag(name).unwrap_or("?").to_string(),
+  };
+  let mut text = format!(
+    "{} - {} {}",
+    player("White", "WhiteElo"),
+    player("Black", "BlackElo"),
+    game.outcome
+  );
+  if let Some(event) = game.tag("Event").filter(|event| *event != "?") {
+    text.push_str(", ");
+    text.push_str(event);
+  }
+  if let Some(year) = game.tag("Date").and_then(|date| date.get(..4)) {
+    if year != "????" {
+      text.push(' ');
+      text.push_str(year);
+    }
+  }
+  text
+}
+
+/// Writes the index of `games` to `path`. Games without a result still
+/// go in, they just count for no side.
+pub fn build(
+  games: &[Game],
+  options: &BuildOptions,
+  path: &Path,
+) -> anyhow::Result<()> {
+  let mut stats: HashMap<(u64, u16), MoveStats> = HashMap::new();
+  for (id, game) in games.iter().enumerate() {
+    let rating = game_rating(game);
+    let mut position = game.start.clone();
+    for &mv in game.moves.iter().take(options.max_ply) {
+      let stats = stats
+        .entry((key(&position), encode_move(&position, mv)))
+        .or_insert_with(|| MoveStats::new(mv));
+      match game.outcome {
+        Outcome::WhiteWins => stats.white += 1,
+        Outcome::BlackWins => stats.black += 1,
+        Outcome::Draw => stats.draws += 1,
+        Outcome::Unknown => {}
+      }
+      if let Some(rating) = rating {
+        stats.rating_sum += rating as u64;
+        stats.rated += 1;
+      }
+      stats.games.push(id as u32);
+      position.play(mv);
+    }
+  }
+  let ratings: Vec<u32> =
+    games.iter().map(|game| game_rating(game).unwrap_or(0)).collect();
+  let mut entries: Vec<((u64, u16), MoveStats)> = stats.into_iter().collect();
+  entries
+    .sort_by_key(|((key, mv), stats)| (*key, u32::MAX - stats.total(), *mv));
+
+  let mut out = BufWriter::new(File::create(path)?);
+  out.write_all(MAGIC)?;
+  out.write_all(&(entries.len() as u32).to_be_bytes())?;
+  out.write_all(&(games.len() as u32).to_be_bytes())?;
+  for ((key, mv), stats) in &mut entries {
+    stats.games.sort_by_key(|&id| u32::MAX - ratings[id as usize]);
+    stats.games.truncate(TOP_GAMES);
+    out.write_all(&key.to_be_bytes())?;
+    out.write_all(&mv.to_be_bytes())?;
+    for count in [stats.white, stats.draws, stats.black] {
+      out.write_all(&count.to_be_bytes())?;
+    }
+    out.write_all(&stats.rating_sum.to_be_bytes())?;
+    out.write_all(&stats.rated.to_be_bytes())?;
+    for i in 0..TOP_GAMES {
+      let id = stats.games.get(i).copied().unwrap_or(NO_GAME);
+      out.write_all(&id.to_be_bytes())?;
+    }
+  }
+
+  // offsets from the end of the table, each game a summary and its PGN
+  let texts: Vec<(String, String)> =
+    games.iter().map(|game| (summary(game), game.to_pgn())).collect();
+  let mut offset = 0u64;
+  for (summary, pgn) in &texts {
+    out.write_all(&offset.to_be_bytes())?;
+    offset += 8 + summary.len() as u64 + pgn.len() as u64;
+  }
+  for (summary, pgn) in &texts {
+    for text in [summary, pgn] {
+      out.write_all(&(text.len() as u32).to_be_bytes())?;
+      out.write_all(text.as_bytes())?;
+    }
+  }
+  out.flush()?;
+  Ok(())
+}
+
+pub struct Explorer {
+  file: File,
+  entries: u64,
+  games: u64,
+}
+
+impl Explorer {
+  pub fn open(path: &Path) -> anyhow::Result<Explorer> {
+    let mut file = File::open(path)?;
+    let mut header = [0; HEADER_SIZE as usize];
+    file.read_exact(&mut header)?;
+    if &header[..8] != MAGIC {
+      bail!("{} is not an explorer index", path.display());
+    }
+    let entries = u32::from_be_bytes(header[8..12].try_into().unwrap()) as u64;
+    let games = u32::from_be_bytes(header[12..16].try_into().unwrap()) as u64;
+    let explorer = Explorer { file, entries, games };
+    if explorer.file.metadata()?.len() < explorer.games_start() + games * 8 {
+      bail!("{} is truncated", path.display());
+    }
+    Ok(explorer)
+  }
+
+  /// Number of games indexed.
+  pub fn games(&self) -> usize {
+    self.games as usize
+  }
+
+  fn games_start(&self) -> u64 {
+    HEADER_SIZE + self.entries * ENTRY_SIZE
+  }
+
+  fn read_at<const N: usize>(
+    &self,
+    offset: u64,
+  ) -> anyhow::Result<[u8; N]> {
+    let mut file = &self.file;
+    let mut bytes = [0; N];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut bytes)?;
+    Ok(bytes)
+  }
+
+  fn key_at(
+    &self,
+    index: u64,
+  ) -> anyhow::Result<u64> {
+    Ok(u64::from_be_bytes(self.read_at(HEADER_SIZE + index * ENTRY_SIZE)?))
+  }
+
+  /// The moves played from `position`, most played first.
+  pub fn moves(
+    &self,
+    position: &Position,
+  ) -> anyhow::Result<Vec<MoveStats>> {
+    let key = key(position);
+    // first entry with the key
+    let (mut low, mut high) = (0, self.entries);
+    while low < high {
+      let middle = (low + high) / 2;
+      if self.key_at(middle)? < key {
+        low = middle + 1;
+      } else {
+        high = middle;
+      }
+    }
+    let mut moves = Vec::new();
+    for index in low..self.entries {
+      let bytes: [u8; ENTRY_SIZE as usize] =
+        self.read_at(HEADER_SIZE + index * ENTRY_SIZE)?;
+      let u32_at =
+        |at: usize| u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap());
+      if u64::from_be_bytes(bytes[..8].try_into().unwrap()) != key {
+        break;
+      }
+      let mv = decode_move(position, u16::from_be_bytes([bytes[8], bytes[9]]));
+      moves.push(MoveStats {
+        mv,
+        white: u32_at(10),
+        draws: u32_at(14),
+        black: u32_at(18),
+        rating_sum: u64::from_be_bytes(bytes[22..30].try_into().unwrap()),
+        rated: u32_at(30),
+        games: (0..TOP_GAMES)
+          .map(|i| u32_at(34 + 4 * i))
+          .filter(|&id| id != NO_GAME)
+          .collect(),
+      });
+    }
+    Ok(moves)
+  }
+
+  /// Summary and PGN of a game.
+  fn texts(
+    &self,
+    id: u32,
+  ) -> anyhow::Result<(String, String)> {
+    if id as u64 >= self.games {
+      bail!("no game {id} in the index");
+    }
+    let table = self.games_start();
+    let offset = u64::from_be_bytes(self.read_at(table + id as u64 * 8)?);
+    let mut file = &self.file;
+    file.seek(SeekFrom::Start(table + self.games * 8 + offset))?;
+    let mut read_text = || -> anyhow::Result<String> {
+      let mut len = [0; 4];
+      file.read_exact(&mut len)?;
+      let mut text = vec![0; u32::from_be_bytes(len) as usize];
+      file.read_exact(&mut text)?;
+      Ok(String::from_utf8(text)?)
+    };
+    Ok((read_text()?, read_text()?))
+  }
+
+  pub fn summary(
+    &self,
+    id: u32,
+  ) -> anyhow::Result<String> {
+    Ok(self.texts(id)?.0)
+  }
+
+  pub fn game(
+    &self,
+    id: u32,
+  ) -> anyhow::Result<Game> {
+    let (_, text) = self.texts(id)?;
+    let mut games = pgn::parse(&text)?;
+    games.pop().ok_or_else(|| anyhow!("game {id} has no moves"))
+  }
+}
@@ -0,0 +1,529 @@
+//! A game as a tree of moves: the main line and nested variations, each
+//! move with its comment, annotation glyphs, clock and board markings.
+//! The first child of a node continues the line, later ones are
+//! variations of it.
+
+use std::{fmt::Write as _, time::Duration};
+
+use anyhow::bail;
+
+use crate::{
+  board::{Move, Position, Square},
+  search::Score,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+/// Colors of `[%cal]` and `[%csl]` markings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum MarkColor {
+  #[default]
+  Green,
+  Red,
+  Yellow,
+  Blue,
+}
+
+impl MarkColor {
+  pub const ALL: [MarkColor; 4] =
+    [MarkColor::Green, MarkColor::Red, MarkColor::Yellow, MarkColor::Blue];
+
+  pub fn char(self) -> char {
+    match self {
+      MarkColor::Green => 'G',
+      MarkColor::Red => 'R',
+      MarkColor::Yellow => 'Y',
+      MarkColor::Blue => 'B',
+    }
+  }
+
+  pub fn from_char(c: char) -> Option<MarkColor> {
+    MarkColor::ALL.into_iter().find(|color| color.char() == c)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ArrowMark {
+  pub from: Square,
+  pub to: Square,
+  pub color: MarkColor,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SquareMark {
+  pub square: Square,
+  pub color: MarkColor,
+}
+
+/// Numeric annotation glyphs of the move suffixes, `$1` to `$6`.
+const SUFFIXES: [(&str, u8); 6] =
+  [("!!", 3), ("??", 4), ("!?", 5), ("?!", 6), ("!", 1), ("?", 2)];
+
+/// Glyph of a move suffix like `!?`.
+pub fn nag_of_suffix(suffix: &str) -> Option<u8> {
+  SUFFIXES.iter().find(|(text, _)| *text == suffix).map(|&(_, nag)| nag)
+}
+
+/// How a glyph is shown next to a move: `!?` for the suffixes and `$n`
+/// for the others.
+pub fn nag_text(nag: u8) -> String {
+  match SUFFIXES.iter().find(|&&(_, glyph)| glyph == nag) {
+    Some((text, _)) => text.to_string(),
+    None => format!("${nag}"),
+  }
+}
+
+/// `h:mm:ss` as in `[%clk]`.
+pub fn format_clock(clock: Duration) -> String {
+  let seconds = clock.as_secs();
+  let (hours, minutes, seconds) =
+    (seconds / 3600, seconds / 60 % 60, seconds % 60);
+  match clock.subsec_millis() {
+    0 => format!("{hours}:{minutes:02}:{seconds:02}"),
+    millis => format!("{hours}:{minutes:02}:{seconds:02}.{millis:03}"),
+  }
+}
+
+/// `[%eval]` values are in pawns from white's point of view, `#-3` when
+/// black mates in three.
+fn format_eval(score: Score) -> String {
+  match score {
+    Score::Cp(cp) => format!("{:.2}", cp as f64 / 100.0),
+    Score::Mate(moves) => format!("#{moves}"),
+  }
+}
+
+fn parse_eval(text: &str) -> Option<Score> {
+  // some writers add the depth, as in `0.17,20`
+  let text = text.split(',').next()?.trim();
+  match text.strip_prefix('#') {
+    Some(moves) => Some(Score::Mate(moves.parse().ok()?)),
+    None => Some(Score::Cp((text.parse::<f64>().ok()? * 100.0).round() as i32)),
+  }
+}
+
+fn parse_clock(text: &str) -> Option<Duration> {
+  let mut seconds = 0.0;
+  for part in text.split(':') {
+    seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+  }
+  Duration::try_from_secs_f64(seconds).ok()
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Annotations {
+  /// Comment text without the embedded commands.
+  pub comment: String,
+  pub nags: Vec<u8>,
+  /// Time left on the clock after the move.
+  pub clock: Option<Duration>,
+  /// Evaluation of the position after the move, from white's side.
+  pub eval: Option<Score>,
+  pub arrows: Vec<ArrowMark>,
+  pub highlights: Vec<SquareMark>,
+}
+
+impl Annotations {
+  pub fn is_empty(&self) -> bool {
+    self.comment.is_empty()
+      && self.nags.is_empty()
+      && self.clock.is_none()
+      && self.eval.is_none()
+      && self.arrows.is_empty()
+      && self.highlights.is_empty()
+  }
+
+  /// Takes in a PGN comment, pulling out the `[%clk]`, `[%eval]`, `[%cal]`
+  /// and `[%csl]` commands and appending the remaining text.
+  pub fn add_comment(
+    &mut self,
+    text: &str,
+  ) {
+    let mut rest = text;
+    let mut plain = String::new();
+    while let Some(start) = rest.find("[%") {
+      plain.push_str(&rest[..start]);
+      let Some(end) = rest[start..].find(']') else {
+        break;
+      };
+      let command = &rest[start + 2..start + end];
+      rest = &rest[start + end + 1..];
+      let (name, args) = command.split_once(' ').unwrap_or((command, ""));
+      let args = args.split(',').map(str::trim).filter(|arg| !arg.is_empty());
+      match name {
+        "clk" => self.clock = parse_clock(command[3..].trim()),
+        "eval" => self.eval = parse_eval(command[4..].trim()),
+        "cal" => self.arrows.extend(args.filter_map(|arg| {
+          let color = MarkColor::from_char(arg.chars().next()?)?;
+          let from = arg.get(1..3)?.parse().ok()?;
+          let to = arg.get(3..5)?.parse().ok()?;
+          Some(ArrowMark { from, to, color })
+        })),
+        "csl" => self.highlights.extend(args.filter_map(|arg| {
+          let color = MarkColor::from_char(arg.chars().next()?)?;
+          Some(SquareMark { square: arg.get(1..3)?.parse().ok()?, color })
+        })),
+        // unknown commands are kept as they are
+        _ => {
+          let _ = write!(plain, "[%{command}]");
+        }
+      }
+    }
+    plain.push_str(rest);
+    let plain = plain.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !plain.is_empty() {
+      if !self.comment.is_empty() {
+        self.comment.push(' ');
+      }
+      self.comment.push_str(&plain);
+    }
+  }
+
+  /// The comment as written to PGN, commands first. Empty when there is
+  /// nothing to say.
+  pub fn pgn_comment(&self) -> String {
+    let mut parts = Vec::new();
+    if !self.highlights.is_empty() {
+      let marks: Vec<String> = self
+        .highlights
+        .iter()
+        .map(|mark| format!("{}{}", mark.color.char(), mark.square))
+        .collect();
+      parts.push(format!("[%csl {}]", marks.join(",")));
+    }
+    if !self.arrows.is_empty() {
+      let marks: Vec<String> = self
+        .arrows
+        .iter()
+        .map(|mark| format!("{}{}{}", mark.color.char(), mark.from, mark.to))
+        .collect();
+      parts.push(format!("[%cal {}]", marks.join(",")));
+    }
+    if let Some(eval) = self.eval {
+      parts.push(format!("[%eval {}]", format_eval(eval)));
+    }
+    if let Some(clock) = self.clock {
+      parts.push(format!("[%clk {}]", format_clock(clock)));
+    }
+    if !self.comment.is_empty() {
+      // a closing brace would end the comment early
+      parts.push(self.comment.replace('}', ")"));
+    }
+    parts.join(" ")
+  }
+
+  /// Adds the arrow, or removes it when it is already there in the same
+  /// color, as drawing the same arrow twice does on analysis boards.
+  pub fn toggle_arrow(
+    &mut self,
+    arrow: ArrowMark,
+  ) {
+    let existing = self
+      .arrows
+      .iter()
+      .position(|mark| mark.from == arrow.from && mark.to == arrow.to);
+    match existing {
+      Some(i) if self.arrows[i].color == arrow.color => {
+        self.arrows.remove(i);
+      }
+      Some(i) => self.arrows[i] = arrow,
+      None => self.arrows.push(arrow),
+    }
+  }
+
+  pub fn toggle_highlight(
+    &mut self,
+    highlight: SquareMark,
+  ) {
+    let existing =
+      self.highlights.iter().position(|mark| mark.square == highlight.square);
+    match existing {
+      Some(i) if self.highlights[i].color == highlight.color => {
+        self.highlights.remove(i);
+      }
+      Some(i) => self.highlights[i] = highlight,
+      None => self.highlights.push(highlight),
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct Node {
+  /// `None` only for the root.
+  pub mv: Option<Move>,
+  pub parent: Option<NodeId>,
+  pub children: Vec<NodeId>,
+  /// Position after the move.
+  pub position: Position,
+  pub annotations: Annotations,
+}
+
+#[derive(Clone, Debug)]
+pub struct GameTree {
+  /// Deleted variations stay here, unreachable, so ids never dangle.
+  nodes: Vec<Node>,
+  current: NodeId,
+}
+
+impl Default for GameTree {
+  fn default() -> Self {
+    Self::new(Position::startpos())
+  }
+}
+
+impl GameTree {
+  pub fn new(start: Position) -> Self {
+    let root = Node {
+      mv: None,
+      parent: None,
+      children: Vec::new(),
+      position: start,
+      annotations: Annotations::default(),
+    };
+    Self { nodes: vec![root], current: NodeId(0) }
+  }
+
+  /// A tree of a single line, with the last position shown.
+  pub fn from_moves(
+    start: Position,
+    moves: &[Move],
+  ) -> anyhow::Result<Self> {
+    let mut tree = Self::new(start);
+    for &mv in moves {
+      tree.play(mv)?;
+    }
+    Ok(tree)
+  }
+
+  pub fn root(&self) -> NodeId {
+    NodeId(0)
+  }
+
+  pub fn current(&self) -> NodeId {
+    self.current
+  }
+
+  pub fn node(
+    &self,
+    id: NodeId,
+  ) -> &Node {
+    &self.nodes[id.0]
+  }
+
+  pub fn annotations_mut(
+    &mut self,
+    id: NodeId,
+  ) -> &mut Annotations {
+    &mut self.nodes[id.0].annotations
+  }
+
+  pub fn start(&self) -> &Position {
+    &self.node(self.root()).position
+  }
+
+  /// The shown position.
+  pub fn position(&self) -> &Position {
+    &self.node(self.current).position
+  }
+
+  /// Nodes from the root's first move down to `id`.
+  pub fn path(
+    &self,
+    id: NodeId,
+  ) -> Vec<NodeId> {
+    let mut path = Vec::new();
+    let mut node = id;
+    while let Some(parent) = self.node(node).parent {
+      path.push(node);
+      node = parent;
+    }
+    path.reverse();
+    path
+  }
+
+  /// Moves leading from the start to `id`.
+  pub fn moves_to(
+    &self,
+    id: NodeId,
+  ) -> Vec<Move> {
+    self.path(id).into_iter().filter_map(|node| self.node(node).mv).collect()
+  }
+
+  /// Moves leading to the shown position.
+  pub fn played(&self) -> Vec<Move> {
+    self.moves_to(self.current)
+  }
+
+  /// Nodes of the main line, without the root.
+  pub fn mainline(&self) -> Vec<NodeId> {
+    let mut line = Vec::new();
+    let mut node = self.root();
+    while let Some(&next) = self.node(node).children.first() {
+      line.push(next);
+      node = next;
+    }
+    line
+  }
+
+  pub fn is_mainline(
+    &self,
+    id: NodeId,
+  ) -> bool {
+    self.path(id).into_iter().all(|node| {
+      let parent = self.node(node).parent.unwrap();
+      self.node(parent).children.first() == Some(&node)
+    })
+  }
+
+  /// Adds `mv` after `parent`, as a new variation when the line already
+  /// goes on. Playing a move that is already there returns its node.
+  pub fn add_move(
+    &mut self,
+    parent: NodeId,
+    mv: Move,
+  ) -> anyhow::Result<NodeId> {
+    let node = self.node(parent);
+    if let Some(&child) =
+      node.children.iter().find(|&&child| self.node(child).mv == Some(mv))
+    {
+      return Ok(child);
+    }
+    if !node.position.is_legal(mv) {
+      bail!("illegal move {mv} in {}", node.position.to_fen());
+    }
+    let id = NodeId(self.nodes.len());
+    let position = node.position.played(mv);
+    self.nodes.push(Node {
+      mv: Some(mv),
+      parent: Some(parent),
+      children: Vec::new(),
+      position,
+      annotations: Annotations::default(),
+    });
+    self.nodes[parent.0].children.push(id);
+    Ok(id)
+  }
+
+  /// Plays a move on the shown position and shows the result.
+  pub fn play(
+    &mut self,
+    mv: Move,
+  ) -> anyhow::Result<NodeId> {
+    self.current = self.add_move(self.current, mv)?;
+    Ok(self.current)
+  }
+
+  pub fn go_to(
+    &mut self,
+    id: NodeId,
+  ) {
+    self.current = id;
+  }
+
+  /// Returns whether there was a move to take back.
+  pub fn back(&mut self) -> bool {
+    match self.node(self.current).parent {
+      Some(parent) => {
+        self.current = parent;
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// Steps along the line the shown move belongs to.
+  pub fn forward(&mut self) -> bool {
+    match self.node(self.current).children.first() {
+      Some(&next) => {
+        self.current = next;
+        true
+      }
+      None => false,
+    }
+  }
+
+  pub fn to_start(&mut self) -> bool {
+    let moved = self.current != self.root();
+    self.current = self.root();
+    moved
+  }
+
+  /// Goes to the end of the line the shown move belongs to.
+  pub fn to_end(&mut self) -> bool {
+    let moved = self.forward();
+    while self.forward() {}
+    moved
+  }
+
+  /// Switches to the neighbouring alternative of the shown move,
+  /// `offset` -1 for the one before and 1 for the one after.
+  pub fn switch_variation(
+    &mut self,
+    offset: isize,
+  ) -> bool {
+    let Some(parent) = self.node(self.current).parent else {
+      return false;
+    };
+    let siblings = &self.node(parent).children;
+    let index = siblings.iter().position(|&id| id == self.current).unwrap();
+    match index.checked_add_signed(offset).and_then(|i| siblings.get(i)) {
+      Some(&sibling) => {
+        self.current = sibling;
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// The node where the variation holding `id` branches off: the closest
+  /// ancestor, or `id` itself, that is not its parent's first child.
+  fn variation_start(
+    &self,
+    id: NodeId,
+  ) -> Option<NodeId> {
+    self.path(id).into_iter().rev().find(|&node| {
+      let parent = self.node(node).parent.unwrap();
+      self.node(parent).children.first() != Some(&node)
+    })
+  }
+
+  /// Moves the variation holding `id` one step towards the main line.
+  /// Returns false when `id` is on the main line already.
+  pub fn promote(
+    &mut self,
+    id: NodeId,
+  ) -> bool {
+    let Some(start) = self.variation_start(id) else {
+      return false;
+    };
+    let parent = self.node(start).parent.unwrap();
+    let children = &mut self.nodes[parent.0].children;
+    let index = children.iter().position(|&child| child == start).unwrap();
+    children.swap(index - 1, index);
+    true
+  }
+
+  /// Promotes the variation holding `id` until it is the main line.
+  pub fn make_mainline(
+    &mut self,
+    id: NodeId,
+  ) {
+    while self.promote(id) {}
+  }
+
+  /// Removes `id` and everything after it. The shown position moves back
+  /// to its parent if it was in the removed part.
+  pub fn delete(
+    &mut self,
+    id: NodeId,
+  ) {
+    let Some(parent) = self.node(id).parent else {
+      return;
+    };
+    self.nodes[parent.0].children.retain(|&child| child != id);
+    if self.path(self.current).contains(&id) {
+      self.current = parent;
+    }
+  }
+}
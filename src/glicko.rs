@@ -0,0 +1,116 @@
+//! Glicko-2 ratings, as in Glickman's "Example of the Glicko-2 system":
+//! a rating with its deviation and volatility, updated after a rating
+//! period of games at once.
+
+use std::f64::consts::PI;
+
+/// Converts between the Glicko and the Glicko-2 scale.
+const SCALE: f64 = 173.7178;
+/// Convergence tolerance of the volatility iteration.
+const EPSILON: f64 = 1e-6;
+/// Constrains the change of volatility, the paper suggests 0.3 to 1.2.
+pub const TAU: f64 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rating {
+  pub rating: f64,
+  pub deviation: f64,
+  pub volatility: f64,
+}
+
+impl Default for Rating {
+  fn default() -> Self {
+    Self { rating: 1500.0, deviation: 350.0, volatility: 0.06 }
+  }
+}
+
+fn g(phi: f64) -> f64 {
+  1.0 / (1.0 + 3.0 * phi * phi / (PI * PI)).sqrt()
+}
+
+impl Rating {
+  pub fn new(
+    rating: f64,
+    deviation: f64,
+  ) -> Self {
+    Self { rating, deviation, ..Default::default() }
+  }
+
+  /// Expected score against `opponent`.
+  pub fn expected(
+    &self,
+    opponent: &Rating,
+  ) -> f64 {
+    let (mu, mu_j) = (self.mu(), opponent.mu());
+    1.0 / (1.0 + (-g(opponent.phi()) * (mu - mu_j)).exp())
+  }
+
+  fn mu(&self) -> f64 {
+    (self.rating - 1500.0) / SCALE
+  }
+
+  fn phi(&self) -> f64 {
+    self.deviation / SCALE
+  }
+
+  /// The rating after a period with these games, each against an
+  /// opponent's rating from before the period with the score, 1 for a
+  /// win. Without games only the deviation grows.
+  pub fn updated(
+    &self,
+    games: &[(Rating, f64)],
+    tau: f64,
+  ) -> Rating {
+    let (mu, phi, sigma) = (self.mu(), self.phi(), self.volatility);
+    if games.is_empty() {
+      let deviation = (phi * phi + sigma * sigma).sqrt() * SCALE;
+      return Rating { deviation, ..*self };
+    }
+
+    let mut v_inverse = 0.0;
+    let mut improvement = 0.0;
+    for (opponent, score) in games {
+      let g = g(opponent.phi());
+      let expected = self.expected(opponent);
+      v_inverse += g * g * expected * (1.0 - expected);
+      improvement += g * (score - expected);
+    }
+    let v = 1.0 / v_inverse;
+    let delta = v * improvement;
+
+    // new volatility through the Illinois algorithm
+    let a = (sigma * sigma).ln();
+    let f = |x: f64| {
+      let ex = x.exp();
+      let d = phi * phi + v + ex;
+      ex * (delta * delta - d) / (2.0 * d * d) - (x - a) / (tau * tau)
+    };
+    let mut lower = a;
+    let mut upper = if delta * delta > phi * phi + v {
+      (delta * delta - phi * phi - v).ln()
+    } else {
+      let mut k = 1.0;
+      while f(a - k * tau) < 0.0 {
+        k += 1.0;
+      }
+      a - k * tau
+    };
+    let (mut f_lower, mut f_upper) = (f(lower), f(upper));
+    while (upper - lower).abs() > EPSILON {
+      let c = lower + (lower - upper) * f_lower / (f_upper - f_lower);
+      let f_c = f(c);
+      if f_c * f_upper <= 0.0 {
+        (lower, f_lower) = (upper, f_upper);
+      } else {
+        f_lower /= 2.0;
+      }
+      (upper, f_upper) = (c, f_c);
+    }
+    let volatility = (lower / 2.0).exp();
+
+    let phi_star = (phi * phi + volatility * volatility).sqrt();
+    let phi = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+    let mu = mu + phi * phi * improvement;
+    Rating { rating: mu * SCALE + 1500.0, deviation: phi * SCALE, volatility }
+  }
+}
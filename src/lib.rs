@@ -1,29 +1,82 @@
 use std::sync::Arc;
 
-use ui::EguiRenderer;
+use ui::{
+  analysis_panel, broadcast_panel, chat_panel, correspondence_panel,
+  database_panel, eval_bar, explorer_panel, game_panel, game_tree, net_panel,
+  puzzle_panel, ratings_panel, repertoire_panel, review_panel, server_panel,
+  BroadcastPanel, ChatPanel, CorrespondencePanel, DatabasePanel, EguiRenderer,
+  ExplorerPanel, GamePanel, NetPanel, PuzzlePanel, RatingsPanel,
+  RepertoirePanel, ServerPanel,
+};
 use winit::{
-  event::{Event, KeyEvent, WindowEvent},
+  event::{ElementState, Event, KeyEvent, MouseButton, WindowEvent},
   event_loop::EventLoopWindowTarget,
-  keyboard::PhysicalKey,
+  keyboard::{ModifiersState, PhysicalKey},
   window::Window,
 };
 
 use discipline::{
   camera::{self, Camera as _},
-  glam::{self, Mat4, Quat, Vec3},
+  glam::{self, Mat4, Quat, Vec2, Vec3},
   setup,
   wgpu::{self, util::DeviceExt},
 };
 
+pub mod analysis;
+pub mod board;
+pub mod book;
+pub mod broadcast;
+#[cfg(target_arch = "wasm32")]
+pub mod browser;
+pub mod chat;
+pub mod client;
+pub mod control;
+pub mod correspondence;
 mod cube;
+pub mod database;
 mod depth;
+pub mod eco;
+pub mod epd;
+mod eval;
+pub mod explorer;
+pub mod gametree;
+pub mod glicko;
 mod grid;
+pub mod lichess;
+pub mod net;
+mod overlay;
 mod pbr;
+pub mod pgn;
+pub mod premove;
+pub mod protocol;
+pub mod puzzle;
+pub mod ratings;
+pub mod repertoire;
+pub mod review;
+pub mod san;
+pub mod search;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod server;
+pub mod sprt;
+pub mod suite;
+pub mod syzygy;
+pub mod tournament;
+pub mod transport;
+mod tt;
+pub mod uci;
 mod ui;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod websocket;
+mod zobrist;
 
+use analysis::Analysis;
+use board::{Color, Square};
 use cube::Cube;
 use depth::Depth;
+use gametree::{ArrowMark, GameTree, MarkColor, SquareMark};
 use grid::Grid;
+use overlay::{Arrow, Circle, Ghost, Overlay};
+use review::Reviewer;
 
 struct Game {
   iad: discipline::InstanceAdapterDevice,
@@ -34,6 +87,36 @@ struct Game {
   cube: Cube,
   debug_grid: Grid,
   depth: Depth,
+
+  overlay: Overlay,
+  // marks drawn with the right mouse button
+  cursor: Option<Vec2>,
+  modifiers: ModifiersState,
+  /// Square the drag started on and the one under the cursor.
+  drawing: Option<(Square, Square)>,
+  /// The same for a piece dragged with the left button.
+  dragging: Option<(Square, Square)>,
+
+  record: pgn::Game,
+  tree: GameTree,
+  game: GamePanel,
+  analysis: Analysis,
+  reviewer: Reviewer,
+  puzzles: PuzzlePanel,
+  explorer: ExplorerPanel,
+  database: DatabasePanel,
+  repertoire: RepertoirePanel,
+  net: NetPanel,
+  server: ServerPanel,
+  chat: ChatPanel,
+  ratings: RatingsPanel,
+  broadcast: BroadcastPanel,
+  correspondence: CorrespondencePanel,
+  // text fields of the panels
+  move_input: String,
+  pgn_text: String,
+  engine_path: String,
+  tablebase_path: String,
 }
 
 #[derive(Debug)]
@@ -146,6 +229,7 @@ pub async fn run() -> anyhow::Result<()> {
   let grid_input = camera.grid_input(80.0);
   let debug_grid =
     Grid::new(preferred_format, &iad.device, &iad.queue, &grid_input);
+  let overlay = Overlay::new(preferred_format, &iad.device, camera.view);
   let mut game = Game {
     iad,
     background_color,
@@ -154,6 +238,30 @@ pub async fn run() -> anyhow::Result<()> {
     cube,
     debug_grid,
     depth,
+    overlay,
+    cursor: None,
+    modifiers: ModifiersState::empty(),
+    drawing: None,
+    dragging: None,
+    record: pgn::Game::default(),
+    tree: GameTree::default(),
+    game: GamePanel::default(),
+    analysis: Analysis::default(),
+    reviewer: Reviewer::default(),
+    puzzles: PuzzlePanel::default(),
+    explorer: ExplorerPanel::default(),
+    database: DatabasePanel::default(),
+    repertoire: RepertoirePanel::default(),
+    net: NetPanel::default(),
+    server: ServerPanel::default(),
+    chat: ChatPanel::default(),
+    ratings: RatingsPanel::default(),
+    broadcast: BroadcastPanel::default(),
+    correspondence: CorrespondencePanel::default(),
+    move_input: String::new(),
+    pgn_text: String::new(),
+    engine_path: String::new(),
+    tablebase_path: String::new(),
   };
 
   let event_lambda =
@@ -215,6 +323,7 @@ fn process_event(
 
       let grid_input = game.camera.grid_input(80.0);
       game.cube.update_camera(&game.iad.queue, game.camera.view);
+      game.overlay.update_camera(&game.iad.queue, game.camera.view);
       game.debug_grid.write_uniform(&game.iad.queue, &grid_input);
       // TODO: how to pass resize event to egui?
       window.request_redraw();
@@ -248,10 +357,70 @@ fn process_event(
       game.iad.queue.submit(Some(encoder.finish()));
       surface_texture.present();
     }
+    Event::WindowEvent {
+      event: WindowEvent::ModifiersChanged(modifiers),
+      ..
+    } => game.modifiers = modifiers.state(),
+    Event::WindowEvent {
+      event: WindowEvent::CursorMoved { position, .. },
+      ..
+    } => {
+      game.cursor = Some(Vec2::new(position.x as f32, position.y as f32));
+      let under = cursor_square(game, &window);
+      for drag in [&mut game.drawing, &mut game.dragging] {
+        if let (Some((from, to)), Some(under)) = (*drag, under) {
+          if under != to {
+            *drag = Some((from, under));
+            window.request_redraw();
+          }
+        }
+      }
+    }
+    Event::WindowEvent {
+      event: WindowEvent::MouseInput { state, button: MouseButton::Left, .. },
+      ..
+    } => {
+      match state {
+        ElementState::Pressed => {
+          game.dragging = cursor_square(game, &window).map(|sq| (sq, sq));
+        }
+        ElementState::Released => {
+          if let Some((from, to)) = game.dragging.take() {
+            drag_piece(game, from, to);
+          }
+        }
+      }
+      window.request_redraw();
+    }
+    Event::WindowEvent {
+      event: WindowEvent::MouseInput { state, button: MouseButton::Right, .. },
+      ..
+    } => {
+      match state {
+        ElementState::Pressed => {
+          game.drawing = cursor_square(game, &window).map(|sq| (sq, sq));
+        }
+        ElementState::Released => {
+          // a right click cancels the premoves before marking
+          let cancelled = game.drawing.is_some_and(|(from, to)| from == to)
+            && (game.server.cancel_premoves() | game.net.cancel_premoves());
+          if let Some((from, to)) = game.drawing.take() {
+            if !cancelled {
+              draw_mark(game, from, to);
+            }
+          }
+        }
+      }
+      window.request_redraw();
+    }
     Event::WindowEvent {
       event: WindowEvent::KeyboardInput { event, .. },
       ..
     } => {
+      if let EventResult::Redraw = maybe_navigate(&mut game.tree, &event) {
+        window.request_redraw();
+        return Ok(());
+      }
       let result = maybe_move_camera(&mut game.camera, event);
       if matches!(result, EventResult::Ignored) {
         return Ok(());
@@ -261,6 +430,7 @@ fn process_event(
 
       let grid_input = game.camera.grid_input(80.0);
       game.cube.update_camera(&game.iad.queue, game.camera.view);
+      game.overlay.update_camera(&game.iad.queue, game.camera.view);
       game.debug_grid.write_uniform(&game.iad.queue, &grid_input);
 
       window.request_redraw();
@@ -275,6 +445,36 @@ enum EventResult {
   Redraw,
 }
 
+/// Arrow keys step through the game: left and right along the line, up
+/// and down between the alternatives of the shown move, home and end to
+/// either end of the line.
+fn maybe_navigate(
+  tree: &mut GameTree,
+  key: &KeyEvent,
+) -> EventResult {
+  use winit::keyboard::{KeyCode, PhysicalKey};
+
+  if matches!(key.state, winit::event::ElementState::Released) {
+    return EventResult::Ignored;
+  }
+  let PhysicalKey::Code(code) = key.physical_key else {
+    return EventResult::Ignored;
+  };
+  let moved = match code {
+    KeyCode::ArrowLeft => tree.back(),
+    KeyCode::ArrowRight => tree.forward(),
+    KeyCode::ArrowUp => tree.switch_variation(-1),
+    KeyCode::ArrowDown => tree.switch_variation(1),
+    KeyCode::Home => tree.to_start(),
+    KeyCode::End => tree.to_end(),
+    _ => false,
+  };
+  match moved {
+    true => EventResult::Redraw,
+    false => EventResult::Ignored,
+  }
+}
+
 fn maybe_move_camera(
   camera: &mut Camera,
   key: KeyEvent,
@@ -294,16 +494,16 @@ fn maybe_move_camera(
     }
   };
   match code {
-    KeyCode::ArrowUp => {
+    KeyCode::KeyW => {
       camera.flying.translation.y += camera.speed;
     }
-    KeyCode::ArrowDown => {
+    KeyCode::KeyS => {
       camera.flying.translation.y -= camera.speed;
     }
-    KeyCode::ArrowLeft => {
+    KeyCode::KeyA => {
       camera.flying.translation.x += camera.speed;
     }
-    KeyCode::ArrowRight => {
+    KeyCode::KeyD => {
       camera.flying.translation.x -= camera.speed;
     }
     KeyCode::Equal => {
@@ -323,6 +523,38 @@ fn redraw(
   game: &mut Game,
   frame: &mut Frame,
 ) {
+  let annotations = &game.tree.node(game.tree.current()).annotations;
+  let mut arrows = best_move_arrows(&game.analysis);
+  arrows.extend(annotations.arrows.iter().map(|mark| Arrow {
+    from: mark.from,
+    to: mark.to,
+    color: mark_rgba(mark.color),
+  }));
+  if let Some((from, to)) = game.drawing {
+    let color = mark_rgba(mark_color(game.modifiers));
+    arrows.push(Arrow { from, to, color });
+  }
+  let circles: Vec<Circle> = annotations
+    .highlights
+    .iter()
+    .map(|mark| Circle { square: mark.square, color: mark_rgba(mark.color) })
+    .collect();
+  // pieces being dragged or premoved show as ghosts where they go
+  let mut ghosts = game.server.ghosts();
+  ghosts.extend(game.net.ghosts());
+  if let Some((from, to)) = game.dragging.filter(|(from, to)| from != to) {
+    ghosts.extend(game.tree.position().piece_at(from).map(|piece| (to, piece)));
+  }
+  let ghosts: Vec<Ghost> = ghosts
+    .into_iter()
+    .map(|(square, piece)| Ghost {
+      square,
+      piece,
+      color: ghost_rgba(piece.color),
+    })
+    .collect();
+  game.overlay.set(&game.iad.queue, &arrows, &circles, &ghosts);
+
   let iad = &game.iad;
   let view = &mut frame.view;
   let encoder = &mut frame.encoder;
@@ -363,6 +595,7 @@ fn redraw(
   // TODO: explain why
   game.cube.render(&mut rpass);
   game.debug_grid.render(&mut rpass);
+  game.overlay.render(&mut rpass);
 
   // next thing:
   // set camera parameters
@@ -386,6 +619,11 @@ fn redraw_ui(
     size_in_pixels: [size.width, size.height],
     pixels_per_point,
   };
+  game.analysis.poll();
+  if game.analysis.is_searching() {
+    // keep repainting while the engine reports progress
+    window.request_redraw();
+  }
   let egui_lambda = |cx: &egui::Context| {
     egui::Window::new("Debug")
       .resizable(true)
@@ -400,6 +638,7 @@ fn redraw_ui(
 
           let grid_input = game.camera.grid_input(80.0);
           game.cube.update_camera(&game.iad.queue, game.camera.view);
+          game.overlay.update_camera(&game.iad.queue, game.camera.view);
           game.debug_grid.write_uniform(&game.iad.queue, &grid_input);
         }
 
@@ -409,9 +648,88 @@ fn redraw_ui(
             .color_edit_button_rgba_unmultiplied(&mut game.background_color)
             .changed()
           {}
-        })
+        });
       });
 
+    eval_bar(cx, &game.analysis);
+    egui::Window::new("Moves").resizable(true).default_open(true).show(
+      cx,
+      |ui| {
+        game_tree(
+          ui,
+          &mut game.record,
+          &mut game.tree,
+          &mut game.move_input,
+          &mut game.pgn_text,
+        )
+      },
+    );
+    egui::Window::new("Game").default_open(false).show(cx, |ui| {
+      game_panel(ui, &mut game.game, &mut game.record, &mut game.tree)
+    });
+    egui::Window::new("Analysis").resizable(true).default_open(true).show(
+      cx,
+      |ui| {
+        analysis_panel(
+          ui,
+          &mut game.analysis,
+          &game.tree,
+          &mut game.engine_path,
+          &mut game.tablebase_path,
+        )
+      },
+    );
+
+    egui::Window::new("Review").default_open(false).show(cx, |ui| {
+      review_panel(ui, &mut game.reviewer, &mut game.tree, &game.engine_path)
+    });
+
+    egui::Window::new("Puzzles")
+      .default_open(false)
+      .show(cx, |ui| puzzle_panel(ui, &mut game.puzzles, &mut game.tree));
+
+    egui::Window::new("Network")
+      .default_open(false)
+      .show(cx, |ui| net_panel(ui, &mut game.net, &mut game.tree));
+
+    egui::Window::new("Server")
+      .default_open(false)
+      .show(cx, |ui| server_panel(ui, &mut game.server, &mut game.tree));
+
+    egui::Window::new("Chat").default_open(false).show(cx, |ui| {
+      chat_panel(
+        ui,
+        &mut game.chat,
+        &mut game.net,
+        &mut game.server,
+        &mut game.record,
+      )
+    });
+
+    egui::Window::new("Ratings").default_open(false).show(cx, |ui| {
+      ratings_panel(ui, &mut game.ratings, &mut game.game, &mut game.server)
+    });
+
+    egui::Window::new("Broadcast").default_open(false).show(cx, |ui| {
+      broadcast_panel(ui, &mut game.broadcast, &mut game.record, &mut game.tree)
+    });
+
+    egui::Window::new("Correspondence").default_open(false).show(cx, |ui| {
+      correspondence_panel(ui, &mut game.correspondence, &mut game.tree)
+    });
+
+    egui::Window::new("Repertoire").default_open(false).show(cx, |ui| {
+      repertoire_panel(ui, &mut game.repertoire, &mut game.tree)
+    });
+
+    egui::Window::new("Explorer").default_open(false).show(cx, |ui| {
+      explorer_panel(ui, &mut game.explorer, &mut game.record, &mut game.tree)
+    });
+
+    egui::Window::new("Database").default_open(false).show(cx, |ui| {
+      database_panel(ui, &mut game.database, &mut game.record, &mut game.tree)
+    });
+
     // let menu_frame = egui::Frame::none()
     //     .fill(egui::Color32::DARK_GRAY)
     //     .inner_margin(egui::Margin::same(10.));
@@ -441,6 +759,100 @@ fn redraw_ui(
     screen_descriptor,
     egui_lambda,
   );
+
+  // navigating the game restarts the analysis on the shown position
+  if let Err(err) = game.analysis.follow(game.tree.start(), &game.tree.played())
+  {
+    log::error!("failed to restart analysis: {err:#}");
+  }
+}
+
+/// Arrows for the first moves of the engine lines, the best one in green
+/// and the others fading with their rank.
+fn best_move_arrows(analysis: &Analysis) -> Vec<Arrow> {
+  analysis
+    .best_moves()
+    .into_iter()
+    .enumerate()
+    .map(|(rank, mv)| {
+      let color = if rank == 0 {
+        [0.1, 0.7, 0.2, 0.8]
+      } else {
+        [0.2, 0.4, 0.9, 0.6 / rank as f32]
+      };
+      Arrow { from: mv.from, to: mv.to, color }
+    })
+    .collect()
+}
+
+fn cursor_square(
+  game: &Game,
+  window: &Window,
+) -> Option<Square> {
+  let cursor = game.cursor?;
+  let size = window.inner_size();
+  let size = Vec2::new(size.width as f32, size.height as f32);
+  let ndc =
+    Vec2::new(cursor.x / size.x * 2.0 - 1.0, 1.0 - cursor.y / size.y * 2.0);
+  overlay::square_at(game.camera.view, ndc)
+}
+
+fn ghost_rgba(color: Color) -> [f32; 4] {
+  color.fold([0.95, 0.93, 0.88, 0.45], [0.15, 0.13, 0.12, 0.45])
+}
+
+/// A piece dragged on the board goes to the online game shown, as a move
+/// or a premove, or else is played on the board.
+fn drag_piece(
+  game: &mut Game,
+  from: Square,
+  to: Square,
+) {
+  if from == to || game.server.drag(from, to) || game.net.drag(from, to) {
+    return;
+  }
+  let Some(mv) = premove::dragged(game.tree.position(), from, to) else {
+    return;
+  };
+  if let Err(err) = game.tree.play(mv) {
+    log::warn!("can't play {mv}: {err}");
+  }
+}
+
+/// Colors as on analysis boards online: green, red with shift, blue with
+/// alt and yellow with both.
+fn mark_color(modifiers: ModifiersState) -> MarkColor {
+  match (modifiers.shift_key(), modifiers.alt_key()) {
+    (false, false) => MarkColor::Green,
+    (true, false) => MarkColor::Red,
+    (false, true) => MarkColor::Blue,
+    (true, true) => MarkColor::Yellow,
+  }
+}
+
+fn mark_rgba(color: MarkColor) -> [f32; 4] {
+  match color {
+    MarkColor::Green => [0.08, 0.47, 0.11, 0.8],
+    MarkColor::Red => [0.53, 0.13, 0.13, 0.8],
+    MarkColor::Yellow => [0.9, 0.56, 0.0, 0.8],
+    MarkColor::Blue => [0.0, 0.19, 0.53, 0.8],
+  }
+}
+
+/// Marks the shown position: a circle when the drag ended where it
+/// started, an arrow otherwise. Drawing a mark again removes it.
+fn draw_mark(
+  game: &mut Game,
+  from: Square,
+  to: Square,
+) {
+  let color = mark_color(game.modifiers);
+  let annotations = game.tree.annotations_mut(game.tree.current());
+  if from == to {
+    annotations.toggle_highlight(SquareMark { square: from, color });
+  } else {
+    annotations.toggle_arrow(ArrowMark { from, to, color });
+  }
 }
 
 fn ced(label: Option<&'static str>) -> wgpu::CommandEncoderDescriptor {
@@ -0,0 +1,605 @@
+//! A client for the Lichess board and bot API, or any server speaking it:
+//! streams of newline-delimited JSON for the account's events and for
+//! each game, and plain requests to accept challenges, move and chat.
+//!
+//! HTTP is spoken here over a bare TCP connection per request, which keeps
+//! it testable against a local stand-in. There is no TLS: to reach the
+//! real site, point the base URL at a TLS proxy such as `stunnel`.
+
+use std::{
+  io::{BufRead, BufReader, Read, Write},
+  marker::PhantomData,
+  net::TcpStream,
+  time::Duration,
+};
+
+use anyhow::{anyhow, bail, ensure};
+use serde_json::Value;
+
+use crate::{
+  board::{Color, Move, Position},
+  tournament::TimeControl,
+};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Which of the two APIs to play through; bot accounts use the second.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Api {
+  Board,
+  Bot,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Account {
+  pub id: String,
+  pub username: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Challenge {
+  pub id: String,
+  pub challenger: String,
+  pub variant: String,
+  pub rated: bool,
+  /// `None` for unlimited and correspondence games.
+  pub clock: Option<TimeControl>,
+  /// Side the challenger asked for, `None` for random.
+  pub color: Option<Color>,
+}
+
+/// What happens to the account.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+  Challenge(Challenge),
+  ChallengeCanceled { id: String },
+  ChallengeDeclined { id: String },
+  GameStart { id: String },
+  GameFinish { id: String },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameState {
+  /// All moves from the start, in UCI.
+  pub moves: Vec<Move>,
+  /// Time left for white and black.
+  pub clocks: [Duration; 2],
+  pub increments: [Duration; 2],
+  /// `started` while the game goes on, else how it ended: `mate`,
+  /// `resign`, `outoftime`, `draw`...
+  pub status: String,
+  pub winner: Option<Color>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameFull {
+  pub id: String,
+  /// Names of white and black.
+  pub players: [String; 2],
+  pub start: Position,
+  pub clock: Option<TimeControl>,
+  pub state: GameState,
+}
+
+impl GameFull {
+  /// Side of the player called `name`.
+  pub fn color_of(
+    &self,
+    name: &str,
+  ) -> Option<Color> {
+    [Color::White, Color::Black]
+      .into_iter()
+      .find(|color| self.players[color.index()].eq_ignore_ascii_case(name))
+  }
+}
+
+impl GameState {
+  fn from_json(value: &Value) -> anyhow::Result<GameState> {
+    let moves = str_field(value, "moves")?
+      .split_whitespace()
+      .map(Move::from_uci)
+      .collect::<anyhow::Result<_>>()?;
+    Ok(GameState {
+      moves,
+      clocks: [millis(&value["wtime"]), millis(&value["btime"])],
+      increments: [millis(&value["winc"]), millis(&value["binc"])],
+      status: str_field(value, "status")?.into(),
+      winner: color_field(&value["winner"]),
+    })
+  }
+
+  pub fn is_over(&self) -> bool {
+    self.status != "started" && self.status != "created"
+  }
+
+  /// The position after the moves, from `start`.
+  pub fn position(
+    &self,
+    start: &Position,
+  ) -> anyhow::Result<Position> {
+    let mut position = start.clone();
+    for &mv in &self.moves {
+      ensure!(position.is_legal(mv), "illegal move {mv} in the game");
+      position.play(mv);
+    }
+    Ok(position)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Room {
+  Player,
+  Spectator,
+}
+
+impl Room {
+  fn name(self) -> &'static str {
+    match self {
+      Room::Player => "player",
+      Room::Spectator => "spectator",
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChatLine {
+  pub username: String,
+  pub text: String,
+  pub room: Room,
+}
+
+/// What happens in a game.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GameEvent {
+  /// First in the stream: the players and the game so far.
+  Full(GameFull),
+  /// After each move, and when the game ends.
+  State(GameState),
+  Chat(ChatLine),
+  /// The opponent left or came back; the win can be claimed after the
+  /// seconds given.
+  OpponentGone {
+    gone: bool,
+    claim_win_in: Option<Duration>,
+  },
+}
+
+fn str_field<'a>(
+  value: &'a Value,
+  key: &str,
+) -> anyhow::Result<&'a str> {
+  value[key].as_str().ok_or_else(|| anyhow!("no {key:?} in {value}"))
+}
+
+fn millis(value: &Value) -> Duration {
+  Duration::from_millis(value.as_u64().unwrap_or(0))
+}
+
+fn color_field(value: &Value) -> Option<Color> {
+  match value.as_str()? {
+    "white" => Some(Color::White),
+    "black" => Some(Color::Black),
+    _ => None,
+  }
+}
+
+fn player_name(value: &Value) -> String {
+  match (value["name"].as_str(), value["id"].as_str(), &value["aiLevel"]) {
+    (Some(name), _, _) | (None, Some(name), _) => name.into(),
+    (None, None, Value::Number(level)) => format!("AI level {level}"),
+    _ => "?".into(),
+  }
+}
+
+impl Event {
+  /// `None` for events of kinds not known here.
+  fn from_json(value: &Value) -> anyhow::Result<Option<Event>> {
+    let id = |key: &str| -> anyhow::Result<String> {
+      Ok(str_field(&value[key], "id")?.into())
+    };
+    Ok(Some(match str_field(value, "type")? {
+      "challenge" => {
+        let challenge = &value["challenge"];
+        let control = &challenge["timeControl"];
+        let clock = match control["type"].as_str() {
+          Some("clock") => Some(TimeControl::Clock {
+            base: Duration::from_secs(control["limit"].as_u64().unwrap_or(0)),
+            increment: Duration::from_secs(
+              control["increment"].as_u64().unwrap_or(0),
+            ),
+          }),
+          _ => None,
+        };
+        Event::Challenge(Challenge {
+          id: str_field(challenge, "id")?.into(),
+          challenger: player_name(&challenge["challenger"]),
+          variant: challenge["variant"]["key"]
+            .as_str()
+            .unwrap_or("standard")
+            .into(),
+          rated: challenge["rated"].as_bool().unwrap_or(false),
+          clock,
+          color: color_field(&challenge["color"]),
+        })
+      }
+      "challengeCanceled" => Event::ChallengeCanceled { id: id("challenge")? },
+      "challengeDeclined" => Event::ChallengeDeclined { id: id("challenge")? },
+      "gameStart" => Event::GameStart { id: game_id(&value["game"])? },
+      "gameFinish" => Event::GameFinish { id: game_id(&value["game"])? },
+      _ => return Ok(None),
+    }))
+  }
+}
+
+/// Games carry their id as `gameId`, older servers as `id`.
+fn game_id(game: &Value) -> anyhow::Result<String> {
+  str_field(game, "gameId").or_else(|_| str_field(game, "id")).map(Into::into)
+}
+
+impl GameEvent {
+  fn from_json(value: &Value) -> anyhow::Result<Option<GameEvent>> {
+    Ok(Some(match str_field(value, "type")? {
+      "gameFull" => {
+        let start = match value["initialFen"].as_str() {
+          None | Some("startpos") => Position::startpos(),
+          Some(fen) => Position::from_fen(fen)?,
+        };
+        let clock = &value["clock"];
+        let clock = clock.is_object().then(|| TimeControl::Clock {
+          base: millis(&clock["initial"]),
+          increment: millis(&clock["increment"]),
+        });
+        GameEvent::Full(GameFull {
+          id: str_field(value, "id")?.into(),
+          players: [player_name(&value["white"]), player_name(&value["black"])],
+          start,
+          clock,
+          state: GameState::from_json(&value["state"])?,
+        })
+      }
+      "gameState" => GameEvent::State(GameState::from_json(value)?),
+      "chatLine" => GameEvent::Chat(ChatLine {
+        username: str_field(value, "username")?.into(),
+        text: str_field(value, "text")?.into(),
+        room: match str_field(value, "room")? {
+          "spectator" => Room::Spectator,
+          _ => Room::Player,
+        },
+      }),
+      "opponentGone" => GameEvent::OpponentGone {
+        gone: value["gone"].as_bool().unwrap_or(false),
+        claim_win_in: value["claimWinInSeconds"]
+          .as_u64()
+          .map(Duration::from_secs),
+      },
+      _ => return Ok(None),
+    }))
+  }
+}
+
+/// A response body, as sent: in chunks, by length or until closed.
+enum Body {
+  Chunked { stream: BufReader<TcpStream>, left: usize, done: bool },
+  Length { stream: BufReader<TcpStream>, left: usize },
+  Close(BufReader<TcpStream>),
+}
+
+impl Read for Body {
+  fn read(
+    &mut self,
+    buf: &mut [u8],
+  ) -> std::io::Result<usize> {
+    match self {
+      Body::Chunked { stream, left, done } => {
+        if *done {
+          return Ok(0);
+        }
+        if *left == 0 {
+          let mut size = String::new();
+          stream.read_line(&mut size)?;
+          let size = size.trim();
+          // extensions after a semicolon are allowed and ignored
+          let size = size.split(';').next().unwrap_or("");
+          *left = usize::from_str_radix(size, 16).map_err(|_| {
+            std::io::Error::new(
+              std::io::ErrorKind::InvalidData,
+              format!("invalid chunk size {size:?}"),
+            )
+          })?;
+          if *left == 0 {
+            *done = true;
+            return Ok(0);
+          }
+        }
+        let len = buf.len().min(*left);
+        let read = stream.read(&mut buf[..len])?;
+        if read == 0 {
+          return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        *left -= read;
+        if *left == 0 {
+          let mut crlf = String::new();
+          stream.read_line(&mut crlf)?;
+        }
+        Ok(read)
+      }
+      Body::Length { stream, left } => {
+        let len = buf.len().min(*left);
+        let read = stream.read(&mut buf[..len])?;
+        *left -= read;
+        Ok(read)
+      }
+      Body::Close(stream) => stream.read(buf),
+    }
+  }
+}
+
+/// A stream of events, one JSON object a line. Blank lines keep the
+/// connection alive and are skipped, as are events of unknown kinds.
+pub struct Stream<T> {
+  reader: BufReader<Body>,
+  kind: PhantomData<T>,
+}
+
+impl<T> Stream<T> {
+  fn next_with(
+    &mut self,
+    parse: fn(&Value) -> anyhow::Result<Option<T>>,
+  ) -> Option<anyhow::Result<T>> {
+    let mut line = String::new();
+    loop {
+      line.clear();
+      match self.reader.read_line(&mut line) {
+        Ok(0) => return None,
+        Ok(_) if line.trim().is_empty() => continue,
+        Ok(_) => {}
+        Err(err) => return Some(Err(err.into())),
+      }
+      let parsed = serde_json::from_str(&line)
+        .map_err(anyhow::Error::from)
+        .and_then(|value| parse(&value));
+      match parsed {
+        Ok(Some(event)) => return Some(Ok(event)),
+        Ok(None) => continue,
+        Err(err) => return Some(Err(err)),
+      }
+    }
+  }
+}
+
+impl Iterator for Stream<Event> {
+  type Item = anyhow::Result<Event>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.next_with(Event::from_json)
+  }
+}
+
+impl Iterator for Stream<GameEvent> {
+  type Item = anyhow::Result<GameEvent>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.next_with(GameEvent::from_json)
+  }
+}
+
+/// Escapes a form value.
+fn form_encode(s: &str) -> String {
+  let mut encoded = String::new();
+  for byte in s.bytes() {
+    match byte {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+        encoded.push(byte as char)
+      }
+      b' ' => encoded.push('+'),
+      byte => encoded += &format!("%{byte:02X}"),
+    }
+  }
+  encoded
+}
+
+pub struct Client {
+  /// `host:port` to connect to.
+  host: String,
+  /// Path the API lives under, without the trailing slash.
+  prefix: String,
+  token: String,
+  pub api: Api,
+}
+
+impl Client {
+  /// A client of the server at `base_url`, like `http://localhost:8080`,
+  /// acting for the account `token` belongs to.
+  pub fn new(
+    base_url: &str,
+    token: &str,
+    api: Api,
+  ) -> anyhow::Result<Client> {
+    let Some(rest) = base_url.strip_prefix("http://") else {
+      if base_url.starts_with("https://") {
+        bail!("no TLS here, reach {base_url} through a local TLS proxy");
+      }
+      bail!("{base_url:?} is not an http:// URL");
+    };
+    let (host, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+    ensure!(!host.is_empty(), "no host in {base_url:?}");
+    let host =
+      if host.contains(':') { host.to_string() } else { format!("{host}:80") };
+    let prefix = match prefix.trim_end_matches('/') {
+      "" => String::new(),
+      prefix => format!("/{prefix}"),
+    };
+    Ok(Client { host, prefix, token: token.into(), api })
+  }
+
+  fn request(
+    &self,
+    method: &str,
+    path: &str,
+    form: Option<&[(&str, &str)]>,
+  ) -> anyhow::Result<Body> {
+    let addr = std::net::ToSocketAddrs::to_socket_addrs(&self.host)?
+      .next()
+      .ok_or_else(|| anyhow!("no address for {}", self.host))?;
+    let mut stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+    let body = form.map_or(String::new(), |form| {
+      let fields: Vec<String> = form
+        .iter()
+        .map(|(key, value)| format!("{key}={}", form_encode(value)))
+        .collect();
+      fields.join("&")
+    });
+    let mut head = format!(
+      "{method} {}{path} HTTP/1.1\r\nHost: {}\r\n\
+       Authorization: Bearer {}\r\nAccept: application/x-ndjson\r\n\
+       User-Agent: chess\r\nConnection: close\r\n",
+      self.prefix, self.host, self.token
+    );
+    if form.is_some() {
+      head += "Content-Type: application/x-www-form-urlencoded\r\n";
+    }
+    if method != "GET" {
+      head += &format!("Content-Length: {}\r\n", body.len());
+    }
+    head += "\r\n";
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body.as_bytes())?;
+    stream.flush()?;
+
+    let mut reader = BufReader::new(stream);
+    let mut status = String::new();
+    reader.read_line(&mut status)?;
+    let code: u16 = status
+      .split(' ')
+      .nth(1)
+      .and_then(|code| code.parse().ok())
+      .ok_or_else(|| anyhow!("invalid response {status:?}"))?;
+    let (mut chunked, mut length) = (false, None);
+    loop {
+      let mut line = String::new();
+      ensure!(reader.read_line(&mut line)? > 0, "response cut short");
+      let line = line.trim();
+      if line.is_empty() {
+        break;
+      }
+      let Some((name, value)) = line.split_once(':') else {
+        continue;
+      };
+      match name.trim().to_ascii_lowercase().as_str() {
+        "transfer-encoding" => chunked = value.contains("chunked"),
+        "content-length" => length = value.trim().parse().ok(),
+        _ => {}
+      }
+    }
+    let mut body = match (chunked, length) {
+      (true, _) => Body::Chunked { stream: reader, left: 0, done: false },
+      (false, Some(left)) => Body::Length { stream: reader, left },
+      (false, None) => Body::Close(reader),
+    };
+    if !(200..300).contains(&code) {
+      let mut text = String::new();
+      body.read_to_string(&mut text)?;
+      let error = serde_json::from_str::<Value>(&text)
+        .ok()
+        .and_then(|value| value["error"].as_str().map(String::from))
+        .unwrap_or(text);
+      bail!("{method} {path}: {code} {}", error.trim());
+    }
+    Ok(body)
+  }
+
+  /// Sends a request whose answer does not matter.
+  fn post(
+    &self,
+    path: &str,
+    form: Option<&[(&str, &str)]>,
+  ) -> anyhow::Result<()> {
+    let mut body = self.request("POST", path, form)?;
+    std::io::copy(&mut body, &mut std::io::sink())?;
+    Ok(())
+  }
+
+  fn stream<T>(
+    &self,
+    path: &str,
+  ) -> anyhow::Result<Stream<T>> {
+    let body = self.request("GET", path, None)?;
+    Ok(Stream { reader: BufReader::new(body), kind: PhantomData })
+  }
+
+  /// Where the games of the chosen API are.
+  fn games(&self) -> &'static str {
+    match self.api {
+      Api::Board => "/api/board/game",
+      Api::Bot => "/api/bot/game",
+    }
+  }
+
+  /// The account the token belongs to.
+  pub fn account(&self) -> anyhow::Result<Account> {
+    let mut text = String::new();
+    self.request("GET", "/api/account", None)?.read_to_string(&mut text)?;
+    let value: Value = serde_json::from_str(&text)?;
+    Ok(Account {
+      id: str_field(&value, "id")?.into(),
+      username: str_field(&value, "username")?.into(),
+    })
+  }
+
+  /// Challenges and games of the account as they come, for as long as
+  /// the connection lasts.
+  pub fn events(&self) -> anyhow::Result<Stream<Event>> {
+    self.stream("/api/stream/event")
+  }
+
+  pub fn accept_challenge(
+    &self,
+    id: &str,
+  ) -> anyhow::Result<()> {
+    self.post(&format!("/api/challenge/{id}/accept"), None)
+  }
+
+  pub fn decline_challenge(
+    &self,
+    id: &str,
+    reason: &str,
+  ) -> anyhow::Result<()> {
+    self.post(
+      &format!("/api/challenge/{id}/decline"),
+      Some(&[("reason", reason)]),
+    )
+  }
+
+  /// The whole of a game first, then what happens in it until it ends.
+  pub fn game(
+    &self,
+    id: &str,
+  ) -> anyhow::Result<Stream<GameEvent>> {
+    self.stream(&format!("{}/stream/{id}", self.games()))
+  }
+
+  pub fn play(
+    &self,
+    game: &str,
+    mv: Move,
+  ) -> anyhow::Result<()> {
+    self.post(&format!("{}/{game}/move/{mv}", self.games()), None)
+  }
+
+  pub fn chat(
+    &self,
+    game: &str,
+    room: Room,
+    text: &str,
+  ) -> anyhow::Result<()> {
+    let form = [("room", room.name()), ("text", text)];
+    self.post(&format!("{}/{game}/chat", self.games()), Some(&form))
+  }
+
+  pub fn resign(
+    &self,
+    game: &str,
+  ) -> anyhow::Result<()> {
+    self.post(&format!("{}/{game}/resign", self.games()), None)
+  }
+}
@@ -0,0 +1,615 @@
+//! Two-player games over TCP: one side hosts on a port, the other joins
+//! by address. The host owns the game and sends all of it to whoever
+//! joins, so a player who lost the connection just joins again. Each side
+//! checks the other's moves with its own rules.
+//!
+//! Controls go through the host too: it carries out its own at once and
+//! the joining side's as they come, sending each back in that order, then
+//! the result or the game after a takeback. A player whose opponent left
+//! may claim the game alone.
+//!
+//! The players may chat; each side keeps the lines it saw.
+//!
+//! Sockets are read on their own threads; the window polls for what came
+//! in once a frame.
+
+use std::{
+  io::{BufReader, ErrorKind},
+  net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+  sync::mpsc::{self, RecvTimeoutError, TryRecvError},
+  thread,
+  time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, ensure};
+
+use crate::{
+  board::{Color, Move, Position},
+  chat,
+  control::{self, Controls, Effect},
+  pgn::Outcome,
+  protocol::{
+    read_message, write_message, ChatLine, Control, Message, Room, VERSION,
+  },
+};
+
+/// How long joining waits for the host to send the game.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+  /// The other player said hello.
+  Connected {
+    name: String,
+  },
+  /// The other player moved.
+  Moved(Move),
+  /// A control as the host carried it out, ours included.
+  Control(Color, Control),
+  /// The game ended by a control.
+  Ended(Outcome, String),
+  /// The host sent the whole game again.
+  Synced,
+  /// The other side refused something, or sent something refused.
+  Error(String),
+  Disconnected,
+}
+
+type Incoming = mpsc::Receiver<anyhow::Result<Option<Message>>>;
+
+/// Reads messages on a thread until the connection closes.
+fn spawn_reader(stream: &TcpStream) -> anyhow::Result<Incoming> {
+  let mut reader = BufReader::new(stream.try_clone()?);
+  let (tx, rx) = mpsc::channel();
+  thread::spawn(move || loop {
+    let message = read_message(&mut reader);
+    let done = !matches!(message, Ok(Some(_)));
+    if tx.send(message).is_err() || done {
+      break;
+    }
+  });
+  Ok(rx)
+}
+
+struct Peer {
+  writer: TcpStream,
+  incoming: Incoming,
+  name: Option<String>,
+}
+
+impl Peer {
+  fn new(stream: TcpStream) -> anyhow::Result<Peer> {
+    let incoming = spawn_reader(&stream)?;
+    Ok(Peer { writer: stream, incoming, name: None })
+  }
+
+  fn send(
+    &mut self,
+    message: &Message,
+  ) -> anyhow::Result<()> {
+    write_message(&mut self.writer, message)
+  }
+}
+
+/// The reader thread holds a clone of the socket, shutting it down is
+/// what closes the connection.
+impl Drop for Peer {
+  fn drop(&mut self) {
+    let _ = self.writer.shutdown(Shutdown::Both);
+  }
+}
+
+enum Role {
+  Host(TcpListener),
+  Client(SocketAddr),
+}
+
+pub struct NetGame {
+  /// Name of the local player.
+  pub name: String,
+  /// Side of the local player.
+  pub color: Color,
+  start: Position,
+  moves: Vec<Move>,
+  position: Position,
+  role: Role,
+  peer: Option<Peer>,
+  /// Whether the other player was ever here.
+  met: bool,
+  controls: Controls,
+  result: Option<(Outcome, String)>,
+  chat: Vec<ChatLine>,
+}
+
+impl NetGame {
+  /// Waits for a player to join on `addr`, playing `color` from `start`.
+  pub fn host(
+    addr: impl ToSocketAddrs,
+    name: &str,
+    color: Color,
+    start: Position,
+  ) -> anyhow::Result<NetGame> {
+    let listener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    Ok(NetGame {
+      name: name.into(),
+      color,
+      position: start.clone(),
+      start,
+      moves: Vec::new(),
+      role: Role::Host(listener),
+      peer: None,
+      met: false,
+      controls: Controls::default(),
+      result: None,
+      chat: Vec::new(),
+    })
+  }
+
+  /// Joins the game hosted at `addr`, waiting for the host to send it.
+  pub fn join(
+    addr: impl ToSocketAddrs,
+    name: &str,
+  ) -> anyhow::Result<NetGame> {
+    let addr = addr
+      .to_socket_addrs()?
+      .next()
+      .ok_or_else(|| anyhow!("no address to join"))?;
+    let mut game = NetGame {
+      name: name.into(),
+      color: Color::White,
+      start: Position::startpos(),
+      moves: Vec::new(),
+      position: Position::startpos(),
+      role: Role::Client(addr),
+      peer: None,
+      met: false,
+      controls: Controls::default(),
+      result: None,
+      chat: Vec::new(),
+    };
+    game.connect()?;
+    Ok(game)
+  }
+
+  fn connect(&mut self) -> anyhow::Result<()> {
+    let Role::Client(addr) = self.role else {
+      bail!("only the joining side connects");
+    };
+    let stream = TcpStream::connect_timeout(&addr, HANDSHAKE_TIMEOUT)?;
+    stream.set_nodelay(true)?;
+    let mut peer = Peer::new(stream)?;
+    peer.send(&Message::Hello { version: VERSION, name: self.name.clone() })?;
+    let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+    loop {
+      let timeout = deadline.saturating_duration_since(Instant::now());
+      let message = match peer.incoming.recv_timeout(timeout) {
+        Ok(message) => message?,
+        Err(RecvTimeoutError::Timeout) => bail!("the host did not answer"),
+        Err(RecvTimeoutError::Disconnected) => None,
+      };
+      match message {
+        Some(Message::Hello { version, name }) => {
+          if version != VERSION {
+            bail!("the host speaks version {version}, we speak {VERSION}");
+          }
+          peer.name = Some(name);
+        }
+        Some(Message::Sync { color, start, moves }) => {
+          self.adopt(color, start, moves)?;
+          self.peer = Some(peer);
+          self.met = true;
+          return Ok(());
+        }
+        Some(Message::Error { message }) => {
+          bail!("the host refused: {message}")
+        }
+        Some(message) => bail!("unexpected {message} before the game"),
+        None => bail!("the host closed the connection"),
+      }
+    }
+  }
+
+  /// Joins again after losing the connection, taking the game as the
+  /// host has it.
+  pub fn reconnect(&mut self) -> anyhow::Result<()> {
+    self.peer = None;
+    self.connect()
+  }
+
+  fn adopt(
+    &mut self,
+    color: Color,
+    start: Position,
+    moves: Vec<Move>,
+  ) -> anyhow::Result<()> {
+    let mut position = start.clone();
+    for &mv in &moves {
+      if !position.is_legal(mv) {
+        bail!("illegal move {mv} in the game sent");
+      }
+      position.play(mv);
+    }
+    (self.color, self.start, self.moves, self.position) =
+      (color, start, moves, position);
+    self.controls.clear();
+    let (_, history) = control::replay(&self.start, &self.moves);
+    self.result = control::game_over(&self.position, &history)
+      .map(|(outcome, reason)| (outcome, reason.into()));
+    Ok(())
+  }
+
+  /// Where a hosted game listens.
+  pub fn local_addr(&self) -> Option<SocketAddr> {
+    match &self.role {
+      Role::Host(listener) => listener.local_addr().ok(),
+      Role::Client(_) => None,
+    }
+  }
+
+  pub fn is_host(&self) -> bool {
+    matches!(self.role, Role::Host(_))
+  }
+
+  /// Whether the other player is there and said hello.
+  pub fn is_connected(&self) -> bool {
+    self.peer.as_ref().is_some_and(|peer| peer.name.is_some())
+  }
+
+  pub fn opponent(&self) -> Option<&str> {
+    self.peer.as_ref()?.name.as_deref()
+  }
+
+  pub fn start(&self) -> &Position {
+    &self.start
+  }
+
+  pub fn moves(&self) -> &[Move] {
+    &self.moves
+  }
+
+  pub fn position(&self) -> &Position {
+    &self.position
+  }
+
+  pub fn is_our_turn(&self) -> bool {
+    self.result.is_none() && self.position.side_to_move() == self.color
+  }
+
+  /// How the game ended, when it did.
+  pub fn result(&self) -> Option<&(Outcome, String)> {
+    self.result.as_ref()
+  }
+
+  /// Whether the other player was here and left.
+  fn away(&self) -> bool {
+    self.met && !self.is_connected()
+  }
+
+  /// The draw offer or takeback standing, and who made it.
+  pub fn offer(&self) -> Option<(Control, Color)> {
+    self.controls.offer(self.moves.len())
+  }
+
+  /// Whether the local player may do `control`.
+  pub fn allows(
+    &self,
+    control: Control,
+  ) -> bool {
+    self.result.is_none()
+      && self.controls.allows(
+        self.color,
+        control,
+        &self.start,
+        &self.moves,
+        self.away(),
+      )
+  }
+
+  /// Does a control of the local player. The host carries it out at
+  /// once; the joining side sends it and waits for the host to send it
+  /// back, unless the host is gone.
+  pub fn control(
+    &mut self,
+    control: Control,
+  ) -> anyhow::Result<()> {
+    ensure!(self.result.is_none(), "the game is over");
+    let ply = self.moves.len();
+    let message = Message::Control { ply, color: self.color, control };
+    let away = self.away();
+    if self.is_host() || !self.is_connected() {
+      let effect = self.controls.apply(
+        self.color,
+        control,
+        &self.start,
+        &self.moves,
+        away,
+      )?;
+      self.send(&message);
+      self.carry_out(effect);
+    } else {
+      let mut controls = self.controls.clone();
+      controls.apply(self.color, control, &self.start, &self.moves, away)?;
+      self.send(&message);
+    }
+    Ok(())
+  }
+
+  /// Carries out what a control did, telling the joining side.
+  fn carry_out(
+    &mut self,
+    effect: Effect,
+  ) {
+    match effect {
+      Effect::Offered | Effect::Declined => {}
+      Effect::TakeBack(count) => {
+        self.moves.truncate(self.moves.len() - count);
+        self.position = control::replay(&self.start, &self.moves).0;
+        self.sync();
+      }
+      Effect::End(outcome, reason) => {
+        self.result = Some((outcome, reason.into()));
+        self.send(&Message::End { outcome, reason: reason.into() });
+      }
+    }
+  }
+
+  /// Plays a move of either side, ending the game by the rules after it.
+  fn push(
+    &mut self,
+    mv: Move,
+  ) {
+    self.moves.push(mv);
+    self.position.play(mv);
+    let (_, history) = control::replay(&self.start, &self.moves);
+    self.result = control::game_over(&self.position, &history)
+      .map(|(outcome, reason)| (outcome, reason.into()));
+  }
+
+  /// Plays a move of the local player and sends it. Without a connection
+  /// the move is only played here; the other side gets it on joining.
+  pub fn play(
+    &mut self,
+    mv: Move,
+  ) -> anyhow::Result<()> {
+    if !self.is_our_turn() {
+      bail!("it is not our turn");
+    }
+    if !self.position.is_legal(mv) {
+      bail!("illegal move {mv}");
+    }
+    let ply = self.moves.len();
+    self.push(mv);
+    self.send(&Message::Move { ply, mv });
+    Ok(())
+  }
+
+  /// The chat so far, ours and the other player's.
+  pub fn chat(&self) -> &[ChatLine] {
+    &self.chat
+  }
+
+  /// Says something to the other player.
+  pub fn say(
+    &mut self,
+    text: &str,
+  ) -> anyhow::Result<()> {
+    ensure!(self.is_connected(), "nobody is here to hear it");
+    let text = chat::clean(text);
+    ensure!(!text.is_empty(), "nothing to say");
+    let ply = self.moves.len();
+    self.send(&Message::Chat { ply, text: text.clone() });
+    let from = self.name.clone();
+    self.chat.push(ChatLine { ply, room: Room::Players, from, text });
+    Ok(())
+  }
+
+  /// Sends if connected, dropping the connection if that fails.
+  fn send(
+    &mut self,
+    message: &Message,
+  ) {
+    let Some(peer) = &mut self.peer else {
+      return;
+    };
+    if let Err(err) = peer.send(message) {
+      log::warn!("lost the connection: {err:#}");
+      self.peer = None;
+    }
+  }
+
+  /// Sends the game with the offer standing or how it ended.
+  fn sync(&mut self) {
+    let sync = Message::Sync {
+      color: !self.color,
+      start: self.start.clone(),
+      moves: self.moves.clone(),
+    };
+    self.send(&sync);
+    let ply = self.moves.len();
+    if let Some((control, color)) = self.offer() {
+      self.send(&Message::Control { ply, color, control });
+    }
+    if let Some((outcome, reason)) = self.result.clone() {
+      self.send(&Message::End { outcome, reason });
+    }
+  }
+
+  /// Sends the other side a `bye`, which they see as a disconnect, and
+  /// drops the connection. The game is kept, so it can go on later.
+  pub fn leave(&mut self) {
+    self.send(&Message::Bye);
+    self.peer = None;
+  }
+
+  /// Takes in a player joining and the messages that came in.
+  pub fn poll(&mut self) -> Vec<Event> {
+    let mut events = Vec::new();
+    if let Role::Host(listener) = &self.role {
+      match listener.accept() {
+        Ok((stream, addr)) => {
+          let accepted = stream.set_nonblocking(false).and_then(|()| {
+            stream.set_nodelay(true)?;
+            Ok(stream)
+          });
+          match accepted.map_err(anyhow::Error::from).and_then(Peer::new) {
+            Ok(mut peer) if self.peer.is_some() => {
+              let message = "the game has two players".to_string();
+              let _ = peer.send(&Message::Error { message });
+            }
+            Ok(peer) => {
+              log::info!("{addr} joined");
+              self.peer = Some(peer);
+              let hello =
+                Message::Hello { version: VERSION, name: self.name.clone() };
+              self.send(&hello);
+            }
+            Err(err) => log::warn!("can't take in {addr}: {err:#}"),
+          }
+        }
+        Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+        Err(err) => log::warn!("can't accept players: {err}"),
+      }
+    }
+
+    while let Some(peer) = &mut self.peer {
+      let message = match peer.incoming.try_recv() {
+        Ok(Ok(Some(message))) => message,
+        Ok(Ok(None)) | Err(TryRecvError::Disconnected) => {
+          self.peer = None;
+          events.push(Event::Disconnected);
+          break;
+        }
+        Ok(Err(err)) => {
+          log::warn!("lost the connection: {err:#}");
+          self.peer = None;
+          events.push(Event::Disconnected);
+          break;
+        }
+        Err(TryRecvError::Empty) => break,
+      };
+      self.receive(message, &mut events);
+    }
+    events
+  }
+
+  fn receive(
+    &mut self,
+    message: Message,
+    events: &mut Vec<Event>,
+  ) {
+    match message {
+      Message::Hello { version, name } => {
+        if version != VERSION {
+          let message = format!("we speak version {VERSION}, not {version}");
+          self.send(&Message::Error { message: message.clone() });
+          self.peer = None;
+          events.push(Event::Error(message));
+          return;
+        }
+        if let Some(peer) = &mut self.peer {
+          peer.name = Some(name.clone());
+        }
+        self.met = true;
+        if self.is_host() {
+          self.sync();
+        }
+        events.push(Event::Connected { name });
+      }
+      Message::Sync { color, start, moves } if !self.is_host() => {
+        match self.adopt(color, start, moves) {
+          Ok(()) => events.push(Event::Synced),
+          Err(err) => events.push(Event::Error(format!("{err:#}"))),
+        }
+      }
+      Message::Move { ply, mv } => {
+        let fits = ply == self.moves.len()
+          && self.result.is_none()
+          && !self.is_our_turn()
+          && self.position.is_legal(mv);
+        if fits {
+          self.push(mv);
+          events.push(Event::Moved(mv));
+        } else if self.is_host() {
+          // the other side is behind or ahead, it gets the game as is
+          self.sync();
+        } else {
+          let message = format!("move {mv} at ply {ply} does not fit");
+          self.send(&Message::Error { message });
+        }
+      }
+      Message::Control { ply, color, control } if self.is_host() => {
+        let refusal = if color == self.color {
+          Some(format!("{control} is not yours to send"))
+        } else if self.result.is_some() {
+          Some("the game is over".to_string())
+        } else if ply != self.moves.len() {
+          Some(format!("{control} at ply {ply} does not fit"))
+        } else {
+          None
+        };
+        let applied = match refusal {
+          Some(message) => Err(anyhow!(message)),
+          None => {
+            self.controls.apply(color, control, &self.start, &self.moves, false)
+          }
+        };
+        match applied {
+          Ok(effect) => {
+            self.send(&Message::Control { ply, color, control });
+            events.push(Event::Control(color, control));
+            if let Effect::End(outcome, reason) = effect {
+              events.push(Event::Ended(outcome, reason.into()));
+            }
+            self.carry_out(effect);
+          }
+          Err(err) => {
+            self.send(&Message::Error { message: format!("{err:#}") });
+            self.sync();
+          }
+        }
+      }
+      Message::Control { ply, color, control } => {
+        // the host checked it, and sends what comes of it
+        if ply <= self.moves.len() {
+          let moves = &self.moves[..ply];
+          let applied =
+            self.controls.apply(color, control, &self.start, moves, true);
+          if let Err(err) = applied {
+            log::warn!("can't follow {control}: {err:#}");
+          }
+          events.push(Event::Control(color, control));
+        }
+      }
+      Message::End { outcome, reason } if !self.is_host() => {
+        self.result = Some((outcome, reason.clone()));
+        events.push(Event::Ended(outcome, reason));
+      }
+      Message::Chat { ply, text } => {
+        let from = self.opponent().unwrap_or("opponent").to_string();
+        let text = chat::clean(&text);
+        self.chat.push(ChatLine { ply, room: Room::Players, from, text });
+      }
+      Message::Error { message } => {
+        if self.is_host() {
+          self.sync();
+        }
+        events.push(Event::Error(message));
+      }
+      Message::Bye => {
+        self.peer = None;
+        events.push(Event::Disconnected);
+      }
+      message @ (Message::Sync { .. } | Message::End { .. }) => {
+        let message = format!("unexpected {message}");
+        self.send(&Message::Error { message });
+      }
+    }
+  }
+}
+
+impl Drop for NetGame {
+  fn drop(&mut self) {
+    self.leave();
+  }
+}
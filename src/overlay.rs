@@ -0,0 +1,474 @@
+//! Flat arrows between squares and circles around them, floating just
+//! above the board, and translucent ghosts of pieces about to move.
+//! Drawn after the opaque geometry, tested against its depth without
+//! writing any.
+//! The board lies in the z = 0 plane centered at the origin, one unit
+//! per square, with a1 at negative x and y.
+
+use std::{borrow::Cow, f32::consts::TAU, mem};
+
+use bytemuck::{Pod, Zeroable};
+use discipline::{
+  glam::{Mat4, Vec2, Vec3, Vec4Swizzles},
+  wgpu::{self, util::DeviceExt},
+};
+
+use crate::{
+  board::{Piece, PieceKind, Square},
+  depth::depth_stencil_for_pipeline,
+};
+
+const MAX_VERTICES: usize = 32768;
+// shaft and head, two and one triangles
+const VERTICES_PER_ARROW: usize = 9;
+const CIRCLE_SEGMENTS: usize = 32;
+// high enough to avoid z-fighting with the board
+const HEIGHT: f32 = 0.02;
+const SHAFT_WIDTH: f32 = 0.15;
+const HEAD_WIDTH: f32 = 0.4;
+const HEAD_LENGTH: f32 = 0.35;
+const CIRCLE_RADIUS: f32 = 0.46;
+const CIRCLE_WIDTH: f32 = 0.08;
+const GHOST_SEGMENTS: usize = 12;
+const KNIGHT_THICKNESS: f32 = 0.16;
+
+/// Outlines of the pieces turned on a lathe, radius and height from the
+/// bottom up, a square being one unit across. The knight only turns its
+/// base, see `KNIGHT_HEAD`.
+fn profile(kind: PieceKind) -> &'static [(f32, f32)] {
+  match kind {
+    PieceKind::Pawn => &[
+      (0.0, 0.0),
+      (0.3, 0.0),
+      (0.3, 0.06),
+      (0.2, 0.12),
+      (0.11, 0.32),
+      (0.17, 0.36),
+      (0.11, 0.4),
+      (0.15, 0.46),
+      (0.13, 0.53),
+      (0.07, 0.57),
+      (0.0, 0.58),
+    ],
+    PieceKind::Knight => &[
+      (0.0, 0.0),
+      (0.31, 0.0),
+      (0.31, 0.06),
+      (0.21, 0.12),
+      (0.17, 0.26),
+      (0.0, 0.26),
+    ],
+    PieceKind::Bishop => &[
+      (0.0, 0.0),
+      (0.31, 0.0),
+      (0.31, 0.06),
+      (0.21, 0.12),
+      (0.11, 0.5),
+      (0.19, 0.54),
+      (0.11, 0.58),
+      (0.16, 0.7),
+      (0.1, 0.82),
+      (0.04, 0.86),
+      (0.05, 0.89),
+      (0.0, 0.92),
+    ],
+    PieceKind::Rook => &[
+      (0.0, 0.0),
+      (0.32, 0.0),
+      (0.32, 0.06),
+      (0.24, 0.12),
+      (0.19, 0.5),
+      (0.25, 0.54),
+      (0.25, 0.68),
+      (0.18, 0.68),
+      (0.18, 0.62),
+      (0.0, 0.62),
+    ],
+    PieceKind::Queen => &[
+      (0.0, 0.0),
+      (0.33, 0.0),
+      (0.33, 0.06),
+      (0.23, 0.13),
+      (0.12, 0.6),
+      (0.21, 0.64),
+      (0.13, 0.68),
+      (0.2, 0.88),
+      (0.12, 0.9),
+      (0.06, 0.96),
+      (0.0, 0.98),
+    ],
+    PieceKind::King => &[
+      (0.0, 0.0),
+      (0.33, 0.0),
+      (0.33, 0.06),
+      (0.23, 0.13),
+      (0.13, 0.66),
+      (0.22, 0.7),
+      (0.14, 0.74),
+      (0.19, 0.92),
+      (0.05, 0.95),
+      (0.05, 1.04),
+      (0.1, 1.04),
+      (0.1, 1.08),
+      (0.0, 1.12),
+    ],
+  }
+}
+
+/// The knight's head seen from the side, forward and height, standing on
+/// its turned base.
+const KNIGHT_HEAD: [(f32, f32); 8] = [
+  (-0.16, 0.24),
+  (0.16, 0.24),
+  (0.1, 0.42),
+  (0.26, 0.52),
+  (0.24, 0.62),
+  (0.06, 0.76),
+  (-0.04, 0.8),
+  (-0.18, 0.58),
+];
+
+pub fn square_center(sq: Square) -> Vec3 {
+  Vec3::new(sq.file() as f32 - 3.5, sq.rank() as f32 - 3.5, 0.0)
+}
+
+/// The square under a point of the screen, in normalized device
+/// coordinates, seen through `camera_view`.
+pub fn square_at(
+  camera_view: Mat4,
+  ndc: Vec2,
+) -> Option<Square> {
+  // two points along the ray, at depths finite for any depth convention
+  let inverse = camera_view.inverse();
+  let unproject = |depth: f32| {
+    let point = inverse * ndc.extend(depth).extend(1.0);
+    point.xyz() / point.w
+  };
+  let (near, far) = (unproject(0.25), unproject(0.75));
+  let direction = far - near;
+  if direction.z.abs() < f32::EPSILON {
+    return None;
+  }
+  let t = -near.z / direction.z;
+  if t < 0.0 {
+    return None;
+  }
+  let point = near + direction * t;
+  let (file, rank) = ((point.x + 4.0).floor(), (point.y + 4.0).floor());
+  if !(0.0..8.0).contains(&file) || !(0.0..8.0).contains(&rank) {
+    return None;
+  }
+  Some(Square::new(file as u8, rank as u8))
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Arrow {
+  pub from: Square,
+  pub to: Square,
+  pub color: [f32; 4],
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Circle {
+  pub square: Square,
+  pub color: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Vertex {
+  _pos: [f32; 3],
+  _color: [f32; 4],
+}
+
+fn arrow_vertices(arrow: &Arrow) -> [Vertex; VERTICES_PER_ARROW] {
+  let from = square_center(arrow.from).truncate();
+  let to = square_center(arrow.to).truncate();
+  let direction = (to - from).normalize_or_zero();
+  let side = direction.perp();
+  // start a bit off the center so that the origin piece stays visible
+  let start = from + direction * 0.25;
+  let neck = to - direction * HEAD_LENGTH;
+
+  let vertex = |point: Vec2| Vertex {
+    _pos: [point.x, point.y, HEIGHT],
+    _color: arrow.color,
+  };
+  let shaft = SHAFT_WIDTH / 2.0;
+  let head = HEAD_WIDTH / 2.0;
+  [
+    vertex(start - side * shaft),
+    vertex(neck - side * shaft),
+    vertex(neck + side * shaft),
+    //
+    vertex(start - side * shaft),
+    vertex(neck + side * shaft),
+    vertex(start + side * shaft),
+    //
+    vertex(neck - side * head),
+    vertex(to),
+    vertex(neck + side * head),
+  ]
+}
+
+fn circle_vertices(circle: &Circle) -> Vec<Vertex> {
+  let center = square_center(circle.square).truncate();
+  let point = |angle: f32, radius: f32| {
+    let point = center + Vec2::from_angle(angle) * radius;
+    Vertex { _pos: [point.x, point.y, HEIGHT], _color: circle.color }
+  };
+  let (outer, inner) = (CIRCLE_RADIUS, CIRCLE_RADIUS - CIRCLE_WIDTH);
+  (0..CIRCLE_SEGMENTS)
+    .flat_map(|i| {
+      let a = TAU * i as f32 / CIRCLE_SEGMENTS as f32;
+      let b = TAU * (i + 1) as f32 / CIRCLE_SEGMENTS as f32;
+      [
+        point(a, inner),
+        point(a, outer),
+        point(b, outer),
+        //
+        point(a, inner),
+        point(b, outer),
+        point(b, inner),
+      ]
+    })
+    .collect()
+}
+
+/// A translucent piece standing on a square.
+#[derive(Clone, Copy, Debug)]
+pub struct Ghost {
+  pub square: Square,
+  pub piece: Piece,
+  pub color: [f32; 4],
+}
+
+/// Darkens the faces turned away from a light above the board, so that a
+/// ghost reads as a solid.
+fn shade(
+  color: [f32; 4],
+  normal: Vec3,
+) -> [f32; 4] {
+  let light = Vec3::new(0.3, -0.5, 0.8).normalize();
+  let lit = 0.55 + 0.45 * normal.normalize_or_zero().dot(light).max(0.0);
+  [color[0] * lit, color[1] * lit, color[2] * lit, color[3]]
+}
+
+fn ghost_vertices(ghost: &Ghost) -> Vec<Vertex> {
+  let center = square_center(ghost.square) + Vec3::Z * HEIGHT;
+  let vertex =
+    |point: Vec3, color| Vertex { _pos: point.to_array(), _color: color };
+  let profile = profile(ghost.piece.kind);
+  let mut vertices = Vec::new();
+  for i in 0..GHOST_SEGMENTS {
+    let a = TAU * i as f32 / GHOST_SEGMENTS as f32;
+    let b = TAU * (i + 1) as f32 / GHOST_SEGMENTS as f32;
+    let middle = Vec2::from_angle((a + b) / 2.0);
+    for pair in profile.windows(2) {
+      let [(r0, h0), (r1, h1)] = [pair[0], pair[1]];
+      let normal = (middle * (h1 - h0)).extend(r0 - r1);
+      let color = shade(ghost.color, normal);
+      let point = |angle: f32, radius: f32, height: f32| {
+        let around = Vec2::from_angle(angle) * radius;
+        vertex(center + around.extend(height), color)
+      };
+      vertices.extend([
+        point(a, r0, h0),
+        point(b, r0, h0),
+        point(b, r1, h1),
+        //
+        point(a, r0, h0),
+        point(b, r1, h1),
+        point(a, r1, h1),
+      ]);
+    }
+  }
+  if ghost.piece.kind == PieceKind::Knight {
+    // the head looks at the other side
+    let forward = ghost.piece.color.fold(1.0, -1.0);
+    let half = KNIGHT_THICKNESS / 2.0;
+    let point = |(ahead, height): (f32, f32), side: f32, color| {
+      vertex(center + Vec3::new(side, ahead * forward, height), color)
+    };
+    let count = KNIGHT_HEAD.len() as f32;
+    let sum =
+      KNIGHT_HEAD.iter().fold((0.0, 0.0), |sum, p| (sum.0 + p.0, sum.1 + p.1));
+    let middle = (sum.0 / count, sum.1 / count);
+    for (i, &p) in KNIGHT_HEAD.iter().enumerate() {
+      let q = KNIGHT_HEAD[(i + 1) % KNIGHT_HEAD.len()];
+      for side in [-half, half] {
+        let color = shade(ghost.color, Vec3::X * side);
+        vertices.extend([
+          point(middle, side, color),
+          point(p, side, color),
+          point(q, side, color),
+        ]);
+      }
+      let outward = Vec3::new(0.0, (q.1 - p.1) * forward, p.0 - q.0);
+      let color = shade(ghost.color, outward);
+      vertices.extend([
+        point(p, -half, color),
+        point(q, -half, color),
+        point(q, half, color),
+        //
+        point(p, -half, color),
+        point(q, half, color),
+        point(p, half, color),
+      ]);
+    }
+  }
+  vertices
+}
+
+pub struct Overlay {
+  vertex_buf: wgpu::Buffer,
+  bind_group: wgpu::BindGroup,
+  uniform_buf: wgpu::Buffer,
+  pipeline: wgpu::RenderPipeline,
+  num_vertices: u32,
+}
+
+impl Overlay {
+  pub fn new(
+    format: wgpu::TextureFormat,
+    device: &wgpu::Device,
+    camera_view: Mat4,
+  ) -> Self {
+    let vertex_size = mem::size_of::<Vertex>();
+    let vertex_buf = device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Overlay vertex buffer"),
+      size: (vertex_size * MAX_VERTICES) as u64,
+      usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    });
+    let attributes = &wgpu::vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32x4,
+    ];
+    let vertex_buffers = [wgpu::VertexBufferLayout {
+      array_stride: vertex_size as wgpu::BufferAddress,
+      step_mode: wgpu::VertexStepMode::Vertex,
+      attributes,
+    }];
+
+    let bind_group_layout =
+      device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: None,
+        entries: &[wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::VERTEX,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        }],
+      });
+    let pipeline_layout =
+      device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+      });
+
+    let uniform_buf =
+      device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Overlay uniform buffer"),
+        contents: bytemuck::bytes_of(&camera_view),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      layout: &bind_group_layout,
+      entries: &[wgpu::BindGroupEntry {
+        binding: 0,
+        resource: uniform_buf.as_entire_binding(),
+      }],
+      label: None,
+    });
+
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+      label: Some("Overlay shader"),
+      source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+        "overlay.wgsl"
+      ))),
+    });
+
+    let mut color_target_state: wgpu::ColorTargetState = format.into();
+    color_target_state.blend = Some(wgpu::BlendState::ALPHA_BLENDING);
+
+    // marks are translucent, they are tested against depth but must not
+    // hide what is drawn after them
+    let mut depth_stencil = depth_stencil_for_pipeline();
+    depth_stencil.as_mut().unwrap().depth_write_enabled = false;
+    let pipeline =
+      device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Overlay render pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+          module: &shader,
+          entry_point: "vs_main",
+          buffers: &vertex_buffers,
+        },
+        fragment: Some(wgpu::FragmentState {
+          module: &shader,
+          entry_point: "fs_main",
+          targets: &[Some(color_target_state)],
+        }),
+        primitive: wgpu::PrimitiveState {
+          // NOTE: marks are visible from both sides
+          cull_mode: None,
+          ..Default::default()
+        },
+        depth_stencil,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+      });
+
+    Self { vertex_buf, bind_group, uniform_buf, pipeline, num_vertices: 0 }
+  }
+
+  pub fn update_camera(
+    &mut self,
+    queue: &wgpu::Queue,
+    camera_view: Mat4,
+  ) {
+    queue.write_buffer(&self.uniform_buf, 0, bytemuck::bytes_of(&camera_view));
+  }
+
+  /// Replaces the marks shown, circles below arrows, and the ghosts.
+  /// Whatever does not fit in `MAX_VERTICES` is dropped.
+  pub fn set(
+    &mut self,
+    queue: &wgpu::Queue,
+    arrows: &[Arrow],
+    circles: &[Circle],
+    ghosts: &[Ghost],
+  ) {
+    let mut vertices: Vec<Vertex> =
+      circles.iter().flat_map(circle_vertices).collect();
+    let arrows = arrows.iter().filter(|arrow| arrow.from != arrow.to);
+    vertices.extend(arrows.flat_map(arrow_vertices));
+    vertices.extend(ghosts.iter().flat_map(ghost_vertices));
+    vertices.truncate(MAX_VERTICES - MAX_VERTICES % 3);
+    self.num_vertices = vertices.len() as u32;
+    if !vertices.is_empty() {
+      queue.write_buffer(&self.vertex_buf, 0, bytemuck::cast_slice(&vertices));
+    }
+  }
+
+  pub fn render<'rpass>(
+    &'rpass mut self,
+    rpass: &mut wgpu::RenderPass<'rpass>,
+  ) {
+    if self.num_vertices == 0 {
+      return;
+    }
+    rpass.push_debug_group("Overlay rendering");
+    rpass.set_pipeline(&self.pipeline);
+    rpass.set_bind_group(0, &self.bind_group, &[]);
+    rpass.set_vertex_buffer(0, self.vertex_buf.slice(..));
+    rpass.draw(0..self.num_vertices, 0..1);
+    rpass.pop_debug_group();
+  }
+}
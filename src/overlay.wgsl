@@ -0,0 +1,24 @@
+struct VertexOutput {
+  @location(0) color : vec4<f32>,
+  @builtin(position) position : vec4<f32>,
+};
+
+struct UniformInput {
+  camera_transform : mat4x4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> uniform_input : UniformInput;
+
+@vertex fn vs_main(
+  @location(0) position : vec3<f32>,
+  @location(1) color : vec4<f32>,
+) -> VertexOutput {
+  var result : VertexOutput;
+  result.color = color;
+  result.position = uniform_input.camera_transform * vec4(position, 1.0);
+  return result;
+}
+
+@fragment fn fs_main(vertex : VertexOutput)->@location(0) vec4<f32> {
+  return vertex.color;
+}
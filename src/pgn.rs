@@ -0,0 +1,416 @@
+//! Portable Game Notation: tag pairs and movetext. `parse` keeps the main
+//! line of each game, `parse_trees` its variations and annotations too.
+
+use std::fmt::{self, Write as _};
+
+use anyhow::{anyhow, bail};
+
+use crate::{
+  board::{Color, Move, Position, STARTING_FEN},
+  gametree::{self, GameTree, NodeId},
+  san,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Outcome {
+  WhiteWins,
+  BlackWins,
+  Draw,
+  Unknown,
+}
+
+impl Outcome {
+  pub fn as_str(self) -> &'static str {
+    match self {
+      Outcome::WhiteWins => "1-0",
+      Outcome::BlackWins => "0-1",
+      Outcome::Draw => "1/2-1/2",
+      Outcome::Unknown => "*",
+    }
+  }
+
+  pub fn parse(s: &str) -> Option<Outcome> {
+    match s {
+      "1-0" => Some(Outcome::WhiteWins),
+      "0-1" => Some(Outcome::BlackWins),
+      "1/2-1/2" => Some(Outcome::Draw),
+      "*" => Some(Outcome::Unknown),
+      _ => None,
+    }
+  }
+
+  pub fn winner(self) -> Option<Color> {
+    match self {
+      Outcome::WhiteWins => Some(Color::White),
+      Outcome::BlackWins => Some(Color::Black),
+      Outcome::Draw | Outcome::Unknown => None,
+    }
+  }
+
+  /// Points scored by `color`: 1 for a win, 0.5 for a draw.
+  pub fn score(
+    self,
+    color: Color,
+  ) -> Option<f64> {
+    match (self, self.winner()) {
+      (Outcome::Unknown, _) => None,
+      (_, None) => Some(0.5),
+      (_, Some(winner)) => Some(if winner == color { 1.0 } else { 0.0 }),
+    }
+  }
+}
+
+impl fmt::Display for Outcome {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct Game {
+  /// Tag pairs in the order they appear.
+  pub tags: Vec<(String, String)>,
+  pub start: Position,
+  pub moves: Vec<Move>,
+  pub outcome: Outcome,
+}
+
+impl Default for Game {
+  fn default() -> Self {
+    Self {
+      tags: Vec::new(),
+      start: Position::startpos(),
+      moves: Vec::new(),
+      outcome: Outcome::Unknown,
+    }
+  }
+}
+
+impl Game {
+  pub fn tag(
+    &self,
+    name: &str,
+  ) -> Option<&str> {
+    self
+      .tags
+      .iter()
+      .find(|(tag, _)| tag == name)
+      .map(|(_, value)| value.as_str())
+  }
+
+  /// Sets a tag, replacing an earlier value.
+  pub fn set_tag(
+    &mut self,
+    name: &str,
+    value: impl Into<String>,
+  ) {
+    let value = value.into();
+    match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+      Some((_, old)) => *old = value,
+      None => self.tags.push((name.into(), value)),
+    }
+  }
+
+  /// Position after all the moves.
+  pub fn end(&self) -> Position {
+    let mut position = self.start.clone();
+    for &mv in &self.moves {
+      position.play(mv);
+    }
+    position
+  }
+
+  /// The game as PGN text, tags first and movetext wrapped at 80 columns.
+  pub fn to_pgn(&self) -> String {
+    let movetext = san::line(&self.start, &self.moves);
+    self.write(movetext.split(' ').map(String::from).collect())
+  }
+
+  /// Like `to_pgn`, with the moves, variations and annotations of `tree`
+  /// in place of `moves`.
+  pub fn to_pgn_with(
+    &self,
+    tree: &GameTree,
+  ) -> String {
+    let mut words = Vec::new();
+    push_comment(&mut words, &tree.node(tree.root()).annotations);
+    write_line(tree, tree.root(), true, &mut words);
+    let game = Game { start: tree.start().clone(), ..self.clone() };
+    game.write(words)
+  }
+
+  fn write(
+    &self,
+    words: Vec<String>,
+  ) -> String {
+    let mut tags = self.tags.clone();
+    let fen = self.start.to_fen();
+    if fen != STARTING_FEN && !tags.iter().any(|(tag, _)| tag == "FEN") {
+      tags.push(("SetUp".into(), "1".into()));
+      tags.push(("FEN".into(), fen));
+    }
+    let mut text = String::new();
+    for (name, value) in &tags {
+      let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+      let _ = writeln!(text, "[{name} \"{value}\"]");
+    }
+    text.push('\n');
+
+    let mut column = 0;
+    let words =
+      words.iter().map(String::as_str).filter(|word| !word.is_empty());
+    for word in words.chain([self.outcome.as_str()]) {
+      if column > 0 && column + 1 + word.len() > 80 {
+        text.push('\n');
+        column = 0;
+      } else if column > 0 {
+        text.push(' ');
+        column += 1;
+      }
+      text.push_str(word);
+      column += word.len();
+    }
+    text.push('\n');
+    text
+  }
+}
+
+/// Adds the words of a `{comment}`, if there is anything to say.
+fn push_comment(
+  words: &mut Vec<String>,
+  annotations: &gametree::Annotations,
+) {
+  let comment = annotations.pgn_comment();
+  let start = words.len();
+  words.extend(comment.split_whitespace().map(String::from));
+  if words.len() > start {
+    words[start].insert(0, '{');
+    words.last_mut().unwrap().push('}');
+  }
+}
+
+/// Adds the move of `id` with its number, glyphs and comment. Returns
+/// whether the next move needs its number repeated.
+fn write_move(
+  tree: &GameTree,
+  id: NodeId,
+  numbered: bool,
+  words: &mut Vec<String>,
+) -> bool {
+  let node = tree.node(id);
+  let parent = tree.node(node.parent.unwrap());
+  let position = &parent.position;
+  let number = position.fullmove_number();
+  if position.side_to_move() == Color::White {
+    words.push(format!("{number}."));
+  } else if numbered {
+    words.push(format!("{number}..."));
+  }
+  let mut san = san::to_san(position, node.mv.unwrap());
+  let mut nags = node.annotations.nags.iter().peekable();
+  // the first glyph goes on the move when it is one of `!?` and friends
+  if let Some(nag) = nags.next_if(|&&nag| (1..=6).contains(&nag)) {
+    san.push_str(&gametree::nag_text(*nag));
+  }
+  words.push(san);
+  words.extend(nags.map(|nag| format!("${nag}")));
+  let len = words.len();
+  push_comment(words, &node.annotations);
+  words.len() > len
+}
+
+/// Adds the line after `id`, each move followed by its variations.
+fn write_line(
+  tree: &GameTree,
+  id: NodeId,
+  mut numbered: bool,
+  words: &mut Vec<String>,
+) {
+  let mut node = id;
+  while let Some((&main, variations)) = tree.node(node).children.split_first() {
+    numbered = write_move(tree, main, numbered, words);
+    for &variation in variations {
+      let mut inner = Vec::new();
+      let numbered = write_move(tree, variation, true, &mut inner);
+      write_line(tree, variation, numbered, &mut inner);
+      inner[0].insert(0, '(');
+      inner.last_mut().unwrap().push(')');
+      words.append(&mut inner);
+    }
+    numbered |= !variations.is_empty();
+    node = main;
+  }
+}
+
+/// Reads the main line of every game of a PGN file.
+pub fn parse(text: &str) -> anyhow::Result<Vec<Game>> {
+  Ok(parse_trees(text)?.into_iter().map(|(game, _)| game).collect())
+}
+
+/// Reads every game of a PGN file with its variations and annotations.
+/// The trees show their start position.
+pub fn parse_trees(text: &str) -> anyhow::Result<Vec<(Game, GameTree)>> {
+  let mut games = Vec::new();
+  let mut reader = Reader { text, offset: 0 };
+  while let Some(game) = reader.next_game()? {
+    games.push(game);
+  }
+  Ok(games)
+}
+
+struct Reader<'a> {
+  text: &'a str,
+  offset: usize,
+}
+
+impl<'a> Reader<'a> {
+  fn rest(&self) -> &'a str {
+    &self.text[self.offset..]
+  }
+
+  fn line(&self) -> usize {
+    self.text[..self.offset].lines().count().max(1)
+  }
+
+  fn skip_whitespace(&mut self) {
+    let rest = self.rest();
+    self.offset += rest.len() - rest.trim_start().len();
+  }
+
+  /// Reads a `{comment}`, without the braces.
+  fn comment(&mut self) -> anyhow::Result<&'a str> {
+    let rest = self.rest();
+    let end = rest
+      .find('}')
+      .ok_or_else(|| anyhow!("line {}: unterminated comment", self.line()))?;
+    self.offset += end + 1;
+    Ok(&rest[1..end])
+  }
+
+  fn tag(&mut self) -> anyhow::Result<(String, String)> {
+    let line = self.line();
+    let rest = self.rest();
+    let end =
+      rest.find(']').ok_or_else(|| anyhow!("line {line}: unterminated tag"))?;
+    let inner = rest[1..end].trim();
+    self.offset += end + 1;
+    let (name, value) = inner
+      .split_once(' ')
+      .ok_or_else(|| anyhow!("line {line}: invalid tag"))?;
+    let value = value.trim().trim_matches('"');
+    Ok((name.into(), value.replace("\\\"", "\"").replace("\\\\", "\\")))
+  }
+
+  fn next_game(&mut self) -> anyhow::Result<Option<(Game, GameTree)>> {
+    let mut game = Game::default();
+    self.skip_whitespace();
+    if self.rest().is_empty() {
+      return Ok(None);
+    }
+    while self.rest().starts_with('[') {
+      let (name, value) = self.tag()?;
+      if name == "FEN" {
+        game.start = Position::from_fen(&value)?;
+      }
+      game.tags.push((name, value));
+      self.skip_whitespace();
+    }
+
+    let mut tree = GameTree::new(game.start.clone());
+    // where to go back to at the end of each open variation
+    let mut variations = Vec::new();
+    loop {
+      self.skip_whitespace();
+      let rest = self.rest();
+      let Some(c) = rest.chars().next() else {
+        break;
+      };
+      match c {
+        '{' => {
+          let comment = self.comment()?;
+          tree.annotations_mut(tree.current()).add_comment(comment);
+        }
+        ';' => {
+          let end = rest.find('\n').unwrap_or(rest.len());
+          self.offset += end;
+          tree.annotations_mut(tree.current()).add_comment(&rest[1..end]);
+        }
+        '%' => self.offset += rest.find('\n').unwrap_or(rest.len()),
+        '(' => {
+          self.offset += 1;
+          variations.push(tree.current());
+          if !tree.back() {
+            bail!("line {}: variation before the first move", self.line());
+          }
+        }
+        ')' => {
+          self.offset += 1;
+          let end = variations
+            .pop()
+            .ok_or_else(|| anyhow!("line {}: unbalanced ')'", self.line()))?;
+          tree.go_to(end);
+        }
+        // tags of the next game, the result was missing
+        '[' => break,
+        '}' => bail!("line {}: unbalanced '}}'", self.line()),
+        _ => {
+          let len = rest
+            .find(|c: char| c.is_whitespace() || "{}();[".contains(c))
+            .unwrap_or(rest.len());
+          let token = &rest[..len];
+          self.offset += len;
+          if let Some(outcome) = Outcome::parse(token) {
+            if variations.is_empty() {
+              game.outcome = outcome;
+              break;
+            }
+            continue;
+          }
+          // move numbers may be glued to the move, as in `1.e4`
+          let token = token.rsplit('.').next().unwrap_or_default();
+          if token.is_empty() || token.bytes().all(|c| c.is_ascii_digit()) {
+            continue;
+          }
+          let mv_len = token.trim_end_matches(['!', '?']).len();
+          let (mv, suffix) = token.split_at(mv_len);
+          let nag =
+            match (mv.strip_prefix('$'), suffix) {
+              (Some(nag), _) => Some(nag.parse().map_err(|_| {
+                anyhow!("line {}: invalid {token}", self.line())
+              })?),
+              (None, "") => None,
+              (None, suffix) => {
+                Some(gametree::nag_of_suffix(suffix).ok_or_else(|| {
+                  anyhow!("line {}: invalid {token}", self.line())
+                })?)
+              }
+            };
+          if !mv.is_empty() && !mv.starts_with('$') {
+            let parsed = san::parse_san(tree.position(), mv)
+              .map_err(|err| anyhow!("line {}: {err}", self.line()))?;
+            tree.play(parsed)?;
+          }
+          if let Some(nag) = nag {
+            if tree.current() != tree.root() {
+              tree.annotations_mut(tree.current()).nags.push(nag);
+            }
+          }
+        }
+      }
+    }
+    if !variations.is_empty() {
+      bail!("line {}: unterminated variation", self.line());
+    }
+    if let Some(outcome) = game.tag("Result").and_then(Outcome::parse) {
+      if game.outcome == Outcome::Unknown {
+        game.outcome = outcome;
+      }
+    }
+    game.moves = tree.moves_to(*tree.mainline().last().unwrap_or(&tree.root()));
+    tree.to_start();
+    Ok(Some((game, tree)))
+  }
+}
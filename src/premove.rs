@@ -0,0 +1,184 @@
+//! Premoves for fast games: moves queued while the opponent thinks and
+//! played the moment the turn comes, without waiting on the player.
+//!
+//! Not knowing the opponent's move, a premove only has to be a way the
+//! piece moves: a pawn may take on an empty square, where the opponent
+//! may capture first. Each premove is checked on the board as the ones
+//! before it leave it, castling taking the rook along. When its turn
+//! comes a premove that is not legal cancels the whole queue.
+
+use anyhow::{bail, ensure};
+
+use crate::board::{
+  bishop_attacks, king_attacks, knight_attacks, pawn_attacks, rook_attacks,
+  Color, Move, Piece, PieceKind, Position, Square,
+};
+
+/// Finds the legal move of a piece dragged from `from` to `to`, a queen
+/// when promoting.
+pub fn dragged(
+  position: &Position,
+  from: Square,
+  to: Square,
+) -> Option<Move> {
+  let moves = position.legal_moves();
+  let mut candidates = moves.iter().filter(|mv| mv.from == from && mv.to == to);
+  let first = *candidates.next()?;
+  Some(Move { promotion: first.promotion.map(|_| PieceKind::Queen), ..first })
+}
+
+/// Whether `piece` moves from `from` to `to` on some board.
+fn reaches(
+  piece: Piece,
+  from: Square,
+  to: Square,
+) -> bool {
+  let target = 1u64 << to.index();
+  let targets = match piece.kind {
+    PieceKind::Pawn => {
+      let (forward, start_rank): (i8, u8) = piece.color.fold((1, 1), (-1, 6));
+      let ranks = to.rank() as i8 - from.rank() as i8;
+      let pushed = to.file() == from.file()
+        && (ranks == forward
+          || ranks == 2 * forward && from.rank() == start_rank);
+      return pushed || pawn_attacks(piece.color, from) & target != 0;
+    }
+    PieceKind::Knight => knight_attacks(from),
+    PieceKind::Bishop => bishop_attacks(from, 0),
+    PieceKind::Rook => rook_attacks(from, 0),
+    PieceKind::Queen => bishop_attacks(from, 0) | rook_attacks(from, 0),
+    PieceKind::King => {
+      let back_rank = piece.color.fold(0, 7);
+      let castles = from == Square::new(4, back_rank)
+        && to.rank() == back_rank
+        && (to.file() == 6 || to.file() == 2);
+      return castles || king_attacks(from) & target != 0;
+    }
+  };
+  targets & target != 0
+}
+
+fn board(position: &Position) -> [Option<Piece>; 64] {
+  let mut board = [None; 64];
+  for (sq, piece) in position.board() {
+    board[sq.index()] = Some(piece);
+  }
+  board
+}
+
+/// Plays a premove on `board`, returning the pieces it moved: the piece
+/// and, when the king castles, its rook.
+fn premove(
+  board: &mut [Option<Piece>; 64],
+  mv: Move,
+) -> Vec<(Square, Square)> {
+  let mut moved = vec![(mv.from, mv.to)];
+  let king =
+    board[mv.from.index()].filter(|piece| piece.kind == PieceKind::King);
+  if let Some(king) =
+    king.filter(|_| mv.from.file().abs_diff(mv.to.file()) == 2)
+  {
+    let rank = mv.from.rank();
+    let (corner, over) = match mv.to.file() {
+      6 => (Square::new(7, rank), Square::new(5, rank)),
+      _ => (Square::new(0, rank), Square::new(3, rank)),
+    };
+    if board[corner.index()] == Some(Piece::new(king.color, PieceKind::Rook)) {
+      moved.push((corner, over));
+    }
+  }
+  for &(from, to) in &moved {
+    let piece = board[from.index()].take();
+    board[to.index()] = piece.map(|piece| match mv.promotion {
+      Some(kind) if from == mv.from => Piece::new(piece.color, kind),
+      _ => piece,
+    });
+  }
+  moved
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Premoves {
+  moves: Vec<Move>,
+}
+
+impl Premoves {
+  pub fn moves(&self) -> &[Move] {
+    &self.moves
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.moves.is_empty()
+  }
+
+  pub fn clear(&mut self) {
+    self.moves.clear();
+  }
+
+  /// The pieces the queued premoves move, on the squares they end up,
+  /// castling rooks included: the ghosts to show on the board.
+  pub fn ghosts(
+    &self,
+    position: &Position,
+  ) -> Vec<(Square, Piece)> {
+    let mut board = board(position);
+    let mut squares: Vec<Square> = Vec::new();
+    for &mv in &self.moves {
+      for (from, to) in premove(&mut board, mv) {
+        squares.retain(|&sq| sq != from && sq != to);
+        squares.push(to);
+      }
+    }
+    let pieces = squares.into_iter().map(|sq| Some((sq, board[sq.index()]?)));
+    pieces.flatten().collect()
+  }
+
+  /// Queues a premove of `color` dragged from `from` to `to`, with the
+  /// opponent to move in `position`.
+  pub fn push(
+    &mut self,
+    position: &Position,
+    color: Color,
+    from: Square,
+    to: Square,
+  ) -> anyhow::Result<Move> {
+    // the board as the queued premoves leave it
+    let mut board = board(position);
+    for &mv in &self.moves {
+      premove(&mut board, mv);
+    }
+    let Some(piece) = board[from.index()].filter(|piece| piece.color == color)
+    else {
+      bail!("no piece of ours on {from}");
+    };
+    ensure!(
+      board[to.index()].is_none_or(|taken| taken.color != color),
+      "{to} is ours"
+    );
+    ensure!(reaches(piece, from, to), "the piece on {from} can't go to {to}");
+    let last_rank = color.fold(7, 0);
+    let promotion = (piece.kind == PieceKind::Pawn && to.rank() == last_rank)
+      .then_some(PieceKind::Queen);
+    let mv = Move { from, to, promotion };
+    self.moves.push(mv);
+    Ok(mv)
+  }
+
+  /// The premove to play now that the turn came, if it is legal in
+  /// `position`. Otherwise the queue is cancelled.
+  pub fn next(
+    &mut self,
+    position: &Position,
+  ) -> Option<Move> {
+    if self.moves.is_empty() {
+      return None;
+    }
+    let mv = self.moves.remove(0);
+    if position.is_legal(mv) {
+      Some(mv)
+    } else {
+      self.moves.clear();
+      None
+    }
+  }
+}
@@ -0,0 +1,981 @@
+//! The messages networked games exchange, one per line of text with the
+//! kind first, so a session can be followed with `nc`. Two players
+//! talking directly send [`Message`]s:
+//!
+//! ```text
+//! hello 1 Ann
+//! sync white e2e4,e7e5 rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2
+//! move 2 g1f3
+//! control 3 black offer-draw
+//! control 3 white decline-draw
+//! chat 3 that was close
+//! error move 3 is illegal
+//! end 0-1 resignation
+//! bye
+//! ```
+//!
+//! Besides moves, players send [`Control`]s: draw offers, takebacks,
+//! resigning and so on, after how many moves and by which side. The host
+//! of a game, or the server, applies them in the order they come and
+//! sends each on, the joining side's own included, so both follow the
+//! same offers; see `control` for the rules.
+//!
+//! With a server in between, clients send [`Request`]s and the server
+//! answers with [`Update`]s: the lobby's seeks and games, then the games
+//! played or watched with the clocks in milliseconds as the server keeps
+//! them.
+//!
+//! ```text
+//! > hello 1 Ann
+//! < hello 1 server
+//! > seek 300+2 white
+//! < seek 1 Ann 300+2 white -
+//! < listed 4 Ann Bob 300+2
+//! < game 4 Ann Bob 300+2 white 300000 300000 - rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1
+//! > move 4 0 e2e4
+//! < move 4 0 e2e4 301500 300000
+//! > control 4 1 resign
+//! < control 4 1 white resign
+//! < end 4 0-1 resignation
+//! ```
+//!
+//! Players also learn when their opponent leaves or comes back, which
+//! lets them claim the game while the opponent is gone.
+//!
+//! Games have two chat rooms: the players talk in one, the spectators in
+//! the other, and neither hears the other. The server says which room a
+//! line went to and after how many moves, and checks it first; see `chat`.
+//!
+//! ```text
+//! > chat 4 good luck
+//! < chat 4 0 players Ann good luck
+//! ```
+//!
+//! A broadcast sends [`Relay`] messages to its viewers, who say nothing
+//! back: the game so far to each newcomer, then the changes to its main
+//! line, the annotations in PGN comment form.
+//!
+//! ```text
+//! hello 1 Club championship
+//! tag White Ann
+//! start rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1
+//! move 0 e2e4 - [%clk 0:29:40]
+//! move 1 c7c5 5 [%clk 0:29:52] The Sicilian!
+//! truncate 1
+//! result 1-0
+//! ```
+//!
+//! Both sides open with `hello` and the protocol version; a peer with
+//! another version gets an `error` and is dropped. Names are one word.
+
+use std::{
+  fmt,
+  io::{BufRead, Read, Write},
+  str::FromStr,
+  time::Duration,
+};
+
+use anyhow::{anyhow, bail};
+
+use crate::{
+  board::{Color, Move, Position},
+  pgn::{self, Outcome},
+  tournament::TimeControl,
+};
+
+pub const VERSION: u32 = 1;
+/// Lines longer than this are refused, a peer can't make us buffer more.
+const MAX_LINE: usize = 1 << 20;
+
+/// What a player does in a game besides moving.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Control {
+  OfferDraw,
+  AcceptDraw,
+  DeclineDraw,
+  Resign,
+  /// Asks to take back the own last move.
+  AskTakeback,
+  AcceptTakeback,
+  DeclineTakeback,
+  /// Calls the game off before both sides moved.
+  Abort,
+  /// Claims a draw by threefold repetition or the fifty-move rule.
+  ClaimDraw,
+  /// Claims the game of an opponent who left.
+  ClaimWin,
+}
+
+impl Control {
+  pub const ALL: [Control; 10] = [
+    Control::OfferDraw,
+    Control::AcceptDraw,
+    Control::DeclineDraw,
+    Control::Resign,
+    Control::AskTakeback,
+    Control::AcceptTakeback,
+    Control::DeclineTakeback,
+    Control::Abort,
+    Control::ClaimDraw,
+    Control::ClaimWin,
+  ];
+
+  pub fn as_str(self) -> &'static str {
+    match self {
+      Control::OfferDraw => "offer-draw",
+      Control::AcceptDraw => "accept-draw",
+      Control::DeclineDraw => "decline-draw",
+      Control::Resign => "resign",
+      Control::AskTakeback => "ask-takeback",
+      Control::AcceptTakeback => "accept-takeback",
+      Control::DeclineTakeback => "decline-takeback",
+      Control::Abort => "abort",
+      Control::ClaimDraw => "claim-draw",
+      Control::ClaimWin => "claim-win",
+    }
+  }
+}
+
+impl fmt::Display for Control {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl FromStr for Control {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> anyhow::Result<Control> {
+    Control::ALL
+      .into_iter()
+      .find(|control| control.as_str() == s)
+      .ok_or_else(|| anyhow!("unknown control {s:?}"))
+  }
+}
+
+/// Where a chat line is heard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Room {
+  Players,
+  Spectators,
+}
+
+impl Room {
+  pub const ALL: [Room; 2] = [Room::Players, Room::Spectators];
+
+  pub fn as_str(self) -> &'static str {
+    match self {
+      Room::Players => "players",
+      Room::Spectators => "spectators",
+    }
+  }
+}
+
+impl fmt::Display for Room {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl FromStr for Room {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> anyhow::Result<Room> {
+    Room::ALL
+      .into_iter()
+      .find(|room| room.as_str() == s)
+      .ok_or_else(|| anyhow!("unknown room {s:?}"))
+  }
+}
+
+/// A line said in a game, after `ply` moves. The text is one line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChatLine {
+  pub ply: usize,
+  pub room: Room,
+  pub from: String,
+  pub text: String,
+}
+
+impl fmt::Display for ChatLine {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    let ChatLine { ply, room, from, text } = self;
+    write!(f, "{ply} {room} {from} {text}")
+  }
+}
+
+impl FromStr for ChatLine {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> anyhow::Result<ChatLine> {
+    let [ply, room, from, text] = fields("chat", s)?;
+    Ok(ChatLine {
+      ply: ply.parse()?,
+      room: room.parse()?,
+      from: from.into(),
+      text: text.into(),
+    })
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+  /// First message each way.
+  Hello { version: u32, name: String },
+  /// The whole game: the receiver's color, the start and the moves. The
+  /// host sends it on each connection and when the sides disagree.
+  Sync { color: Color, start: Position, moves: Vec<Move> },
+  /// A move, after `ply` moves of the game.
+  Move { ply: usize, mv: Move },
+  /// A control of `color`, after `ply` moves.
+  Control { ply: usize, color: Color, control: Control },
+  /// The game ended otherwise than on the board, sent by the host.
+  End { outcome: Outcome, reason: String },
+  /// A chat line, after `ply` moves.
+  Chat { ply: usize, text: String },
+  /// Something the receiver sent was refused.
+  Error { message: String },
+  /// The sender is leaving.
+  Bye,
+}
+
+/// The first words of a message, the last field taking the rest.
+fn fields<'a, const N: usize>(
+  kind: &str,
+  rest: &'a str,
+) -> anyhow::Result<[&'a str; N]> {
+  let fields: Vec<&str> = rest.splitn(N, ' ').collect();
+  fields.try_into().map_err(|_| anyhow!("short {kind:?} message"))
+}
+
+fn format_moves(moves: &[Move]) -> String {
+  let moves: Vec<String> = moves.iter().map(Move::to_string).collect();
+  if moves.is_empty() {
+    "-".into()
+  } else {
+    moves.join(",")
+  }
+}
+
+fn parse_moves(s: &str) -> anyhow::Result<Vec<Move>> {
+  match s {
+    "-" => Ok(Vec::new()),
+    moves => moves.split(',').map(Move::from_uci).collect(),
+  }
+}
+
+fn color_name(color: Color) -> &'static str {
+  color.fold("white", "black")
+}
+
+fn parse_color(s: &str) -> anyhow::Result<Color> {
+  match s {
+    "white" => Ok(Color::White),
+    "black" => Ok(Color::Black),
+    _ => bail!("invalid color {s:?}"),
+  }
+}
+
+impl fmt::Display for Message {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    match self {
+      Message::Hello { version, name } => write!(f, "hello {version} {name}"),
+      Message::Sync { color, start, moves } => write!(
+        f,
+        "sync {} {} {}",
+        color_name(*color),
+        format_moves(moves),
+        start.to_fen()
+      ),
+      Message::Move { ply, mv } => write!(f, "move {ply} {mv}"),
+      Message::Control { ply, color, control } => {
+        write!(f, "control {ply} {} {control}", color_name(*color))
+      }
+      Message::End { outcome, reason } => write!(f, "end {outcome} {reason}"),
+      Message::Chat { ply, text } => write!(f, "chat {ply} {text}"),
+      Message::Error { message } => write!(f, "error {message}"),
+      Message::Bye => write!(f, "bye"),
+    }
+  }
+}
+
+impl FromStr for Message {
+  type Err = anyhow::Error;
+
+  fn from_str(line: &str) -> anyhow::Result<Message> {
+    let line = line.trim();
+    let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+    Ok(match kind {
+      "hello" => {
+        let [version, name] = fields(kind, rest)?;
+        Message::Hello { version: version.parse()?, name: name.into() }
+      }
+      "sync" => {
+        let [color, moves, start] = fields(kind, rest)?;
+        Message::Sync {
+          color: parse_color(color)?,
+          start: Position::from_fen(start)?,
+          moves: parse_moves(moves)?,
+        }
+      }
+      "move" => {
+        let [ply, mv] = fields(kind, rest)?;
+        Message::Move { ply: ply.parse()?, mv: Move::from_uci(mv)? }
+      }
+      "control" => {
+        let [ply, color, control] = fields(kind, rest)?;
+        Message::Control {
+          ply: ply.parse()?,
+          color: parse_color(color)?,
+          control: control.parse()?,
+        }
+      }
+      "end" => {
+        let [outcome, reason] = fields(kind, rest)?;
+        Message::End {
+          outcome: Outcome::parse(outcome)
+            .ok_or_else(|| anyhow!("invalid result {outcome:?}"))?,
+          reason: reason.into(),
+        }
+      }
+      "chat" => {
+        let [ply, text] = fields(kind, rest)?;
+        Message::Chat { ply: ply.parse()?, text: text.into() }
+      }
+      "error" => Message::Error { message: rest.into() },
+      "bye" => Message::Bye,
+      _ => bail!("unknown message {line:?}"),
+    })
+  }
+}
+
+/// A side to play, `any` for whichever the other player leaves.
+fn side_name(color: Option<Color>) -> &'static str {
+  color.map_or("any", color_name)
+}
+
+fn parse_side(s: &str) -> anyhow::Result<Option<Color>> {
+  match s {
+    "any" => Ok(None),
+    color => parse_color(color).map(Some),
+  }
+}
+
+/// Only clocks make sense between people.
+fn parse_control(s: &str) -> anyhow::Result<TimeControl> {
+  match s.parse()? {
+    control @ TimeControl::Clock { .. } => Ok(control),
+    _ => bail!("{s:?} is not a clock"),
+  }
+}
+
+fn parse_millis(s: &str) -> anyhow::Result<Duration> {
+  Ok(Duration::from_millis(s.parse()?))
+}
+
+/// What a client asks of the server.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+  Hello {
+    version: u32,
+    name: String,
+  },
+  /// Offers a game to anyone in the lobby, playing `color`.
+  Seek {
+    control: TimeControl,
+    color: Option<Color>,
+  },
+  /// Offers a game to one player.
+  Challenge {
+    to: String,
+    control: TimeControl,
+    color: Option<Color>,
+  },
+  /// Withdraws an own seek, or declines a challenge.
+  Cancel {
+    seek: u32,
+  },
+  /// Takes up a seek or a challenge, starting the game.
+  Accept {
+    seek: u32,
+  },
+  /// A move in a game, after `ply` moves.
+  Move {
+    game: u32,
+    ply: usize,
+    mv: Move,
+  },
+  /// A control in a game, after `ply` moves.
+  Control {
+    game: u32,
+    ply: usize,
+    control: Control,
+  },
+  /// Gets the whole of a game and follows it; a player gets an own game
+  /// again.
+  Watch {
+    game: u32,
+  },
+  Unwatch {
+    game: u32,
+  },
+  /// Says something in a game, in the players' room or the spectators'
+  /// as the sender plays or watches it.
+  Chat {
+    game: u32,
+    text: String,
+  },
+  /// Sends a correspondence game to its other player, kept for them
+  /// until they come.
+  Post {
+    to: String,
+    game: String,
+    days: u32,
+    start: Position,
+    moves: Vec<Move>,
+  },
+  Bye,
+}
+
+impl fmt::Display for Request {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    match self {
+      Request::Hello { version, name } => write!(f, "hello {version} {name}"),
+      Request::Seek { control, color } => {
+        write!(f, "seek {control} {}", side_name(*color))
+      }
+      Request::Challenge { to, control, color } => {
+        write!(f, "challenge {to} {control} {}", side_name(*color))
+      }
+      Request::Cancel { seek } => write!(f, "cancel {seek}"),
+      Request::Accept { seek } => write!(f, "accept {seek}"),
+      Request::Move { game, ply, mv } => write!(f, "move {game} {ply} {mv}"),
+      Request::Control { game, ply, control } => {
+        write!(f, "control {game} {ply} {control}")
+      }
+      Request::Watch { game } => write!(f, "watch {game}"),
+      Request::Unwatch { game } => write!(f, "unwatch {game}"),
+      Request::Chat { game, text } => write!(f, "chat {game} {text}"),
+      Request::Post { to, game, days, start, moves } => write!(
+        f,
+        "post {to} {game} {days} {} {}",
+        format_moves(moves),
+        start.to_fen()
+      ),
+      Request::Bye => write!(f, "bye"),
+    }
+  }
+}
+
+impl FromStr for Request {
+  type Err = anyhow::Error;
+
+  fn from_str(line: &str) -> anyhow::Result<Request> {
+    let line = line.trim();
+    let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+    Ok(match kind {
+      "hello" => {
+        let [version, name] = fields(kind, rest)?;
+        Request::Hello { version: version.parse()?, name: name.into() }
+      }
+      "seek" => {
+        let [control, color] = fields(kind, rest)?;
+        Request::Seek {
+          control: parse_control(control)?,
+          color: parse_side(color)?,
+        }
+      }
+      "challenge" => {
+        let [to, control, color] = fields(kind, rest)?;
+        Request::Challenge {
+          to: to.into(),
+          control: parse_control(control)?,
+          color: parse_side(color)?,
+        }
+      }
+      "cancel" => Request::Cancel { seek: rest.parse()? },
+      "accept" => Request::Accept { seek: rest.parse()? },
+      "move" => {
+        let [game, ply, mv] = fields(kind, rest)?;
+        Request::Move {
+          game: game.parse()?,
+          ply: ply.parse()?,
+          mv: Move::from_uci(mv)?,
+        }
+      }
+      "control" => {
+        let [game, ply, control] = fields(kind, rest)?;
+        Request::Control {
+          game: game.parse()?,
+          ply: ply.parse()?,
+          control: control.parse()?,
+        }
+      }
+      "watch" => Request::Watch { game: rest.parse()? },
+      "unwatch" => Request::Unwatch { game: rest.parse()? },
+      "chat" => {
+        let [game, text] = fields(kind, rest)?;
+        Request::Chat { game: game.parse()?, text: text.into() }
+      }
+      "post" => {
+        let [to, game, days, moves, start] = fields(kind, rest)?;
+        Request::Post {
+          to: to.into(),
+          game: game.into(),
+          days: days.parse()?,
+          start: Position::from_fen(start)?,
+          moves: parse_moves(moves)?,
+        }
+      }
+      "bye" => Request::Bye,
+      _ => bail!("unknown request {line:?}"),
+    })
+  }
+}
+
+/// A seek or challenge waiting in the lobby.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Seek {
+  pub id: u32,
+  pub from: String,
+  pub control: TimeControl,
+  /// Side the seeker plays.
+  pub color: Option<Color>,
+  /// The player challenged, `None` for an open seek.
+  pub to: Option<String>,
+}
+
+/// A game being played on the server.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameInfo {
+  pub id: u32,
+  pub white: String,
+  pub black: String,
+  pub control: TimeControl,
+}
+
+impl GameInfo {
+  /// The PGN of the game, tagged with who played on which server.
+  pub fn record(
+    &self,
+    server: &str,
+    start: Position,
+    moves: Vec<Move>,
+  ) -> pgn::Game {
+    let mut record = pgn::Game { start, moves, ..Default::default() };
+    record.set_tag("Event", format!("Game {} on {server}", self.id));
+    record.set_tag("White", &self.white);
+    record.set_tag("Black", &self.black);
+    record.set_tag("TimeControl", self.control.to_string());
+    record
+  }
+}
+
+/// What the server tells a client.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Update {
+  Hello {
+    version: u32,
+    name: String,
+  },
+  Seek(Seek),
+  /// A seek was taken up or withdrawn.
+  Unseek {
+    seek: u32,
+  },
+  /// A game started, for the lobby.
+  Listed(GameInfo),
+  /// The whole of a game played or watched: the receiver's side, `None`
+  /// for a spectator, and the clocks as of sending.
+  Game {
+    info: GameInfo,
+    color: Option<Color>,
+    start: Position,
+    moves: Vec<Move>,
+    clocks: [Duration; 2],
+  },
+  /// A move, after `ply` moves, and the clocks after it.
+  Move {
+    game: u32,
+    ply: usize,
+    mv: Move,
+    clocks: [Duration; 2],
+  },
+  /// A control of a player, after `ply` moves, as the server applied it.
+  Control {
+    game: u32,
+    ply: usize,
+    color: Color,
+    control: Control,
+  },
+  /// The player of `color` lost the connection.
+  Gone {
+    game: u32,
+    color: Color,
+  },
+  /// The player of `color` is back.
+  Back {
+    game: u32,
+    color: Color,
+  },
+  End {
+    game: u32,
+    outcome: Outcome,
+    reason: String,
+  },
+  /// A chat line of a game, as the server passed it on.
+  Chat {
+    game: u32,
+    line: ChatLine,
+  },
+  /// A correspondence game another player sent.
+  Post {
+    from: String,
+    game: String,
+    days: u32,
+    start: Position,
+    moves: Vec<Move>,
+  },
+  Error {
+    message: String,
+  },
+}
+
+impl fmt::Display for Update {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    let millis = |clocks: &[Duration; 2]| {
+      format!("{} {}", clocks[0].as_millis(), clocks[1].as_millis())
+    };
+    match self {
+      Update::Hello { version, name } => write!(f, "hello {version} {name}"),
+      Update::Seek(Seek { id, from, control, color, to }) => write!(
+        f,
+        "seek {id} {from} {control} {} {}",
+        side_name(*color),
+        to.as_deref().unwrap_or("-")
+      ),
+      Update::Unseek { seek } => write!(f, "unseek {seek}"),
+      Update::Listed(GameInfo { id, white, black, control }) => {
+        write!(f, "listed {id} {white} {black} {control}")
+      }
+      Update::Game { info, color, start, moves, clocks } => {
+        let GameInfo { id, white, black, control } = info;
+        write!(
+          f,
+          "game {id} {white} {black} {control} {} {} {} {}",
+          color.map_or("watching", color_name),
+          millis(clocks),
+          format_moves(moves),
+          start.to_fen()
+        )
+      }
+      Update::Move { game, ply, mv, clocks } => {
+        write!(f, "move {game} {ply} {mv} {}", millis(clocks))
+      }
+      Update::Control { game, ply, color, control } => {
+        write!(f, "control {game} {ply} {} {control}", color_name(*color))
+      }
+      Update::Gone { game, color } => {
+        write!(f, "gone {game} {}", color_name(*color))
+      }
+      Update::Back { game, color } => {
+        write!(f, "back {game} {}", color_name(*color))
+      }
+      Update::End { game, outcome, reason } => {
+        write!(f, "end {game} {outcome} {reason}")
+      }
+      Update::Chat { game, line } => write!(f, "chat {game} {line}"),
+      Update::Post { from, game, days, start, moves } => write!(
+        f,
+        "post {from} {game} {days} {} {}",
+        format_moves(moves),
+        start.to_fen()
+      ),
+      Update::Error { message } => write!(f, "error {message}"),
+    }
+  }
+}
+
+impl FromStr for Update {
+  type Err = anyhow::Error;
+
+  fn from_str(line: &str) -> anyhow::Result<Update> {
+    let line = line.trim();
+    let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+    Ok(match kind {
+      "hello" => {
+        let [version, name] = fields(kind, rest)?;
+        Update::Hello { version: version.parse()?, name: name.into() }
+      }
+      "seek" => {
+        let [id, from, control, color, to] = fields(kind, rest)?;
+        Update::Seek(Seek {
+          id: id.parse()?,
+          from: from.into(),
+          control: parse_control(control)?,
+          color: parse_side(color)?,
+          to: Some(to.to_string()).filter(|to| to != "-"),
+        })
+      }
+      "unseek" => Update::Unseek { seek: rest.parse()? },
+      "listed" => {
+        let [id, white, black, control] = fields(kind, rest)?;
+        Update::Listed(GameInfo {
+          id: id.parse()?,
+          white: white.into(),
+          black: black.into(),
+          control: parse_control(control)?,
+        })
+      }
+      "game" => {
+        let [id, white, black, control, color, white_ms, black_ms, moves, start] =
+          fields(kind, rest)?;
+        Update::Game {
+          info: GameInfo {
+            id: id.parse()?,
+            white: white.into(),
+            black: black.into(),
+            control: parse_control(control)?,
+          },
+          color: match color {
+            "watching" => None,
+            color => Some(parse_color(color)?),
+          },
+          start: Position::from_fen(start)?,
+          moves: parse_moves(moves)?,
+          clocks: [parse_millis(white_ms)?, parse_millis(black_ms)?],
+        }
+      }
+      "move" => {
+        let [game, ply, mv, white_ms, black_ms] = fields(kind, rest)?;
+        Update::Move {
+          game: game.parse()?,
+          ply: ply.parse()?,
+          mv: Move::from_uci(mv)?,
+          clocks: [parse_millis(white_ms)?, parse_millis(black_ms)?],
+        }
+      }
+      "control" => {
+        let [game, ply, color, control] = fields(kind, rest)?;
+        Update::Control {
+          game: game.parse()?,
+          ply: ply.parse()?,
+          color: parse_color(color)?,
+          control: control.parse()?,
+        }
+      }
+      "gone" => {
+        let [game, color] = fields(kind, rest)?;
+        Update::Gone { game: game.parse()?, color: parse_color(color)? }
+      }
+      "back" => {
+        let [game, color] = fields(kind, rest)?;
+        Update::Back { game: game.parse()?, color: parse_color(color)? }
+      }
+      "end" => {
+        let [game, outcome, reason] = fields(kind, rest)?;
+        Update::End {
+          game: game.parse()?,
+          outcome: Outcome::parse(outcome)
+            .ok_or_else(|| anyhow!("invalid result {outcome:?}"))?,
+          reason: reason.into(),
+        }
+      }
+      "chat" => {
+        let [game, line] = fields(kind, rest)?;
+        Update::Chat { game: game.parse()?, line: line.parse()? }
+      }
+      "post" => {
+        let [from, game, days, moves, start] = fields(kind, rest)?;
+        Update::Post {
+          from: from.into(),
+          game: game.into(),
+          days: days.parse()?,
+          start: Position::from_fen(start)?,
+          moves: parse_moves(moves)?,
+        }
+      }
+      "error" => Update::Error { message: rest.into() },
+      _ => bail!("unknown update {line:?}"),
+    })
+  }
+}
+
+/// What a broadcast tells its viewers.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Relay {
+  /// First message, naming the broadcast.
+  Hello {
+    version: u32,
+    name: String,
+  },
+  /// A PGN tag of the game, like the players.
+  Tag {
+    name: String,
+    value: String,
+  },
+  /// A new game from `start`, dropping the moves.
+  Start {
+    start: Position,
+  },
+  /// The move after `ply` moves, with its glyphs and PGN comment.
+  Move {
+    ply: usize,
+    mv: Move,
+    nags: Vec<u8>,
+    comment: String,
+  },
+  /// New glyphs and comment for the move after `ply` moves.
+  Annotate {
+    ply: usize,
+    nags: Vec<u8>,
+    comment: String,
+  },
+  /// Takes back the moves after the first `ply`.
+  Truncate {
+    ply: usize,
+  },
+  Result {
+    outcome: Outcome,
+  },
+  Bye,
+}
+
+fn format_nags(nags: &[u8]) -> String {
+  let nags: Vec<String> = nags.iter().map(u8::to_string).collect();
+  if nags.is_empty() {
+    "-".into()
+  } else {
+    nags.join(",")
+  }
+}
+
+/// Glyphs and the comment taking the rest of the line.
+fn parse_annotations(s: &str) -> anyhow::Result<(Vec<u8>, String)> {
+  let (nags, comment) = s.split_once(' ').unwrap_or((s, ""));
+  let nags = match nags {
+    "-" => Vec::new(),
+    nags => nags.split(',').map(str::parse).collect::<Result<_, _>>()?,
+  };
+  Ok((nags, comment.into()))
+}
+
+impl fmt::Display for Relay {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    match self {
+      Relay::Hello { version, name } => write!(f, "hello {version} {name}"),
+      Relay::Tag { name, value } => write!(f, "tag {name} {value}"),
+      Relay::Start { start } => write!(f, "start {}", start.to_fen()),
+      Relay::Move { ply, mv, nags, comment } => {
+        write!(f, "move {ply} {mv} {} {comment}", format_nags(nags))
+      }
+      Relay::Annotate { ply, nags, comment } => {
+        write!(f, "annotate {ply} {} {comment}", format_nags(nags))
+      }
+      Relay::Truncate { ply } => write!(f, "truncate {ply}"),
+      Relay::Result { outcome } => write!(f, "result {outcome}"),
+      Relay::Bye => write!(f, "bye"),
+    }
+  }
+}
+
+impl FromStr for Relay {
+  type Err = anyhow::Error;
+
+  fn from_str(line: &str) -> anyhow::Result<Relay> {
+    let line = line.trim();
+    let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+    Ok(match kind {
+      "hello" => {
+        let [version, name] = fields(kind, rest)?;
+        Relay::Hello { version: version.parse()?, name: name.into() }
+      }
+      "tag" => {
+        let (name, value) = rest.split_once(' ').unwrap_or((rest, ""));
+        Relay::Tag { name: name.into(), value: value.into() }
+      }
+      "start" => Relay::Start { start: Position::from_fen(rest)? },
+      "move" => {
+        let [ply, mv, annotations] = fields(kind, rest)?;
+        let (nags, comment) = parse_annotations(annotations)?;
+        Relay::Move {
+          ply: ply.parse()?,
+          mv: Move::from_uci(mv)?,
+          nags,
+          comment,
+        }
+      }
+      "annotate" => {
+        let [ply, annotations] = fields(kind, rest)?;
+        let (nags, comment) = parse_annotations(annotations)?;
+        Relay::Annotate { ply: ply.parse()?, nags, comment }
+      }
+      "truncate" => Relay::Truncate { ply: rest.parse()? },
+      "result" => Relay::Result {
+        outcome: Outcome::parse(rest)
+          .ok_or_else(|| anyhow!("invalid result {rest:?}"))?,
+      },
+      "bye" => Relay::Bye,
+      _ => bail!("unknown relay message {line:?}"),
+    })
+  }
+}
+
+/// Writes a message and its newline.
+pub fn write_message(
+  writer: &mut impl Write,
+  message: &impl fmt::Display,
+) -> anyhow::Result<()> {
+  writeln!(writer, "{message}")?;
+  writer.flush()?;
+  Ok(())
+}
+
+/// Reads the next [`Message`] from the other player, `None` at the end
+/// of the stream.
+pub fn read_message(
+  reader: &mut impl BufRead
+) -> anyhow::Result<Option<Message>> {
+  read(reader)
+}
+
+/// Reads the next message of any kind, skipping blank lines.
+pub fn read<M: FromStr<Err = anyhow::Error>>(
+  reader: &mut impl BufRead
+) -> anyhow::Result<Option<M>> {
+  let mut line = String::new();
+  loop {
+    line.clear();
+    let read = reader.take(MAX_LINE as u64).read_line(&mut line)?;
+    if read == 0 {
+      return Ok(None);
+    }
+    if read == MAX_LINE && !line.ends_with('\n') {
+      bail!("line over {MAX_LINE} bytes");
+    }
+    if !line.trim().is_empty() {
+      return line.parse().map(Some);
+    }
+  }
+}
@@ -0,0 +1,291 @@
+//! Tactics puzzles in the CSV format of the open lichess puzzle database:
+//! `PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,
+//! GameUrl,OpeningTags`. The FEN is the position before the opponent's
+//! move that sets the puzzle up, the moves alternate from there.
+
+use std::{collections::HashSet, fs, io, path::Path};
+
+use anyhow::{anyhow, bail};
+
+use crate::{
+  board::{Move, Position},
+  glicko::{self, Rating},
+};
+
+#[derive(Clone, Debug)]
+pub struct Puzzle {
+  pub id: String,
+  pub start: Position,
+  /// The setup move, then the solution with the opponent's replies.
+  pub moves: Vec<Move>,
+  pub rating: Rating,
+  pub popularity: i32,
+  pub plays: u32,
+  pub themes: Vec<String>,
+  pub url: String,
+}
+
+impl Puzzle {
+  pub fn has_theme(
+    &self,
+    theme: &str,
+  ) -> bool {
+    self.themes.iter().any(|own| own == theme)
+  }
+
+  /// The position the solver starts from, after the setup move.
+  pub fn position(&self) -> Position {
+    self.start.played(self.moves[0])
+  }
+}
+
+pub fn parse_record(line: &str) -> anyhow::Result<Puzzle> {
+  let fields: Vec<&str> = line.trim_end().split(',').collect();
+  if fields.len() < 8 {
+    bail!("expected at least 8 fields, got {}", fields.len());
+  }
+  let start = Position::from_fen(fields[1])?;
+  let mut position = start.clone();
+  let mut moves = Vec::new();
+  for uci in fields[2].split_whitespace() {
+    let mv = Move::from_uci(uci)?;
+    if !position.is_legal(mv) {
+      bail!("illegal move {mv} in {}", position.to_fen());
+    }
+    position.play(mv);
+    moves.push(mv);
+  }
+  if moves.len() < 2 {
+    bail!("puzzle {} has no solution", fields[0]);
+  }
+  let number = |i: usize| -> anyhow::Result<f64> {
+    let field = fields[i];
+    field.parse().map_err(|_| anyhow!("invalid number {field:?}"))
+  };
+  Ok(Puzzle {
+    id: fields[0].to_string(),
+    start,
+    moves,
+    rating: Rating::new(number(3)?, number(4)?),
+    popularity: number(5)? as i32,
+    plays: number(6)? as u32,
+    themes: fields[7].split_whitespace().map(String::from).collect(),
+    url: fields.get(8).unwrap_or(&"").to_string(),
+  })
+}
+
+/// Reads every puzzle of a CSV file, skipping the header line if there
+/// is one.
+pub fn parse_csv(text: &str) -> anyhow::Result<Vec<Puzzle>> {
+  text
+    .lines()
+    .enumerate()
+    .filter(|(i, line)| {
+      !line.trim().is_empty() && (*i > 0 || !line.starts_with("PuzzleId"))
+    })
+    .map(|(i, line)| {
+      parse_record(line).map_err(|err| anyhow!("line {}: {err}", i + 1))
+    })
+    .collect()
+}
+
+/// Every theme of the puzzles, sorted.
+pub fn themes(puzzles: &[Puzzle]) -> Vec<String> {
+  let mut themes: Vec<String> = puzzles
+    .iter()
+    .flat_map(|puzzle| puzzle.themes.iter().cloned())
+    .collect::<HashSet<_>>()
+    .into_iter()
+    .collect();
+  themes.sort();
+  themes
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+  /// Right move, the opponent replied.
+  Correct {
+    reply: Move,
+  },
+  Solved,
+  Wrong,
+}
+
+/// One try at a puzzle.
+#[derive(Clone, Debug)]
+pub struct Attempt {
+  pub puzzle: Puzzle,
+  /// Index in `puzzle.moves` of the next move to find.
+  ply: usize,
+  position: Position,
+  failed: bool,
+}
+
+impl Attempt {
+  /// Plays the setup move.
+  pub fn new(puzzle: Puzzle) -> Self {
+    let position = puzzle.position();
+    Self { puzzle, ply: 1, position, failed: false }
+  }
+
+  pub fn position(&self) -> &Position {
+    &self.position
+  }
+
+  pub fn is_solved(&self) -> bool {
+    self.ply >= self.puzzle.moves.len()
+  }
+
+  /// Whether a wrong move was played or the solution shown.
+  pub fn has_failed(&self) -> bool {
+    self.failed
+  }
+
+  /// The move to find.
+  pub fn hint(&self) -> Option<Move> {
+    self.puzzle.moves.get(self.ply).copied()
+  }
+
+  /// Gives up: counts as failed.
+  pub fn give_up(&mut self) -> Option<Move> {
+    self.failed = true;
+    self.hint()
+  }
+
+  /// Checks a move of the solver. Any mate solves the puzzle, not just
+  /// the one in the solution. Wrong moves are not played.
+  pub fn play(
+    &mut self,
+    mv: Move,
+  ) -> Verdict {
+    let Some(expected) = self.hint() else {
+      return Verdict::Solved;
+    };
+    if !self.position.is_legal(mv) {
+      return Verdict::Wrong;
+    }
+    let after = self.position.played(mv);
+    if after.is_checkmate() {
+      self.position = after;
+      self.ply = self.puzzle.moves.len();
+      return Verdict::Solved;
+    }
+    if mv != expected {
+      self.failed = true;
+      return Verdict::Wrong;
+    }
+    self.position = after;
+    self.ply += 1;
+    match self.puzzle.moves.get(self.ply) {
+      Some(&reply) => {
+        self.position.play(reply);
+        self.ply += 1;
+        Verdict::Correct { reply }
+      }
+      None => Verdict::Solved,
+    }
+  }
+}
+
+/// The solver's rating and which puzzles they have seen.
+#[derive(Clone, Debug, Default)]
+pub struct Trainer {
+  pub rating: Rating,
+  pub solved: u32,
+  pub failed: u32,
+  pub attempted: HashSet<String>,
+}
+
+impl Trainer {
+  /// The unseen puzzle closest to the solver's rating, among those with
+  /// `theme` if there is one.
+  pub fn next<'a>(
+    &self,
+    puzzles: &'a [Puzzle],
+    theme: Option<&str>,
+  ) -> Option<&'a Puzzle> {
+    puzzles
+      .iter()
+      .filter(|puzzle| !self.attempted.contains(&puzzle.id))
+      .filter(|puzzle| theme.is_none_or(|theme| puzzle.has_theme(theme)))
+      .min_by(|a, b| {
+        let distance =
+          |puzzle: &Puzzle| (puzzle.rating.rating - self.rating.rating).abs();
+        distance(a).total_cmp(&distance(b))
+      })
+  }
+
+  /// Counts a finished attempt as a game against the puzzle.
+  pub fn record(
+    &mut self,
+    puzzle: &Puzzle,
+    solved: bool,
+  ) {
+    let score = if solved { 1.0 } else { 0.0 };
+    self.rating = self.rating.updated(&[(puzzle.rating, score)], glicko::TAU);
+    if solved {
+      self.solved += 1;
+    } else {
+      self.failed += 1;
+    }
+    self.attempted.insert(puzzle.id.clone());
+  }
+
+  /// A line `rating deviation volatility solved failed`, then the ids of
+  /// the puzzles seen, one per line.
+  pub fn to_text(&self) -> String {
+    let Rating { rating, deviation, volatility } = self.rating;
+    let mut text = format!(
+      "{rating} {deviation} {volatility} {} {}\n",
+      self.solved, self.failed
+    );
+    let mut attempted: Vec<&String> = self.attempted.iter().collect();
+    attempted.sort();
+    for id in attempted {
+      text.push_str(id);
+      text.push('\n');
+    }
+    text
+  }
+
+  pub fn from_text(text: &str) -> anyhow::Result<Trainer> {
+    let mut lines = text.lines();
+    let first = lines.next().ok_or_else(|| anyhow!("empty rating file"))?;
+    let fields: Vec<&str> = first.split_whitespace().collect();
+    let [rating, deviation, volatility, solved, failed] = fields[..] else {
+      bail!("invalid rating line {first:?}");
+    };
+    Ok(Trainer {
+      rating: Rating {
+        rating: rating.parse()?,
+        deviation: deviation.parse()?,
+        volatility: volatility.parse()?,
+      },
+      solved: solved.parse()?,
+      failed: failed.parse()?,
+      attempted: lines
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(String::from)
+        .collect(),
+    })
+  }
+
+  /// Reads the rating file, starting afresh if there is none yet.
+  pub fn load(path: &Path) -> anyhow::Result<Trainer> {
+    match fs::read_to_string(path) {
+      Ok(text) => Trainer::from_text(&text),
+      Err(err) if err.kind() == io::ErrorKind::NotFound => {
+        Ok(Trainer::default())
+      }
+      Err(err) => Err(err.into()),
+    }
+  }
+
+  pub fn save(
+    &self,
+    path: &Path,
+  ) -> anyhow::Result<()> {
+    Ok(fs::write(path, self.to_text())?)
+  }
+}
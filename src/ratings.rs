@@ -0,0 +1,300 @@
+//! Ratings of the players of a game database, Glicko-2 as in `glicko`,
+//! one for each category of time control. Each game is a rating period
+//! of its own, both players rated from before it.
+//!
+//! The games rated are those of a [`Database`], in its order, so the
+//! ratings can always be recomputed from it; the ratings file only saves
+//! doing so, keeping how many games it counted and each rating after each
+//! game for the history.
+
+use std::{
+  collections::BTreeMap, fmt, fs, io, path::Path, str::FromStr, time::Duration,
+};
+
+use anyhow::{anyhow, bail};
+
+use crate::{
+  board::Color,
+  database::Database,
+  glicko::{self, Rating},
+  pgn::Game,
+  tournament::TimeControl,
+};
+
+/// A rating whose deviation is above this is not settled yet.
+const PROVISIONAL: f64 = 110.0;
+
+/// Games rated together, by how long they take.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Category {
+  Bullet,
+  Blitz,
+  Rapid,
+  Classical,
+  Correspondence,
+}
+
+impl Category {
+  pub const ALL: [Category; 5] = [
+    Category::Bullet,
+    Category::Blitz,
+    Category::Rapid,
+    Category::Classical,
+    Category::Correspondence,
+  ];
+
+  pub fn as_str(self) -> &'static str {
+    match self {
+      Category::Bullet => "bullet",
+      Category::Blitz => "blitz",
+      Category::Rapid => "rapid",
+      Category::Classical => "classical",
+      Category::Correspondence => "correspondence",
+    }
+  }
+
+  /// The category of a clock, by the time of a game of forty moves each.
+  pub fn of(control: &TimeControl) -> Option<Category> {
+    let TimeControl::Clock { base, increment } = *control else {
+      return None;
+    };
+    let estimate = base + increment * 40;
+    Some(if estimate < Duration::from_secs(180) {
+      Category::Bullet
+    } else if estimate < Duration::from_secs(480) {
+      Category::Blitz
+    } else if estimate < Duration::from_secs(1500) {
+      Category::Rapid
+    } else {
+      Category::Classical
+    })
+  }
+
+  /// The category of a game by its tags: correspondence with days per
+  /// move, otherwise the `TimeControl` tag.
+  pub fn of_game(game: &Game) -> Option<Category> {
+    if game.tag("DaysPerMove").is_some() {
+      return Some(Category::Correspondence);
+    }
+    Category::of(&game.tag("TimeControl")?.parse().ok()?)
+  }
+}
+
+impl fmt::Display for Category {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl FromStr for Category {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> anyhow::Result<Category> {
+    Category::ALL
+      .into_iter()
+      .find(|category| category.as_str() == s)
+      .ok_or_else(|| anyhow!("unknown category {s:?}"))
+  }
+}
+
+/// A player in one category.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Player {
+  /// The rating after each game, the first game first.
+  pub history: Vec<Rating>,
+}
+
+impl Player {
+  pub fn rating(&self) -> Rating {
+    self.history.last().copied().unwrap_or_default()
+  }
+
+  pub fn games(&self) -> usize {
+    self.history.len()
+  }
+
+  pub fn is_provisional(&self) -> bool {
+    self.rating().deviation > PROVISIONAL
+  }
+}
+
+/// The players of a game, if it is rated: one with a result between two
+/// players named in a category, unless tagged `Rated "false"`.
+fn rated(game: &Game) -> Option<(Category, [&str; 2], f64)> {
+  if game.tag("Rated") == Some("false") {
+    return None;
+  }
+  let category = Category::of_game(game)?;
+  let score = game.outcome.score(Color::White)?;
+  let name = |tag| game.tag(tag).map(str::trim).filter(|&name| name != "?");
+  let (white, black) = (name("White")?, name("Black")?);
+  if white.is_empty() || black.is_empty() || white == black {
+    return None;
+  }
+  Some((category, [white, black], score))
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Ratings {
+  players: BTreeMap<(Category, String), Player>,
+  /// Games of the database counted so far, rated or not.
+  games: usize,
+}
+
+impl Ratings {
+  /// Rates the games of a database from the first.
+  pub fn recompute(database: &Database) -> anyhow::Result<Ratings> {
+    let mut ratings = Ratings::default();
+    ratings.catch_up(database)?;
+    Ok(ratings)
+  }
+
+  /// Rates the games added to the database since the last time,
+  /// returning how many were rated.
+  pub fn catch_up(
+    &mut self,
+    database: &Database,
+  ) -> anyhow::Result<usize> {
+    if self.games > database.len() {
+      bail!("the ratings counted more games than the database has");
+    }
+    let mut rated = 0;
+    for id in self.games..database.len() {
+      if self.rate(&database.game(id as u32)?) {
+        rated += 1;
+      }
+      self.games = id + 1;
+    }
+    Ok(rated)
+  }
+
+  /// Rates a game just added to the database, counting it without reading
+  /// it back. Returns whether it was rated.
+  pub fn add(
+    &mut self,
+    game: &Game,
+  ) -> bool {
+    self.games += 1;
+    self.rate(game)
+  }
+
+  /// Updates the ratings of both players after a game, if it is rated.
+  fn rate(
+    &mut self,
+    game: &Game,
+  ) -> bool {
+    let Some((category, [white, black], score)) = rated(game) else {
+      return false;
+    };
+    let before = [white, black].map(|name| self.rating(name, category));
+    for (name, color) in [(white, Color::White), (black, Color::Black)] {
+      let (opponent, score) =
+        color.fold((before[1], score), (before[0], 1.0 - score));
+      let rating =
+        before[color.index()].updated(&[(opponent, score)], glicko::TAU);
+      let player = self.players.entry((category, name.into())).or_default();
+      player.history.push(rating);
+    }
+    true
+  }
+
+  /// Games of the database counted so far.
+  pub fn games(&self) -> usize {
+    self.games
+  }
+
+  pub fn player(
+    &self,
+    name: &str,
+    category: Category,
+  ) -> Option<&Player> {
+    self.players.get(&(category, name.to_string()))
+  }
+
+  /// A player's rating, the starting one if they have no games yet.
+  pub fn rating(
+    &self,
+    name: &str,
+    category: Category,
+  ) -> Rating {
+    self.player(name, category).map(Player::rating).unwrap_or_default()
+  }
+
+  /// The players of a category, best first.
+  pub fn leaderboard(
+    &self,
+    category: Category,
+  ) -> Vec<(&str, &Player)> {
+    let mut players: Vec<(&str, &Player)> = self
+      .players
+      .iter()
+      .filter(|((of, _), _)| *of == category)
+      .map(|((_, name), player)| (name.as_str(), player))
+      .collect();
+    players.sort_by(|(_, a), (_, b)| {
+      b.rating().rating.total_cmp(&a.rating().rating)
+    });
+    players
+  }
+
+  /// A line with the games counted, then a line `category rating
+  /// deviation volatility name` for each rating in each history.
+  pub fn to_text(&self) -> String {
+    let mut text = format!("{}\n", self.games);
+    for ((category, name), player) in &self.players {
+      for Rating { rating, deviation, volatility } in &player.history {
+        text.push_str(&format!(
+          "{category} {rating} {deviation} {volatility} {name}\n"
+        ));
+      }
+    }
+    text
+  }
+
+  pub fn from_text(text: &str) -> anyhow::Result<Ratings> {
+    let mut lines = text.lines();
+    let first = lines.next().ok_or_else(|| anyhow!("empty ratings file"))?;
+    let mut ratings =
+      Ratings { players: BTreeMap::new(), games: first.trim().parse()? };
+    for line in lines.filter(|line| !line.trim().is_empty()) {
+      let fields: Vec<&str> = line.splitn(5, ' ').collect();
+      let [category, rating, deviation, volatility, name] = fields[..] else {
+        bail!("invalid rating line {line:?}");
+      };
+      let rating = Rating {
+        rating: rating.parse()?,
+        deviation: deviation.parse()?,
+        volatility: volatility.parse()?,
+      };
+      let player =
+        ratings.players.entry((category.parse()?, name.into())).or_default();
+      player.history.push(rating);
+    }
+    Ok(ratings)
+  }
+
+  /// Reads the ratings file, starting afresh if there is none yet.
+  pub fn load(path: &Path) -> anyhow::Result<Ratings> {
+    match fs::read_to_string(path) {
+      Ok(text) => Ratings::from_text(&text),
+      Err(err) if err.kind() == io::ErrorKind::NotFound => {
+        Ok(Ratings::default())
+      }
+      Err(err) => Err(err.into()),
+    }
+  }
+
+  /// Writes the ratings file through a temporary one, so that it is never
+  /// seen half written.
+  pub fn save(
+    &self,
+    path: &Path,
+  ) -> anyhow::Result<()> {
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, self.to_text())?;
+    Ok(fs::rename(&temporary, path)?)
+  }
+}
@@ -0,0 +1,371 @@
+//! Opening and endgame repertoires drilled with spaced repetition. A
+//! repertoire is read from PGN: every variation is a line to know for one
+//! side. Drilling a line, the trainer plays the other side and the user
+//! must find the repertoire moves; how well they did schedules the next
+//! review of the line as in SuperMemo's SM-2.
+
+use std::{
+  collections::HashMap,
+  fs, io,
+  path::Path,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail};
+
+use crate::{
+  board::{Color, Move, Position},
+  gametree::{GameTree, NodeId},
+  pgn,
+};
+
+/// Days since the Unix epoch.
+pub fn today() -> u64 {
+  let since = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+  since.as_secs() / 86400
+}
+
+/// One line of the repertoire, ending with a move of its side.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+  pub start: Position,
+  pub moves: Vec<Move>,
+}
+
+impl Line {
+  /// Names the line in the schedule file: its moves in UCI, after the
+  /// FEN and a `;` when it does not start from the usual position.
+  pub fn key(&self) -> String {
+    let moves: Vec<String> = self.moves.iter().map(Move::to_string).collect();
+    match self.start == Position::startpos() {
+      true => moves.join(" "),
+      false => format!("{};{}", self.start.to_fen(), moves.join(" ")),
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct Repertoire {
+  pub color: Color,
+  pub lines: Vec<Line>,
+  /// Moves of the repertoire's side in each position, by its hash.
+  moves: HashMap<u64, Vec<Move>>,
+}
+
+fn leaves(
+  tree: &GameTree,
+  id: NodeId,
+  found: &mut Vec<NodeId>,
+) {
+  let children = &tree.node(id).children;
+  if children.is_empty() {
+    found.push(id);
+  }
+  for &child in children {
+    leaves(tree, child, found);
+  }
+}
+
+impl Repertoire {
+  /// The lines of `color` in every game and variation of the trees. Moves
+  /// of the other side after the last move of `color` are left out.
+  pub fn new(
+    color: Color,
+    trees: &[GameTree],
+  ) -> Repertoire {
+    let mut lines = Vec::new();
+    for tree in trees {
+      let mut ends = Vec::new();
+      leaves(tree, tree.root(), &mut ends);
+      for end in ends {
+        let mut moves = tree.moves_to(end);
+        let first = tree.start().side_to_move();
+        let is_own = |ply: usize| ply.is_multiple_of(2) == (first == color);
+        while !moves.is_empty() && !is_own(moves.len() - 1) {
+          moves.pop();
+        }
+        let line = Line { start: tree.start().clone(), moves };
+        if !line.moves.is_empty() && !lines.contains(&line) {
+          lines.push(line);
+        }
+      }
+    }
+
+    let mut moves: HashMap<u64, Vec<Move>> = HashMap::new();
+    for line in &lines {
+      let mut position = line.start.clone();
+      for &mv in &line.moves {
+        if position.side_to_move() == color {
+          let known = moves.entry(position.hash()).or_default();
+          if !known.contains(&mv) {
+            known.push(mv);
+          }
+        }
+        position.play(mv);
+      }
+    }
+    Repertoire { color, lines, moves }
+  }
+
+  pub fn from_pgn(
+    color: Color,
+    text: &str,
+  ) -> anyhow::Result<Repertoire> {
+    let trees: Vec<GameTree> =
+      pgn::parse_trees(text)?.into_iter().map(|(_, tree)| tree).collect();
+    let repertoire = Repertoire::new(color, &trees);
+    if repertoire.lines.is_empty() {
+      bail!("no {} moves in the repertoire", color.fold("white", "black"));
+    }
+    Ok(repertoire)
+  }
+
+  /// The repertoire's moves in a position, whichever line leads there.
+  pub fn moves_at(
+    &self,
+    position: &Position,
+  ) -> &[Move] {
+    self.moves.get(&position.hash()).map_or(&[], Vec::as_slice)
+  }
+}
+
+/// Review state of a line, as in SM-2.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Card {
+  /// Day of the next review, see [`today`].
+  pub due: u64,
+  /// Days between the last review and the next.
+  pub interval: u32,
+  /// Reviews passed in a row.
+  pub repetitions: u32,
+  pub ease: f64,
+}
+
+impl Default for Card {
+  fn default() -> Self {
+    Self { due: 0, interval: 0, repetitions: 0, ease: 2.5 }
+  }
+}
+
+impl Card {
+  /// Schedules the next review after one graded `quality`, from 0 for a
+  /// blackout to 5 for a perfect recall. Below 3 the line starts over.
+  pub fn review(
+    &mut self,
+    quality: u8,
+    today: u64,
+  ) {
+    let quality = quality.min(5);
+    if quality < 3 {
+      self.repetitions = 0;
+      self.interval = 1;
+    } else {
+      self.interval = match self.repetitions {
+        0 => 1,
+        1 => 6,
+        _ => (self.interval as f64 * self.ease).round() as u32,
+      };
+      self.repetitions += 1;
+    }
+    let miss = (5 - quality) as f64;
+    self.ease = (self.ease + 0.1 - miss * (0.08 + miss * 0.02)).max(1.3);
+    self.due = today + self.interval as u64;
+  }
+}
+
+/// The cards of the lines reviewed so far, by line key.
+#[derive(Clone, Debug, Default)]
+pub struct Schedule {
+  pub cards: HashMap<String, Card>,
+}
+
+impl Schedule {
+  /// The line to drill next: the most overdue one, then a line never
+  /// drilled. `None` when nothing is due `today`.
+  pub fn next(
+    &self,
+    repertoire: &Repertoire,
+    today: u64,
+  ) -> Option<usize> {
+    (0..repertoire.lines.len())
+      .map(|i| (i, self.cards.get(&repertoire.lines[i].key())))
+      .filter(|(_, card)| card.is_none_or(|card| card.due <= today))
+      .min_by_key(|(_, card)| card.map_or((1, 0), |card| (0, card.due)))
+      .map(|(i, _)| i)
+  }
+
+  /// Lines due `today`, new ones included.
+  pub fn due(
+    &self,
+    repertoire: &Repertoire,
+    today: u64,
+  ) -> usize {
+    let due = |line: &Line| {
+      self.cards.get(&line.key()).is_none_or(|card| card.due <= today)
+    };
+    repertoire.lines.iter().filter(|line| due(line)).count()
+  }
+
+  pub fn record(
+    &mut self,
+    line: &Line,
+    quality: u8,
+    today: u64,
+  ) {
+    self.cards.entry(line.key()).or_default().review(quality, today);
+  }
+
+  /// A line per card: `due interval repetitions ease`, a tab and the key.
+  pub fn to_text(&self) -> String {
+    let mut cards: Vec<(&String, &Card)> = self.cards.iter().collect();
+    cards.sort_by_key(|(key, _)| *key);
+    let mut text = String::new();
+    for (key, card) in cards {
+      let Card { due, interval, repetitions, ease } = card;
+      text.push_str(&format!("{due} {interval} {repetitions} {ease}\t{key}\n"));
+    }
+    text
+  }
+
+  pub fn from_text(text: &str) -> anyhow::Result<Schedule> {
+    let mut cards = HashMap::new();
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+      let (card, key) = line
+        .split_once('\t')
+        .ok_or_else(|| anyhow!("invalid schedule line {line:?}"))?;
+      let fields: Vec<&str> = card.split_whitespace().collect();
+      let [due, interval, repetitions, ease] = fields[..] else {
+        bail!("invalid schedule line {line:?}");
+      };
+      let card = Card {
+        due: due.parse()?,
+        interval: interval.parse()?,
+        repetitions: repetitions.parse()?,
+        ease: ease.parse()?,
+      };
+      cards.insert(key.to_string(), card);
+    }
+    Ok(Schedule { cards })
+  }
+
+  /// Reads the schedule file, starting afresh if there is none yet.
+  pub fn load(path: &Path) -> anyhow::Result<Schedule> {
+    match fs::read_to_string(path) {
+      Ok(text) => Schedule::from_text(&text),
+      Err(err) if err.kind() == io::ErrorKind::NotFound => {
+        Ok(Schedule::default())
+      }
+      Err(err) => Err(err.into()),
+    }
+  }
+
+  pub fn save(
+    &self,
+    path: &Path,
+  ) -> anyhow::Result<()> {
+    Ok(fs::write(path, self.to_text())?)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+  /// The move of the line, the trainer answered with `reply`.
+  Correct { reply: Move },
+  /// The last move of the line.
+  Done,
+  /// A repertoire move, but of another line. Not played.
+  Alternative,
+  /// Not in the repertoire. Not played.
+  Wrong,
+}
+
+/// Going through one line.
+#[derive(Clone, Debug)]
+pub struct Drill {
+  pub line: Line,
+  /// Index in the line of the next move.
+  ply: usize,
+  position: Position,
+  mistakes: u32,
+  shown: bool,
+}
+
+impl Drill {
+  /// Plays the other side's moves up to the first one to find.
+  pub fn new(
+    line: Line,
+    color: Color,
+  ) -> Drill {
+    let mut position = line.start.clone();
+    let mut ply = 0;
+    while ply < line.moves.len() && position.side_to_move() != color {
+      position.play(line.moves[ply]);
+      ply += 1;
+    }
+    Drill { line, ply, position, mistakes: 0, shown: false }
+  }
+
+  pub fn position(&self) -> &Position {
+    &self.position
+  }
+
+  /// Moves played so far, from the start of the line.
+  pub fn played(&self) -> &[Move] {
+    &self.line.moves[..self.ply]
+  }
+
+  pub fn is_done(&self) -> bool {
+    self.ply >= self.line.moves.len()
+  }
+
+  /// The move to find.
+  pub fn expected(&self) -> Option<Move> {
+    self.line.moves.get(self.ply).copied()
+  }
+
+  /// Gives the move away, the line then counts as forgotten.
+  pub fn show(&mut self) -> Option<Move> {
+    self.shown = true;
+    self.expected()
+  }
+
+  pub fn play(
+    &mut self,
+    mv: Move,
+    repertoire: &Repertoire,
+  ) -> Step {
+    let Some(expected) = self.expected() else {
+      return Step::Done;
+    };
+    if mv != expected {
+      if repertoire.moves_at(&self.position).contains(&mv) {
+        return Step::Alternative;
+      }
+      if self.position.is_legal(mv) {
+        self.mistakes += 1;
+      }
+      return Step::Wrong;
+    }
+    self.position.play(mv);
+    self.ply += 1;
+    match self.line.moves.get(self.ply) {
+      Some(&reply) => {
+        self.position.play(reply);
+        self.ply += 1;
+        Step::Correct { reply }
+      }
+      None => Step::Done,
+    }
+  }
+
+  /// SM-2 grade of the drill: 5 without a mistake, 3 with one, 1 with
+  /// more and 0 when a move was shown.
+  pub fn quality(&self) -> u8 {
+    match (self.shown, self.mistakes) {
+      (true, _) => 0,
+      (false, 0) => 5,
+      (false, 1) => 3,
+      (false, _) => 1,
+    }
+  }
+}
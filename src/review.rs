@@ -0,0 +1,381 @@
+//! Game review: an engine evaluates every position of the main line and
+//! each move is judged by how much of the mover's winning chances it gave
+//! away. Thresholds and the accuracy formula follow lichess.
+
+use std::{
+  sync::mpsc::{self, TryRecvError},
+  thread,
+};
+
+use anyhow::bail;
+
+use crate::{
+  analysis::{white_pov, Analyzer},
+  board::{Color, Move, Position},
+  gametree::{GameTree, NodeId},
+  san,
+  search::{Limits, Score, SearchEvent},
+};
+
+/// Centipawns past which a position counts as won, no matter how much
+/// more material there is.
+const MAX_CP: i32 = 1000;
+
+/// Judgement of a move by the winning chances lost, in percent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Judgement {
+  Inaccuracy,
+  Mistake,
+  Blunder,
+}
+
+impl Judgement {
+  pub const ALL: [Judgement; 3] =
+    [Judgement::Inaccuracy, Judgement::Mistake, Judgement::Blunder];
+
+  /// Least loss of winning chances, in percent, for the judgement.
+  pub fn threshold(self) -> f64 {
+    match self {
+      Judgement::Inaccuracy => 5.0,
+      Judgement::Mistake => 10.0,
+      Judgement::Blunder => 15.0,
+    }
+  }
+
+  pub fn of(win_loss: f64) -> Option<Judgement> {
+    Judgement::ALL
+      .into_iter()
+      .rev()
+      .find(|judgement| win_loss >= judgement.threshold())
+  }
+
+  /// `?!`, `?` and `??`.
+  pub fn nag(self) -> u8 {
+    match self {
+      Judgement::Inaccuracy => 6,
+      Judgement::Mistake => 2,
+      Judgement::Blunder => 4,
+    }
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Judgement::Inaccuracy => "Inaccuracy",
+      Judgement::Mistake => "Mistake",
+      Judgement::Blunder => "Blunder",
+    }
+  }
+}
+
+/// Winning chances of white in percent, 50 for an even position.
+pub fn white_win(score: Score) -> f64 {
+  match score {
+    Score::Cp(cp) => {
+      let cp = cp.clamp(-MAX_CP, MAX_CP) as f64;
+      100.0 / (1.0 + (-0.00368208 * cp).exp())
+    }
+    Score::Mate(moves) if moves > 0 => 100.0,
+    Score::Mate(_) => 0.0,
+  }
+}
+
+/// Accuracy of a move losing `win_loss` percent of winning chances,
+/// 100 for a move keeping them all.
+pub fn move_accuracy(win_loss: f64) -> f64 {
+  let accuracy = 103.1668 * (-0.04354 * win_loss).exp() - 3.1669;
+  accuracy.clamp(0.0, 100.0)
+}
+
+/// Evaluation of one position of the game.
+#[derive(Clone, Debug)]
+pub struct Eval {
+  /// From white's point of view, `None` once the game is over.
+  pub score: Option<Score>,
+  /// What the engine would play, empty once the game is over.
+  pub best_line: Vec<Move>,
+  /// Winning chances of white in percent.
+  pub white_win: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct MoveReview {
+  pub mv: Move,
+  pub color: Color,
+  /// Centipawns the mover gave away, mates counting as `MAX_CP`.
+  pub cp_loss: i32,
+  /// Winning chances the mover gave away, in percent.
+  pub win_loss: f64,
+  pub accuracy: f64,
+  pub judgement: Option<Judgement>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Review {
+  /// The position before each move, then the final one.
+  pub evals: Vec<Eval>,
+  pub moves: Vec<MoveReview>,
+}
+
+impl Review {
+  fn of_color(
+    &self,
+    color: Color,
+  ) -> impl Iterator<Item = &MoveReview> {
+    self.moves.iter().filter(move |review| review.color == color)
+  }
+
+  /// Accuracy of a player over the game: the mean of the arithmetic and
+  /// harmonic means of their move accuracies, so that a few bad moves
+  /// weigh more than in a plain average.
+  pub fn accuracy(
+    &self,
+    color: Color,
+  ) -> Option<f64> {
+    let accuracies: Vec<f64> =
+      self.of_color(color).map(|review| review.accuracy).collect();
+    if accuracies.is_empty() {
+      return None;
+    }
+    let n = accuracies.len() as f64;
+    let mean = accuracies.iter().sum::<f64>() / n;
+    let harmonic = n
+      / accuracies.iter().map(|accuracy| 1.0 / accuracy.max(1.0)).sum::<f64>();
+    Some((mean + harmonic) / 2.0)
+  }
+
+  /// Average centipawn loss of a player.
+  pub fn average_cp_loss(
+    &self,
+    color: Color,
+  ) -> Option<f64> {
+    let losses: Vec<i32> =
+      self.of_color(color).map(|review| review.cp_loss).collect();
+    if losses.is_empty() {
+      return None;
+    }
+    Some(losses.iter().sum::<i32>() as f64 / losses.len() as f64)
+  }
+
+  pub fn count(
+    &self,
+    color: Color,
+    judgement: Judgement,
+  ) -> usize {
+    self
+      .of_color(color)
+      .filter(|review| review.judgement == Some(judgement))
+      .count()
+  }
+
+  /// Writes the review into the main line of `tree`: evaluations, glyphs
+  /// for the judged moves with a comment naming the better move, and the
+  /// engine's line as a variation. Earlier judgement glyphs are replaced.
+  pub fn annotate(
+    &self,
+    tree: &mut GameTree,
+  ) -> anyhow::Result<()> {
+    let mut parent = tree.root();
+    for (i, review) in self.moves.iter().enumerate() {
+      let node = tree.add_move(parent, review.mv)?;
+      let (before, after) = (&self.evals[i], &self.evals[i + 1]);
+      let annotations = tree.annotations_mut(node);
+      annotations.eval = after.score;
+      let judgements = Judgement::ALL.map(Judgement::nag);
+      annotations.nags.retain(|nag| !judgements.contains(nag));
+      if let Some(judgement) = review.judgement {
+        annotations.nags.insert(0, judgement.nag());
+        let position = &tree.node(parent).position;
+        let text = match before.best_line.first() {
+          Some(&best) => format!(
+            "{}. {} was best.",
+            judgement.name(),
+            san::to_san(position, best)
+          ),
+          None => format!("{}.", judgement.name()),
+        };
+        let annotations = tree.annotations_mut(node);
+        if !annotations.comment.contains(&text) {
+          annotations.add_comment(&text);
+        }
+        if before.best_line.first() != Some(&review.mv) {
+          let mut variation = parent;
+          for &mv in &before.best_line {
+            variation = tree.add_move(variation, mv)?;
+          }
+        }
+      }
+      parent = node;
+    }
+    Ok(())
+  }
+}
+
+/// Evaluates the position after `moves`.
+pub fn evaluate(
+  analyzer: &mut Analyzer,
+  start: &Position,
+  moves: &[Move],
+  limits: &Limits,
+) -> anyhow::Result<Eval> {
+  let mut position = start.clone();
+  for &mv in moves {
+    position.play(mv);
+  }
+  if position.legal_moves().is_empty() {
+    let white_win = match position.is_check() {
+      false => 50.0,
+      true if position.side_to_move() == Color::White => 0.0,
+      true => 100.0,
+    };
+    return Ok(Eval { score: None, best_line: Vec::new(), white_win });
+  }
+
+  let mut last = None;
+  for event in analyzer.start(start, moves, limits.clone())? {
+    match event {
+      SearchEvent::Info(info) if info.multipv <= 1 => last = Some(info),
+      SearchEvent::Info(_) => {}
+      SearchEvent::Done(_) => break,
+    }
+  }
+  let Some(info) = last else {
+    bail!("no evaluation of {}", position.to_fen());
+  };
+  let score = white_pov(info.score, position.side_to_move());
+  Ok(Eval {
+    score: Some(score),
+    best_line: info.pv,
+    white_win: white_win(score),
+  })
+}
+
+/// Reviews the main line of `tree`, calling `on_position` with the number
+/// of positions done and the total after each.
+pub fn review(
+  analyzer: &mut Analyzer,
+  tree: &GameTree,
+  limits: &Limits,
+  mut on_position: impl FnMut(usize, usize),
+) -> anyhow::Result<Review> {
+  let mainline = tree.mainline();
+  let moves = tree.moves_to(*mainline.last().unwrap_or(&tree.root()));
+  analyzer.new_game()?;
+  let mut evals = Vec::with_capacity(moves.len() + 1);
+  for ply in 0..=moves.len() {
+    evals.push(evaluate(analyzer, tree.start(), &moves[..ply], limits)?);
+    on_position(ply + 1, moves.len() + 1);
+  }
+
+  let mut position = tree.start().clone();
+  let moves = moves
+    .iter()
+    .zip(evals.windows(2))
+    .map(|(&mv, evals)| {
+      let color = position.side_to_move();
+      position.play(mv);
+      let sign = color.fold(1.0, -1.0);
+      let win_loss =
+        (sign * (evals[0].white_win - evals[1].white_win)).max(0.0);
+      let cp = |eval: &Eval| match eval.score {
+        Some(Score::Cp(cp)) => cp.clamp(-MAX_CP, MAX_CP),
+        Some(Score::Mate(moves)) => MAX_CP * moves.signum(),
+        None => ((eval.white_win - 50.0) / 50.0 * MAX_CP as f64) as i32,
+      };
+      let cp_loss = (sign as i32 * (cp(&evals[0]) - cp(&evals[1]))).max(0);
+      MoveReview {
+        mv,
+        color,
+        cp_loss,
+        win_loss,
+        accuracy: move_accuracy(win_loss),
+        judgement: Judgement::of(win_loss),
+      }
+    })
+    .collect();
+  Ok(Review { evals, moves })
+}
+
+/// Progress of a review running in the background.
+pub enum Progress {
+  Position(usize, usize),
+  Done(anyhow::Result<Review>),
+}
+
+/// A review running on its own thread, so the window stays responsive,
+/// and the last finished one.
+pub struct Reviewer {
+  pub review: Option<Review>,
+  /// Search depth per position.
+  pub depth: u32,
+  events: Option<mpsc::Receiver<Progress>>,
+  progress: (usize, usize),
+}
+
+impl Default for Reviewer {
+  fn default() -> Self {
+    Self { review: None, depth: 10, events: None, progress: (0, 0) }
+  }
+}
+
+impl Reviewer {
+  pub fn is_running(&self) -> bool {
+    self.events.is_some()
+  }
+
+  /// Positions done and the total.
+  pub fn progress(&self) -> (usize, usize) {
+    self.progress
+  }
+
+  /// Starts reviewing the main line of `tree` with `analyzer` at
+  /// `depth`, dropping a review still running.
+  pub fn start(
+    &mut self,
+    mut analyzer: Analyzer,
+    tree: &GameTree,
+  ) {
+    let limits = Limits { depth: Some(self.depth), ..Default::default() };
+    let (tx, rx) = mpsc::channel();
+    self.progress = (0, tree.mainline().len() + 1);
+    let tree = tree.clone();
+    thread::spawn(move || {
+      let progress = tx.clone();
+      let result = review(&mut analyzer, &tree, &limits, |done, total| {
+        let _ = progress.send(Progress::Position(done, total));
+      });
+      let _ = tx.send(Progress::Done(result));
+    });
+    self.events = Some(rx);
+  }
+
+  /// Takes in the progress made, returning the review when it is done.
+  pub fn poll(&mut self) -> Option<anyhow::Result<&Review>> {
+    let events = self.events.as_ref()?;
+    loop {
+      match events.try_recv() {
+        Ok(Progress::Position(done, total)) => self.progress = (done, total),
+        Ok(Progress::Done(result)) => {
+          self.events = None;
+          return Some(result.map(|review| &*self.review.insert(review)));
+        }
+        Err(TryRecvError::Empty) => return None,
+        Err(TryRecvError::Disconnected) => {
+          self.events = None;
+          return Some(Err(anyhow::anyhow!("review stopped")));
+        }
+      }
+    }
+  }
+}
+
+/// Index in the review of the position `id` shows, if it is on the main
+/// line.
+pub fn ply_of(
+  tree: &GameTree,
+  id: NodeId,
+) -> Option<usize> {
+  if !tree.is_mainline(id) {
+    return None;
+  }
+  Some(tree.path(id).len())
+}
@@ -0,0 +1,158 @@
+//! Standard algebraic notation, as used by PGN and shown to players.
+
+use anyhow::{anyhow, bail};
+
+use crate::board::{Color, Move, PieceKind, Position, Square};
+
+fn is_castling(
+  position: &Position,
+  mv: Move,
+) -> bool {
+  position.piece_at(mv.from).is_some_and(|piece| piece.kind == PieceKind::King)
+    && mv.from.file().abs_diff(mv.to.file()) == 2
+}
+
+/// SAN of a legal move, with the check or mate suffix.
+pub fn to_san(
+  position: &Position,
+  mv: Move,
+) -> String {
+  let mut san = san_without_suffix(position, mv);
+  let next = position.played(mv);
+  if next.is_check() {
+    san.push(if next.legal_moves().is_empty() { '#' } else { '+' });
+  }
+  san
+}
+
+fn san_without_suffix(
+  position: &Position,
+  mv: Move,
+) -> String {
+  if is_castling(position, mv) {
+    return if mv.to.file() == 6 { "O-O" } else { "O-O-O" }.into();
+  }
+  let kind = position.piece_at(mv.from).expect("no piece to move").kind;
+  let capture = position.is_capture(mv);
+  let mut san = String::new();
+  if kind == PieceKind::Pawn {
+    if capture {
+      san.push((b'a' + mv.from.file()) as char);
+    }
+  } else {
+    san.push(kind.char());
+    // other pieces of the same kind that could go to the same square
+    let rivals: Vec<Square> = position
+      .legal_moves()
+      .into_iter()
+      .filter(|other| other.to == mv.to && other.from != mv.from)
+      .filter(|other| position.piece_at(other.from).unwrap().kind == kind)
+      .map(|other| other.from)
+      .collect();
+    if !rivals.is_empty() {
+      let file = mv.from.to_string();
+      if rivals.iter().all(|sq| sq.file() != mv.from.file()) {
+        san.push_str(&file[..1]);
+      } else if rivals.iter().all(|sq| sq.rank() != mv.from.rank()) {
+        san.push_str(&file[1..]);
+      } else {
+        san.push_str(&file);
+      }
+    }
+  }
+  if capture {
+    san.push('x');
+  }
+  san.push_str(&mv.to.to_string());
+  if let Some(kind) = mv.promotion {
+    san.push('=');
+    san.push(kind.char());
+  }
+  san
+}
+
+/// Finds the legal move a SAN string stands for. Tolerates missing or
+/// superfluous capture marks, check suffixes, annotations and `0-0`.
+pub fn parse_san(
+  position: &Position,
+  san: &str,
+) -> anyhow::Result<Move> {
+  let cleaned: String = san
+    .trim()
+    .chars()
+    .filter(|c| !matches!(c, 'x' | '=' | '+' | '#' | '!' | '?'))
+    .map(|c| if c == '0' { 'O' } else { c })
+    .collect();
+  let legal = position.legal_moves();
+
+  if cleaned == "O-O" || cleaned == "O-O-O" {
+    let file = if cleaned == "O-O" { 6 } else { 2 };
+    return legal
+      .into_iter()
+      .find(|&mv| is_castling(position, mv) && mv.to.file() == file)
+      .ok_or_else(|| anyhow!("illegal castling {san:?}"));
+  }
+
+  let mut rest = cleaned.as_str();
+  let kind = match rest.chars().next() {
+    Some(c @ ('N' | 'B' | 'R' | 'Q' | 'K')) => {
+      rest = &rest[1..];
+      PieceKind::from_char(c).unwrap()
+    }
+    Some(_) => PieceKind::Pawn,
+    None => bail!("empty move"),
+  };
+  let mut promotion = None;
+  if kind == PieceKind::Pawn {
+    if let Some(c @ ('N' | 'B' | 'R' | 'Q')) = rest.chars().last() {
+      promotion = PieceKind::from_char(c);
+      rest = &rest[..rest.len() - 1];
+    }
+  }
+  if rest.len() < 2 || !rest.is_ascii() {
+    bail!("invalid move {san:?}");
+  }
+  let to: Square = rest[rest.len() - 2..].parse()?;
+  let hint = &rest[..rest.len() - 2];
+  let hint_file = hint.bytes().find(u8::is_ascii_lowercase);
+  let hint_rank = hint.bytes().find(u8::is_ascii_digit);
+
+  let mut candidates = legal.into_iter().filter(|mv| {
+    mv.to == to
+      && mv.promotion == promotion
+      && position.piece_at(mv.from).unwrap().kind == kind
+      && hint_file.is_none_or(|file| mv.from.file() == file - b'a')
+      && hint_rank.is_none_or(|rank| mv.from.rank() == rank - b'1')
+  });
+  match (candidates.next(), candidates.next()) {
+    (Some(mv), None) => Ok(mv),
+    (Some(_), Some(_)) => bail!("ambiguous move {san:?}"),
+    (None, _) => bail!("illegal move {san:?} in {}", position.to_fen()),
+  }
+}
+
+/// A sequence of moves in SAN with move numbers, like `12. e4 e5 13. Nf3`
+/// or `12... e5 13. Nf3` when black moves first.
+pub fn line(
+  position: &Position,
+  moves: &[Move],
+) -> String {
+  let mut position = position.clone();
+  let mut text = String::new();
+  for (i, &mv) in moves.iter().enumerate() {
+    if !position.is_legal(mv) {
+      break;
+    }
+    let number = position.fullmove_number();
+    if position.side_to_move() == Color::White {
+      text.push_str(&format!("{number}. "));
+    } else if i == 0 {
+      text.push_str(&format!("{number}... "));
+    }
+    text.push_str(&to_san(&position, mv));
+    text.push(' ');
+    position.play(mv);
+  }
+  text.pop();
+  text
+}
@@ -0,0 +1,926 @@
+//! Built-in engine: iterative deepening alpha-beta search.
+//! With more than one thread the search runs Lazy SMP: every thread
+//! searches the same root independently and they only share what they
+//! find through the lock-free transposition table. The first thread is
+//! the main one, it alone manages time and reports results.
+
+use std::{
+  fmt,
+  sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    mpsc, Arc,
+  },
+  thread,
+  time::{Duration, Instant},
+};
+
+use crate::{
+  board::{Move, PieceKind, Position},
+  book::{Book, Selection},
+  eval::{self, PIECE_VALUES},
+  syzygy::{Tablebase, Wdl},
+  tt::{Bound, Entry, TranspositionTable},
+};
+
+pub const MAX_PLY: usize = 128;
+pub const DEFAULT_HASH_MB: usize = 16;
+pub const MAX_THREADS: usize = 256;
+pub const MAX_MULTI_PV: usize = 256;
+
+const INFINITY: i32 = 32_000;
+/// Score of delivering mate right now, mate at ply `n` is `MATE - n`.
+const MATE: i32 = 31_000;
+const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+/// Score of a position the tablebase says is won, less the ply it is
+/// reached at, so that the search heads for it but never calls it mate.
+const TB_WIN: i32 = MATE_BOUND - MAX_PLY as i32;
+const TB_BOUND: i32 = TB_WIN - MAX_PLY as i32;
+// nodes between two checks of the clock and the stop flag
+const CHECK_INTERVAL: u64 = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Score {
+  /// Centipawns from the point of view of the side to move.
+  Cp(i32),
+  /// Mate in that many moves, negative if the side to move gets mated.
+  Mate(i32),
+}
+
+impl Score {
+  fn from_internal(score: i32) -> Score {
+    if score >= MATE_BOUND {
+      Score::Mate((MATE - score + 1) / 2)
+    } else if score <= -MATE_BOUND {
+      Score::Mate(-(MATE + score) / 2)
+    } else {
+      Score::Cp(score)
+    }
+  }
+}
+
+/// Formats the score the way UCI `info` lines expect it.
+impl fmt::Display for Score {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    match self {
+      Score::Cp(cp) => write!(f, "cp {cp}"),
+      Score::Mate(moves) => write!(f, "mate {moves}"),
+    }
+  }
+}
+
+/// When to stop searching, mirrors the arguments of UCI `go`.
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+  pub depth: Option<u32>,
+  pub nodes: Option<u64>,
+  pub movetime: Option<Duration>,
+  pub wtime: Option<Duration>,
+  pub btime: Option<Duration>,
+  pub winc: Option<Duration>,
+  pub binc: Option<Duration>,
+  pub movestogo: Option<u32>,
+  /// Keep searching until stopped, even after reaching the max depth.
+  pub infinite: bool,
+}
+
+impl Limits {
+  pub fn depth(depth: u32) -> Self {
+    Self { depth: Some(depth), ..Default::default() }
+  }
+
+  pub fn movetime(movetime: Duration) -> Self {
+    Self { movetime: Some(movetime), ..Default::default() }
+  }
+
+  /// Soft limit for starting another iteration and the hard deadline.
+  fn time_budget(
+    &self,
+    position: &Position,
+  ) -> Option<(Duration, Duration)> {
+    if let Some(movetime) = self.movetime {
+      return Some((movetime, movetime));
+    }
+    let (time, inc) = position
+      .side_to_move()
+      .fold((self.wtime, self.winc), (self.btime, self.binc));
+    let time = time?;
+    let inc = inc.unwrap_or_default();
+    let moves_to_go = self.movestogo.unwrap_or(30).max(1);
+    let budget = time / moves_to_go + inc * 3 / 4;
+    let hard = (budget * 3).min(time / 2);
+    Some((budget.min(hard), hard))
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchInfo {
+  /// Rank of the line when searching several, 1 for the best one.
+  pub multipv: usize,
+  pub depth: u32,
+  pub seldepth: u32,
+  pub score: Score,
+  pub nodes: u64,
+  pub time: Duration,
+  pub hashfull: u32,
+  pub pv: Vec<Move>,
+}
+
+impl SearchInfo {
+  pub fn nps(&self) -> u64 {
+    let micros = self.time.as_micros().max(1) as u64;
+    self.nodes.saturating_mul(1_000_000) / micros
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+  /// `None` only when the root position has no legal moves.
+  pub best_move: Option<Move>,
+  pub ponder: Option<Move>,
+  pub info: Option<SearchInfo>,
+}
+
+#[derive(Clone, Debug)]
+pub enum SearchEvent {
+  /// Sent by the main thread after every completed iteration.
+  Info(SearchInfo),
+  /// Always the last event of a search.
+  Done(SearchResult),
+}
+
+/// Handle to the built-in engine. Searches run on their own threads and
+/// report through a channel, so starting or stopping one never waits for
+/// the search itself.
+///
+/// With one thread and a depth or node limit the search is fully
+/// deterministic, provided the hash is cleared with [`Engine::new_game`].
+pub struct Engine {
+  tt: Arc<TranspositionTable>,
+  threads: usize,
+  multi_pv: usize,
+  book: Option<(Book, Selection)>,
+  tablebase: Option<Arc<Tablebase>>,
+  stop: Arc<AtomicBool>,
+  handle: Option<thread::JoinHandle<()>>,
+}
+
+impl Default for Engine {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Engine {
+  pub fn new() -> Self {
+    Self {
+      tt: Arc::new(TranspositionTable::new(DEFAULT_HASH_MB)),
+      threads: 1,
+      multi_pv: 1,
+      book: None,
+      tablebase: None,
+      stop: Arc::new(AtomicBool::new(false)),
+      handle: None,
+    }
+  }
+
+  pub fn threads(&self) -> usize {
+    self.threads
+  }
+
+  /// Takes effect with the next search.
+  pub fn set_threads(
+    &mut self,
+    threads: usize,
+  ) {
+    self.threads = threads.clamp(1, MAX_THREADS);
+  }
+
+  pub fn multi_pv(&self) -> usize {
+    self.multi_pv
+  }
+
+  /// Number of best lines to report, takes effect with the next search.
+  pub fn set_multi_pv(
+    &mut self,
+    lines: usize,
+  ) {
+    self.multi_pv = lines.clamp(1, MAX_MULTI_PV);
+  }
+
+  /// Opening book to play from while it knows the position. Infinite
+  /// searches, as used for analysis, always search.
+  pub fn set_book(
+    &mut self,
+    book: Option<(Book, Selection)>,
+  ) {
+    self.book = book;
+  }
+
+  /// Endgame tables to probe, takes effect with the next search. At the
+  /// root only the moves keeping the best result are searched, below it
+  /// positions right after a capture or pawn move get their score from
+  /// the tables.
+  pub fn set_tablebase(
+    &mut self,
+    tablebase: Option<Arc<Tablebase>>,
+  ) {
+    self.tablebase = tablebase;
+  }
+
+  /// Replaces the transposition table, a running search keeps the old one.
+  pub fn set_hash_size(
+    &mut self,
+    megabytes: usize,
+  ) {
+    self.tt = Arc::new(TranspositionTable::new(megabytes));
+  }
+
+  pub fn new_game(&mut self) {
+    self.stop();
+    self.tt.clear();
+  }
+
+  pub fn is_searching(&self) -> bool {
+    self.handle.as_ref().is_some_and(|handle| !handle.is_finished())
+  }
+
+  /// Signals the running search to stop, it will still send `Done`.
+  pub fn stop(&self) {
+    self.stop.store(true, Ordering::Relaxed);
+  }
+
+  /// Starts searching `position` in the background, stopping any search
+  /// still running. `history` holds the hashes of the positions played
+  /// before it, for repetition detection.
+  pub fn start(
+    &mut self,
+    position: Position,
+    history: Vec<u64>,
+    limits: Limits,
+  ) -> mpsc::Receiver<SearchEvent> {
+    self.stop();
+    self.stop = Arc::new(AtomicBool::new(false));
+
+    let (tx, rx) = mpsc::channel();
+    let book_move = match &self.book {
+      Some((book, selection)) if !limits.infinite => {
+        book.pick(&position, *selection)
+      }
+      _ => None,
+    };
+    if let Some(mv) = book_move {
+      let result =
+        SearchResult { best_move: Some(mv), ponder: None, info: None };
+      tx.send(SearchEvent::Done(result)).unwrap();
+      self.handle = None;
+      return rx;
+    }
+
+    let shared = Shared {
+      tt: self.tt.clone(),
+      stop: self.stop.clone(),
+      nodes: AtomicU64::new(0),
+      start: Instant::now(),
+      deadline: limits.time_budget(&position).map(|(_, hard)| hard),
+      limits,
+      multi_pv: self.multi_pv,
+      tablebase: self.tablebase.clone(),
+      root_moves: Vec::new(),
+    };
+    let threads = self.threads;
+    let handle = thread::Builder::new()
+      .name("search".into())
+      .spawn(move || {
+        let mut shared = shared;
+        if let Some(tablebase) = &shared.tablebase {
+          if tablebase.covers(&position) {
+            shared.root_moves =
+              tablebase.best_moves(&position).unwrap_or_else(|err| {
+                log::warn!("no tablebase moves: {err:#}");
+                Vec::new()
+              });
+          }
+        }
+        let result = thread::scope(|scope| {
+          for id in 1..threads {
+            let (shared, position, history) = (&shared, &position, &history);
+            scope.spawn(move || {
+              Worker::new(id, shared, history).search(position, None)
+            });
+          }
+          let result =
+            Worker::new(0, &shared, &history).search(&position, Some(&tx));
+          shared.stop.store(true, Ordering::Relaxed);
+          result
+        });
+        // the receiver may be gone already, nobody is interested then
+        let _ = tx.send(SearchEvent::Done(result));
+      })
+      .expect("failed to spawn search thread");
+    self.handle = Some(handle);
+    rx
+  }
+
+  /// Runs a search to completion on the calling thread's behalf.
+  pub fn search(
+    &mut self,
+    position: Position,
+    history: Vec<u64>,
+    limits: Limits,
+  ) -> SearchResult {
+    let events = self.start(position, history, limits);
+    loop {
+      match events.recv() {
+        Ok(SearchEvent::Done(result)) => return result,
+        Ok(SearchEvent::Info(_)) => {}
+        Err(_) => panic!("search thread died"),
+      }
+    }
+  }
+}
+
+impl Drop for Engine {
+  fn drop(&mut self) {
+    self.stop();
+  }
+}
+
+struct Shared {
+  tt: Arc<TranspositionTable>,
+  stop: Arc<AtomicBool>,
+  nodes: AtomicU64,
+  start: Instant,
+  deadline: Option<Duration>,
+  limits: Limits,
+  multi_pv: usize,
+  tablebase: Option<Arc<Tablebase>>,
+  /// The root moves the tablebase keeps, all of them when empty.
+  root_moves: Vec<Move>,
+}
+
+struct Worker<'a> {
+  id: usize,
+  shared: &'a Shared,
+  /// Hashes of every position from the game start to the current node's
+  /// parent, for repetition detection.
+  stack: Vec<u64>,
+  nodes: u64,
+  seldepth: u32,
+  stopped: bool,
+  killers: [[Option<Move>; 2]; MAX_PLY],
+  history: Box<[[i32; 64]; 64]>,
+  pv: Vec<Vec<Move>>,
+  /// Root moves left out because an earlier line of the same iteration
+  /// already covers them.
+  excluded: Vec<Move>,
+}
+
+impl<'a> Worker<'a> {
+  fn new(
+    id: usize,
+    shared: &'a Shared,
+    history: &[u64],
+  ) -> Self {
+    Self {
+      id,
+      shared,
+      stack: history.to_vec(),
+      nodes: 0,
+      seldepth: 0,
+      stopped: false,
+      killers: [[None; 2]; MAX_PLY],
+      history: Box::new([[0; 64]; 64]),
+      pv: vec![Vec::new(); MAX_PLY + 1],
+      excluded: Vec::new(),
+    }
+  }
+
+  fn is_main(&self) -> bool {
+    self.id == 0
+  }
+
+  fn search(
+    &mut self,
+    root: &Position,
+    report: Option<&mpsc::Sender<SearchEvent>>,
+  ) -> SearchResult {
+    let limits = &self.shared.limits;
+    let max_depth = limits.depth.unwrap_or(MAX_PLY as u32 - 1).max(1);
+    let soft_limit = limits.time_budget(root).map(|(soft, _)| soft);
+    let mut legal = root.legal_moves();
+    let root_moves = &self.shared.root_moves;
+    if !root_moves.is_empty() {
+      legal.retain(|mv| root_moves.contains(mv));
+    }
+    let mut result = SearchResult {
+      best_move: legal.first().copied(),
+      ponder: None,
+      info: None,
+    };
+    if legal.is_empty() {
+      return result;
+    }
+    // helpers only look for the best line
+    let lines =
+      if self.is_main() { self.shared.multi_pv.min(legal.len()) } else { 1 };
+
+    let mut scores = vec![0; lines];
+    let mut depth = 1;
+    while depth <= max_depth {
+      // helpers skip ahead every other iteration to desynchronize
+      let iteration_depth = if self.is_main() {
+        depth
+      } else {
+        (depth + (self.id as u32 + depth) % 2).min(max_depth)
+      };
+      self.excluded.clear();
+      for (index, score) in scores.iter_mut().enumerate() {
+        self.seldepth = 0;
+        *score = self.aspiration(root, iteration_depth as i32, *score);
+        if self.stopped {
+          break;
+        }
+
+        let pv = self.pv[0].clone();
+        let Some(&best) = pv.first() else {
+          break;
+        };
+        self.excluded.push(best);
+        let info = SearchInfo {
+          multipv: index + 1,
+          depth: iteration_depth,
+          seldepth: self.seldepth.max(iteration_depth),
+          score: Score::from_internal(*score),
+          nodes: self.shared.nodes.load(Ordering::Relaxed) + self.nodes,
+          time: self.shared.start.elapsed(),
+          hashfull: self.shared.tt.hashfull(),
+          pv,
+        };
+        if let Some(report) = report {
+          let _ = report.send(SearchEvent::Info(info.clone()));
+        }
+        if index == 0 {
+          result.best_move = Some(best);
+          result.ponder = info.pv.get(1).copied();
+          result.info = Some(info);
+        }
+      }
+      if self.stopped {
+        break;
+      }
+
+      let elapsed = self.shared.start.elapsed();
+      if self.is_main() && soft_limit.is_some_and(|soft| elapsed >= soft / 2) {
+        break;
+      }
+      // a found mate won't get any better unless asked to look deeper
+      let mate = matches!(Score::from_internal(scores[0]), Score::Mate(_));
+      if self.is_main() && mate && !limits.infinite && limits.depth.is_none() {
+        break;
+      }
+      depth += 1;
+    }
+
+    if self.is_main() && limits.infinite {
+      // UCI forbids reporting the best move before being told to stop
+      while !self.shared.stop.load(Ordering::Relaxed) {
+        thread::sleep(Duration::from_millis(1));
+      }
+    }
+    self.flush_nodes();
+    result
+  }
+
+  /// Searches with a narrow window around the previous score first,
+  /// widening it until the result lands inside.
+  fn aspiration(
+    &mut self,
+    root: &Position,
+    depth: i32,
+    previous: i32,
+  ) -> i32 {
+    if depth < 5 {
+      return self.negamax(root, depth, -INFINITY, INFINITY, 0);
+    }
+    let mut delta = 30;
+    let (mut alpha, mut beta) = (previous - delta, previous + delta);
+    loop {
+      let score = self.negamax(root, depth, alpha, beta, 0);
+      if self.stopped {
+        return score;
+      }
+      if score <= alpha {
+        alpha = (alpha - delta).max(-INFINITY);
+      } else if score >= beta {
+        beta = (beta + delta).min(INFINITY);
+      } else {
+        return score;
+      }
+      delta *= 2;
+    }
+  }
+
+  fn flush_nodes(&mut self) {
+    self.shared.nodes.fetch_add(self.nodes, Ordering::Relaxed);
+    self.nodes = 0;
+  }
+
+  fn count_node(&mut self) {
+    self.nodes += 1;
+    if self.nodes < CHECK_INTERVAL {
+      return;
+    }
+    self.flush_nodes();
+    let shared = self.shared;
+    let total = shared.nodes.load(Ordering::Relaxed);
+    let out_of_nodes = shared.limits.nodes.is_some_and(|max| total >= max);
+    let out_of_time = shared
+      .deadline
+      .is_some_and(|deadline| shared.start.elapsed() >= deadline);
+    if self.is_main() && (out_of_nodes || out_of_time) {
+      shared.stop.store(true, Ordering::Relaxed);
+    }
+  }
+
+  fn should_stop(&mut self) -> bool {
+    if !self.stopped && self.shared.stop.load(Ordering::Relaxed) {
+      self.stopped = true;
+    }
+    self.stopped
+  }
+
+  fn is_draw(
+    &self,
+    position: &Position,
+  ) -> bool {
+    if position.halfmove_clock() >= 100 || position.is_insufficient_material() {
+      return true;
+    }
+    // positions with the same side to move are an even number of plies
+    // back, and none before the last capture or pawn move can repeat
+    let hash = position.hash();
+    self
+      .stack
+      .iter()
+      .rev()
+      .take(position.halfmove_clock() as usize)
+      .skip(1)
+      .step_by(2)
+      .any(|&previous| previous == hash)
+  }
+
+  fn skipped_at_root(
+    &self,
+    mv: Move,
+  ) -> bool {
+    let root_moves = &self.shared.root_moves;
+    self.excluded.contains(&mv)
+      || (!root_moves.is_empty() && !root_moves.contains(&mv))
+  }
+
+  /// The tablebase score of a position right after a capture or pawn
+  /// move, when the tables have it. Later on the fifty-move rule may
+  /// turn their result around.
+  fn probe_tablebase(
+    &self,
+    position: &Position,
+    ply: usize,
+  ) -> Option<i32> {
+    let tablebase = self.shared.tablebase.as_ref()?;
+    if position.halfmove_clock() != 0 || !tablebase.covers(position) {
+      return None;
+    }
+    let score = match tablebase.probe_wdl(position).ok()? {
+      Wdl::Win => TB_WIN - ply as i32,
+      Wdl::Loss => -TB_WIN + ply as i32,
+      Wdl::CursedWin | Wdl::Draw | Wdl::BlessedLoss => 0,
+    };
+    Some(score)
+  }
+
+  fn negamax(
+    &mut self,
+    position: &Position,
+    mut depth: i32,
+    mut alpha: i32,
+    beta: i32,
+    ply: usize,
+  ) -> i32 {
+    self.pv[ply].clear();
+    let pv_node = beta - alpha > 1;
+    let root = ply == 0;
+
+    if !root {
+      if self.is_draw(position) {
+        return 0;
+      }
+      if ply >= MAX_PLY - 1 {
+        return eval::evaluate(position);
+      }
+    }
+
+    let in_check = position.is_check();
+    if in_check {
+      depth += 1;
+    }
+    if depth <= 0 {
+      return self.quiescence(position, alpha, beta, ply);
+    }
+
+    self.count_node();
+    if self.should_stop() {
+      return 0;
+    }
+
+    let hash = position.hash();
+    let entry = self.shared.tt.probe(hash);
+    if let Some(entry) = entry {
+      let score = score_from_tt(entry.score, ply);
+      let cutoff = match entry.bound {
+        Bound::Exact => true,
+        Bound::Lower => score >= beta,
+        Bound::Upper => score <= alpha,
+      };
+      if !root && !pv_node && entry.depth as i32 >= depth && cutoff {
+        return score;
+      }
+    }
+    if !root {
+      if let Some(score) = self.probe_tablebase(position, ply) {
+        return score;
+      }
+    }
+
+    let has_pieces = position.pieces_of_color(position.side_to_move())
+      & !position.pieces_of_kind(PieceKind::Pawn)
+      & !position.pieces_of_kind(PieceKind::King)
+      != 0;
+    if !pv_node && !in_check && depth >= 3 && has_pieces {
+      let static_eval = eval::evaluate(position);
+      if static_eval >= beta {
+        let reduction = 2 + depth / 4;
+        let mut null = position.clone();
+        null.play_null();
+        self.stack.push(hash);
+        let score = -self.negamax(
+          &null,
+          depth - 1 - reduction,
+          -beta,
+          -beta + 1,
+          ply + 1,
+        );
+        self.stack.pop();
+        if self.stopped {
+          return 0;
+        }
+        if score >= beta {
+          return if score >= MATE_BOUND { beta } else { score };
+        }
+      }
+    }
+
+    let mut moves = Vec::with_capacity(64);
+    position.pseudo_legal_moves(&mut moves);
+    let tt_move = entry.and_then(|entry| entry.mv);
+    let mut scored = self.order_moves(position, moves, tt_move, ply);
+
+    let original_alpha = alpha;
+    let mut best_score = -INFINITY;
+    let mut best_move = None;
+    let mut searched = 0;
+    let mut index = 0;
+    while let Some(mv) = pick_next(&mut scored, index) {
+      index += 1;
+      if root && self.skipped_at_root(mv) {
+        continue;
+      }
+      let next = position.played(mv);
+      if !next.was_legal() {
+        continue;
+      }
+      let quiet = !position.is_capture(mv) && mv.promotion.is_none();
+
+      self.stack.push(hash);
+      let score = if searched == 0 {
+        -self.negamax(&next, depth - 1, -beta, -alpha, ply + 1)
+      } else {
+        // late quiet moves are searched shallower first
+        let mut reduction = 0;
+        if depth >= 3 && searched >= 4 && quiet && !in_check && !next.is_check()
+        {
+          reduction = if searched >= 10 { 2 } else { 1 };
+        }
+        let mut score = -self.negamax(
+          &next,
+          depth - 1 - reduction,
+          -alpha - 1,
+          -alpha,
+          ply + 1,
+        );
+        if score > alpha && reduction > 0 {
+          score = -self.negamax(&next, depth - 1, -alpha - 1, -alpha, ply + 1);
+        }
+        if score > alpha && score < beta {
+          score = -self.negamax(&next, depth - 1, -beta, -alpha, ply + 1);
+        }
+        score
+      };
+      self.stack.pop();
+      searched += 1;
+
+      if self.stopped {
+        return 0;
+      }
+      if score > best_score {
+        best_score = score;
+        best_move = Some(mv);
+        if score > alpha {
+          alpha = score;
+          let (head, tail) = self.pv.split_at_mut(ply + 1);
+          head[ply].clear();
+          head[ply].push(mv);
+          head[ply].extend_from_slice(&tail[0]);
+        }
+      }
+      if alpha >= beta {
+        if quiet {
+          self.remember_cutoff(mv, depth, ply);
+        }
+        break;
+      }
+    }
+
+    if searched == 0 {
+      return if in_check { -MATE + ply as i32 } else { 0 };
+    }
+    if root && !self.excluded.is_empty() {
+      // the best moves were left out, this is no score of the position
+      return best_score;
+    }
+
+    let bound = if best_score >= beta {
+      Bound::Lower
+    } else if alpha > original_alpha {
+      Bound::Exact
+    } else {
+      Bound::Upper
+    };
+    let entry = Entry {
+      mv: best_move,
+      score: score_to_tt(best_score, ply),
+      depth: depth.clamp(0, u8::MAX as i32) as u8,
+      bound,
+    };
+    self.shared.tt.store(hash, entry);
+    best_score
+  }
+
+  /// Resolves captures until the position is quiet, so that the static
+  /// evaluation isn't taken in the middle of an exchange.
+  fn quiescence(
+    &mut self,
+    position: &Position,
+    mut alpha: i32,
+    beta: i32,
+    ply: usize,
+  ) -> i32 {
+    self.count_node();
+    self.seldepth = self.seldepth.max(ply as u32);
+    if self.should_stop() {
+      return 0;
+    }
+    if ply >= MAX_PLY - 1 {
+      return eval::evaluate(position);
+    }
+
+    let in_check = position.is_check();
+    if !in_check {
+      let stand_pat = eval::evaluate(position);
+      if stand_pat >= beta {
+        return stand_pat;
+      }
+      alpha = alpha.max(stand_pat);
+    }
+
+    let mut moves = Vec::with_capacity(32);
+    position.pseudo_legal_moves(&mut moves);
+    if !in_check {
+      moves.retain(|&mv| position.is_capture(mv) || mv.promotion.is_some());
+    }
+    let mut scored = self.order_moves(position, moves, None, ply);
+
+    let mut best_score = if in_check { -INFINITY } else { alpha };
+    let mut any_legal = false;
+    let mut index = 0;
+    while let Some(mv) = pick_next(&mut scored, index) {
+      index += 1;
+      let next = position.played(mv);
+      if !next.was_legal() {
+        continue;
+      }
+      any_legal = true;
+      let score = -self.quiescence(&next, -beta, -alpha, ply + 1);
+      if self.stopped {
+        return 0;
+      }
+      if score > best_score {
+        best_score = score;
+        alpha = alpha.max(score);
+        if score >= beta {
+          break;
+        }
+      }
+    }
+    if in_check && !any_legal {
+      return -MATE + ply as i32;
+    }
+    best_score
+  }
+
+  fn order_moves(
+    &self,
+    position: &Position,
+    moves: Vec<Move>,
+    tt_move: Option<Move>,
+    ply: usize,
+  ) -> Vec<(Move, i32)> {
+    moves
+      .into_iter()
+      .map(|mv| {
+        let score = if Some(mv) == tt_move {
+          1_000_000
+        } else if position.is_capture(mv) {
+          // most valuable victim, least valuable attacker
+          let victim = position
+            .piece_at(mv.to)
+            .map_or(PieceKind::Pawn, |piece| piece.kind);
+          let attacker = position.piece_at(mv.from).unwrap().kind;
+          100_000 + 10 * PIECE_VALUES[victim.index()] - attacker.index() as i32
+        } else if mv.promotion == Some(PieceKind::Queen) {
+          90_000
+        } else if self.killers[ply][0] == Some(mv) {
+          80_000
+        } else if self.killers[ply][1] == Some(mv) {
+          79_000
+        } else {
+          self.history[mv.from.index()][mv.to.index()]
+        };
+        (mv, score)
+      })
+      .collect()
+  }
+
+  fn remember_cutoff(
+    &mut self,
+    mv: Move,
+    depth: i32,
+    ply: usize,
+  ) {
+    if self.killers[ply][0] != Some(mv) {
+      self.killers[ply][1] = self.killers[ply][0];
+      self.killers[ply][0] = Some(mv);
+    }
+    let entry = &mut self.history[mv.from.index()][mv.to.index()];
+    *entry = (*entry + depth * depth).min(70_000);
+  }
+}
+
+/// Selection sort step: moves the best remaining move to `index`.
+fn pick_next(
+  moves: &mut [(Move, i32)],
+  index: usize,
+) -> Option<Move> {
+  let best = (index..moves.len()).max_by_key(|&i| moves[i].1)?;
+  moves.swap(index, best);
+  Some(moves[index].0)
+}
+
+// mate and tablebase scores are stored relative to the node, not to the root
+fn score_to_tt(
+  score: i32,
+  ply: usize,
+) -> i32 {
+  if score >= TB_BOUND {
+    score + ply as i32
+  } else if score <= -TB_BOUND {
+    score - ply as i32
+  } else {
+    score
+  }
+}
+
+fn score_from_tt(
+  score: i32,
+  ply: usize,
+) -> i32 {
+  if score >= TB_BOUND {
+    score - ply as i32
+  } else if score <= -TB_BOUND {
+    score + ply as i32
+  } else {
+    score
+  }
+}
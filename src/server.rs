@@ -0,0 +1,912 @@
+//! A game server: players meet in a lobby, seek or challenge each other
+//! with a time control and play there, the server checking the moves and
+//! keeping the clocks and the controls: draw offers, takebacks and the
+//! like. Anyone can watch a game. Players are known by name, so after
+//! losing the connection they say hello again and get their games back,
+//! their clocks having run meanwhile; until then the opponent may claim
+//! the game. The players of a game chat in one room, its spectators in
+//! another, each line checked by the server's filters and rate limit.
+//! Correspondence games are only passed on, kept for players away until
+//! they come. Given a database, the server stores each game that ends
+//! there and rates it, see `ratings`.
+//!
+//! One thread owns all the state and takes in what the connections read,
+//! each connection has a thread of its own writing to it, so a client slow
+//! to read holds up no one else; see `protocol` for the messages, `transport` for how they travel and
+//! `bin/server.rs` to run one.
+
+use std::{
+  collections::{BTreeMap, HashMap, HashSet},
+  net::{SocketAddr, TcpListener, ToSocketAddrs},
+  path::{Path, PathBuf},
+  sync::mpsc::{self, RecvTimeoutError},
+  thread,
+  time::{Duration, Instant},
+};
+
+use crate::{
+  board::{Color, Move, Position},
+  chat::{self, Filter, RateLimit},
+  control::{self, game_over, Controls, Effect},
+  database::Database,
+  pgn::{self, Outcome},
+  protocol::{
+    ChatLine, Control, GameInfo, Request, Room, Seek, Update, VERSION,
+  },
+  ratings::Ratings,
+  tournament::TimeControl,
+  transport::{self, Writer},
+};
+
+/// How often flags are checked without anything coming in.
+const TICK: Duration = Duration::from_millis(50);
+/// A client that does not read for this long is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+enum Incoming {
+  Connected(u32, mpsc::Sender<Update>),
+  Read(u32, anyhow::Result<Option<Request>>),
+}
+
+struct Client {
+  outgoing: mpsc::Sender<Update>,
+  name: Option<String>,
+}
+
+struct Game {
+  info: GameInfo,
+  start: Position,
+  moves: Vec<Move>,
+  position: Position,
+  /// Hash of every position, for repetitions.
+  history: Vec<u64>,
+  /// Time left when the side to move started thinking.
+  clocks: [Duration; 2],
+  increment: Duration,
+  turn_started: Instant,
+  /// Connections watching.
+  spectators: HashSet<u32>,
+  controls: Controls,
+  /// The chat of both rooms.
+  chat: Vec<ChatLine>,
+}
+
+impl Game {
+  fn player(
+    &self,
+    color: Color,
+  ) -> &str {
+    color.fold(&self.info.white, &self.info.black)
+  }
+
+  fn color_of(
+    &self,
+    name: &str,
+  ) -> Option<Color> {
+    [Color::White, Color::Black]
+      .into_iter()
+      .find(|&color| self.player(color) == name)
+  }
+
+  /// The clocks as they stand at `now`.
+  fn clocks(
+    &self,
+    now: Instant,
+  ) -> [Duration; 2] {
+    let mut clocks = self.clocks;
+    let side = self.position.side_to_move().index();
+    clocks[side] = clocks[side].saturating_sub(now - self.turn_started);
+    clocks
+  }
+
+  /// The chat of a room so far.
+  fn chat_of(
+    &self,
+    room: Room,
+  ) -> impl Iterator<Item = Update> + '_ {
+    let lines = self.chat.iter().filter(move |line| line.room == room);
+    lines.map(|line| Update::Chat { game: self.info.id, line: line.clone() })
+  }
+
+  fn state(
+    &self,
+    color: Option<Color>,
+    now: Instant,
+  ) -> Update {
+    Update::Game {
+      info: self.info.clone(),
+      color,
+      start: self.start.clone(),
+      moves: self.moves.clone(),
+      clocks: self.clocks(now),
+    }
+  }
+}
+
+/// Where the games that ended go: a database, with the ratings of its
+/// players kept up with it and saved after each game. It runs on a thread
+/// of its own, the games in play never wait for the disk.
+struct Archive {
+  database: Database,
+  ratings: Ratings,
+  ratings_path: PathBuf,
+}
+
+impl Archive {
+  /// Stores and rates the games sent until the server goes away.
+  fn run(
+    mut self,
+    games: mpsc::Receiver<pgn::Game>,
+  ) {
+    for record in games {
+      if let Err(err) = self.keep(&record) {
+        let event = record.tag("Event").unwrap_or("a game");
+        log::error!("can't keep {event}: {err:#}");
+      }
+    }
+  }
+
+  fn keep(
+    &mut self,
+    record: &pgn::Game,
+  ) -> anyhow::Result<()> {
+    self.database.import(std::slice::from_ref(record))?;
+    self.ratings.add(record);
+    self.ratings.save(&self.ratings_path)
+  }
+}
+
+pub struct Server {
+  name: String,
+  next_id: u32,
+  clients: HashMap<u32, Client>,
+  /// Connection of each player who said hello.
+  names: HashMap<String, u32>,
+  seeks: BTreeMap<u32, Seek>,
+  games: BTreeMap<u32, Game>,
+  /// Correspondence games waiting for their players, the last post of
+  /// each game.
+  posts: HashMap<String, Vec<Update>>,
+  /// Connections whose writes failed, closed after the current event.
+  filters: Vec<Box<dyn Filter>>,
+  chat_limit: RateLimit,
+  /// Where to send the games that end, if they are kept.
+  archive: Option<mpsc::Sender<pgn::Game>>,
+}
+
+fn is_valid_name(name: &str) -> bool {
+  !name.is_empty() && name != "-" && !name.contains(char::is_whitespace)
+}
+
+impl Server {
+  pub fn new(name: &str) -> Server {
+    Server {
+      name: name.into(),
+      next_id: 1,
+      clients: HashMap::new(),
+      names: HashMap::new(),
+      seeks: BTreeMap::new(),
+      games: BTreeMap::new(),
+      posts: HashMap::new(),
+      filters: Vec::new(),
+      chat_limit: RateLimit::default(),
+      archive: None,
+    }
+  }
+
+  /// Runs chat lines through `filter` too, after the filters added
+  /// before.
+  pub fn add_filter(
+    &mut self,
+    filter: impl Filter + 'static,
+  ) {
+    self.filters.push(Box::new(filter));
+  }
+
+  pub fn set_chat_limit(
+    &mut self,
+    limit: RateLimit,
+  ) {
+    self.chat_limit = limit;
+  }
+
+  /// Stores the games that end in the database at `database` and rates
+  /// them, saving the ratings to `ratings`. Games the ratings have not
+  /// counted yet are rated first.
+  pub fn keep_games(
+    &mut self,
+    database: &Path,
+    ratings: &Path,
+  ) -> anyhow::Result<()> {
+    let database = Database::open(database)?;
+    let mut rated = Ratings::load(ratings)?;
+    rated.catch_up(&database)?;
+    rated.save(ratings)?;
+    let archive =
+      Archive { database, ratings: rated, ratings_path: ratings.into() };
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || archive.run(rx));
+    self.archive = Some(tx);
+    Ok(())
+  }
+
+  /// Serves the connections coming to `listener` until it fails.
+  pub fn run(
+    mut self,
+    listener: TcpListener,
+  ) -> anyhow::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+      for (id, stream) in (1..).zip(listener.incoming()) {
+        let stream = match stream {
+          Ok(stream) => stream,
+          Err(err) => {
+            log::warn!("can't accept a connection: {err}");
+            continue;
+          }
+        };
+        let tx = tx.clone();
+        // the handshake of a WebSocket may take a while
+        thread::spawn(move || {
+          let accepted = stream
+            .set_write_timeout(Some(WRITE_TIMEOUT))
+            .map_err(anyhow::Error::from)
+            .and_then(|()| transport::accept(stream));
+          let (mut reader, writer) = match accepted {
+            Ok(accepted) => accepted,
+            Err(err) => {
+              log::warn!("can't take connection {id}: {err:#}");
+              return;
+            }
+          };
+          let (outgoing, updates) = mpsc::channel();
+          thread::spawn(move || write_updates(id, writer, updates));
+          if tx.send(Incoming::Connected(id, outgoing)).is_err() {
+            return;
+          }
+          loop {
+            let read = reader.read();
+            let done = !matches!(read, Ok(Some(_)));
+            if tx.send(Incoming::Read(id, read)).is_err() || done {
+              break;
+            }
+          }
+        });
+      }
+    });
+
+    loop {
+      match rx.recv_timeout(TICK) {
+        Ok(Incoming::Connected(id, outgoing)) => {
+          self.clients.insert(id, Client { outgoing, name: None });
+        }
+        Ok(Incoming::Read(id, Ok(Some(request)))) => {
+          self.handle(id, request, Instant::now())
+        }
+        Ok(Incoming::Read(id, Ok(None))) => self.disconnect(id),
+        Ok(Incoming::Read(id, Err(err))) => {
+          self.send(id, &Update::Error { message: format!("{err:#}") });
+          self.disconnect(id);
+        }
+        Err(RecvTimeoutError::Timeout) => {}
+        Err(RecvTimeoutError::Disconnected) => {
+          anyhow::bail!("stopped taking connections")
+        }
+      }
+      self.tick(Instant::now());
+    }
+  }
+
+  /// Forgets a connection with its seeks. Its games go on.
+  fn disconnect(
+    &mut self,
+    id: u32,
+  ) {
+    // the writer closes the connection once it sent what is left
+    let Some(client) = self.clients.remove(&id) else {
+      return;
+    };
+    for game in self.games.values_mut() {
+      game.spectators.remove(&id);
+    }
+    let Some(name) = client.name else {
+      return;
+    };
+    log::info!("{name} left");
+    self.names.remove(&name);
+    let games: Vec<(u32, Color)> = self
+      .games
+      .values()
+      .filter_map(|game| Some((game.info.id, game.color_of(&name)?)))
+      .collect();
+    for (game, color) in games {
+      self.send_to_game(game, &Update::Gone { game, color });
+    }
+    let seeks: Vec<u32> = self
+      .seeks
+      .values()
+      .filter(|seek| seek.from == name || seek.to.as_ref() == Some(&name))
+      .map(|seek| seek.id)
+      .collect();
+    for seek in seeks {
+      self.seeks.remove(&seek);
+      self.broadcast(&Update::Unseek { seek });
+    }
+  }
+
+  fn send(
+    &mut self,
+    id: u32,
+    update: &Update,
+  ) {
+    // when the writer gave up, the reader is about to tell
+    if let Some(client) = self.clients.get(&id) {
+      let _ = client.outgoing.send(update.clone());
+    }
+  }
+
+  fn send_to(
+    &mut self,
+    name: &str,
+    update: &Update,
+  ) {
+    if let Some(&id) = self.names.get(name) {
+      self.send(id, update);
+    }
+  }
+
+  /// Sends to everyone in the lobby.
+  fn broadcast(
+    &mut self,
+    update: &Update,
+  ) {
+    let ids: Vec<u32> = self.names.values().copied().collect();
+    for id in ids {
+      self.send(id, update);
+    }
+  }
+
+  fn error(
+    &mut self,
+    id: u32,
+    message: impl Into<String>,
+  ) {
+    self.send(id, &Update::Error { message: message.into() });
+  }
+
+  fn next_id(&mut self) -> u32 {
+    self.next_id += 1;
+    self.next_id - 1
+  }
+
+  /// Handles a request from connection `id`.
+  pub fn handle(
+    &mut self,
+    id: u32,
+    request: Request,
+    now: Instant,
+  ) {
+    let name = self.clients.get(&id).and_then(|client| client.name.clone());
+    let (request, name) = match (request, name) {
+      (Request::Hello { version, name }, None) => {
+        return self.hello(id, version, name, now);
+      }
+      (Request::Hello { .. }, Some(_)) => {
+        return self.error(id, "hello again?");
+      }
+      (Request::Bye, _) => return self.disconnect(id),
+      (_, None) => return self.error(id, "say hello first"),
+      (request, Some(name)) => (request, name),
+    };
+    match (request, name) {
+      (Request::Seek { control, color }, name) => {
+        let seek =
+          Seek { id: self.next_id(), from: name, control, color, to: None };
+        self.seeks.insert(seek.id, seek.clone());
+        self.broadcast(&Update::Seek(seek));
+      }
+      (Request::Challenge { to, control, color }, name) => {
+        if to == name || !self.names.contains_key(&to) {
+          return self.error(id, format!("{to} is not here"));
+        }
+        let seek = Seek {
+          id: self.next_id(),
+          from: name,
+          control,
+          color,
+          to: Some(to.clone()),
+        };
+        self.seeks.insert(seek.id, seek.clone());
+        self.send(id, &Update::Seek(seek.clone()));
+        self.send_to(&to, &Update::Seek(seek));
+      }
+      (Request::Cancel { seek }, name) => {
+        let mine = self.seeks.get(&seek).is_some_and(|seek| {
+          seek.from == name || seek.to.as_ref() == Some(&name)
+        });
+        if !mine {
+          return self.error(id, format!("no seek {seek} of yours"));
+        }
+        self.seeks.remove(&seek);
+        self.broadcast(&Update::Unseek { seek });
+      }
+      (Request::Accept { seek }, name) => self.accept(id, seek, name, now),
+      (Request::Move { game, ply, mv }, name) => {
+        self.play(id, game, ply, mv, name, now)
+      }
+      (Request::Control { game, ply, control }, name) => {
+        self.control(id, game, ply, control, name, now)
+      }
+      (Request::Watch { game }, name) => {
+        let Some(watched) = self.games.get_mut(&game) else {
+          return self.error(id, format!("no game {game}"));
+        };
+        // players get their own games again
+        let color = watched.color_of(&name);
+        let new = color.is_none() && watched.spectators.insert(id);
+        let mut updates = vec![watched.state(color, now)];
+        if new {
+          updates.extend(watched.chat_of(Room::Spectators));
+        }
+        for update in updates {
+          self.send(id, &update);
+        }
+      }
+      (Request::Unwatch { game }, _) => {
+        if let Some(watched) = self.games.get_mut(&game) {
+          watched.spectators.remove(&id);
+        }
+      }
+      (Request::Chat { game, text }, name) => {
+        self.chat(id, game, &text, name, now)
+      }
+      (Request::Post { to, game, days, start, moves }, name) => {
+        if to == name || !is_valid_name(&to) || !is_valid_name(&game) {
+          return self.error(id, format!("can't post {game} to {to}"));
+        }
+        let mut position = start.clone();
+        for &mv in &moves {
+          if !position.is_legal(mv) {
+            return self.error(id, format!("illegal move {mv} in {game}"));
+          }
+          position.play(mv);
+        }
+        let post = Update::Post { from: name, game, days, start, moves };
+        match self.names.get(&to) {
+          Some(&to) => self.send(to, &post),
+          None => self.keep_post(to, post),
+        }
+      }
+      (Request::Hello { .. } | Request::Bye, _) => unreachable!(),
+    }
+  }
+
+  /// Keeps a post for a player away, in place of an older one of the game.
+  fn keep_post(
+    &mut self,
+    to: String,
+    post: Update,
+  ) {
+    let Update::Post { game, .. } = &post else {
+      return;
+    };
+    let posts = self.posts.entry(to).or_default();
+    posts.retain(
+      |kept| !matches!(kept, Update::Post { game: kept, .. } if kept == game),
+    );
+    posts.push(post);
+  }
+
+  fn hello(
+    &mut self,
+    id: u32,
+    version: u32,
+    name: String,
+    now: Instant,
+  ) {
+    let refusal = if version != VERSION {
+      Some(format!("we speak version {VERSION}, not {version}"))
+    } else if !is_valid_name(&name) {
+      Some(format!("{name:?} is not a name, use one word"))
+    } else if self.names.contains_key(&name) {
+      Some(format!("{name} is already here"))
+    } else {
+      None
+    };
+    if let Some(message) = refusal {
+      self.error(id, message);
+      return self.disconnect(id);
+    }
+    log::info!("{name} joined");
+    if let Some(client) = self.clients.get_mut(&id) {
+      client.name = Some(name.clone());
+    }
+    self.names.insert(name.clone(), id);
+    self.send(id, &Update::Hello { version: VERSION, name: self.name.clone() });
+
+    let mut updates = Vec::new();
+    for seek in self.seeks.values() {
+      if seek.to.is_none() || seek.from == name || seek.to == Some(name.clone())
+      {
+        updates.push(Update::Seek(seek.clone()));
+      }
+    }
+    for game in self.games.values() {
+      updates.push(Update::Listed(game.info.clone()));
+    }
+    // games left running when the connection was lost
+    let mut back = Vec::new();
+    for game in self.games.values() {
+      let Some(color) = game.color_of(&name) else {
+        continue;
+      };
+      let game_id = game.info.id;
+      back.push((game_id, color));
+      updates.push(game.state(Some(color), now));
+      let ply = game.moves.len();
+      if let Some((control, color)) = game.controls.offer(ply) {
+        updates.push(Update::Control { game: game_id, ply, color, control });
+      }
+      if !self.names.contains_key(game.player(!color)) {
+        updates.push(Update::Gone { game: game_id, color: !color });
+      }
+      updates.extend(game.chat_of(Room::Players));
+    }
+    updates.extend(self.posts.remove(&name).unwrap_or_default());
+    for update in updates {
+      self.send(id, &update);
+    }
+    for (game, color) in back {
+      self.send_to_game(game, &Update::Back { game, color });
+    }
+  }
+
+  fn accept(
+    &mut self,
+    id: u32,
+    seek: u32,
+    name: String,
+    now: Instant,
+  ) {
+    let open = self.seeks.get(&seek).is_some_and(|seek| {
+      seek.from != name && seek.to.as_ref().is_none_or(|to| *to == name)
+    });
+    if !open {
+      return self.error(id, format!("no seek {seek} to accept"));
+    }
+    let seek = self.seeks.remove(&seek).unwrap();
+    self.broadcast(&Update::Unseek { seek: seek.id });
+    let seeker = seek.color.unwrap_or(if fastrand::bool() {
+      Color::White
+    } else {
+      Color::Black
+    });
+    let (white, black) = match seeker {
+      Color::White => (seek.from, name),
+      Color::Black => (name, seek.from),
+    };
+    let TimeControl::Clock { base, increment } = seek.control else {
+      unreachable!("seeks have clocks");
+    };
+    let start = Position::startpos();
+    let game = Game {
+      info: GameInfo {
+        id: self.next_id(),
+        white: white.clone(),
+        black: black.clone(),
+        control: seek.control,
+      },
+      history: vec![start.hash()],
+      position: start.clone(),
+      start,
+      moves: Vec::new(),
+      clocks: [base; 2],
+      increment,
+      turn_started: now,
+      spectators: HashSet::new(),
+      controls: Controls::default(),
+      chat: Vec::new(),
+    };
+    log::info!("game {}: {white} - {black}", game.info.id);
+    self.broadcast(&Update::Listed(game.info.clone()));
+    let states = [
+      (white, game.state(Some(Color::White), now)),
+      (black, game.state(Some(Color::Black), now)),
+    ];
+    self.games.insert(game.info.id, game);
+    for (player, state) in states {
+      self.send_to(&player, &state);
+    }
+  }
+
+  fn play(
+    &mut self,
+    id: u32,
+    game_id: u32,
+    ply: usize,
+    mv: Move,
+    name: String,
+    now: Instant,
+  ) {
+    let Some(game) = self.games.get_mut(&game_id) else {
+      return self.error(id, format!("no game {game_id}"));
+    };
+    let side = game.position.side_to_move();
+    let refusal = if game.color_of(&name) != Some(side) {
+      Some(format!("not your move in game {game_id}"))
+    } else if ply != game.moves.len() {
+      Some(format!("game {game_id} is at ply {}", game.moves.len()))
+    } else if !game.position.is_legal(mv) {
+      Some(format!("{mv} is illegal"))
+    } else {
+      None
+    };
+    if let Some(message) = refusal {
+      let state = game.state(game.color_of(&name), now);
+      self.error(id, message);
+      return self.send(id, &state);
+    }
+
+    let elapsed = now - game.turn_started;
+    if elapsed >= game.clocks[side.index()] {
+      return self.flag(game_id, now);
+    }
+    game.clocks[side.index()] -= elapsed;
+    game.clocks[side.index()] += game.increment;
+    game.turn_started = now;
+    game.moves.push(mv);
+    game.position.play(mv);
+    game.history.push(game.position.hash());
+    let update = Update::Move { game: game_id, ply, mv, clocks: game.clocks };
+    let end = game_over(&game.position, &game.history);
+    self.send_to_game(game_id, &update);
+    if let Some((outcome, reason)) = end {
+      self.end(game_id, outcome, reason);
+    }
+  }
+
+  fn control(
+    &mut self,
+    id: u32,
+    game_id: u32,
+    ply: usize,
+    control: Control,
+    name: String,
+    now: Instant,
+  ) {
+    let Some(game) = self.games.get_mut(&game_id) else {
+      return self.error(id, format!("no game {game_id}"));
+    };
+    let Some(color) = game.color_of(&name) else {
+      return self.error(id, format!("you don't play game {game_id}"));
+    };
+    if ply != game.moves.len() {
+      let message = format!("game {game_id} is at ply {}", game.moves.len());
+      let state = game.state(Some(color), now);
+      self.error(id, message);
+      return self.send(id, &state);
+    }
+    let away = !self.names.contains_key(game.player(!color));
+    let applied =
+      game.controls.apply(color, control, &game.start, &game.moves, away);
+    let effect = match applied {
+      Ok(effect) => effect,
+      Err(err) => return self.error(id, format!("{err:#}")),
+    };
+    let update = Update::Control { game: game_id, ply, color, control };
+    self.send_to_game(game_id, &update);
+    match effect {
+      Effect::Offered | Effect::Declined => {}
+      Effect::TakeBack(count) => self.take_back(game_id, count, now),
+      Effect::End(outcome, reason) => self.end(game_id, outcome, reason),
+    }
+  }
+
+  /// Takes back the last `count` moves of a game, sending the whole of it
+  /// again to the players and the spectators.
+  fn take_back(
+    &mut self,
+    game_id: u32,
+    count: usize,
+    now: Instant,
+  ) {
+    let Some(game) = self.games.get_mut(&game_id) else {
+      return;
+    };
+    game.clocks = game.clocks(now);
+    game.turn_started = now;
+    game.moves.truncate(game.moves.len() - count);
+    (game.position, game.history) = control::replay(&game.start, &game.moves);
+    let mut states = Vec::new();
+    for color in [Color::White, Color::Black] {
+      if let Some(&id) = self.names.get(game.player(color)) {
+        states.push((id, game.state(Some(color), now)));
+      }
+    }
+    for &id in &game.spectators {
+      states.push((id, game.state(None, now)));
+    }
+    for (id, state) in states {
+      self.send(id, &state);
+    }
+  }
+
+  /// Passes a chat line on to the room of the sender, the players' or
+  /// the spectators', once the filters and the rate limit let it.
+  fn chat(
+    &mut self,
+    id: u32,
+    game_id: u32,
+    text: &str,
+    name: String,
+    now: Instant,
+  ) {
+    let Some(game) = self.games.get(&game_id) else {
+      return self.error(id, format!("no game {game_id}"));
+    };
+    let room = if game.color_of(&name).is_some() {
+      Room::Players
+    } else if game.spectators.contains(&id) {
+      Room::Spectators
+    } else {
+      return self.error(id, format!("you don't play or watch game {game_id}"));
+    };
+    let mut text = chat::clean(text);
+    if text.is_empty() {
+      return self.error(id, "nothing to say");
+    }
+    if let Err(err) = self.chat_limit.check(&name, now) {
+      return self.error(id, format!("{err:#}"));
+    }
+    for filter in &self.filters {
+      match filter.check(&name, &text) {
+        Ok(checked) => text = checked,
+        Err(err) => return self.error(id, format!("{err:#}")),
+      }
+    }
+    let Some(game) = self.games.get_mut(&game_id) else {
+      return;
+    };
+    let line = ChatLine { ply: game.moves.len(), room, from: name, text };
+    game.chat.push(line.clone());
+    let mut ids: Vec<u32> = match room {
+      Room::Players => [Color::White, Color::Black]
+        .into_iter()
+        .filter_map(|color| self.names.get(game.player(color)).copied())
+        .collect(),
+      Room::Spectators => game.spectators.iter().copied().collect(),
+    };
+    ids.sort_unstable();
+    let update = Update::Chat { game: game_id, line };
+    for id in ids {
+      self.send(id, &update);
+    }
+  }
+
+  /// Sends to the players and the spectators of a game.
+  fn send_to_game(
+    &mut self,
+    game_id: u32,
+    update: &Update,
+  ) {
+    let Some(game) = self.games.get(&game_id) else {
+      return;
+    };
+    let mut ids: Vec<u32> = game.spectators.iter().copied().collect();
+    for color in [Color::White, Color::Black] {
+      ids.extend(self.names.get(game.player(color)).copied());
+    }
+    ids.sort_unstable();
+    ids.dedup();
+    for id in ids {
+      self.send(id, update);
+    }
+  }
+
+  /// Ends a game: it is stored and rated if games are kept, then its
+  /// players and spectators learn how, the lobby that it is over.
+  fn end(
+    &mut self,
+    game_id: u32,
+    outcome: Outcome,
+    reason: &str,
+  ) {
+    if let (Some(archive), Some(game)) =
+      (&self.archive, self.games.get(&game_id))
+    {
+      let mut record =
+        game.info.record(&self.name, game.start.clone(), game.moves.clone());
+      record.outcome = outcome;
+      record.set_tag("Termination", reason);
+      if archive.send(record).is_err() {
+        log::error!("can't keep game {game_id}, the archive stopped");
+      }
+    }
+    let update =
+      Update::End { game: game_id, outcome, reason: reason.to_string() };
+    self.send_to_game(game_id, &update);
+    if let Some(game) = self.games.remove(&game_id) {
+      log::info!("game {game_id} ended {outcome} by {reason}");
+      let ids: Vec<u32> = self
+        .names
+        .iter()
+        .filter(|(name, _)| game.color_of(name).is_none())
+        .filter(|(_, id)| !game.spectators.contains(id))
+        .map(|(_, &id)| id)
+        .collect();
+      for id in ids {
+        self.send(id, &update);
+      }
+    }
+  }
+
+  /// The side to move ran out of time: it loses, unless the opponent has
+  /// nothing left to mate with and it is a draw.
+  fn flag(
+    &mut self,
+    game_id: u32,
+    now: Instant,
+  ) {
+    let Some(game) = self.games.get_mut(&game_id) else {
+      return;
+    };
+    let side = game.position.side_to_move();
+    game.clocks = game.clocks(now);
+    let (outcome, reason) = match game.position.can_mate(!side) {
+      true => (side.fold(Outcome::BlackWins, Outcome::WhiteWins), "time"),
+      false => (Outcome::Draw, "time against insufficient material"),
+    };
+    self.end(game_id, outcome, reason);
+  }
+
+  /// Ends the games whose side to move ran out of time by `now`.
+  pub fn tick(
+    &mut self,
+    now: Instant,
+  ) {
+    let flagged: Vec<u32> = self
+      .games
+      .values()
+      .filter(|game| {
+        let side = game.position.side_to_move().index();
+        now - game.turn_started >= game.clocks[side]
+      })
+      .map(|game| game.info.id)
+      .collect();
+    for game in flagged {
+      self.flag(game, now);
+    }
+  }
+}
+
+/// Writes the updates for connection `id` until the server forgets it or
+/// a write fails, then closes the connection, which ends its reader too.
+fn write_updates(
+  id: u32,
+  mut writer: Writer,
+  updates: mpsc::Receiver<Update>,
+) {
+  for update in updates {
+    if let Err(err) = writer.send(&update) {
+      log::warn!("can't write to connection {id}: {err:#}");
+      break;
+    }
+  }
+  writer.close();
+}
+
+/// Runs a server on `addr` in the background, returning where it listens.
+pub fn spawn(
+  addr: impl ToSocketAddrs,
+  name: &str,
+) -> anyhow::Result<SocketAddr> {
+  let listener = TcpListener::bind(addr)?;
+  let addr = listener.local_addr()?;
+  let server = Server::new(name);
+  thread::spawn(move || {
+    if let Err(err) = server.run(listener) {
+      log::error!("server stopped: {err:#}");
+    }
+  });
+  Ok(addr)
+}
@@ -0,0 +1,263 @@
+//! Sequential probability ratio test for engine patches, on pentanomial
+//! statistics: games are played in pairs with the same opening and colors
+//! swapped, and each pair scores 0, 0.5, 1, 1.5 or 2 points. Pairs cancel
+//! most of the opening's bias, which makes the test need fewer games.
+//!
+//! The log-likelihood ratio uses the usual normal approximation with
+//! bounds in logistic Elo, as cutechess and older fishtest do.
+
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use anyhow::{anyhow, bail};
+
+use crate::{board::Color, tournament::GameRecord};
+
+/// Pair scores of exactly the same value leave no variance, this keeps
+/// the ratio finite until the first differing pair.
+const MIN_VARIANCE: f64 = 1e-3;
+
+/// Expected score of a player `elo` points stronger.
+pub fn elo_to_score(elo: f64) -> f64 {
+  1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+pub fn score_to_elo(score: f64) -> f64 {
+  -400.0 * (1.0 / score - 1.0).log10()
+}
+
+/// Standard normal distribution function, through the Abramowitz and
+/// Stegun approximation of erf (error below 1.5e-7).
+fn phi(x: f64) -> f64 {
+  let z = x.abs() / std::f64::consts::SQRT_2;
+  let t = 1.0 / (1.0 + 0.3275911 * z);
+  let poly = t
+    * (0.254829592
+      + t
+        * (-0.284496736
+          + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+  let erf = 1.0 - poly * (-z * z).exp();
+  if x >= 0.0 {
+    (1.0 + erf) / 2.0
+  } else {
+    (1.0 - erf) / 2.0
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Params {
+  /// Elo difference of the null hypothesis, the patch is no better.
+  pub elo0: f64,
+  /// Elo difference of the alternative, the patch gains this much.
+  pub elo1: f64,
+  /// Chance of accepting a patch that is no better.
+  pub alpha: f64,
+  /// Chance of rejecting a patch that gains `elo1`.
+  pub beta: f64,
+}
+
+impl Default for Params {
+  fn default() -> Self {
+    Self { elo0: 0.0, elo1: 5.0, alpha: 0.05, beta: 0.05 }
+  }
+}
+
+impl Params {
+  /// Lower and upper bound of the log-likelihood ratio.
+  pub fn bounds(&self) -> (f64, f64) {
+    let lower = (self.beta / (1.0 - self.alpha)).ln();
+    let upper = ((1.0 - self.beta) / self.alpha).ln();
+    (lower, upper)
+  }
+}
+
+/// `elo0=0,elo1=5,alpha=0.05,beta=0.05`, missing values keep defaults.
+impl FromStr for Params {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> anyhow::Result<Self> {
+    let mut params = Params::default();
+    for field in s.split(',').filter(|field| !field.is_empty()) {
+      let (key, value) = field
+        .split_once('=')
+        .ok_or_else(|| anyhow!("expected key=value, got {field:?}"))?;
+      let value: f64 = value.parse()?;
+      match key {
+        "elo0" => params.elo0 = value,
+        "elo1" => params.elo1 = value,
+        "alpha" => params.alpha = value,
+        "beta" => params.beta = value,
+        _ => bail!("unknown sprt parameter {key:?}"),
+      }
+    }
+    if params.elo0 >= params.elo1 {
+      bail!("elo0 must be below elo1");
+    }
+    // at 0 or 1 a bound is infinite and the test never stops
+    let open = |p: f64| p > 0.0 && p < 1.0;
+    if !open(params.alpha) || !open(params.beta) {
+      bail!("alpha and beta must be between 0 and 1");
+    }
+    Ok(params)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+  /// The patch is no better, reject it.
+  H0,
+  /// The patch gains at least `elo1`, accept it.
+  H1,
+}
+
+/// Results of one engine, the one under test, against its opponent.
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+  /// Number of pairs scoring 0, 0.5, 1, 1.5 and 2 points.
+  pub pentanomial: [u32; 5],
+  pub wins: u32,
+  pub draws: u32,
+  pub losses: u32,
+  /// Score of the first finished game of pairs still being played.
+  unpaired: HashMap<usize, f64>,
+}
+
+impl Stats {
+  /// Counts a finished game, `engine` being the index of the engine under
+  /// test in the tournament. Games it did not play are ignored.
+  pub fn add(
+    &mut self,
+    record: &GameRecord,
+    engine: usize,
+  ) {
+    let pairing = &record.pairing;
+    let color = if pairing.white == engine {
+      Color::White
+    } else if pairing.black == engine {
+      Color::Black
+    } else {
+      return;
+    };
+    let Some(score) = record.game.outcome.score(color) else {
+      return;
+    };
+    if score == 1.0 {
+      self.wins += 1;
+    } else if score == 0.0 {
+      self.losses += 1;
+    } else {
+      self.draws += 1;
+    }
+    match self.unpaired.remove(&pairing.pair) {
+      Some(first) => self.pentanomial[((first + score) * 2.0) as usize] += 1,
+      None => {
+        self.unpaired.insert(pairing.pair, score);
+      }
+    }
+  }
+
+  pub fn games(&self) -> u32 {
+    self.wins + self.draws + self.losses
+  }
+
+  pub fn pairs(&self) -> u32 {
+    self.pentanomial.iter().sum()
+  }
+
+  pub fn draw_ratio(&self) -> f64 {
+    self.draws as f64 / self.games().max(1) as f64
+  }
+
+  /// Mean score per game and the variance of a pair's mean, over the
+  /// completed pairs.
+  fn mean_and_variance(&self) -> Option<(f64, f64)> {
+    let pairs = self.pairs() as f64;
+    if pairs == 0.0 {
+      return None;
+    }
+    let frequencies = self.pentanomial.map(|count| count as f64 / pairs);
+    let mean: f64 =
+      frequencies.iter().enumerate().map(|(i, p)| p * i as f64 / 4.0).sum();
+    let variance: f64 = frequencies
+      .iter()
+      .enumerate()
+      .map(|(i, p)| p * (i as f64 / 4.0 - mean).powi(2))
+      .sum();
+    Some((mean, variance.max(MIN_VARIANCE)))
+  }
+
+  /// Elo difference with the half width of its 95% confidence interval.
+  pub fn elo(&self) -> Option<(f64, f64)> {
+    let (mean, variance) = self.mean_and_variance()?;
+    let error = 1.959964 * (variance / self.pairs() as f64).sqrt();
+    let clamp = |score: f64| score.clamp(1e-6, 1.0 - 1e-6);
+    let elo = score_to_elo(clamp(mean));
+    let high = score_to_elo(clamp(mean + error));
+    let low = score_to_elo(clamp(mean - error));
+    Some((elo, (high - low) / 2.0))
+  }
+
+  /// Likelihood of superiority: the chance that the engine under test is
+  /// the stronger one.
+  pub fn los(&self) -> Option<f64> {
+    let (mean, variance) = self.mean_and_variance()?;
+    let error = (variance / self.pairs() as f64).sqrt();
+    Some(phi((mean - 0.5) / error))
+  }
+
+  /// Log-likelihood ratio of `elo1` against `elo0`.
+  pub fn llr(
+    &self,
+    params: &Params,
+  ) -> f64 {
+    let Some((mean, variance)) = self.mean_and_variance() else {
+      return 0.0;
+    };
+    let (score0, score1) =
+      (elo_to_score(params.elo0), elo_to_score(params.elo1));
+    self.pairs() as f64 * (score1 - score0) * (2.0 * mean - score0 - score1)
+      / (2.0 * variance)
+  }
+
+  pub fn verdict(
+    &self,
+    params: &Params,
+  ) -> Option<Verdict> {
+    let llr = self.llr(params);
+    let (lower, upper) = params.bounds();
+    if llr >= upper {
+      Some(Verdict::H1)
+    } else if llr <= lower {
+      Some(Verdict::H0)
+    } else {
+      None
+    }
+  }
+}
+
+/// One line status as printed after every game, like `games 200 (+60
+/// =90 -50), Elo 17.4 +- 30.1, LOS 87.0%, draws 45.0%, pentanomial [2,
+/// 18, 45, 30, 5]`.
+impl fmt::Display for Stats {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    write!(
+      f,
+      "games {} (+{} ={} -{})",
+      self.games(),
+      self.wins,
+      self.draws,
+      self.losses
+    )?;
+    if let (Some((elo, error)), Some(los)) = (self.elo(), self.los()) {
+      write!(f, ", Elo {elo:.1} +- {error:.1}, LOS {:.1}%", los * 100.0)?;
+    }
+    write!(
+      f,
+      ", draws {:.1}%, pentanomial {:?}",
+      self.draw_ratio() * 100.0,
+      self.pentanomial
+    )
+  }
+}
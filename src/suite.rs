@@ -0,0 +1,123 @@
+//! Test suites: EPD records with best moves (`bm`) or moves to avoid
+//! (`am`), searched one by one to see how many the engine solves and how
+//! fast. See `bin/suite.rs` for the command line.
+
+use std::time::{Duration, Instant};
+
+use anyhow::bail;
+
+use crate::{
+  analysis::Analyzer,
+  board::Move,
+  epd::Epd,
+  san,
+  search::{Limits, SearchEvent},
+};
+
+/// What the record asks for: its `bm` and `am` moves, parsed from SAN
+/// against the record's position.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Goal {
+  pub best: Vec<Move>,
+  pub avoid: Vec<Move>,
+}
+
+impl Goal {
+  pub fn of(epd: &Epd) -> anyhow::Result<Goal> {
+    let moves = |op: &str| -> anyhow::Result<Vec<Move>> {
+      let sans = epd.op(op).unwrap_or_default();
+      sans.iter().map(|san| san::parse_san(&epd.position, san)).collect()
+    };
+    let goal = Goal { best: moves("bm")?, avoid: moves("am")? };
+    if goal.best.is_empty() && goal.avoid.is_empty() {
+      bail!("record has neither bm nor am");
+    }
+    Ok(goal)
+  }
+
+  pub fn is_solved_by(
+    &self,
+    mv: Move,
+  ) -> bool {
+    (self.best.is_empty() || self.best.contains(&mv))
+      && !self.avoid.contains(&mv)
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct PositionResult {
+  pub id: String,
+  pub best_move: Option<Move>,
+  pub solved: bool,
+  /// When the engine settled on a solving move for good.
+  pub solved_after: Option<Duration>,
+  pub time: Duration,
+  pub depth: u32,
+  pub nodes: u64,
+}
+
+/// Searches one record and checks the engine's final choice.
+pub fn run_position(
+  analyzer: &mut Analyzer,
+  epd: &Epd,
+  limits: &Limits,
+) -> anyhow::Result<PositionResult> {
+  let goal = Goal::of(epd)?;
+  let started = Instant::now();
+  let events = analyzer.start(&epd.position, &[], limits.clone())?;
+  let mut solved_after = None;
+  let (mut depth, mut nodes) = (0, 0);
+  let mut best_move = None;
+  for event in events {
+    match event {
+      SearchEvent::Info(info) if info.multipv <= 1 => {
+        depth = info.depth;
+        nodes = info.nodes;
+        match info.pv.first() {
+          Some(&mv) if goal.is_solved_by(mv) => {
+            solved_after.get_or_insert(started.elapsed());
+          }
+          _ => solved_after = None,
+        }
+      }
+      SearchEvent::Info(_) => {}
+      SearchEvent::Done(result) => {
+        best_move = result.best_move;
+        break;
+      }
+    }
+  }
+  let time = started.elapsed();
+  let solved = best_move.is_some_and(|mv| goal.is_solved_by(mv));
+  Ok(PositionResult {
+    id: epd.id().unwrap_or("?").to_string(),
+    best_move,
+    solved,
+    solved_after: if solved { solved_after.or(Some(time)) } else { None },
+    time,
+    depth,
+    nodes,
+  })
+}
+
+/// Runs every record, calling `on_position` after each. Records without
+/// a valid `bm` or `am` are skipped with a warning.
+pub fn run(
+  analyzer: &mut Analyzer,
+  records: &[Epd],
+  limits: &Limits,
+  mut on_position: impl FnMut(&Epd, &PositionResult),
+) -> anyhow::Result<Vec<PositionResult>> {
+  let mut results = Vec::with_capacity(records.len());
+  for epd in records {
+    if let Err(err) = Goal::of(epd) {
+      log::warn!("skipping {}: {err}", epd.id().unwrap_or("?"));
+      continue;
+    }
+    analyzer.new_game()?;
+    let result = run_position(analyzer, epd, limits)?;
+    on_position(epd, &result);
+    results.push(result);
+  }
+  Ok(results)
+}
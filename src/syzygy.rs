@@ -0,0 +1,1284 @@
+//! Syzygy endgame tablebases: the tables found on disk, read into memory
+//! the first time a position needs them, and probed for win/draw/loss
+//! (WDL) or the distance to the next capture or pawn move (DTZ). The
+//! tables are decoded the way Stockfish's `tbprobe.cpp` does it: a
+//! position is turned into an index by mirroring it into a canonical
+//! form, and the value at that index is found in blocks of canonical
+//! Huffman codes whose symbols expand into runs of values.
+//! https://github.com/syzygy1/tb
+
+use std::{
+  collections::HashMap,
+  fmt, fs,
+  io::Read,
+  ops::Neg,
+  path::{Path, PathBuf},
+  sync::OnceLock,
+};
+
+use anyhow::{anyhow, bail, ensure};
+
+use crate::board::{king_attacks, Color, Move, PieceKind, Position, Square};
+
+const WDL_SUFFIX: &str = "rtbw";
+const DTZ_SUFFIX: &str = "rtbz";
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+
+/// Most pieces on the board any table has, kings included.
+const MAX_PIECES: usize = 7;
+
+// flags of a compressed table
+const STM: u8 = 1;
+const MAPPED: u8 = 2;
+const WIN_PLIES: u8 = 4;
+const LOSS_PLIES: u8 = 8;
+const WIDE: u8 = 16;
+const SINGLE_VALUE: u8 = 128;
+
+/// Game-theoretic value for the side to move. Cursed wins and blessed
+/// losses are decided by the fifty-move rule.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+  Loss,
+  BlessedLoss,
+  Draw,
+  CursedWin,
+  Win,
+}
+
+impl Wdl {
+  /// As the tables store it, -2 for a loss to 2 for a win.
+  fn from_value(value: i32) -> Wdl {
+    match value {
+      ..=-2 => Wdl::Loss,
+      -1 => Wdl::BlessedLoss,
+      0 => Wdl::Draw,
+      1 => Wdl::CursedWin,
+      _ => Wdl::Win,
+    }
+  }
+
+  fn value(self) -> i32 {
+    self as i32 - 2
+  }
+}
+
+/// The value for the other side.
+impl Neg for Wdl {
+  type Output = Wdl;
+
+  fn neg(self) -> Wdl {
+    Wdl::from_value(-self.value())
+  }
+}
+
+/// Distance to zeroing: plies until the next capture or pawn move with
+/// best play, positive when the side to move wins and zero for draws.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dtz(pub i32);
+
+/// How the analysis panel puts a probe result: "Win in 12", "Draw",
+/// "Loss in 7". The distance counts plies to the next zeroing move.
+impl fmt::Display for Dtz {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    match self.0 {
+      0 => write!(f, "Draw"),
+      n if n > 0 => write!(f, "Win in {n}"),
+      n => write!(f, "Loss in {}", -n),
+    }
+  }
+}
+
+/// The DTZ of a zeroing move that leads to `wdl`.
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+  match wdl {
+    Wdl::Win => 1,
+    Wdl::CursedWin => 101,
+    Wdl::Draw => 0,
+    Wdl::BlessedLoss => -101,
+    Wdl::Loss => -1,
+  }
+}
+
+/// Table name of the material on the board, as in `KRPvKR`, with the
+/// side of `first` before the `v`.
+fn material_name(
+  position: &Position,
+  first: Color,
+) -> String {
+  let side = |color: Color| -> String {
+    PieceKind::ALL
+      .into_iter()
+      .rev()
+      .flat_map(|kind| {
+        let count = position.pieces(color, kind).count_ones() as usize;
+        std::iter::repeat_n(kind.char(), count)
+      })
+      .collect()
+  };
+  format!("{}v{}", side(first), side(!first))
+}
+
+/// Piece as the tables code it: 1 to 6 for white's pawn to king, 8 more
+/// for black's.
+fn piece_code(
+  color: Color,
+  kind: PieceKind,
+) -> u8 {
+  kind.index() as u8 + 1 + color.fold(0, 8)
+}
+
+/// How far below the a1-h8 diagonal a square is, negative above it.
+fn off_diagonal(sq: usize) -> i32 {
+  (sq & 7) as i32 - (sq >> 3) as i32
+}
+
+/// Index tables shared by all the tables, as in `Tablebases::init`.
+struct Maps {
+  /// `binomial[k][n]`: ways to choose k squares out of n.
+  binomial: [[u64; 64]; MAX_PIECES],
+  /// Squares a2 to h7, the one of the leading pawn first: nearest the
+  /// edge, then lowest.
+  map_pawns: [usize; 64],
+  lead_pawn_idx: [[u64; 64]; MAX_PIECES],
+  lead_pawns_size: [[u64; 4]; MAX_PIECES],
+  /// Squares above the a1-h8 diagonal, 0 to 27.
+  map_b1h1h7: [u64; 64],
+  /// Squares of the a1-d1-d4 triangle, 0 to 9, the diagonal last.
+  map_a1d1d4: [usize; 64],
+  /// Both kings, the first one in the triangle: 462 legal placements.
+  map_kk: [[u64; 64]; 10],
+}
+
+fn maps() -> &'static Maps {
+  static MAPS: OnceLock<Maps> = OnceLock::new();
+  MAPS.get_or_init(|| {
+    let mut maps = Maps {
+      binomial: [[0; 64]; MAX_PIECES],
+      map_pawns: [0; 64],
+      lead_pawn_idx: [[0; 64]; MAX_PIECES],
+      lead_pawns_size: [[0; 4]; MAX_PIECES],
+      map_b1h1h7: [0; 64],
+      map_a1d1d4: [0; 64],
+      map_kk: [[0; 64]; 10],
+    };
+    for n in 0..64 {
+      for k in 0..MAX_PIECES.min(n + 1) {
+        maps.binomial[k][n] = match (k, n) {
+          (0, _) => 1,
+          (_, 0) => 0,
+          _ => maps.binomial[k - 1][n - 1] + maps.binomial[k][n - 1],
+        };
+      }
+    }
+
+    let mut code = 0;
+    for sq in 0..64 {
+      if off_diagonal(sq) > 0 {
+        maps.map_b1h1h7[sq] = code;
+        code += 1;
+      }
+    }
+    let mut code = 0;
+    let mut diagonal = Vec::new();
+    for sq in 0..=27 {
+      if sq & 7 > 3 {
+        continue;
+      }
+      if off_diagonal(sq) > 0 {
+        maps.map_a1d1d4[sq] = code;
+        code += 1;
+      } else if off_diagonal(sq) == 0 {
+        diagonal.push(sq);
+      }
+    }
+    for sq in diagonal {
+      maps.map_a1d1d4[sq] = code;
+      code += 1;
+    }
+
+    let mut code = 0;
+    let mut both_on_diagonal = Vec::new();
+    for idx in 0..10 {
+      for first in 0..=27 {
+        let in_triangle = first & 7 <= 3 && off_diagonal(first) >= 0;
+        if !in_triangle || maps.map_a1d1d4[first] != idx {
+          continue;
+        }
+        let taken = king_attacks(Square::from_index(first as u8)) | 1 << first;
+        for second in 0..64 {
+          if taken & 1 << second != 0
+            || (off_diagonal(first) == 0 && off_diagonal(second) < 0)
+          {
+            continue;
+          }
+          if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+            both_on_diagonal.push((idx, second));
+          } else {
+            maps.map_kk[idx][second] = code;
+            code += 1;
+          }
+        }
+      }
+    }
+    for (idx, second) in both_on_diagonal {
+      maps.map_kk[idx][second] = code;
+      code += 1;
+    }
+
+    let mut available = 47;
+    for count in 1..MAX_PIECES {
+      for file in 0..4 {
+        let mut idx = 0;
+        for rank in 1..7 {
+          let sq = rank * 8 + file;
+          if count == 1 {
+            maps.map_pawns[sq] = available;
+            maps.map_pawns[sq ^ 7] = available - 1;
+            available = available.saturating_sub(2);
+          }
+          maps.lead_pawn_idx[count][sq] = idx;
+          idx += maps.binomial[count - 1][maps.map_pawns[sq]];
+        }
+        maps.lead_pawns_size[count][file] = idx;
+      }
+    }
+    maps
+  })
+}
+
+/// What a table's name tells: the pieces of each side, white being the
+/// side before the `v`.
+struct Material {
+  counts: [[usize; 6]; 2],
+}
+
+impl Material {
+  fn from_name(name: &str) -> anyhow::Result<Material> {
+    let (white, black) =
+      name.split_once('v').ok_or_else(|| anyhow!("no table {name:?}"))?;
+    let mut counts = [[0; 6]; 2];
+    for (side, pieces) in [white, black].into_iter().enumerate() {
+      for c in pieces.chars() {
+        let kind = PieceKind::from_char(c)
+          .filter(|_| c.is_ascii_uppercase())
+          .ok_or_else(|| anyhow!("no table {name:?}"))?;
+        counts[side][kind.index()] += 1;
+      }
+    }
+    let kings = PieceKind::King.index();
+    ensure!(
+      counts[0][kings] == 1 && counts[1][kings] == 1,
+      "no table {name:?}"
+    );
+    Ok(Material { counts })
+  }
+
+  fn pawns(
+    &self,
+    color: Color,
+  ) -> usize {
+    self.counts[color.index()][PieceKind::Pawn.index()]
+  }
+}
+
+/// One compressed table of a file: the positions of a side to move and,
+/// with pawns, of a file of the leading pawn.
+#[derive(Clone, Debug, Default)]
+struct Pairs {
+  flags: u8,
+  /// Piece codes in the order the position is encoded.
+  pieces: Vec<u8>,
+  /// Sizes of the groups of pieces encoded together.
+  group_len: Vec<usize>,
+  /// Factor of each group in the index, then the size of the table.
+  group_idx: Vec<u64>,
+  block_size: usize,
+  span: u64,
+  blocks: usize,
+  min_sym_len: usize,
+  lowest_sym: usize,
+  base64: Vec<u64>,
+  /// Values each symbol expands into, less one.
+  sym_len: Vec<usize>,
+  btree: usize,
+  sparse_index: usize,
+  sparse_index_size: usize,
+  block_lengths: usize,
+  block_length_size: usize,
+  data: usize,
+  /// Where the DTZ values of each result start in the map.
+  map_idx: [usize; 4],
+}
+
+/// A table file read into memory.
+struct TableFile {
+  bytes: Vec<u8>,
+  dtz: bool,
+  /// Whether both sides have the same pieces.
+  symmetric: bool,
+  has_pawns: bool,
+  /// Pawns of the leading color, then of the other one.
+  pawns: [usize; 2],
+  pieces: usize,
+  /// Whether some piece other than a king is alone of its kind.
+  unique: bool,
+  /// By side to move, then by file of the leading pawn.
+  pairs: Vec<Vec<Pairs>>,
+  map: usize,
+}
+
+impl fmt::Debug for TableFile {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    f.debug_struct("TableFile")
+      .field("bytes", &self.bytes.len())
+      .field("dtz", &self.dtz)
+      .finish_non_exhaustive()
+  }
+}
+
+impl TableFile {
+  fn byte(
+    &self,
+    at: usize,
+  ) -> u8 {
+    self.bytes.get(at).copied().unwrap_or(0)
+  }
+
+  fn u16_le(
+    &self,
+    at: usize,
+  ) -> usize {
+    u16::from_le_bytes([self.byte(at), self.byte(at + 1)]) as usize
+  }
+
+  fn u32_le(
+    &self,
+    at: usize,
+  ) -> usize {
+    let bytes = [0, 1, 2, 3].map(|i| self.byte(at + i));
+    u32::from_le_bytes(bytes) as usize
+  }
+
+  fn u32_be(
+    &self,
+    at: usize,
+  ) -> u64 {
+    let bytes = [0, 1, 2, 3].map(|i| self.byte(at + i));
+    u32::from_be_bytes(bytes) as u64
+  }
+
+  /// Reads the header of the file named after `material`.
+  fn parse(
+    bytes: Vec<u8>,
+    material: &Material,
+    dtz: bool,
+  ) -> anyhow::Result<TableFile> {
+    let [white, black] = material.counts;
+    let symmetric = white == black;
+    let has_pawns =
+      material.pawns(Color::White) + material.pawns(Color::Black) > 0;
+    let unique = material
+      .counts
+      .iter()
+      .any(|counts| counts[..PieceKind::King.index()].contains(&1));
+    // the side with fewer pawns leads, for better compression
+    let (white_pawns, black_pawns) =
+      (material.pawns(Color::White), material.pawns(Color::Black));
+    let lead =
+      black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+    let pawns = if lead {
+      [white_pawns, black_pawns]
+    } else {
+      [black_pawns, white_pawns]
+    };
+    let pieces = material.counts.iter().flatten().sum();
+    ensure!(pieces <= MAX_PIECES, "too many pieces");
+    let mut table = TableFile {
+      bytes,
+      dtz,
+      symmetric,
+      has_pawns,
+      pawns,
+      pieces,
+      unique,
+      pairs: Vec::new(),
+      map: 0,
+    };
+
+    let flags = table.byte(4);
+    ensure!(
+      (flags & 1 != 0) != symmetric && (flags & 2 != 0) == has_pawns,
+      "the table doesn't match its name"
+    );
+    let sides = if !dtz && !symmetric { 2 } else { 1 };
+    let files = if has_pawns { 4 } else { 1 };
+    let both_pawns = has_pawns && pawns[1] > 0;
+    table.pairs = vec![vec![Pairs::default(); files]; sides];
+
+    let mut at = 5;
+    for file in 0..files {
+      let second = if both_pawns { table.byte(at + 1) } else { 0xff };
+      let order = [
+        [table.byte(at) & 15, second & 15],
+        [table.byte(at) >> 4, second >> 4],
+      ];
+      at += 1 + both_pawns as usize;
+      for _ in 0..pieces {
+        let code = table.byte(at);
+        for (side, pairs) in table.pairs.iter_mut().enumerate() {
+          pairs[file].pieces.push(if side == 0 {
+            code & 15
+          } else {
+            code >> 4
+          });
+        }
+        at += 1;
+      }
+      for (side, order) in order.into_iter().enumerate().take(sides) {
+        table.set_groups(side, file, order);
+      }
+    }
+    at += at & 1;
+
+    for file in 0..files {
+      for side in 0..sides {
+        at = table.set_sizes(side, file, at)?;
+      }
+    }
+    if dtz {
+      at = table.set_dtz_map(files, at);
+    }
+    for file in 0..files {
+      for side in 0..sides {
+        let pairs = &mut table.pairs[side][file];
+        pairs.sparse_index = at;
+        at += pairs.sparse_index_size * 6;
+      }
+    }
+    for file in 0..files {
+      for side in 0..sides {
+        let pairs = &mut table.pairs[side][file];
+        pairs.block_lengths = at;
+        at += pairs.block_length_size * 2;
+      }
+    }
+    for file in 0..files {
+      for side in 0..sides {
+        at = (at + 63) & !63;
+        let pairs = &mut table.pairs[side][file];
+        pairs.data = at;
+        at += pairs.blocks * pairs.block_size;
+      }
+    }
+    ensure!(at <= table.bytes.len(), "the table is truncated");
+    Ok(table)
+  }
+
+  /// Splits the pieces into the groups encoded together, in the order
+  /// the table gives.
+  fn set_groups(
+    &mut self,
+    side: usize,
+    file: usize,
+    order: [u8; 2],
+  ) {
+    let maps = maps();
+    let pairs = &mut self.pairs[side][file];
+    let mut first_len: i32 = match (self.has_pawns, self.unique) {
+      (true, _) => 0,
+      (false, true) => 3,
+      (false, false) => 2,
+    };
+    let mut group_len = vec![1];
+    for i in 1..self.pieces {
+      first_len -= 1;
+      if first_len > 0 || pairs.pieces[i] == pairs.pieces[i - 1] {
+        *group_len.last_mut().unwrap() += 1;
+      } else {
+        group_len.push(1);
+      }
+    }
+    let groups = group_len.len();
+    let both_pawns = self.has_pawns && self.pawns[1] > 0;
+    let mut next = if both_pawns { 2 } else { 1 };
+    let mut free =
+      64 - group_len[0] - if both_pawns { group_len[1] } else { 0 };
+    let mut group_idx = vec![0; groups + 1];
+    let mut idx = 1;
+    let mut k = 0;
+    while next < groups || k == order[0] || k == order[1] {
+      if k == order[0] {
+        group_idx[0] = idx;
+        idx *= match (self.has_pawns, self.unique) {
+          (true, _) => maps.lead_pawns_size[group_len[0]][file],
+          (false, true) => 31332,
+          (false, false) => 462,
+        };
+      } else if k == order[1] {
+        group_idx[1] = idx;
+        idx *= maps.binomial[group_len[1]][48 - group_len[0]];
+      } else {
+        group_idx[next] = idx;
+        idx *= maps.binomial[group_len[next]][free];
+        free -= group_len[next];
+        next += 1;
+      }
+      k += 1;
+    }
+    group_idx[groups] = idx;
+    (pairs.group_len, pairs.group_idx) = (group_len, group_idx);
+  }
+
+  /// Reads how a table is compressed, returning where the next starts.
+  fn set_sizes(
+    &mut self,
+    side: usize,
+    file: usize,
+    mut at: usize,
+  ) -> anyhow::Result<usize> {
+    let flags = self.byte(at);
+    let mut pairs = std::mem::take(&mut self.pairs[side][file]);
+    pairs.flags = flags;
+    at += 1;
+    if flags & SINGLE_VALUE != 0 {
+      // the one value is kept as the shortest symbol length
+      pairs.min_sym_len = self.byte(at) as usize;
+      self.pairs[side][file] = pairs;
+      return Ok(at + 1);
+    }
+    let size = *pairs.group_idx.last().unwrap();
+    pairs.block_size = 1 << self.byte(at);
+    pairs.span = 1 << self.byte(at + 1);
+    pairs.sparse_index_size = size.div_ceil(pairs.span) as usize;
+    let padding = self.byte(at + 2) as usize;
+    pairs.blocks = self.u32_le(at + 3);
+    pairs.block_length_size = pairs.blocks + padding;
+    let max_sym_len = self.byte(at + 7) as usize;
+    pairs.min_sym_len = self.byte(at + 8) as usize;
+    ensure!(
+      pairs.min_sym_len >= 1 && max_sym_len >= pairs.min_sym_len,
+      "invalid symbol lengths"
+    );
+    at += 9;
+    pairs.lowest_sym = at;
+
+    // the canonical code of the longest symbols starts at zero, those
+    // of each shorter length after the longer ones
+    let lengths = max_sym_len - pairs.min_sym_len + 1;
+    let mut base64 = vec![0u64; lengths];
+    for i in (0..lengths - 1).rev() {
+      let lowest = self.u16_le(at + 2 * i) as u64;
+      let next = self.u16_le(at + 2 * (i + 1)) as u64;
+      base64[i] = (base64[i + 1] + lowest).wrapping_sub(next) / 2;
+    }
+    for (i, base) in base64.iter_mut().enumerate() {
+      let shift = 64 - i - pairs.min_sym_len;
+      *base = if shift >= 64 { 0 } else { *base << shift };
+    }
+    pairs.base64 = base64;
+    at += lengths * 2;
+
+    let symbols = self.u16_le(at);
+    at += 2;
+    pairs.btree = at;
+    let mut sym_len = vec![0; symbols];
+    let mut visited = vec![false; symbols];
+    for sym in 0..symbols {
+      if !visited[sym] {
+        sym_len[sym] =
+          self.set_sym_len(&pairs, sym, &mut sym_len, &mut visited)?;
+      }
+    }
+    pairs.sym_len = sym_len;
+    self.pairs[side][file] = pairs;
+    Ok(at + symbols * 3 + (symbols & 1))
+  }
+
+  /// The left and right halves a symbol pairs up, the right one being
+  /// 0xfff for a symbol that is a value of its own.
+  fn pair(
+    &self,
+    pairs: &Pairs,
+    sym: usize,
+  ) -> (usize, usize) {
+    let at = pairs.btree + 3 * sym;
+    let [a, b, c] = [0, 1, 2].map(|i| self.byte(at + i) as usize);
+    ((b & 15) << 8 | a, c << 4 | b >> 4)
+  }
+
+  fn set_sym_len(
+    &self,
+    pairs: &Pairs,
+    sym: usize,
+    sym_len: &mut [usize],
+    visited: &mut [bool],
+  ) -> anyhow::Result<usize> {
+    visited[sym] = true;
+    let (left, right) = self.pair(pairs, sym);
+    if right == 0xfff {
+      return Ok(0);
+    }
+    ensure!(left < sym_len.len() && right < sym_len.len(), "invalid symbol");
+    for half in [left, right] {
+      if !visited[half] {
+        sym_len[half] = self.set_sym_len(pairs, half, sym_len, visited)?;
+      }
+    }
+    Ok(sym_len[left] + sym_len[right] + 1)
+  }
+
+  /// Finds where the values of each result start in a DTZ map.
+  fn set_dtz_map(
+    &mut self,
+    files: usize,
+    mut at: usize,
+  ) -> usize {
+    self.map = at;
+    for file in 0..files {
+      let flags = self.pairs[0][file].flags;
+      if flags & MAPPED == 0 {
+        continue;
+      }
+      if flags & WIDE != 0 {
+        at += at & 1;
+        for i in 0..4 {
+          self.pairs[0][file].map_idx[i] = (at - self.map) / 2 + 1;
+          at += 2 * self.u16_le(at) + 2;
+        }
+      } else {
+        for i in 0..4 {
+          self.pairs[0][file].map_idx[i] = at - self.map + 1;
+          at += self.byte(at) as usize + 1;
+        }
+      }
+    }
+    at + (at & 1)
+  }
+
+  /// Where a position is in the file: the side to move and file of the
+  /// leading pawn of its table, and its index there. `None` when the
+  /// DTZ table only has positions of the other side to move.
+  fn index(
+    &self,
+    position: &Position,
+    black_stronger: bool,
+  ) -> anyhow::Result<Option<(usize, usize, u64)>> {
+    let maps = maps();
+
+    // tables have the stronger side as white, and symmetric ones only
+    // white to move
+    let black = position.side_to_move() == Color::Black;
+    let flip = black_stronger || (self.symmetric && black);
+    let (flip_color, flip_squares) = if flip { (8, 56) } else { (0, 0) };
+    let stm = (flip ^ black) as usize;
+
+    let mut squares = Vec::with_capacity(self.pieces);
+    let mut pieces = Vec::with_capacity(self.pieces);
+    let mut lead_pawns = 0;
+    let mut file = 0;
+    if self.has_pawns {
+      let lead = self.pairs[0][0].pieces[0] ^ flip_color;
+      let color = if lead & 8 == 0 { Color::White } else { Color::Black };
+      lead_pawns = position.pieces(color, PieceKind::Pawn);
+      for sq in Square::all().filter(|sq| lead_pawns & 1 << sq.index() != 0) {
+        squares.push(sq.index() ^ flip_squares);
+        pieces.push(lead);
+      }
+      let lead =
+        (0..squares.len()).max_by_key(|&i| maps.map_pawns[squares[i]]).unwrap();
+      squares.swap(0, lead);
+      file = (squares[0] & 7).min(7 - (squares[0] & 7));
+    }
+    let lead_count = squares.len();
+
+    if self.dtz {
+      let flags = self.pairs[0][file].flags;
+      if (flags & STM) as usize != stm && (!self.symmetric || self.has_pawns) {
+        return Ok(None);
+      }
+    }
+
+    for (sq, piece) in position.board() {
+      if lead_pawns & 1 << sq.index() == 0 {
+        squares.push(sq.index() ^ flip_squares);
+        pieces.push(piece_code(piece.color, piece.kind) ^ flip_color);
+      }
+    }
+    ensure!(squares.len() == self.pieces, "the table doesn't match");
+    let side = stm % self.pairs.len();
+    let pairs = &self.pairs[side][file];
+
+    // the pieces in the order of the table
+    for i in lead_count..squares.len().saturating_sub(1) {
+      if let Some(j) =
+        (i + 1..squares.len()).find(|&j| pairs.pieces[i] == pieces[j])
+      {
+        pieces.swap(i, j);
+        squares.swap(i, j);
+      }
+    }
+    if squares[0] & 7 > 3 {
+      squares.iter_mut().for_each(|sq| *sq ^= 7);
+    }
+
+    let mut idx;
+    if self.has_pawns {
+      idx = maps.lead_pawn_idx[lead_count][squares[0]];
+      squares[1..lead_count].sort_by_key(|&sq| maps.map_pawns[sq]);
+      for (i, &sq) in squares.iter().enumerate().take(lead_count).skip(1) {
+        idx += maps.binomial[i][maps.map_pawns[sq]];
+      }
+    } else {
+      if squares[0] >> 3 > 3 {
+        squares.iter_mut().for_each(|sq| *sq ^= 56);
+      }
+      // the first piece off the diagonal goes below it
+      for i in 0..pairs.group_len[0] {
+        match off_diagonal(squares[i]) {
+          0 => continue,
+          off if off < 0 => {
+            for sq in &mut squares[i..] {
+              *sq = (*sq >> 3 | *sq << 3) & 63;
+            }
+          }
+          _ => {}
+        }
+        break;
+      }
+      idx = if self.unique {
+        let [first, second, third] = [squares[0], squares[1], squares[2]];
+        let adjust1 = (second > first) as u64;
+        let adjust2 = (third > first) as u64 + (third > second) as u64;
+        let rank = |sq: usize| (sq >> 3) as u64;
+        if off_diagonal(first) != 0 {
+          (maps.map_a1d1d4[first] as u64 * 63 + second as u64 - adjust1) * 62
+            + third as u64
+            - adjust2
+        } else if off_diagonal(second) != 0 {
+          (6 * 63 + rank(first) * 28 + maps.map_b1h1h7[second]) * 62
+            + third as u64
+            - adjust2
+        } else if off_diagonal(third) != 0 {
+          6 * 63 * 62
+            + 4 * 28 * 62
+            + rank(first) * 7 * 28
+            + (rank(second) - adjust1) * 28
+            + maps.map_b1h1h7[third]
+        } else {
+          6 * 63 * 62
+            + 4 * 28 * 62
+            + 4 * 7 * 28
+            + rank(first) * 7 * 6
+            + (rank(second) - adjust1) * 6
+            + (rank(third) - adjust2)
+        }
+      } else {
+        maps.map_kk[maps.map_a1d1d4[squares[0]]][squares[1]]
+      };
+    }
+
+    // the other groups by the squares left to them, in ascending order
+    idx *= pairs.group_idx[0];
+    let mut start = pairs.group_len[0];
+    let mut remaining_pawns = self.has_pawns && self.pawns[1] > 0;
+    for next in 1..pairs.group_len.len() {
+      let len = pairs.group_len[next];
+      let (before, group) = squares.split_at_mut(start);
+      group[..len].sort_unstable();
+      let mut n = 0;
+      for (i, &sq) in group[..len].iter().enumerate() {
+        let adjust = before.iter().filter(|&&other| sq > other).count();
+        let available = sq - adjust - if remaining_pawns { 8 } else { 0 };
+        n += maps.binomial[i + 1][available];
+      }
+      remaining_pawns = false;
+      idx += n * pairs.group_idx[next];
+      start += len;
+    }
+
+    Ok(Some((side, file, idx)))
+  }
+
+  /// The value at `idx` of a table.
+  fn decompress(
+    &self,
+    pairs: &Pairs,
+    idx: u64,
+  ) -> anyhow::Result<usize> {
+    if pairs.flags & SINGLE_VALUE != 0 {
+      return Ok(pairs.min_sym_len);
+    }
+    let size = *pairs.group_idx.last().unwrap();
+    ensure!(idx < size, "index {idx} out of the table");
+
+    // the sparse index points into the block of every span/2 + k*span
+    let k = (idx / pairs.span) as usize;
+    let entry = pairs.sparse_index + 6 * k;
+    let mut block = self.u32_le(entry);
+    let mut offset = self.u16_le(entry + 4) as i64;
+    offset += (idx % pairs.span) as i64 - (pairs.span / 2) as i64;
+    let block_length =
+      |block: usize| self.u16_le(pairs.block_lengths + 2 * block) as i64 + 1;
+    while offset < 0 {
+      ensure!(block > 0, "invalid sparse index");
+      block -= 1;
+      offset += block_length(block);
+    }
+    while offset >= block_length(block) {
+      offset -= block_length(block);
+      block += 1;
+      ensure!(block < pairs.block_length_size, "invalid sparse index");
+    }
+
+    let mut at = pairs.data + block * pairs.block_size;
+    let mut buf = self.u32_be(at) << 32 | self.u32_be(at + 4);
+    at += 8;
+    let mut buf_size = 64;
+    let mut sym;
+    loop {
+      let mut len = 0;
+      while len + 1 < pairs.base64.len() && buf < pairs.base64[len] {
+        len += 1;
+      }
+      let bits = len + pairs.min_sym_len;
+      sym = ((buf - pairs.base64[len]) >> (64 - bits)) as usize;
+      sym += self.u16_le(pairs.lowest_sym + 2 * len);
+      ensure!(sym < pairs.sym_len.len(), "invalid symbol");
+      let values = pairs.sym_len[sym] as i64 + 1;
+      if offset < values {
+        break;
+      }
+      offset -= values;
+      buf <<= bits;
+      buf_size -= bits;
+      if buf_size <= 32 {
+        buf_size += 32;
+        buf |= self.u32_be(at) << (64 - buf_size);
+        at += 4;
+      }
+    }
+
+    // the symbol stands for a pair of symbols, down to single values
+    while pairs.sym_len[sym] != 0 {
+      let (left, right) = self.pair(pairs, sym);
+      let values = pairs.sym_len[left] as i64 + 1;
+      if offset < values {
+        sym = left;
+      } else {
+        offset -= values;
+        sym = right;
+      }
+    }
+    Ok(self.pair(pairs, sym).0)
+  }
+
+  /// The DTZ in plies of a value read for a position of result `wdl`.
+  fn map_dtz(
+    &self,
+    file: usize,
+    mut value: usize,
+    wdl: Wdl,
+  ) -> i32 {
+    let pairs = &self.pairs[0][file];
+    let flags = pairs.flags;
+    if flags & MAPPED != 0 {
+      let result = match wdl {
+        Wdl::Win => 0,
+        Wdl::Loss => 1,
+        Wdl::CursedWin => 2,
+        Wdl::BlessedLoss => 3,
+        Wdl::Draw => 0,
+      };
+      let idx = pairs.map_idx[result] + value;
+      value = if flags & WIDE != 0 {
+        self.u16_le(self.map + 2 * idx)
+      } else {
+        self.byte(self.map + idx) as usize
+      };
+    }
+    let value = value as i32;
+    let in_moves = match wdl {
+      Wdl::Win => flags & WIN_PLIES == 0,
+      Wdl::Loss => flags & LOSS_PLIES == 0,
+      _ => true,
+    };
+    if in_moves {
+      value * 2 + 1
+    } else {
+      value + 1
+    }
+  }
+}
+
+/// Where a table file puts its positions, for writing one the way the
+/// test fixtures are written. `header` is a file naming its pieces, with
+/// a single value in each part.
+#[doc(hidden)]
+pub struct Layout(TableFile);
+
+impl Layout {
+  pub fn new(
+    name: &str,
+    header: Vec<u8>,
+    dtz: bool,
+  ) -> anyhow::Result<Layout> {
+    TableFile::parse(header, &Material::from_name(name)?, dtz).map(Layout)
+  }
+
+  /// Positions in each part, by side to move then file of the leading
+  /// pawn.
+  pub fn sizes(&self) -> Vec<Vec<u64>> {
+    let size = |pairs: &Pairs| pairs.group_idx.last().copied().unwrap_or(0);
+    self.0.pairs.iter().map(|files| files.iter().map(size).collect()).collect()
+  }
+
+  /// The part and index of a position, white being the side the table
+  /// names first; `None` when a DTZ table leaves it out.
+  pub fn index(
+    &self,
+    position: &Position,
+  ) -> anyhow::Result<Option<(usize, usize, u64)>> {
+    self.0.index(position, false)
+  }
+}
+
+#[derive(Debug)]
+struct Table {
+  wdl: Option<PathBuf>,
+  dtz: Option<PathBuf>,
+  loaded: [OnceLock<Result<TableFile, String>>; 2],
+}
+
+/// What a table says about a position.
+enum Probe {
+  Value(i32),
+  /// The DTZ table only has positions of the other side to move.
+  ChangeStm,
+}
+
+/// The tables found in one or more directories, indexed by material.
+#[derive(Debug, Default)]
+pub struct Tablebase {
+  paths: String,
+  tables: HashMap<String, Table>,
+  max_pieces: usize,
+}
+
+impl Tablebase {
+  /// Looks for tables in every directory of a `SyzygyPath` style list,
+  /// separated by `:` (`;` on Windows). Files that aren't tables are
+  /// skipped with a warning.
+  pub fn open(paths: &str) -> anyhow::Result<Tablebase> {
+    let separator = if cfg!(windows) { ';' } else { ':' };
+    let mut tablebase =
+      Tablebase { paths: paths.into(), ..Tablebase::default() };
+    for dir in paths.split(separator).filter(|dir| !dir.is_empty()) {
+      tablebase.add_directory(Path::new(dir))?;
+    }
+    Ok(tablebase)
+  }
+
+  fn add_directory(
+    &mut self,
+    dir: &Path,
+  ) -> anyhow::Result<()> {
+    let entries = fs::read_dir(dir)
+      .map_err(|err| anyhow!("can't read {}: {err}", dir.display()))?;
+    for entry in entries {
+      let path = entry?.path();
+      let (Some(name), Some(suffix)) = (
+        path.file_stem().and_then(|name| name.to_str()),
+        path.extension().and_then(|suffix| suffix.to_str()),
+      ) else {
+        continue;
+      };
+      let magic = match suffix {
+        WDL_SUFFIX => WDL_MAGIC,
+        DTZ_SUFFIX => DTZ_MAGIC,
+        _ => continue,
+      };
+      let checked = Material::from_name(name).and_then(|_| {
+        let mut header = [0; 4];
+        fs::File::open(&path)?.read_exact(&mut header)?;
+        ensure!(header == magic, "not a syzygy table");
+        Ok(())
+      });
+      if let Err(err) = checked {
+        log::warn!("skipping {}: {err:#}", path.display());
+        continue;
+      }
+
+      let pieces = name.chars().filter(|c| *c != 'v').count();
+      self.max_pieces = self.max_pieces.max(pieces);
+      let table = self.tables.entry(name.into()).or_insert(Table {
+        wdl: None,
+        dtz: None,
+        loaded: Default::default(),
+      });
+      if suffix == WDL_SUFFIX {
+        table.wdl = Some(path);
+      } else {
+        table.dtz = Some(path);
+      }
+    }
+    Ok(())
+  }
+
+  /// The directories as given to [`Tablebase::open`].
+  pub fn paths(&self) -> &str {
+    &self.paths
+  }
+
+  /// Largest number of pieces, kings included, any table covers.
+  pub fn max_pieces(&self) -> usize {
+    self.max_pieces
+  }
+
+  /// Whether the tables may know the position: few enough pieces and
+  /// no castling rights.
+  pub fn covers(
+    &self,
+    position: &Position,
+  ) -> bool {
+    position.occupied().count_ones() as usize <= self.max_pieces
+      && [Color::White, Color::Black].into_iter().all(|color| {
+        !position.can_castle(color, true) && !position.can_castle(color, false)
+      })
+  }
+
+  /// The table of the material on the board, read on first use, and
+  /// whether it has black's pieces as white's.
+  fn file(
+    &self,
+    position: &Position,
+    dtz: bool,
+  ) -> anyhow::Result<(&TableFile, bool)> {
+    let (name, flipped, table) = [Color::White, Color::Black]
+      .into_iter()
+      .find_map(|color| {
+        let name = material_name(position, color);
+        let table = self.tables.get(&name)?;
+        Some((name, color == Color::Black, table))
+      })
+      .ok_or_else(|| {
+        anyhow!("missing table {}", material_name(position, Color::White))
+      })?;
+    let (path, suffix) = match dtz {
+      false => (&table.wdl, WDL_SUFFIX),
+      true => (&table.dtz, DTZ_SUFFIX),
+    };
+    let path =
+      path.as_ref().ok_or_else(|| anyhow!("missing {name}.{suffix}"))?;
+    let loaded = table.loaded[dtz as usize].get_or_init(|| {
+      let read =
+        fs::read(path).map_err(anyhow::Error::from).and_then(|bytes| {
+          TableFile::parse(bytes, &Material::from_name(&name)?, dtz)
+        });
+      read.map_err(|err| format!("can't read {}: {err:#}", path.display()))
+    });
+    match loaded {
+      Ok(file) => Ok((file, flipped && !file.symmetric)),
+      Err(err) => bail!("{err}"),
+    }
+  }
+
+  /// The value a table stores for the position: the WDL value, or the
+  /// DTZ of a position whose result is `wdl`.
+  fn probe_table(
+    &self,
+    position: &Position,
+    wdl: Option<Wdl>,
+  ) -> anyhow::Result<Probe> {
+    if position.is_insufficient_material() {
+      return Ok(Probe::Value(0));
+    }
+    let (table, black_stronger) = self.file(position, wdl.is_some())?;
+    let Some((side, file, idx)) = table.index(position, black_stronger)? else {
+      return Ok(Probe::ChangeStm);
+    };
+    let value = table.decompress(&table.pairs[side][file], idx)?;
+    Ok(Probe::Value(match wdl {
+      None => value as i32 - 2,
+      Some(wdl) => table.map_dtz(file, value, wdl),
+    }))
+  }
+
+  /// The WDL value, looking at the captures (and pawn moves with
+  /// `zeroing`) first: the tables may store any value where one of
+  /// them wins. Also tells whether such a move is best.
+  fn search(
+    &self,
+    position: &Position,
+    zeroing: bool,
+  ) -> anyhow::Result<(Wdl, bool)> {
+    let moves = position.legal_moves();
+    let mut best = Wdl::Loss;
+    let mut searched = 0;
+    for &mv in &moves {
+      let pawn = position.piece_at(mv.from).unwrap().kind == PieceKind::Pawn;
+      if !(position.is_capture(mv) || zeroing && pawn) {
+        continue;
+      }
+      searched += 1;
+      let (value, _) = self.search(&position.played(mv), false)?;
+      let value = -value;
+      if value > best {
+        best = value;
+        if value == Wdl::Win {
+          return Ok((value, true));
+        }
+      }
+    }
+
+    let all_searched = searched > 0 && searched == moves.len();
+    let value = if all_searched {
+      best
+    } else {
+      match self.probe_table(position, None)? {
+        Probe::Value(value) => Wdl::from_value(value),
+        Probe::ChangeStm => unreachable!("WDL tables have both sides"),
+      }
+    };
+    if best >= value {
+      return Ok((best, best > Wdl::Draw || all_searched));
+    }
+    Ok((value, false))
+  }
+
+  fn check(
+    &self,
+    position: &Position,
+  ) -> anyhow::Result<()> {
+    let pieces = position.occupied().count_ones() as usize;
+    if pieces > self.max_pieces {
+      bail!("no tables for {pieces} pieces");
+    }
+    if !self.covers(position) {
+      bail!("tables have no positions with castling rights");
+    }
+    Ok(())
+  }
+
+  pub fn probe_wdl(
+    &self,
+    position: &Position,
+  ) -> anyhow::Result<Wdl> {
+    if position.is_insufficient_material() {
+      return Ok(Wdl::Draw);
+    }
+    self.check(position)?;
+    Ok(self.search(position, false)?.0)
+  }
+
+  pub fn probe_dtz(
+    &self,
+    position: &Position,
+  ) -> anyhow::Result<Dtz> {
+    if position.is_insufficient_material() {
+      return Ok(Dtz(0));
+    }
+    self.check(position)?;
+    self.dtz(position).map(Dtz)
+  }
+
+  fn dtz(
+    &self,
+    position: &Position,
+  ) -> anyhow::Result<i32> {
+    let (wdl, zeroing) = self.search(position, true)?;
+    if wdl == Wdl::Draw {
+      return Ok(0);
+    }
+    // the table needn't have a value where a zeroing move is best
+    if zeroing {
+      return Ok(dtz_before_zeroing(wdl));
+    }
+    let sign = wdl.value().signum();
+    match self.probe_table(position, Some(wdl))? {
+      Probe::Value(dtz) => {
+        let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+        return Ok((dtz + if cursed { 100 } else { 0 }) * sign);
+      }
+      Probe::ChangeStm => {}
+    }
+
+    // one ply further the table has the positions
+    let mut best = None;
+    for mv in position.legal_moves() {
+      let pawn = position.piece_at(mv.from).unwrap().kind == PieceKind::Pawn;
+      let zeroing = position.is_capture(mv) || pawn;
+      let next = position.played(mv);
+      let mut dtz = if zeroing {
+        -dtz_before_zeroing(self.search(&next, false)?.0)
+      } else {
+        -self.dtz(&next)?
+      };
+      if dtz == 1 && next.is_checkmate() {
+        best = Some(1);
+      }
+      if !zeroing {
+        dtz += dtz.signum();
+      }
+      if dtz.signum() == sign && best.is_none_or(|best| dtz < best) {
+        best = Some(dtz);
+      }
+    }
+    // without moves the side to move is mated
+    Ok(best.unwrap_or(-1))
+  }
+
+  /// The DTZ of each legal move, counted from the position before it.
+  pub fn root_moves(
+    &self,
+    position: &Position,
+  ) -> anyhow::Result<Vec<(Move, Dtz)>> {
+    self.check(position)?;
+    let mut moves = Vec::new();
+    for mv in position.legal_moves() {
+      let next = position.played(mv);
+      let dtz = if next.halfmove_clock() == 0 {
+        dtz_before_zeroing(-self.probe_wdl(&next)?)
+      } else if next.is_checkmate() {
+        1
+      } else {
+        let dtz = -self.probe_dtz(&next)?.0;
+        dtz + dtz.signum()
+      };
+      moves.push((mv, Dtz(dtz)));
+    }
+    Ok(moves)
+  }
+
+  /// The moves that keep the best result the fifty-move rule allows:
+  /// the quickest wins, the draws, or the slowest losses.
+  pub fn best_moves(
+    &self,
+    position: &Position,
+  ) -> anyhow::Result<Vec<Move>> {
+    let clock = position.halfmove_clock() as i32;
+    let rank = |Dtz(dtz): Dtz| match dtz {
+      dtz if dtz > 0 && dtz + clock <= 100 => 1000 - dtz,
+      dtz if dtz > 0 => 1,
+      0 => 0,
+      dtz if -dtz + clock <= 100 => -1000 - dtz,
+      _ => -1,
+    };
+    let moves = self.root_moves(position)?;
+    let best = moves.iter().map(|&(_, dtz)| rank(dtz)).max();
+    Ok(
+      moves
+        .into_iter()
+        .filter(|&(_, dtz)| Some(rank(dtz)) == best)
+        .map(|(mv, _)| mv)
+        .collect(),
+    )
+  }
+}
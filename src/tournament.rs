@@ -0,0 +1,663 @@
+//! Engine-vs-engine matches: round-robin or gauntlet tournaments between
+//! built-in configurations and UCI engines, played headless on several
+//! threads. See `bin/tournament.rs` for the command line.
+
+use std::{
+  collections::{hash_map::Entry, HashMap, VecDeque},
+  fmt, fs,
+  ops::ControlFlow,
+  path::{Path, PathBuf},
+  str::FromStr,
+  sync::{
+    mpsc::{self, RecvTimeoutError},
+    Arc, Mutex,
+  },
+  thread,
+  time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail};
+
+use crate::{
+  analysis::Analyzer,
+  board::{Color, Move, Position},
+  epd,
+  pgn::{self, Game, Outcome},
+  search::{Engine, Limits, Score, SearchEvent},
+  syzygy::{Tablebase, Wdl},
+};
+
+/// How late an engine may answer before losing on time, it covers the
+/// time spent passing the move around.
+const TIME_MARGIN: Duration = Duration::from_millis(50);
+/// Scores are compared in centipawns, mates count as this much.
+const MATE_CP: i32 = 100_000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EngineCommand {
+  BuiltIn,
+  Uci(PathBuf),
+}
+
+#[derive(Clone, Debug)]
+pub struct EngineConfig {
+  pub name: String,
+  pub command: EngineCommand,
+  /// UCI options set before the first game, in order.
+  pub options: Vec<(String, String)>,
+}
+
+impl EngineConfig {
+  pub fn launch(&self) -> anyhow::Result<Analyzer> {
+    let mut analyzer = match &self.command {
+      EngineCommand::BuiltIn => Analyzer::BuiltIn(Engine::new()),
+      EngineCommand::Uci(path) => Analyzer::uci(path)?,
+    };
+    for (name, value) in &self.options {
+      analyzer.set_option(name, value)?;
+    }
+    Ok(analyzer)
+  }
+}
+
+/// Parses cutechess-style specs: `cmd=builtin,name=base,option.Hash=64`
+/// or `cmd=/usr/bin/stockfish`. The name defaults to the command.
+impl FromStr for EngineConfig {
+  type Err = anyhow::Error;
+
+  fn from_str(spec: &str) -> anyhow::Result<Self> {
+    let mut name = None;
+    let mut command = None;
+    let mut options = Vec::new();
+    for field in spec.split(',') {
+      let (key, value) = field
+        .split_once('=')
+        .ok_or_else(|| anyhow!("expected key=value, got {field:?}"))?;
+      match key {
+        "name" => name = Some(value.to_string()),
+        "cmd" if value == "builtin" => command = Some(EngineCommand::BuiltIn),
+        "cmd" => command = Some(EngineCommand::Uci(value.into())),
+        _ => match key.strip_prefix("option.") {
+          Some(option) => options.push((option.to_string(), value.to_string())),
+          None => bail!("unknown engine setting {key:?}"),
+        },
+      }
+    }
+    let command = command.ok_or_else(|| anyhow!("engine without cmd"))?;
+    let name = name.unwrap_or_else(|| match &command {
+      EngineCommand::BuiltIn => "builtin".into(),
+      EngineCommand::Uci(path) => path
+        .file_name()
+        .map_or("uci".into(), |name| name.to_string_lossy().into()),
+    });
+    Ok(EngineConfig { name, command, options })
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeControl {
+  /// Base time per game plus increment per move.
+  Clock {
+    base: Duration,
+    increment: Duration,
+  },
+  MoveTime(Duration),
+  Depth(u32),
+  Nodes(u64),
+}
+
+impl TimeControl {
+  fn limits(
+    self,
+    clocks: [Duration; 2],
+  ) -> Limits {
+    match self {
+      TimeControl::Clock { increment, .. } => Limits {
+        wtime: Some(clocks[Color::White.index()]),
+        btime: Some(clocks[Color::Black.index()]),
+        winc: Some(increment),
+        binc: Some(increment),
+        ..Default::default()
+      },
+      TimeControl::MoveTime(movetime) => Limits::movetime(movetime),
+      TimeControl::Depth(depth) => Limits::depth(depth),
+      TimeControl::Nodes(nodes) => {
+        Limits { nodes: Some(nodes), ..Default::default() }
+      }
+    }
+  }
+}
+
+fn parse_seconds(s: &str) -> anyhow::Result<Duration> {
+  Ok(Duration::try_from_secs_f64(s.parse()?)?)
+}
+
+/// `10+0.1` for a clock in seconds, `st=0.5` for a fixed time per move,
+/// `depth=8` or `nodes=20000`.
+impl FromStr for TimeControl {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> anyhow::Result<Self> {
+    if let Some(movetime) = s.strip_prefix("st=") {
+      return Ok(TimeControl::MoveTime(parse_seconds(movetime)?));
+    }
+    if let Some(depth) = s.strip_prefix("depth=") {
+      return Ok(TimeControl::Depth(depth.parse()?));
+    }
+    if let Some(nodes) = s.strip_prefix("nodes=") {
+      return Ok(TimeControl::Nodes(nodes.parse()?));
+    }
+    let (base, increment) = s.split_once('+').unwrap_or((s, "0"));
+    Ok(TimeControl::Clock {
+      base: parse_seconds(base)?,
+      increment: parse_seconds(increment)?,
+    })
+  }
+}
+
+/// As in the PGN `TimeControl` tag, `-` when there is no clock.
+impl fmt::Display for TimeControl {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    match self {
+      TimeControl::Clock { base, increment } if increment.is_zero() => {
+        write!(f, "{}", base.as_secs_f64())
+      }
+      TimeControl::Clock { base, increment } => {
+        write!(f, "{}+{}", base.as_secs_f64(), increment.as_secs_f64())
+      }
+      TimeControl::MoveTime(movetime) => {
+        write!(f, "{}/move", movetime.as_secs_f64())
+      }
+      TimeControl::Depth(_) | TimeControl::Nodes(_) => write!(f, "-"),
+    }
+  }
+}
+
+/// When to end games early on the engines' say.
+#[derive(Clone, Debug, Default)]
+pub struct Adjudication {
+  /// A side resigns once both engines saw it lost by this many
+  /// centipawns for `resign_moves` moves each.
+  pub resign_score: Option<i32>,
+  pub resign_moves: usize,
+  /// A game is drawn once both engines scored it within this many
+  /// centipawns of equality for `draw_moves` moves each, starting at
+  /// move `draw_after`.
+  pub draw_score: Option<i32>,
+  pub draw_moves: usize,
+  pub draw_after: u32,
+  /// Decides positions the tables cover.
+  pub tablebase: Option<Arc<Tablebase>>,
+}
+
+impl Adjudication {
+  /// `scores` are white's points of view after every engine move.
+  fn adjudicate(
+    &self,
+    position: &Position,
+    scores: &[i32],
+  ) -> Option<(Outcome, &'static str)> {
+    if let Some(tablebase) = &self.tablebase {
+      if let Ok(wdl) = tablebase.probe_wdl(position) {
+        let winner = match wdl {
+          Wdl::Win => position.side_to_move(),
+          Wdl::Loss => !position.side_to_move(),
+          _ => return Some((Outcome::Draw, "tablebase draw")),
+        };
+        return Some((outcome_for(winner), "tablebase win"));
+      }
+    }
+
+    if let Some(threshold) = self.resign_score {
+      let plies = self.resign_moves.max(1) * 2;
+      if scores.len() >= plies {
+        let last = &scores[scores.len() - plies..];
+        if last.iter().all(|&score| score <= -threshold) {
+          return Some((Outcome::BlackWins, "resign"));
+        }
+        if last.iter().all(|&score| score >= threshold) {
+          return Some((Outcome::WhiteWins, "resign"));
+        }
+      }
+    }
+
+    if let Some(threshold) = self.draw_score {
+      let plies = self.draw_moves.max(1) * 2;
+      if position.fullmove_number() >= self.draw_after && scores.len() >= plies
+      {
+        let last = &scores[scores.len() - plies..];
+        if last.iter().all(|&score| score.abs() <= threshold) {
+          return Some((Outcome::Draw, "draw by adjudication"));
+        }
+      }
+    }
+    None
+  }
+}
+
+fn outcome_for(winner: Color) -> Outcome {
+  winner.fold(Outcome::WhiteWins, Outcome::BlackWins)
+}
+
+/// Ends a game by the rules, `history` holding every position's hash.
+pub fn game_over(
+  position: &Position,
+  history: &[u64],
+) -> Option<(Outcome, &'static str)> {
+  if position.legal_moves().is_empty() {
+    return Some(if position.is_check() {
+      (outcome_for(!position.side_to_move()), "checkmate")
+    } else {
+      (Outcome::Draw, "stalemate")
+    });
+  }
+  if position.is_insufficient_material() {
+    return Some((Outcome::Draw, "insufficient material"));
+  }
+  if position.halfmove_clock() >= 100 {
+    return Some((Outcome::Draw, "fifty-move rule"));
+  }
+  let hash = position.hash();
+  if history.iter().filter(|&&seen| seen == hash).count() >= 3 {
+    return Some((Outcome::Draw, "threefold repetition"));
+  }
+  None
+}
+
+/// Starting point of a game: a position and moves forced on both engines.
+#[derive(Clone, Debug)]
+pub struct Opening {
+  pub start: Position,
+  pub moves: Vec<Move>,
+}
+
+/// Reads an EPD suite or the first `plies` moves of every game of a PGN
+/// file, told apart by the extension.
+pub fn load_openings(
+  path: &Path,
+  plies: usize,
+) -> anyhow::Result<Vec<Opening>> {
+  let text = fs::read_to_string(path)?;
+  let is_pgn = path.extension().is_some_and(|extension| extension == "pgn");
+  let openings: Vec<Opening> = if is_pgn {
+    pgn::parse(&text)?
+      .into_iter()
+      .map(|game| Opening {
+        start: game.start,
+        moves: game.moves.into_iter().take(plies).collect(),
+      })
+      .collect()
+  } else {
+    epd::parse(&text)
+      .into_iter()
+      .map(|epd| Opening { start: epd.position, moves: Vec::new() })
+      .collect()
+  };
+  if openings.is_empty() {
+    bail!("no openings in {}", path.display());
+  }
+  Ok(openings)
+}
+
+fn score_cp(score: Score) -> i32 {
+  match score {
+    Score::Cp(cp) => cp,
+    Score::Mate(moves) if moves > 0 => MATE_CP,
+    Score::Mate(_) => -MATE_CP,
+  }
+}
+
+/// Plays one game to the end, `engines` being white and black. The
+/// game's tags are left to the caller except for `Termination`.
+pub fn play_game(
+  mut engines: [&mut Analyzer; 2],
+  opening: &Opening,
+  time_control: TimeControl,
+  adjudication: &Adjudication,
+) -> anyhow::Result<Game> {
+  let mut game = Game {
+    start: opening.start.clone(),
+    moves: opening.moves.clone(),
+    ..Default::default()
+  };
+  let mut position = opening.start.clone();
+  let mut history = vec![position.hash()];
+  for &mv in &opening.moves {
+    if !position.is_legal(mv) {
+      bail!("illegal opening move {mv} in {}", position.to_fen());
+    }
+    position.play(mv);
+    history.push(position.hash());
+  }
+  for engine in engines.iter_mut() {
+    engine.new_game()?;
+  }
+  let mut clocks = match time_control {
+    TimeControl::Clock { base, .. } => [base; 2],
+    _ => [Duration::ZERO; 2],
+  };
+  let mut scores = Vec::new();
+
+  let (outcome, termination) = loop {
+    if let Some(end) = game_over(&position, &history) {
+      break end;
+    }
+    if let Some(end) = adjudication.adjudicate(&position, &scores) {
+      break end;
+    }
+
+    let side = position.side_to_move();
+    let engine = &mut *engines[side.index()];
+    let deadline = match time_control {
+      TimeControl::Clock { .. } => Some(clocks[side.index()] + TIME_MARGIN),
+      TimeControl::MoveTime(movetime) => Some(movetime + TIME_MARGIN),
+      TimeControl::Depth(_) | TimeControl::Nodes(_) => None,
+    };
+    let started = Instant::now();
+    let events =
+      engine.start(&game.start, &game.moves, time_control.limits(clocks))?;
+    let mut score = None;
+    let result = loop {
+      let event = match deadline {
+        Some(deadline) => {
+          let left = deadline.saturating_sub(started.elapsed());
+          events.recv_timeout(left)
+        }
+        None => events.recv().map_err(|_| RecvTimeoutError::Disconnected),
+      };
+      match event {
+        Ok(SearchEvent::Info(info)) if info.multipv <= 1 => {
+          score = Some(info.score)
+        }
+        Ok(SearchEvent::Info(_)) => {}
+        Ok(SearchEvent::Done(result)) => break Ok(result),
+        Err(err) => break Err(err),
+      }
+    };
+    let elapsed = started.elapsed();
+
+    let result = match result {
+      Ok(result) => result,
+      Err(RecvTimeoutError::Timeout) => {
+        engine.stop()?;
+        break (outcome_for(!side), "time forfeit");
+      }
+      // the engine crashed or quit without a move
+      Err(RecvTimeoutError::Disconnected) => {
+        break (outcome_for(!side), "engine disconnected")
+      }
+    };
+    if let TimeControl::Clock { increment, .. } = time_control {
+      let clock = &mut clocks[side.index()];
+      if elapsed > *clock + TIME_MARGIN {
+        break (outcome_for(!side), "time forfeit");
+      }
+      *clock = clock.saturating_sub(elapsed) + increment;
+    }
+    let mv = match result.best_move {
+      Some(mv) if position.is_legal(mv) => mv,
+      _ => break (outcome_for(!side), "illegal move"),
+    };
+    let score = score.or(result.info.map(|info| info.score));
+    if let Some(score) = score {
+      let cp = score_cp(score);
+      scores.push(side.fold(cp, -cp));
+    }
+    position.play(mv);
+    history.push(position.hash());
+    game.moves.push(mv);
+  };
+
+  game.outcome = outcome;
+  game.set_tag("Result", outcome.as_str());
+  game.set_tag("Termination", termination);
+  Ok(game)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+  /// Everyone plays everyone.
+  RoundRobin,
+  /// The first engine plays all the others.
+  Gauntlet,
+}
+
+impl FromStr for Format {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> anyhow::Result<Self> {
+    match s {
+      "round-robin" | "roundrobin" => Ok(Format::RoundRobin),
+      "gauntlet" => Ok(Format::Gauntlet),
+      _ => bail!("unknown tournament format {s:?}"),
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct Settings {
+  pub event: String,
+  pub format: Format,
+  /// Each round every pairing plays one opening twice, swapping colors.
+  pub rounds: usize,
+  pub openings: Vec<Opening>,
+  pub time_control: TimeControl,
+  pub adjudication: Adjudication,
+  /// Games played at the same time.
+  pub concurrency: usize,
+}
+
+impl Default for Settings {
+  fn default() -> Self {
+    Self {
+      event: "Engine tournament".into(),
+      format: Format::RoundRobin,
+      rounds: 1,
+      openings: vec![Opening {
+        start: Position::startpos(),
+        moves: Vec::new(),
+      }],
+      time_control: TimeControl::Clock {
+        base: Duration::from_secs(10),
+        increment: Duration::from_millis(100),
+      },
+      adjudication: Adjudication::default(),
+      concurrency: 1,
+    }
+  }
+}
+
+/// One game of the schedule. Engines are indices into the list given to
+/// [`run`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pairing {
+  pub round: usize,
+  /// Games of the same pair share the opening with colors swapped.
+  pub pair: usize,
+  pub white: usize,
+  pub black: usize,
+  pub opening: usize,
+}
+
+pub fn schedule(
+  engines: usize,
+  settings: &Settings,
+) -> Vec<Pairing> {
+  let opponents: Vec<(usize, usize)> = match settings.format {
+    Format::RoundRobin => {
+      (0..engines).flat_map(|a| (a + 1..engines).map(move |b| (a, b))).collect()
+    }
+    Format::Gauntlet => (1..engines).map(|b| (0, b)).collect(),
+  };
+  let mut pairings = Vec::new();
+  let mut pair = 0;
+  for round in 0..settings.rounds {
+    for &(a, b) in &opponents {
+      let opening = pair % settings.openings.len().max(1);
+      for (white, black) in [(a, b), (b, a)] {
+        pairings.push(Pairing { round, pair, white, black, opening });
+      }
+      pair += 1;
+    }
+  }
+  pairings
+}
+
+#[derive(Clone, Debug)]
+pub struct GameRecord {
+  pub pairing: Pairing,
+  pub game: Game,
+}
+
+/// Plays the schedule, calling `on_game` as games finish. Breaking from
+/// `on_game` ends the tournament once the games being played finish. A
+/// game that fails, like an engine that can't be started, stops it too.
+pub fn run(
+  engines: &[EngineConfig],
+  settings: &Settings,
+  mut on_game: impl FnMut(&GameRecord) -> ControlFlow<()>,
+) -> anyhow::Result<Vec<GameRecord>> {
+  if engines.len() < 2 {
+    bail!("a tournament needs at least two engines");
+  }
+  let queue = Mutex::new(VecDeque::from(schedule(engines.len(), settings)));
+  let (tx, rx) = mpsc::channel();
+  let mut records = Vec::new();
+  let mut first_error = None;
+
+  thread::scope(|scope| {
+    for _ in 0..settings.concurrency.max(1) {
+      let (queue, tx) = (&queue, tx.clone());
+      scope.spawn(move || {
+        // every worker keeps its own engines across games
+        let mut running: HashMap<usize, Analyzer> = HashMap::new();
+        loop {
+          let Some(pairing) = queue.lock().unwrap().pop_front() else {
+            break;
+          };
+          let result = play_pairing(engines, settings, pairing, &mut running);
+          let failed = result.is_err();
+          let _ = tx.send(result.map(|game| GameRecord { pairing, game }));
+          if failed {
+            queue.lock().unwrap().clear();
+            break;
+          }
+        }
+      });
+    }
+    drop(tx);
+    for result in rx {
+      match result {
+        Ok(record) => {
+          if on_game(&record).is_break() {
+            queue.lock().unwrap().clear();
+          }
+          records.push(record);
+        }
+        Err(err) => {
+          first_error.get_or_insert(err);
+        }
+      }
+    }
+  });
+
+  match first_error {
+    Some(err) => Err(err),
+    None => Ok(records),
+  }
+}
+
+fn play_pairing(
+  engines: &[EngineConfig],
+  settings: &Settings,
+  pairing: Pairing,
+  running: &mut HashMap<usize, Analyzer>,
+) -> anyhow::Result<Game> {
+  for index in [pairing.white, pairing.black] {
+    if let Entry::Vacant(entry) = running.entry(index) {
+      entry.insert(engines[index].launch()?);
+    }
+  }
+  // both engines are borrowed at once, take one out meanwhile
+  let mut white = running.remove(&pairing.white).unwrap();
+  let black = running.get_mut(&pairing.black).unwrap();
+  let opening = &settings.openings[pairing.opening];
+  let result = play_game(
+    [&mut white, black],
+    opening,
+    settings.time_control,
+    &settings.adjudication,
+  );
+  running.insert(pairing.white, white);
+
+  let mut game = result?;
+  let termination = game.tag("Termination").unwrap_or_default().to_string();
+  // the seven tag roster comes first
+  game.tags = [
+    ("Event", settings.event.clone()),
+    ("Site", "?".into()),
+    ("Round", format!("{}.{}", pairing.round + 1, pairing.pair + 1)),
+    ("White", engines[pairing.white].name.clone()),
+    ("Black", engines[pairing.black].name.clone()),
+    ("Result", game.outcome.as_str().into()),
+    ("TimeControl", settings.time_control.to_string()),
+    ("Termination", termination),
+  ]
+  .into_iter()
+  .map(|(name, value)| (name.to_string(), value))
+  .collect();
+  Ok(game)
+}
+
+/// Results of one engine, for the table printed after a tournament.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Standing {
+  pub name: String,
+  pub wins: u32,
+  pub draws: u32,
+  pub losses: u32,
+}
+
+impl Standing {
+  pub fn games(&self) -> u32 {
+    self.wins + self.draws + self.losses
+  }
+
+  pub fn points(&self) -> f64 {
+    self.wins as f64 + self.draws as f64 / 2.0
+  }
+}
+
+/// Standings sorted by points, best first.
+pub fn standings(
+  engines: &[EngineConfig],
+  records: &[GameRecord],
+) -> Vec<Standing> {
+  let mut standings: Vec<Standing> = engines
+    .iter()
+    .map(|engine| Standing { name: engine.name.clone(), ..Default::default() })
+    .collect();
+  for record in records {
+    let (white, black) = (record.pairing.white, record.pairing.black);
+    match record.game.outcome {
+      Outcome::WhiteWins => {
+        standings[white].wins += 1;
+        standings[black].losses += 1;
+      }
+      Outcome::BlackWins => {
+        standings[black].wins += 1;
+        standings[white].losses += 1;
+      }
+      Outcome::Draw => {
+        standings[white].draws += 1;
+        standings[black].draws += 1;
+      }
+      Outcome::Unknown => {}
+    }
+  }
+  standings.sort_by(|a, b| b.points().total_cmp(&a.points()));
+  standings
+}
@@ -0,0 +1,1037 @@
+//! How server messages travel: as lines over plain TCP, or over a
+//! WebSocket for browsers, each connection picking an encoding by the
+//! WebSocket protocol it asks for:
+//!
+//! - `chess.text`, the lines of `protocol` in text frames;
+//! - `chess.json`, an object per message, for reading in a browser's
+//!   developer tools: `{"type":"move","game":4,"ply":0,"move":"e2e4"}`;
+//! - `chess.binary`, a kind byte then fixed fields, big-endian, the least
+//!   bytes per move. Lengths, plies and days take four bytes, more than
+//!   any message could hold.
+//!
+//! Asking for none gets JSON. The server tells a WebSocket from lines by
+//! the `GET` opening its handshake, so one port serves both. In a browser
+//! the connection is the page's own WebSocket, see `browser`.
+
+use std::{fmt, str::FromStr, time::Duration};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+  io::BufReader,
+  net::{TcpStream, ToSocketAddrs},
+};
+
+use anyhow::{anyhow, bail, ensure};
+use serde_json::{json, Value};
+
+#[cfg(target_arch = "wasm32")]
+use crate::browser;
+use crate::{
+  board::{Color, Move, PieceKind, Position, Square},
+  pgn::Outcome,
+  protocol::{ChatLine, Control, GameInfo, Request, Room, Seek, Update},
+  tournament::TimeControl,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+  protocol::{read, write_message},
+  websocket,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+  Text,
+  Json,
+  Binary,
+}
+
+impl Encoding {
+  pub const ALL: [Encoding; 3] =
+    [Encoding::Text, Encoding::Json, Encoding::Binary];
+
+  /// The WebSocket protocol naming it.
+  pub fn protocol(self) -> &'static str {
+    match self {
+      Encoding::Text => "chess.text",
+      Encoding::Json => "chess.json",
+      Encoding::Binary => "chess.binary",
+    }
+  }
+}
+
+impl fmt::Display for Encoding {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    f.write_str(self.protocol().trim_start_matches("chess."))
+  }
+}
+
+impl FromStr for Encoding {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> anyhow::Result<Encoding> {
+    Encoding::ALL
+      .into_iter()
+      .find(|encoding| encoding.to_string() == s || encoding.protocol() == s)
+      .ok_or_else(|| anyhow!("unknown encoding {s:?}"))
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transport {
+  /// Lines of text over TCP.
+  Tcp,
+  WebSocket(Encoding),
+}
+
+/// A WebSocket message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Data {
+  Text(String),
+  Binary(Vec<u8>),
+}
+
+/// A message in every encoding.
+pub trait Wire: fmt::Display + FromStr<Err = anyhow::Error> {
+  fn to_json(&self) -> Value;
+  fn from_json(value: &Value) -> anyhow::Result<Self>;
+  fn to_binary(&self) -> Vec<u8>;
+  fn from_binary(bytes: &[u8]) -> anyhow::Result<Self>;
+}
+
+pub(crate) fn encode(
+  message: &impl Wire,
+  encoding: Encoding,
+) -> Data {
+  match encoding {
+    Encoding::Text => Data::Text(message.to_string()),
+    Encoding::Json => Data::Text(message.to_json().to_string()),
+    Encoding::Binary => Data::Binary(message.to_binary()),
+  }
+}
+
+pub(crate) fn decode<M: Wire>(
+  data: Data,
+  encoding: Encoding,
+) -> anyhow::Result<M> {
+  match (data, encoding) {
+    (Data::Text(text), Encoding::Text) => text.parse(),
+    (Data::Text(text), Encoding::Json) => {
+      M::from_json(&serde_json::from_str(&text)?)
+    }
+    (Data::Binary(bytes), Encoding::Binary) => M::from_binary(&bytes),
+    (_, encoding) => bail!("message not in {encoding}"),
+  }
+}
+
+/// The writing half of a connection.
+pub enum Writer {
+  #[cfg(not(target_arch = "wasm32"))]
+  Lines(TcpStream),
+  #[cfg(not(target_arch = "wasm32"))]
+  WebSocket(websocket::Sender, Encoding),
+  #[cfg(target_arch = "wasm32")]
+  Browser(browser::Socket),
+}
+
+impl Writer {
+  pub fn send(
+    &mut self,
+    message: &impl Wire,
+  ) -> anyhow::Result<()> {
+    match self {
+      #[cfg(not(target_arch = "wasm32"))]
+      Writer::Lines(stream) => write_message(stream, message),
+      #[cfg(not(target_arch = "wasm32"))]
+      Writer::WebSocket(sender, encoding) => {
+        sender.send(&encode(message, *encoding))
+      }
+      #[cfg(target_arch = "wasm32")]
+      Writer::Browser(socket) => socket.send(message),
+    }
+  }
+
+  /// Closes the connection, which also ends the reading half.
+  pub fn close(&self) {
+    match self {
+      #[cfg(not(target_arch = "wasm32"))]
+      Writer::Lines(stream) => {
+        let _ = stream.shutdown(std::net::Shutdown::Both);
+      }
+      #[cfg(not(target_arch = "wasm32"))]
+      Writer::WebSocket(sender, _) => sender.close(),
+      #[cfg(target_arch = "wasm32")]
+      Writer::Browser(socket) => socket.close(),
+    }
+  }
+}
+
+/// The reading half of a connection.
+#[cfg(not(target_arch = "wasm32"))]
+pub enum Reader {
+  Lines(BufReader<TcpStream>),
+  WebSocket(websocket::Receiver, Encoding),
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Reader {
+  /// Reads the next message in the connection's own form, a line or a
+  /// WebSocket message in its encoding; `None` once it closed.
+  pub fn read<M: Wire>(&mut self) -> anyhow::Result<Option<M>> {
+    match self {
+      Reader::Lines(reader) => read(reader),
+      Reader::WebSocket(receiver, encoding) => {
+        receiver.recv()?.map(|data| decode(data, *encoding)).transpose()
+      }
+    }
+  }
+}
+
+/// Takes a connection on the server side, lines or a WebSocket.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn accept(stream: TcpStream) -> anyhow::Result<(Reader, Writer)> {
+  stream.set_nodelay(true)?;
+  let mut opening = [0; 4];
+  // nothing but a WebSocket starts with GET
+  let websocket =
+    matches!(stream.peek(&mut opening), Ok(4)) && &opening == b"GET ";
+  if !websocket {
+    let writer = stream.try_clone()?;
+    return Ok((Reader::Lines(BufReader::new(stream)), Writer::Lines(writer)));
+  }
+  let protocols = Encoding::ALL.map(Encoding::protocol);
+  let (sender, receiver, protocol) = websocket::accept(stream, &protocols)?;
+  let encoding = protocol.map_or(Ok(Encoding::Json), |p| p.parse())?;
+  Ok((
+    Reader::WebSocket(receiver, encoding),
+    Writer::WebSocket(sender, encoding),
+  ))
+}
+
+/// Connects to a server at `addr`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn connect(
+  addr: impl ToSocketAddrs,
+  transport: Transport,
+  timeout: Duration,
+) -> anyhow::Result<(Reader, Writer)> {
+  let addr = addr
+    .to_socket_addrs()?
+    .next()
+    .ok_or_else(|| anyhow!("no address to connect to"))?;
+  let stream = TcpStream::connect_timeout(&addr, timeout)?;
+  stream.set_nodelay(true)?;
+  match transport {
+    Transport::Tcp => {
+      let writer = stream.try_clone()?;
+      Ok((Reader::Lines(BufReader::new(stream)), Writer::Lines(writer)))
+    }
+    Transport::WebSocket(encoding) => {
+      // the socket is shared with its clones, timeouts too
+      let handshake = stream.try_clone()?;
+      handshake.set_read_timeout(Some(timeout))?;
+      let host = addr.to_string();
+      let (sender, receiver) =
+        websocket::connect(stream, &host, "/", Some(encoding.protocol()))?;
+      handshake.set_read_timeout(None)?;
+      Ok((
+        Reader::WebSocket(receiver, encoding),
+        Writer::WebSocket(sender, encoding),
+      ))
+    }
+  }
+}
+
+fn color_json(color: Option<Color>) -> Value {
+  color.map_or(Value::Null, |color| json!(color.fold("white", "black")))
+}
+
+fn moves_json(moves: &[Move]) -> Value {
+  json!(moves.iter().map(Move::to_string).collect::<Vec<_>>())
+}
+
+fn clocks_json(clocks: &[Duration; 2]) -> Value {
+  json!([clocks[0].as_millis() as u64, clocks[1].as_millis() as u64])
+}
+
+/// Reads the fields of a JSON message.
+struct Fields<'a>(&'a serde_json::Map<String, Value>);
+
+impl<'a> Fields<'a> {
+  fn of(value: &'a Value) -> anyhow::Result<(&'a str, Fields<'a>)> {
+    let object =
+      value.as_object().ok_or_else(|| anyhow!("message not an object"))?;
+    let fields = Fields(object);
+    Ok((fields.str("type")?, fields))
+  }
+
+  fn get(
+    &self,
+    key: &str,
+  ) -> anyhow::Result<&'a Value> {
+    self.0.get(key).ok_or_else(|| anyhow!("no {key:?} in message"))
+  }
+
+  fn str(
+    &self,
+    key: &str,
+  ) -> anyhow::Result<&'a str> {
+    self.get(key)?.as_str().ok_or_else(|| anyhow!("{key:?} not a string"))
+  }
+
+  fn int<T: TryFrom<u64>>(
+    &self,
+    key: &str,
+  ) -> anyhow::Result<T> {
+    let int =
+      self.get(key)?.as_u64().ok_or_else(|| anyhow!("{key:?} not a number"))?;
+    int.try_into().map_err(|_| anyhow!("{key:?} out of range"))
+  }
+
+  fn mv(
+    &self,
+    key: &str,
+  ) -> anyhow::Result<Move> {
+    Move::from_uci(self.str(key)?)
+  }
+
+  fn moves(&self) -> anyhow::Result<Vec<Move>> {
+    self
+      .get("moves")?
+      .as_array()
+      .ok_or_else(|| anyhow!("\"moves\" not a list"))?
+      .iter()
+      .map(|mv| {
+        Move::from_uci(mv.as_str().ok_or_else(|| anyhow!("invalid move"))?)
+      })
+      .collect()
+  }
+
+  fn start(&self) -> anyhow::Result<Position> {
+    Position::from_fen(self.str("start")?)
+  }
+
+  fn control(&self) -> anyhow::Result<TimeControl> {
+    match self.str("control")?.parse()? {
+      control @ TimeControl::Clock { .. } => Ok(control),
+      _ => bail!("the control is not a clock"),
+    }
+  }
+
+  fn color(
+    &self,
+    key: &str,
+  ) -> anyhow::Result<Option<Color>> {
+    match self.0.get(key) {
+      None | Some(Value::Null) => Ok(None),
+      Some(Value::String(color)) if color == "white" => Ok(Some(Color::White)),
+      Some(Value::String(color)) if color == "black" => Ok(Some(Color::Black)),
+      Some(color) => bail!("invalid color {color}"),
+    }
+  }
+
+  fn side(&self) -> anyhow::Result<Color> {
+    self.color("color")?.ok_or_else(|| anyhow!("no \"color\" in message"))
+  }
+
+  fn game_control(&self) -> anyhow::Result<Control> {
+    self.str("control")?.parse()
+  }
+
+  fn line(&self) -> anyhow::Result<ChatLine> {
+    Ok(ChatLine {
+      ply: self.int("ply")?,
+      room: self.str("room")?.parse()?,
+      from: self.str("from")?.into(),
+      text: self.str("text")?.into(),
+    })
+  }
+
+  /// Milliseconds left for white and black.
+  fn clocks(&self) -> anyhow::Result<[Duration; 2]> {
+    let millis = |ms: &Value| ms.as_u64().map(Duration::from_millis);
+    match self.get("clocks")?.as_array().map(Vec::as_slice) {
+      Some([white, black]) => millis(white).zip(millis(black)),
+      _ => None,
+    }
+    .map(|(white, black)| [white, black])
+    .ok_or_else(|| anyhow!("invalid clocks"))
+  }
+
+  fn info(&self) -> anyhow::Result<GameInfo> {
+    Ok(GameInfo {
+      id: self.int("id")?,
+      white: self.str("white")?.into(),
+      black: self.str("black")?.into(),
+      control: self.control()?,
+    })
+  }
+}
+
+/// Writes binary messages.
+#[derive(Default)]
+struct Bytes(Vec<u8>);
+
+impl Bytes {
+  fn u8(
+    mut self,
+    value: u8,
+  ) -> Self {
+    self.0.push(value);
+    self
+  }
+
+  fn u16(
+    mut self,
+    value: u16,
+  ) -> Self {
+    self.0.extend(value.to_be_bytes());
+    self
+  }
+
+  fn u32(
+    mut self,
+    value: u32,
+  ) -> Self {
+    self.0.extend(value.to_be_bytes());
+    self
+  }
+
+  /// A length or a ply. Messages are read a megabyte at most, so none
+  /// comes near `u32::MAX`.
+  fn count(
+    self,
+    value: usize,
+  ) -> Self {
+    self.u32(value as u32)
+  }
+
+  fn str(
+    self,
+    value: &str,
+  ) -> Self {
+    let mut bytes = self.count(value.len());
+    bytes.0.extend(value.as_bytes());
+    bytes
+  }
+
+  fn millis(
+    self,
+    value: Duration,
+  ) -> Self {
+    self.u32(value.as_millis().min(u32::MAX as u128) as u32)
+  }
+
+  fn clocks(
+    self,
+    clocks: &[Duration; 2],
+  ) -> Self {
+    self.millis(clocks[0]).millis(clocks[1])
+  }
+
+  /// To, from and promotion as in Polyglot books, castling as the king
+  /// moves.
+  fn mv(
+    self,
+    mv: Move,
+  ) -> Self {
+    let promotion = mv.promotion.map_or(0, |kind| kind.index() as u16);
+    self.u16(
+      mv.to.index() as u16 | (mv.from.index() as u16) << 6 | promotion << 12,
+    )
+  }
+
+  fn moves(
+    self,
+    moves: &[Move],
+  ) -> Self {
+    let bytes = self.count(moves.len());
+    moves.iter().fold(bytes, |bytes, &mv| bytes.mv(mv))
+  }
+
+  /// 0 for any, 1 for white, 2 for black.
+  fn color(
+    self,
+    color: Option<Color>,
+  ) -> Self {
+    self.u8(color.map_or(0, |color| color.fold(1, 2)))
+  }
+
+  fn control(
+    self,
+    control: &TimeControl,
+  ) -> Self {
+    match control {
+      TimeControl::Clock { base, increment } => {
+        self.millis(*base).millis(*increment)
+      }
+      _ => self.millis(Duration::ZERO).millis(Duration::ZERO),
+    }
+  }
+
+  fn game_control(
+    self,
+    control: Control,
+  ) -> Self {
+    let index = Control::ALL.iter().position(|&other| other == control);
+    self.u8(index.unwrap() as u8)
+  }
+
+  fn line(
+    self,
+    line: &ChatLine,
+  ) -> Self {
+    let room = Room::ALL.iter().position(|&other| other == line.room);
+    let bytes = self.count(line.ply).u8(room.unwrap() as u8);
+    bytes.str(&line.from).str(&line.text)
+  }
+
+  fn info(
+    self,
+    info: &GameInfo,
+  ) -> Self {
+    self.u32(info.id).str(&info.white).str(&info.black).control(&info.control)
+  }
+}
+
+/// Reads binary messages.
+struct Cursor<'a>(&'a [u8]);
+
+impl Cursor<'_> {
+  fn take<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+    ensure!(self.0.len() >= N, "message cut short");
+    let (taken, rest) = self.0.split_at(N);
+    self.0 = rest;
+    Ok(taken.try_into().unwrap())
+  }
+
+  fn u8(&mut self) -> anyhow::Result<u8> {
+    Ok(self.take::<1>()?[0])
+  }
+
+  fn u16(&mut self) -> anyhow::Result<u16> {
+    Ok(u16::from_be_bytes(self.take()?))
+  }
+
+  fn u32(&mut self) -> anyhow::Result<u32> {
+    Ok(u32::from_be_bytes(self.take()?))
+  }
+
+  fn count(&mut self) -> anyhow::Result<usize> {
+    Ok(self.u32()? as usize)
+  }
+
+  fn str(&mut self) -> anyhow::Result<String> {
+    let len = self.count()?;
+    ensure!(self.0.len() >= len, "message cut short");
+    let (taken, rest) = self.0.split_at(len);
+    self.0 = rest;
+    Ok(String::from_utf8(taken.to_vec())?)
+  }
+
+  fn millis(&mut self) -> anyhow::Result<Duration> {
+    Ok(Duration::from_millis(self.u32()? as u64))
+  }
+
+  fn clocks(&mut self) -> anyhow::Result<[Duration; 2]> {
+    Ok([self.millis()?, self.millis()?])
+  }
+
+  fn mv(&mut self) -> anyhow::Result<Move> {
+    let raw = self.u16()?;
+    let promotion = match raw >> 12 & 7 {
+      0 => None,
+      index => Some(
+        *PieceKind::ALL
+          .get(index as usize)
+          .ok_or_else(|| anyhow!("invalid promotion {index}"))?,
+      ),
+    };
+    Ok(Move {
+      from: Square::from_index((raw >> 6 & 63) as u8),
+      to: Square::from_index((raw & 63) as u8),
+      promotion,
+    })
+  }
+
+  fn moves(&mut self) -> anyhow::Result<Vec<Move>> {
+    (0..self.count()?).map(|_| self.mv()).collect()
+  }
+
+  fn start(&mut self) -> anyhow::Result<Position> {
+    Position::from_fen(&self.str()?)
+  }
+
+  fn color(&mut self) -> anyhow::Result<Option<Color>> {
+    match self.u8()? {
+      0 => Ok(None),
+      1 => Ok(Some(Color::White)),
+      2 => Ok(Some(Color::Black)),
+      color => bail!("invalid color {color}"),
+    }
+  }
+
+  fn control(&mut self) -> anyhow::Result<TimeControl> {
+    Ok(TimeControl::Clock { base: self.millis()?, increment: self.millis()? })
+  }
+
+  fn side(&mut self) -> anyhow::Result<Color> {
+    self.color()?.ok_or_else(|| anyhow!("no side"))
+  }
+
+  fn game_control(&mut self) -> anyhow::Result<Control> {
+    let index = self.u8()?;
+    Control::ALL
+      .get(index as usize)
+      .copied()
+      .ok_or_else(|| anyhow!("invalid control {index}"))
+  }
+
+  fn line(&mut self) -> anyhow::Result<ChatLine> {
+    let ply = self.count()?;
+    let index = self.u8()?;
+    let room = *Room::ALL
+      .get(index as usize)
+      .ok_or_else(|| anyhow!("invalid room {index}"))?;
+    Ok(ChatLine { ply, room, from: self.str()?, text: self.str()? })
+  }
+
+  fn info(&mut self) -> anyhow::Result<GameInfo> {
+    Ok(GameInfo {
+      id: self.u32()?,
+      white: self.str()?,
+      black: self.str()?,
+      control: self.control()?,
+    })
+  }
+
+  fn finish<T>(
+    self,
+    message: T,
+  ) -> anyhow::Result<T> {
+    ensure!(self.0.is_empty(), "{} bytes after the message", self.0.len());
+    Ok(message)
+  }
+}
+
+impl Wire for Request {
+  fn to_json(&self) -> Value {
+    match self {
+      Request::Hello { version, name } => {
+        json!({"type": "hello", "version": version, "name": name})
+      }
+      Request::Seek { control, color } => json!({
+        "type": "seek",
+        "control": control.to_string(),
+        "color": color_json(*color),
+      }),
+      Request::Challenge { to, control, color } => json!({
+        "type": "challenge",
+        "to": to,
+        "control": control.to_string(),
+        "color": color_json(*color),
+      }),
+      Request::Cancel { seek } => json!({"type": "cancel", "seek": seek}),
+      Request::Accept { seek } => json!({"type": "accept", "seek": seek}),
+      Request::Move { game, ply, mv } => json!({
+        "type": "move",
+        "game": game,
+        "ply": ply,
+        "move": mv.to_string(),
+      }),
+      Request::Control { game, ply, control } => json!({
+        "type": "control",
+        "game": game,
+        "ply": ply,
+        "control": control.as_str(),
+      }),
+      Request::Watch { game } => json!({"type": "watch", "game": game}),
+      Request::Unwatch { game } => json!({"type": "unwatch", "game": game}),
+      Request::Chat { game, text } => {
+        json!({"type": "chat", "game": game, "text": text})
+      }
+      Request::Post { to, game, days, start, moves } => json!({
+        "type": "post",
+        "to": to,
+        "game": game,
+        "days": days,
+        "moves": moves_json(moves),
+        "start": start.to_fen(),
+      }),
+      Request::Bye => json!({"type": "bye"}),
+    }
+  }
+
+  fn from_json(value: &Value) -> anyhow::Result<Request> {
+    let (kind, fields) = Fields::of(value)?;
+    Ok(match kind {
+      "hello" => Request::Hello {
+        version: fields.int("version")?,
+        name: fields.str("name")?.into(),
+      },
+      "seek" => Request::Seek {
+        control: fields.control()?,
+        color: fields.color("color")?,
+      },
+      "challenge" => Request::Challenge {
+        to: fields.str("to")?.into(),
+        control: fields.control()?,
+        color: fields.color("color")?,
+      },
+      "cancel" => Request::Cancel { seek: fields.int("seek")? },
+      "accept" => Request::Accept { seek: fields.int("seek")? },
+      "move" => Request::Move {
+        game: fields.int("game")?,
+        ply: fields.int("ply")?,
+        mv: fields.mv("move")?,
+      },
+      "control" => Request::Control {
+        game: fields.int("game")?,
+        ply: fields.int("ply")?,
+        control: fields.game_control()?,
+      },
+      "watch" => Request::Watch { game: fields.int("game")? },
+      "unwatch" => Request::Unwatch { game: fields.int("game")? },
+      "chat" => Request::Chat {
+        game: fields.int("game")?,
+        text: fields.str("text")?.into(),
+      },
+      "post" => Request::Post {
+        to: fields.str("to")?.into(),
+        game: fields.str("game")?.into(),
+        days: fields.int("days")?,
+        start: fields.start()?,
+        moves: fields.moves()?,
+      },
+      "bye" => Request::Bye,
+      _ => bail!("unknown request {kind:?}"),
+    })
+  }
+
+  fn to_binary(&self) -> Vec<u8> {
+    let bytes = Bytes::default();
+    let bytes = match self {
+      Request::Hello { version, name } => bytes.u8(0).u32(*version).str(name),
+      Request::Seek { control, color } => {
+        bytes.u8(1).control(control).color(*color)
+      }
+      Request::Challenge { to, control, color } => {
+        bytes.u8(2).str(to).control(control).color(*color)
+      }
+      Request::Cancel { seek } => bytes.u8(3).u32(*seek),
+      Request::Accept { seek } => bytes.u8(4).u32(*seek),
+      Request::Move { game, ply, mv } => {
+        bytes.u8(5).u32(*game).count(*ply).mv(*mv)
+      }
+      Request::Watch { game } => bytes.u8(6).u32(*game),
+      Request::Unwatch { game } => bytes.u8(7).u32(*game),
+      Request::Bye => bytes.u8(8),
+      Request::Post { to, game, days, start, moves } => bytes
+        .u8(9)
+        .str(to)
+        .str(game)
+        .u32(*days)
+        .str(&start.to_fen())
+        .moves(moves),
+      Request::Control { game, ply, control } => {
+        bytes.u8(10).u32(*game).count(*ply).game_control(*control)
+      }
+      Request::Chat { game, text } => bytes.u8(11).u32(*game).str(text),
+    };
+    bytes.0
+  }
+
+  fn from_binary(bytes: &[u8]) -> anyhow::Result<Request> {
+    let mut cursor = Cursor(bytes);
+    let request = match cursor.u8()? {
+      0 => Request::Hello { version: cursor.u32()?, name: cursor.str()? },
+      1 => Request::Seek { control: cursor.control()?, color: cursor.color()? },
+      2 => Request::Challenge {
+        to: cursor.str()?,
+        control: cursor.control()?,
+        color: cursor.color()?,
+      },
+      3 => Request::Cancel { seek: cursor.u32()? },
+      4 => Request::Accept { seek: cursor.u32()? },
+      5 => Request::Move {
+        game: cursor.u32()?,
+        ply: cursor.count()?,
+        mv: cursor.mv()?,
+      },
+      6 => Request::Watch { game: cursor.u32()? },
+      7 => Request::Unwatch { game: cursor.u32()? },
+      8 => Request::Bye,
+      9 => Request::Post {
+        to: cursor.str()?,
+        game: cursor.str()?,
+        days: cursor.u32()?,
+        start: cursor.start()?,
+        moves: cursor.moves()?,
+      },
+      10 => Request::Control {
+        game: cursor.u32()?,
+        ply: cursor.count()?,
+        control: cursor.game_control()?,
+      },
+      11 => Request::Chat { game: cursor.u32()?, text: cursor.str()? },
+      kind => bail!("unknown request {kind}"),
+    };
+    cursor.finish(request)
+  }
+}
+
+impl Wire for Update {
+  fn to_json(&self) -> Value {
+    match self {
+      Update::Hello { version, name } => {
+        json!({"type": "hello", "version": version, "name": name})
+      }
+      Update::Seek(Seek { id, from, control, color, to }) => json!({
+        "type": "seek",
+        "id": id,
+        "from": from,
+        "control": control.to_string(),
+        "color": color_json(*color),
+        "to": to,
+      }),
+      Update::Unseek { seek } => json!({"type": "unseek", "seek": seek}),
+      Update::Listed(GameInfo { id, white, black, control }) => json!({
+        "type": "listed",
+        "id": id,
+        "white": white,
+        "black": black,
+        "control": control.to_string(),
+      }),
+      Update::Game { info, color, start, moves, clocks } => json!({
+        "type": "game",
+        "id": info.id,
+        "white": info.white,
+        "black": info.black,
+        "control": info.control.to_string(),
+        "color": color_json(*color),
+        "clocks": clocks_json(clocks),
+        "moves": moves_json(moves),
+        "start": start.to_fen(),
+      }),
+      Update::Move { game, ply, mv, clocks } => json!({
+        "type": "move",
+        "game": game,
+        "ply": ply,
+        "move": mv.to_string(),
+        "clocks": clocks_json(clocks),
+      }),
+      Update::Control { game, ply, color, control } => json!({
+        "type": "control",
+        "game": game,
+        "ply": ply,
+        "color": color_json(Some(*color)),
+        "control": control.as_str(),
+      }),
+      Update::Gone { game, color } => {
+        json!({"type": "gone", "game": game, "color": color_json(Some(*color))})
+      }
+      Update::Back { game, color } => {
+        json!({"type": "back", "game": game, "color": color_json(Some(*color))})
+      }
+      Update::End { game, outcome, reason } => json!({
+        "type": "end",
+        "game": game,
+        "result": outcome.as_str(),
+        "reason": reason,
+      }),
+      Update::Chat { game, line } => json!({
+        "type": "chat",
+        "game": game,
+        "ply": line.ply,
+        "room": line.room.as_str(),
+        "from": line.from,
+        "text": line.text,
+      }),
+      Update::Post { from, game, days, start, moves } => json!({
+        "type": "post",
+        "from": from,
+        "game": game,
+        "days": days,
+        "moves": moves_json(moves),
+        "start": start.to_fen(),
+      }),
+      Update::Error { message } => json!({"type": "error", "message": message}),
+    }
+  }
+
+  fn from_json(value: &Value) -> anyhow::Result<Update> {
+    let (kind, fields) = Fields::of(value)?;
+    Ok(match kind {
+      "hello" => Update::Hello {
+        version: fields.int("version")?,
+        name: fields.str("name")?.into(),
+      },
+      "seek" => Update::Seek(Seek {
+        id: fields.int("id")?,
+        from: fields.str("from")?.into(),
+        control: fields.control()?,
+        color: fields.color("color")?,
+        to: match fields.0.get("to") {
+          None | Some(Value::Null) => None,
+          Some(_) => Some(fields.str("to")?.into()),
+        },
+      }),
+      "unseek" => Update::Unseek { seek: fields.int("seek")? },
+      "listed" => Update::Listed(fields.info()?),
+      "game" => Update::Game {
+        info: fields.info()?,
+        color: fields.color("color")?,
+        start: fields.start()?,
+        moves: fields.moves()?,
+        clocks: fields.clocks()?,
+      },
+      "move" => Update::Move {
+        game: fields.int("game")?,
+        ply: fields.int("ply")?,
+        mv: fields.mv("move")?,
+        clocks: fields.clocks()?,
+      },
+      "control" => Update::Control {
+        game: fields.int("game")?,
+        ply: fields.int("ply")?,
+        color: fields.side()?,
+        control: fields.game_control()?,
+      },
+      "gone" => {
+        Update::Gone { game: fields.int("game")?, color: fields.side()? }
+      }
+      "back" => {
+        Update::Back { game: fields.int("game")?, color: fields.side()? }
+      }
+      "end" => Update::End {
+        game: fields.int("game")?,
+        outcome: Outcome::parse(fields.str("result")?)
+          .ok_or_else(|| anyhow!("invalid result"))?,
+        reason: fields.str("reason")?.into(),
+      },
+      "chat" => {
+        Update::Chat { game: fields.int("game")?, line: fields.line()? }
+      }
+      "post" => Update::Post {
+        from: fields.str("from")?.into(),
+        game: fields.str("game")?.into(),
+        days: fields.int("days")?,
+        start: fields.start()?,
+        moves: fields.moves()?,
+      },
+      "error" => Update::Error { message: fields.str("message")?.into() },
+      _ => bail!("unknown update {kind:?}"),
+    })
+  }
+
+  fn to_binary(&self) -> Vec<u8> {
+    let bytes = Bytes::default();
+    let bytes = match self {
+      Update::Hello { version, name } => bytes.u8(0).u32(*version).str(name),
+      Update::Seek(Seek { id, from, control, color, to }) => bytes
+        .u8(1)
+        .u32(*id)
+        .str(from)
+        .control(control)
+        .color(*color)
+        .str(to.as_deref().unwrap_or("")),
+      Update::Unseek { seek } => bytes.u8(2).u32(*seek),
+      Update::Listed(info) => bytes.u8(3).info(info),
+      Update::Game { info, color, start, moves, clocks } => bytes
+        .u8(4)
+        .info(info)
+        .color(*color)
+        .clocks(clocks)
+        .str(&start.to_fen())
+        .moves(moves),
+      Update::Move { game, ply, mv, clocks } => {
+        bytes.u8(5).u32(*game).count(*ply).mv(*mv).clocks(clocks)
+      }
+      Update::End { game, outcome, reason } => {
+        bytes.u8(6).u32(*game).str(outcome.as_str()).str(reason)
+      }
+      Update::Error { message } => bytes.u8(7).str(message),
+      Update::Post { from, game, days, start, moves } => bytes
+        .u8(8)
+        .str(from)
+        .str(game)
+        .u32(*days)
+        .str(&start.to_fen())
+        .moves(moves),
+      Update::Control { game, ply, color, control } => bytes
+        .u8(9)
+        .u32(*game)
+        .count(*ply)
+        .color(Some(*color))
+        .game_control(*control),
+      Update::Gone { game, color } => {
+        bytes.u8(10).u32(*game).color(Some(*color))
+      }
+      Update::Back { game, color } => {
+        bytes.u8(11).u32(*game).color(Some(*color))
+      }
+      Update::Chat { game, line } => bytes.u8(12).u32(*game).line(line),
+    };
+    bytes.0
+  }
+
+  fn from_binary(bytes: &[u8]) -> anyhow::Result<Update> {
+    let mut cursor = Cursor(bytes);
+    let update = match cursor.u8()? {
+      0 => Update::Hello { version: cursor.u32()?, name: cursor.str()? },
+      1 => Update::Seek(Seek {
+        id: cursor.u32()?,
+        from: cursor.str()?,
+        control: cursor.control()?,
+        color: cursor.color()?,
+        to: Some(cursor.str()?).filter(|to| !to.is_empty()),
+      }),
+      2 => Update::Unseek { seek: cursor.u32()? },
+      3 => Update::Listed(cursor.info()?),
+      4 => {
+        let info = cursor.info()?;
+        let color = cursor.color()?;
+        let clocks = cursor.clocks()?;
+        let start = cursor.start()?;
+        let moves = cursor.moves()?;
+        Update::Game { info, color, start, moves, clocks }
+      }
+      5 => Update::Move {
+        game: cursor.u32()?,
+        ply: cursor.count()?,
+        mv: cursor.mv()?,
+        clocks: cursor.clocks()?,
+      },
+      6 => {
+        let game = cursor.u32()?;
+        let outcome = cursor.str()?;
+        Update::End {
+          game,
+          outcome: Outcome::parse(&outcome)
+            .ok_or_else(|| anyhow!("invalid result {outcome:?}"))?,
+          reason: cursor.str()?,
+        }
+      }
+      7 => Update::Error { message: cursor.str()? },
+      8 => Update::Post {
+        from: cursor.str()?,
+        game: cursor.str()?,
+        days: cursor.u32()?,
+        start: cursor.start()?,
+        moves: cursor.moves()?,
+      },
+      9 => Update::Control {
+        game: cursor.u32()?,
+        ply: cursor.count()?,
+        color: cursor.side()?,
+        control: cursor.game_control()?,
+      },
+      10 => Update::Gone { game: cursor.u32()?, color: cursor.side()? },
+      11 => Update::Back { game: cursor.u32()?, color: cursor.side()? },
+      12 => Update::Chat { game: cursor.u32()?, line: cursor.line()? },
+      kind => bail!("unknown update {kind}"),
+    };
+    cursor.finish(update)
+  }
+}
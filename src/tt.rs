@@ -0,0 +1,154 @@
+//! Lock-free transposition table shared by all search threads.
+//! Every slot stores `key ^ data` next to `data` (Hyatt's lockless
+//! hashing), so a slot torn by two concurrent writers fails the key
+//! check instead of handing out a mix of two entries.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::board::{Move, PieceKind, Square};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+  Exact,
+  /// The score is at least this, the search failed high.
+  Lower,
+  /// The score is at most this, the search failed low.
+  Upper,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Entry {
+  pub mv: Option<Move>,
+  pub score: i32,
+  pub depth: u8,
+  pub bound: Bound,
+}
+
+#[derive(Default)]
+struct Slot {
+  check: AtomicU64,
+  data: AtomicU64,
+}
+
+pub struct TranspositionTable {
+  slots: Vec<Slot>,
+}
+
+impl TranspositionTable {
+  pub fn new(megabytes: usize) -> Self {
+    let len = (megabytes.max(1) << 20) / std::mem::size_of::<Slot>();
+    let slots = (0..len).map(|_| Slot::default()).collect();
+    Self { slots }
+  }
+
+  pub fn clear(&self) {
+    for slot in &self.slots {
+      slot.check.store(0, Ordering::Relaxed);
+      slot.data.store(0, Ordering::Relaxed);
+    }
+  }
+
+  fn slot(
+    &self,
+    hash: u64,
+  ) -> &Slot {
+    let index = (hash as u128 * self.slots.len() as u128) >> 64;
+    &self.slots[index as usize]
+  }
+
+  pub fn probe(
+    &self,
+    hash: u64,
+  ) -> Option<Entry> {
+    let slot = self.slot(hash);
+    let data = slot.data.load(Ordering::Relaxed);
+    let check = slot.check.load(Ordering::Relaxed);
+    if data == 0 || check ^ data != hash {
+      return None;
+    }
+    Some(unpack(data))
+  }
+
+  /// Keeps a deeper entry of the same position, replaces anything else.
+  pub fn store(
+    &self,
+    hash: u64,
+    entry: Entry,
+  ) {
+    let slot = self.slot(hash);
+    let old = slot.data.load(Ordering::Relaxed);
+    let same = slot.check.load(Ordering::Relaxed) ^ old == hash;
+    if same && old != 0 && entry.bound != Bound::Exact {
+      let old = unpack(old);
+      if old.depth > entry.depth {
+        return;
+      }
+    }
+    let data = pack(entry);
+    slot.check.store(hash ^ data, Ordering::Relaxed);
+    slot.data.store(data, Ordering::Relaxed);
+  }
+
+  /// Permille of used slots, estimated from the first thousand.
+  pub fn hashfull(&self) -> u32 {
+    let sample = self.slots.len().min(1000);
+    let used = self.slots[..sample]
+      .iter()
+      .filter(|slot| slot.data.load(Ordering::Relaxed) != 0)
+      .count();
+    (used * 1000 / sample.max(1)) as u32
+  }
+}
+
+// data layout: move 0..16, score 16..32, depth 32..40, bound 40..42,
+// bit 42 is always set so that no entry packs to zero
+fn pack(entry: Entry) -> u64 {
+  let bound = match entry.bound {
+    Bound::Exact => 0,
+    Bound::Lower => 1,
+    Bound::Upper => 2,
+  };
+  encode_move(entry.mv) as u64
+    | (entry.score as i16 as u16 as u64) << 16
+    | (entry.depth as u64) << 32
+    | bound << 40
+    | 1 << 42
+}
+
+fn unpack(data: u64) -> Entry {
+  let bound = match (data >> 40) & 3 {
+    0 => Bound::Exact,
+    1 => Bound::Lower,
+    _ => Bound::Upper,
+  };
+  Entry {
+    mv: decode_move(data as u16),
+    score: (data >> 16) as u16 as i16 as i32,
+    depth: (data >> 32) as u8,
+    bound,
+  }
+}
+
+fn encode_move(mv: Option<Move>) -> u16 {
+  let Some(mv) = mv else {
+    return 0;
+  };
+  let promotion = match mv.promotion {
+    None => 0,
+    Some(kind) => kind.index() as u16,
+  };
+  mv.from.index() as u16 | (mv.to.index() as u16) << 6 | promotion << 12
+}
+
+fn decode_move(bits: u16) -> Option<Move> {
+  if bits == 0 {
+    return None;
+  }
+  let from = Square::from_index((bits & 63) as u8);
+  let to = Square::from_index(((bits >> 6) & 63) as u8);
+  let promotion = match bits >> 12 {
+    0 => None,
+    kind => PieceKind::ALL.get(kind as usize).copied(),
+  };
+  Some(Move { from, to, promotion })
+}
@@ -0,0 +1,531 @@
+//! Universal Chess Interface, both ways: a front-end so that any UCI GUI
+//! can drive the built-in engine (see `bin/uci.rs`), and a client that
+//! drives external engines running as subprocesses.
+//! http://wbec-ridderkerk.nl/html/UCIProtocol.html
+
+use std::{
+  collections::VecDeque,
+  io::{BufRead, BufReader, Write},
+  path::Path,
+  process::{Child, ChildStdin, Command, Stdio},
+  sync::{mpsc, Arc, Mutex},
+  thread,
+  time::Duration,
+};
+
+use anyhow::{anyhow, bail};
+
+use crate::{
+  board::{Move, Position},
+  book::{Book, Selection},
+  search::{
+    Engine, Limits, Score, SearchEvent, SearchInfo, SearchResult,
+    DEFAULT_HASH_MB, MAX_MULTI_PV, MAX_THREADS,
+  },
+  syzygy::Tablebase,
+};
+
+type Output = Arc<Mutex<dyn Write + Send>>;
+
+fn send(
+  output: &Output,
+  line: &str,
+) {
+  let mut output = output.lock().unwrap();
+  if let Err(err) = writeln!(output, "{line}").and_then(|_| output.flush()) {
+    log::error!("failed to write uci output: {err}");
+  }
+}
+
+pub fn info_line(info: &SearchInfo) -> String {
+  let pv: Vec<String> = info.pv.iter().map(Move::to_string).collect();
+  format!(
+    "info multipv {} depth {} seldepth {} score {} nodes {} nps {} hashfull {} time {} pv {}",
+    info.multipv,
+    info.depth,
+    info.seldepth,
+    info.score,
+    info.nodes,
+    info.nps(),
+    info.hashfull,
+    info.time.as_millis(),
+    pv.join(" "),
+  )
+}
+
+/// Parses an `info` line that reports a line of play, ignores the rest.
+pub fn parse_info(line: &str) -> Option<SearchInfo> {
+  let mut info = SearchInfo {
+    multipv: 1,
+    depth: 0,
+    seldepth: 0,
+    score: Score::Cp(0),
+    nodes: 0,
+    time: Duration::ZERO,
+    hashfull: 0,
+    pv: Vec::new(),
+  };
+  let mut tokens = line.strip_prefix("info ")?.split_whitespace();
+  let mut has_score = false;
+  while let Some(token) = tokens.next() {
+    let mut value = || tokens.next().unwrap_or_default();
+    match token {
+      "multipv" => info.multipv = value().parse().ok()?,
+      "depth" => info.depth = value().parse().ok()?,
+      "seldepth" => info.seldepth = value().parse().ok()?,
+      "nodes" => info.nodes = value().parse().ok()?,
+      "hashfull" => info.hashfull = value().parse().ok()?,
+      "time" => info.time = Duration::from_millis(value().parse().ok()?),
+      "score" => {
+        let kind = value();
+        let amount = value().parse().ok()?;
+        info.score = match kind {
+          "cp" => Score::Cp(amount),
+          "mate" => Score::Mate(amount),
+          _ => return None,
+        };
+        has_score = true;
+      }
+      "pv" => {
+        info.pv =
+          tokens.by_ref().map_while(|mv| Move::from_uci(mv).ok()).collect();
+      }
+      // free text until the end of the line
+      "string" => return None,
+      _ => {}
+    }
+  }
+  (has_score && !info.pv.is_empty()).then_some(info)
+}
+
+/// Parses the arguments of a `position` command into the position and
+/// the hashes of the positions before it.
+pub fn parse_position(args: &str) -> anyhow::Result<(Position, Vec<u64>)> {
+  let (setup, moves) = match args.split_once("moves") {
+    Some((setup, moves)) => (setup.trim(), moves),
+    None => (args.trim(), ""),
+  };
+  let mut position = if setup == "startpos" {
+    Position::startpos()
+  } else if let Some(fen) = setup.strip_prefix("fen") {
+    Position::from_fen(fen.trim())?
+  } else {
+    bail!("expected startpos or fen: {args:?}");
+  };
+  let mut history = Vec::new();
+  for uci in moves.split_whitespace() {
+    let mv = Move::from_uci(uci)?;
+    if !position.is_legal(mv) {
+      bail!("illegal move {uci} in {}", position.to_fen());
+    }
+    history.push(position.hash());
+    position.play(mv);
+  }
+  Ok((position, history))
+}
+
+pub fn parse_go(args: &str) -> anyhow::Result<Limits> {
+  let mut limits = Limits::default();
+  let mut tokens = args.split_whitespace();
+  while let Some(token) = tokens.next() {
+    if token == "infinite" {
+      limits.infinite = true;
+      continue;
+    }
+    let value =
+      tokens.next().ok_or_else(|| anyhow!("missing value for go {token}"))?;
+    let millis = || value.parse().map(Duration::from_millis);
+    match token {
+      "depth" => limits.depth = Some(value.parse()?),
+      "nodes" => limits.nodes = Some(value.parse()?),
+      "movetime" => limits.movetime = Some(millis()?),
+      "wtime" => limits.wtime = Some(millis()?),
+      "btime" => limits.btime = Some(millis()?),
+      "winc" => limits.winc = Some(millis()?),
+      "binc" => limits.binc = Some(millis()?),
+      "movestogo" => limits.movestogo = Some(value.parse()?),
+      _ => log::warn!("ignoring unsupported go argument {token}"),
+    }
+  }
+  Ok(limits)
+}
+
+struct Session {
+  engine: Engine,
+  position: Position,
+  history: Vec<u64>,
+  output: Output,
+  own_book: bool,
+  book_file: String,
+  best_book_move: bool,
+  /// Forwards events of the running search to the output.
+  reporter: Option<thread::JoinHandle<()>>,
+}
+
+impl Session {
+  /// Stops the running search and waits until its `bestmove` is out.
+  fn finish_search(&mut self) {
+    self.engine.stop();
+    if let Some(reporter) = self.reporter.take() {
+      let _ = reporter.join();
+    }
+  }
+
+  fn set_option(
+    &mut self,
+    args: &str,
+  ) -> anyhow::Result<()> {
+    let args = args.trim().strip_prefix("name").unwrap_or(args).trim();
+    let (name, value) = match args.split_once(" value ") {
+      Some((name, value)) => (name.trim(), value.trim()),
+      None => (args, ""),
+    };
+    match name.to_ascii_lowercase().as_str() {
+      "threads" => self.engine.set_threads(value.parse()?),
+      "multipv" => self.engine.set_multi_pv(value.parse()?),
+      "syzygypath" => self.engine.set_tablebase(tablebase(value)?),
+      "hash" => self.engine.set_hash_size(value.parse()?),
+      "clear hash" => self.engine.new_game(),
+      "ownbook" => {
+        self.own_book = value.parse()?;
+        self.load_book()?
+      }
+      "book file" => {
+        self.book_file = value.replace("<empty>", "");
+        self.load_book()?
+      }
+      "best book move" => {
+        self.best_book_move = value.parse()?;
+        self.load_book()?
+      }
+      _ => bail!("unknown option {name:?}"),
+    }
+    Ok(())
+  }
+
+  fn load_book(&mut self) -> anyhow::Result<()> {
+    if !self.own_book || self.book_file.is_empty() {
+      self.engine.set_book(None);
+      return Ok(());
+    }
+    let book = Book::open(Path::new(&self.book_file))?;
+    let selection =
+      if self.best_book_move { Selection::Best } else { Selection::Weighted };
+    self.engine.set_book(Some((book, selection)));
+    Ok(())
+  }
+
+  fn go(
+    &mut self,
+    args: &str,
+  ) -> anyhow::Result<()> {
+    let limits = parse_go(args)?;
+    self.finish_search();
+    let events =
+      self.engine.start(self.position.clone(), self.history.clone(), limits);
+    let output = self.output.clone();
+    self.reporter = Some(thread::spawn(move || report(events, output)));
+    Ok(())
+  }
+}
+
+/// Opens the tables of a `SyzygyPath` value, none for `<empty>`.
+pub fn tablebase(paths: &str) -> anyhow::Result<Option<Arc<Tablebase>>> {
+  let paths = paths.replace("<empty>", "");
+  if paths.trim().is_empty() {
+    return Ok(None);
+  }
+  Ok(Some(Arc::new(Tablebase::open(paths.trim())?)))
+}
+
+fn report(
+  events: mpsc::Receiver<SearchEvent>,
+  output: Output,
+) {
+  for event in events {
+    match event {
+      SearchEvent::Info(info) => send(&output, &info_line(&info)),
+      SearchEvent::Done(result) => {
+        let best = result.best_move.map_or("0000".into(), |mv| mv.to_string());
+        match result.ponder {
+          Some(ponder) => {
+            send(&output, &format!("bestmove {best} ponder {ponder}"))
+          }
+          None => send(&output, &format!("bestmove {best}")),
+        }
+      }
+    }
+  }
+}
+
+/// Serves UCI commands from `input` until `quit` or end of input.
+pub fn run(
+  input: impl BufRead,
+  output: impl Write + Send + 'static,
+) -> anyhow::Result<()> {
+  let mut session = Session {
+    engine: Engine::new(),
+    position: Position::startpos(),
+    history: Vec::new(),
+    output: Arc::new(Mutex::new(output)),
+    own_book: false,
+    book_file: String::new(),
+    best_book_move: false,
+    reporter: None,
+  };
+
+  for line in input.lines() {
+    let line = line?;
+    let line = line.trim();
+    let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+    let result = match command {
+      "uci" => {
+        let output = &session.output;
+        send(output, concat!("id name chess ", env!("CARGO_PKG_VERSION")));
+        send(output, "id author hqhs");
+        send(
+          output,
+          &format!(
+            "option name Threads type spin default 1 min 1 max {MAX_THREADS}"
+          ),
+        );
+        send(
+          output,
+          &format!(
+            "option name Hash type spin default {DEFAULT_HASH_MB} min 1 max 65536"
+          ),
+        );
+        send(
+          output,
+          &format!(
+            "option name MultiPV type spin default 1 min 1 max {MAX_MULTI_PV}"
+          ),
+        );
+        send(output, "option name Clear Hash type button");
+        send(output, "option name OwnBook type check default false");
+        send(output, "option name Book File type string default <empty>");
+        send(output, "option name Best Book Move type check default false");
+        send(output, "option name SyzygyPath type string default <empty>");
+        send(output, "uciok");
+        Ok(())
+      }
+      "isready" => {
+        send(&session.output, "readyok");
+        Ok(())
+      }
+      "setoption" => {
+        session.finish_search();
+        session.set_option(args)
+      }
+      "ucinewgame" => {
+        session.finish_search();
+        session.engine.new_game();
+        Ok(())
+      }
+      "position" => {
+        session.finish_search();
+        parse_position(args).map(|(position, history)| {
+          session.position = position;
+          session.history = history;
+        })
+      }
+      "go" => session.go(args),
+      "stop" => {
+        session.finish_search();
+        Ok(())
+      }
+      "quit" => break,
+      "" => Ok(()),
+      _ => Err(anyhow!("unknown command {command:?}")),
+    };
+    if let Err(err) = result {
+      log::warn!("{line:?}: {err}");
+    }
+  }
+
+  session.finish_search();
+  Ok(())
+}
+
+/// Searches requested from an external engine whose `bestmove` is still
+/// outstanding, oldest first. Each one collects its own events.
+type Pending = Arc<Mutex<VecDeque<PendingSearch>>>;
+
+struct PendingSearch {
+  events: mpsc::Sender<SearchEvent>,
+  last_info: Option<SearchInfo>,
+}
+
+/// External UCI engine running as a subprocess. Output is read on a
+/// separate thread, so none of the methods wait for the engine.
+pub struct Client {
+  name: Arc<Mutex<Option<String>>>,
+  child: Child,
+  stdin: ChildStdin,
+  pending: Pending,
+}
+
+impl Client {
+  pub fn spawn(path: &Path) -> anyhow::Result<Client> {
+    let mut child = Command::new(path)
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::null())
+      .spawn()
+      .map_err(|err| anyhow!("failed to start {}: {err}", path.display()))?;
+    let stdin = child.stdin.take().unwrap();
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+
+    let name = Arc::new(Mutex::new(None));
+    let pending = Pending::default();
+    let (reader_name, reader_pending) = (name.clone(), pending.clone());
+    thread::Builder::new()
+      .name("uci client".into())
+      .spawn(move || read_engine_output(stdout, reader_name, reader_pending))?;
+
+    let mut client = Client { name, child, stdin, pending };
+    // engines handle commands in order, there is no need to wait for uciok
+    client.send("uci")?;
+    Ok(client)
+  }
+
+  /// The engine's `id name`, once it has introduced itself.
+  pub fn name(&self) -> Option<String> {
+    self.name.lock().unwrap().clone()
+  }
+
+  fn send(
+    &mut self,
+    command: &str,
+  ) -> anyhow::Result<()> {
+    writeln!(self.stdin, "{command}")?;
+    self.stdin.flush()?;
+    Ok(())
+  }
+
+  pub fn set_option(
+    &mut self,
+    name: &str,
+    value: &str,
+  ) -> anyhow::Result<()> {
+    self.send(&format!("setoption name {name} value {value}"))
+  }
+
+  pub fn new_game(&mut self) -> anyhow::Result<()> {
+    self.send("ucinewgame")
+  }
+
+  pub fn is_searching(&self) -> bool {
+    !self.pending.lock().unwrap().is_empty()
+  }
+
+  pub fn stop(&mut self) -> anyhow::Result<()> {
+    if self.is_searching() {
+      self.send("stop")?;
+    }
+    Ok(())
+  }
+
+  /// Starts searching the position reached by `moves` from `start`,
+  /// stopping the previous search, whose `Done` still arrives first.
+  pub fn start(
+    &mut self,
+    start: &Position,
+    moves: &[Move],
+    limits: &Limits,
+  ) -> anyhow::Result<mpsc::Receiver<SearchEvent>> {
+    self.stop()?;
+    let (events, rx) = mpsc::channel();
+    self
+      .pending
+      .lock()
+      .unwrap()
+      .push_back(PendingSearch { events, last_info: None });
+
+    let mut position = format!("position fen {}", start.to_fen());
+    if !moves.is_empty() {
+      let moves: Vec<String> = moves.iter().map(Move::to_string).collect();
+      position.push_str(" moves ");
+      position.push_str(&moves.join(" "));
+    }
+    self.send(&position)?;
+    self.send(&go_command(limits))?;
+    Ok(rx)
+  }
+}
+
+impl Drop for Client {
+  fn drop(&mut self) {
+    if self.send("quit").is_err() {
+      let _ = self.child.kill();
+    }
+  }
+}
+
+pub fn go_command(limits: &Limits) -> String {
+  let mut go = String::from("go");
+  let millis = |duration: Duration| duration.as_millis();
+  if let Some(depth) = limits.depth {
+    go.push_str(&format!(" depth {depth}"));
+  }
+  if let Some(nodes) = limits.nodes {
+    go.push_str(&format!(" nodes {nodes}"));
+  }
+  if let Some(movetime) = limits.movetime {
+    go.push_str(&format!(" movetime {}", millis(movetime)));
+  }
+  for (name, value) in [
+    ("wtime", limits.wtime),
+    ("btime", limits.btime),
+    ("winc", limits.winc),
+    ("binc", limits.binc),
+  ] {
+    if let Some(value) = value {
+      go.push_str(&format!(" {name} {}", millis(value)));
+    }
+  }
+  if let Some(moves) = limits.movestogo {
+    go.push_str(&format!(" movestogo {moves}"));
+  }
+  if limits.infinite {
+    go.push_str(" infinite");
+  }
+  go
+}
+
+fn read_engine_output(
+  stdout: impl BufRead,
+  name: Arc<Mutex<Option<String>>>,
+  pending: Pending,
+) {
+  for line in stdout.lines() {
+    let Ok(line) = line else {
+      break;
+    };
+    let line = line.trim();
+    if let Some(id) = line.strip_prefix("id name ") {
+      *name.lock().unwrap() = Some(id.to_string());
+    } else if let Some(info) = parse_info(line) {
+      let mut pending = pending.lock().unwrap();
+      if let Some(search) = pending.front_mut() {
+        let _ = search.events.send(SearchEvent::Info(info.clone()));
+        if info.multipv == 1 {
+          search.last_info = Some(info);
+        }
+      }
+    } else if let Some(args) = line.strip_prefix("bestmove") {
+      let mut args = args.split_whitespace();
+      let best_move = args.next().and_then(|mv| Move::from_uci(mv).ok());
+      let ponder = match (args.next(), args.next()) {
+        (Some("ponder"), Some(mv)) => Move::from_uci(mv).ok(),
+        _ => None,
+      };
+      if let Some(search) = pending.lock().unwrap().pop_front() {
+        let result = SearchResult { best_move, ponder, info: search.last_info };
+        let _ = search.events.send(SearchEvent::Done(result));
+      }
+    }
+  }
+  // the engine is gone, let everyone waiting know
+  pending.lock().unwrap().clear();
+}
@@ -6,6 +6,36 @@ use egui_wgpu::{Renderer, ScreenDescriptor};
 use egui_winit::{EventResponse, State};
 use winit::{event::WindowEvent, window::Window};
 
+mod analysis;
+mod broadcast;
+mod chat;
+mod correspondence;
+mod database;
+mod explorer;
+mod game;
+mod net;
+mod puzzle;
+mod ratings;
+mod repertoire;
+mod review;
+mod server;
+mod tree;
+
+pub use analysis::{analysis_panel, eval_bar};
+pub use broadcast::{broadcast_panel, BroadcastPanel};
+pub use chat::{chat_panel, ChatPanel};
+pub use correspondence::{correspondence_panel, CorrespondencePanel};
+pub use database::{database_panel, DatabasePanel};
+pub use explorer::{explorer_panel, ExplorerPanel};
+pub use game::{game_panel, GamePanel};
+pub use net::{net_panel, NetPanel};
+pub use puzzle::{puzzle_panel, PuzzlePanel};
+pub use ratings::{ratings_panel, RatingsPanel};
+pub use repertoire::{repertoire_panel, RepertoirePanel};
+pub use review::review_panel;
+pub use server::{server_panel, ServerPanel};
+pub use tree::game_tree;
+
 pub struct EguiRenderer {
   pub context: Context,
   state: State,
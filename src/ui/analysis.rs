@@ -0,0 +1,141 @@
+//! The analysis window and the evaluation bar beside the board.
+
+use std::path::Path;
+
+use egui::Context;
+
+use crate::{
+  analysis::{self, Analysis, Analyzer},
+  gametree::GameTree,
+  san,
+  search::{self, Engine},
+};
+
+/// Engine choice and settings, then the best lines found so far.
+pub fn analysis_panel(
+  ui: &mut egui::Ui,
+  analysis: &mut Analysis,
+  tree: &GameTree,
+  engine_path: &mut String,
+  tablebase_path: &mut String,
+) {
+  let (start, played) = (tree.start(), &tree.played()[..]);
+  let mut result = Ok(());
+
+  let mut enabled = analysis.is_enabled();
+  if ui.checkbox(&mut enabled, "Analyse").changed() {
+    result = analysis.set_enabled(enabled, start, played);
+  }
+
+  ui.horizontal(|ui| {
+    ui.label(format!("Engine: {}", analysis.analyzer.name()));
+    if !matches!(analysis.analyzer, Analyzer::BuiltIn(_))
+      && ui.button("Use built-in").clicked()
+    {
+      let analyzer = Analyzer::BuiltIn(Engine::new());
+      result = analysis.set_analyzer(analyzer, start, played);
+    }
+  });
+  ui.horizontal(|ui| {
+    ui.text_edit_singleline(engine_path);
+    if ui.button("Use UCI engine").clicked() {
+      result = Analyzer::uci(Path::new(engine_path.trim()))
+        .and_then(|analyzer| analysis.set_analyzer(analyzer, start, played));
+    }
+  });
+
+  ui.horizontal(|ui| {
+    ui.text_edit_singleline(tablebase_path);
+    if ui.button("Use tablebases").clicked() {
+      result = analysis.set_tablebase(tablebase_path, start, played);
+    }
+  });
+
+  ui.horizontal(|ui| {
+    ui.label("Lines: ");
+    let mut lines = analysis.multi_pv();
+    let drag =
+      egui::DragValue::new(&mut lines).clamp_range(1..=search::MAX_MULTI_PV);
+    if ui.add(drag).changed() {
+      result = analysis.set_multi_pv(lines, start, played);
+    }
+    ui.label("Threads: ");
+    let mut threads = analysis.threads();
+    let drag =
+      egui::DragValue::new(&mut threads).clamp_range(1..=search::MAX_THREADS);
+    if ui.add(drag).changed() {
+      result = analysis.set_threads(threads, start, played);
+    }
+  });
+  if let Err(err) = result {
+    log::error!("analysis failed: {err:#}");
+  }
+
+  ui.separator();
+  if let Some(best) = analysis.lines().first() {
+    ui.label(format!(
+      "depth {}/{}  nodes {}  nps {}",
+      best.depth,
+      best.seldepth,
+      best.nodes,
+      best.nps()
+    ));
+  }
+  let position = analysis.position().clone();
+  if let Some(tablebase) = analysis.tablebase() {
+    let pieces = position.occupied().count_ones() as usize;
+    if pieces <= tablebase.max_pieces() {
+      match tablebase.probe_dtz(&position) {
+        Ok(dtz) => ui.strong(format!("Tablebase: {dtz}")),
+        Err(err) => ui.weak(format!("Tablebase: {err}")),
+      };
+    }
+  }
+  for line in analysis.lines() {
+    let score = analysis::white_pov(line.score, position.side_to_move());
+    ui.horizontal_wrapped(|ui| {
+      ui.strong(analysis::format_score(score));
+      ui.label(san::line(&position, &line.pv));
+    });
+  }
+}
+
+/// Vertical bar along the left edge, white's share filling it from the
+/// bottom as on a board seen from white's side.
+pub fn eval_bar(
+  cx: &Context,
+  analysis: &Analysis,
+) {
+  let Some(score) = analysis.white_score() else {
+    return;
+  };
+  egui::SidePanel::left("evaluation bar")
+    .exact_width(28.0)
+    .resizable(false)
+    .show(cx, |ui| {
+      let rect = ui.available_rect_before_wrap();
+      let painter = ui.painter();
+      painter.rect_filled(rect, 0.0, egui::Color32::from_gray(40));
+      let share = analysis::white_share(score);
+      let mut white = rect;
+      white.set_top(rect.bottom() - rect.height() * share);
+      painter.rect_filled(white, 0.0, egui::Color32::from_gray(230));
+
+      let (anchor, pos, color) = if share >= 0.5 {
+        (
+          egui::Align2::CENTER_BOTTOM,
+          rect.center_bottom(),
+          egui::Color32::BLACK,
+        )
+      } else {
+        (egui::Align2::CENTER_TOP, rect.center_top(), egui::Color32::WHITE)
+      };
+      painter.text(
+        pos,
+        anchor,
+        analysis::format_score(score),
+        egui::FontId::proportional(10.0),
+        color,
+      );
+    });
+}
@@ -0,0 +1,138 @@
+//! The broadcast window: sending the game shown or watching one.
+
+use crate::{
+  board::Color,
+  broadcast::{Broadcast, Viewer},
+  gametree::{self, GameTree},
+  pgn::{self, Outcome},
+};
+
+use super::game::side;
+
+/// State of the broadcast window.
+pub struct BroadcastPanel {
+  broadcast: Option<Broadcast>,
+  viewer: Option<Viewer>,
+  address: String,
+  name: String,
+  /// Whether the board follows the broadcast game.
+  follow: bool,
+  status: String,
+}
+
+impl Default for BroadcastPanel {
+  fn default() -> Self {
+    Self {
+      broadcast: None,
+      viewer: None,
+      address: "127.0.0.1:7880".into(),
+      name: "Club".into(),
+      follow: true,
+      status: String::new(),
+    }
+  }
+}
+
+/// The last clock shown for `color` in the main line.
+fn last_clock(
+  tree: &GameTree,
+  color: Color,
+) -> Option<std::time::Duration> {
+  tree.mainline().into_iter().rev().find_map(|id| {
+    let node = tree.node(id);
+    let mover = !node.position.side_to_move();
+    node.annotations.clock.filter(|_| mover == color)
+  })
+}
+
+/// Broadcasts the game on the board to viewers, or follows a broadcast.
+/// A viewer can stop following to analyse on the board, and pick the game
+/// up again where it stands.
+pub fn broadcast_panel(
+  ui: &mut egui::Ui,
+  panel: &mut BroadcastPanel,
+  record: &mut pgn::Game,
+  tree: &mut GameTree,
+) {
+  if let Some(broadcast) = &mut panel.broadcast {
+    broadcast.poll();
+    broadcast.publish(tree, record);
+    ui.ctx().request_repaint_after(std::time::Duration::from_millis(100));
+    ui.label(format!(
+      "Broadcasting {} on {}",
+      broadcast.name,
+      panel.address.trim()
+    ));
+    ui.label(format!("{} watching", broadcast.viewers()));
+    if ui.button("Stop").clicked() {
+      panel.broadcast = None;
+      panel.status = "Stopped broadcasting.".into();
+    }
+    ui.label(&panel.status);
+    return;
+  }
+
+  let Some(viewer) = &mut panel.viewer else {
+    ui.horizontal(|ui| {
+      ui.label("Name: ");
+      ui.text_edit_singleline(&mut panel.name);
+    });
+    ui.horizontal(|ui| {
+      ui.label("Address: ");
+      ui.text_edit_singleline(&mut panel.address);
+    });
+    ui.horizontal(|ui| {
+      if ui.button("Broadcast").clicked() {
+        let address = panel.address.trim();
+        match Broadcast::host(address, panel.name.trim()) {
+          Ok(broadcast) => {
+            panel.status = "Broadcasting the board's game.".into();
+            panel.broadcast = Some(broadcast);
+          }
+          Err(err) => panel.status = format!("Can't broadcast: {err:#}"),
+        }
+      }
+      if ui.button("Watch").clicked() {
+        match Viewer::connect(panel.address.trim()) {
+          Ok(viewer) => {
+            panel.status = "Watching.".into();
+            panel.follow = true;
+            panel.viewer = Some(viewer);
+          }
+          Err(err) => panel.status = format!("Can't watch: {err:#}"),
+        }
+      }
+    });
+    ui.label(&panel.status);
+    return;
+  };
+
+  let changed = viewer.poll();
+  if !viewer.is_connected() {
+    panel.status = "The broadcast is over.".into();
+  }
+  ui.ctx().request_repaint_after(std::time::Duration::from_millis(100));
+  ui.label(&viewer.name);
+  for color in [Color::White, Color::Black] {
+    let name = viewer.tag(side(color)).unwrap_or(side(color));
+    match last_clock(viewer.tree(), color) {
+      Some(clock) => {
+        ui.label(format!("{name} {}", gametree::format_clock(clock)))
+      }
+      None => ui.label(name),
+    };
+  }
+  if viewer.outcome() != Outcome::Unknown {
+    ui.label(viewer.outcome().to_string());
+  }
+  let follow = ui.checkbox(&mut panel.follow, "Follow the game");
+  if panel.follow && (changed || follow.changed()) {
+    *tree = viewer.tree().clone();
+    *record = viewer.record();
+  }
+  ui.label(&panel.status);
+  if ui.button("Leave").clicked() {
+    panel.viewer = None;
+    panel.status = "Stopped watching.".into();
+  }
+}
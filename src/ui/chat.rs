@@ -0,0 +1,77 @@
+//! The chat window of the network or server game shown.
+
+use crate::{
+  chat, pgn,
+  protocol::{ChatLine, Room},
+};
+
+use super::{NetPanel, ServerPanel};
+
+/// State of the chat window.
+#[derive(Default)]
+pub struct ChatPanel {
+  input: String,
+  status: String,
+}
+
+/// The chat of the game shown in the server window, or else of the
+/// network game, kept in the tags of the game to be saved with it.
+/// Without either, the chat saved with the game loaded.
+pub fn chat_panel(
+  ui: &mut egui::Ui,
+  panel: &mut ChatPanel,
+  net: &mut NetPanel,
+  server: &mut ServerPanel,
+  record: &mut pgn::Game,
+) {
+  let remote = (server.client.as_ref().zip(server.shown))
+    .and_then(|(client, id)| client.games.get(&id));
+  let live = remote.is_some() || net.game.is_some();
+  let lines: Vec<ChatLine> = match (remote, &net.game) {
+    (Some(game), _) => game.chat.clone(),
+    (None, Some(game)) => game.chat().to_vec(),
+    (None, None) => chat::load(record),
+  };
+  if live && chat::load(record) != lines {
+    chat::save(&lines, record);
+  }
+
+  egui::ScrollArea::vertical().max_height(200.0).stick_to_bottom(true).show(
+    ui,
+    |ui| {
+      for line in &lines {
+        let said = format!("{}: {}", line.from, line.text);
+        match line.room {
+          Room::Players => ui.label(said),
+          Room::Spectators => ui.weak(said),
+        };
+      }
+    },
+  );
+  if !live {
+    return;
+  }
+  ui.horizontal(|ui| {
+    let field = ui.text_edit_singleline(&mut panel.input);
+    let entered =
+      field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+    if !ui.button("Send").clicked() && !entered {
+      return;
+    }
+    let sent = match (&mut server.client, server.shown, &mut net.game) {
+      (Some(client), Some(id), _) if client.games.contains_key(&id) => {
+        client.say(id, &panel.input)
+      }
+      (_, _, Some(game)) => game.say(&panel.input),
+      _ => Ok(()),
+    };
+    match sent {
+      Ok(()) => {
+        panel.input.clear();
+        panel.status.clear();
+      }
+      Err(err) => panel.status = format!("{err:#}"),
+    }
+  });
+  ui.label(&panel.status);
+}
@@ -0,0 +1,336 @@
+//! The correspondence window: games of days per move kept on disk.
+
+use std::path::Path;
+
+use crate::{
+  board::{Color, Move, Position},
+  client::ServerClient,
+  correspondence::{self, Correspondence},
+  gametree::GameTree,
+  protocol::Update,
+  san,
+  transport::Transport,
+};
+
+use super::game::side;
+
+/// State of the correspondence window.
+pub struct CorrespondencePanel {
+  games: Vec<Correspondence>,
+  loaded: bool,
+  dir: String,
+  name: String,
+  /// Id of the game on the board.
+  shown: Option<String>,
+  client: Option<ServerClient>,
+  address: String,
+  opponent: String,
+  color: Color,
+  days: String,
+  input: String,
+  conditional: String,
+  move_file: String,
+  status: String,
+}
+
+impl Default for CorrespondencePanel {
+  fn default() -> Self {
+    Self {
+      games: Vec::new(),
+      loaded: false,
+      dir: "correspondence".into(),
+      name: "Player".into(),
+      shown: None,
+      client: None,
+      address: "127.0.0.1:7879".into(),
+      opponent: String::new(),
+      color: Color::White,
+      days: "3".into(),
+      input: String::new(),
+      conditional: String::new(),
+      move_file: "move.pgn".into(),
+      status: String::new(),
+    }
+  }
+}
+
+/// Moves in SAN or UCI, separated by spaces, from `position`.
+fn parse_line(
+  position: &Position,
+  text: &str,
+) -> anyhow::Result<Vec<Move>> {
+  let mut position = position.clone();
+  let mut moves = Vec::new();
+  for word in text.split_whitespace() {
+    let mv =
+      san::parse_san(&position, word).or_else(|_| Move::from_uci(word))?;
+    anyhow::ensure!(position.is_legal(mv), "illegal move {word}");
+    position.play(mv);
+    moves.push(mv);
+  }
+  Ok(moves)
+}
+
+fn days_and_hours(left: std::time::Duration) -> String {
+  let hours = left.as_secs() / 3600;
+  format!("{}d {}h", hours / 24, hours % 24)
+}
+
+impl CorrespondencePanel {
+  fn save(
+    &mut self,
+    id: &str,
+  ) {
+    let Some(game) = self.games.iter().find(|game| game.id == id) else {
+      return;
+    };
+    if let Err(err) = game.save(Path::new(self.dir.trim())) {
+      self.status = format!("Can't save {id}: {err:#}");
+    }
+  }
+
+  /// Sends a game through the server when connected.
+  fn send(
+    &mut self,
+    id: &str,
+  ) {
+    let Some(game) = self.games.iter().find(|game| game.id == id) else {
+      return;
+    };
+    self.status = match &mut self.client {
+      Some(client) => match client.post(game) {
+        Ok(()) => format!("Sent to {}.", game.opponent()),
+        Err(err) => format!("Can't send: {err:#}"),
+      },
+      None => format!("Export the move file for {}.", game.opponent()),
+    };
+  }
+
+  /// Takes in a game the opponent sent, new or known.
+  fn receive(
+    &mut self,
+    game: Correspondence,
+    now: u64,
+  ) {
+    let id = game.id.clone();
+    let reply = match self.games.iter_mut().find(|known| known.id == id) {
+      Some(known) => known.receive(&game, now),
+      None => {
+        self.games.push(game);
+        Ok(None)
+      }
+    };
+    match reply {
+      Ok(reply) => {
+        self.status = format!("{id} came in.");
+        self.save(&id);
+        if reply.is_some() {
+          self.send(&id);
+        }
+      }
+      Err(err) => self.status = format!("Can't take in {id}: {err:#}"),
+    }
+  }
+}
+
+/// Keeps the correspondence games of a directory, shows the one picked
+/// on the board, and exchanges them with the opponents as move files or
+/// through a game server.
+pub fn correspondence_panel(
+  ui: &mut egui::Ui,
+  panel: &mut CorrespondencePanel,
+  tree: &mut GameTree,
+) {
+  let now = correspondence::now();
+  ui.horizontal(|ui| {
+    ui.label("Name: ");
+    ui.text_edit_singleline(&mut panel.name);
+  });
+  ui.horizontal(|ui| {
+    ui.label("Directory: ");
+    ui.text_edit_singleline(&mut panel.dir);
+    if ui.button("Load").clicked() || !panel.loaded {
+      panel.loaded = true;
+      match correspondence::load(Path::new(panel.dir.trim())) {
+        Ok(games) => panel.games = games,
+        Err(err) => panel.status = format!("Can't load the games: {err:#}"),
+      }
+    }
+  });
+  let flagged: Vec<String> = panel
+    .games
+    .iter_mut()
+    .filter_map(|game| game.check_time(now).then(|| game.id.clone()))
+    .collect();
+  for id in flagged {
+    panel.save(&id);
+  }
+
+  ui.horizontal(|ui| {
+    ui.label("Server: ");
+    ui.text_edit_singleline(&mut panel.address);
+    if panel.client.is_none() && ui.button("Connect").clicked() {
+      let name = panel.name.trim();
+      match ServerClient::connect(panel.address.trim(), name, Transport::Tcp) {
+        Ok(client) => panel.client = Some(client),
+        Err(err) => panel.status = format!("Can't connect: {err:#}"),
+      }
+    }
+    if panel.client.is_some() && ui.button("Disconnect").clicked() {
+      panel.client = None;
+    }
+  });
+  let updates = panel.client.as_mut().map(ServerClient::poll);
+  for update in updates.into_iter().flatten() {
+    match update {
+      Update::Post { from, game, days, start, moves } => {
+        let name = panel.name.trim();
+        match Correspondence::received(
+          &game, &from, name, days, start, &moves, now,
+        ) {
+          Ok(received) => panel.receive(received, now),
+          Err(err) => panel.status = format!("Invalid game {game}: {err:#}"),
+        }
+      }
+      Update::Error { message } => panel.status = format!("Refused: {message}"),
+      _ => {}
+    }
+  }
+  if panel.client.as_ref().is_some_and(|client| !client.is_connected()) {
+    panel.client = None;
+    panel.status = "The connection was lost.".into();
+  }
+  if panel.client.is_some() {
+    ui.ctx().request_repaint_after(std::time::Duration::from_secs(1));
+  }
+
+  ui.horizontal(|ui| {
+    ui.label("Against: ");
+    ui.add(egui::TextEdit::singleline(&mut panel.opponent).desired_width(80.0));
+    for color in [Color::White, Color::Black] {
+      ui.selectable_value(&mut panel.color, color, side(color));
+    }
+    ui.add(egui::TextEdit::singleline(&mut panel.days).desired_width(30.0));
+    ui.label("days");
+    if ui.button("New game").clicked() {
+      match panel.days.trim().parse() {
+        Ok(days) => {
+          let (name, opponent) = (panel.name.trim(), panel.opponent.trim());
+          let game =
+            Correspondence::new(name, opponent, panel.color, days, now);
+          let id = game.id.clone();
+          panel.games.push(game);
+          panel.save(&id);
+          if panel.color == Color::Black {
+            panel.send(&id);
+          }
+          panel.shown = Some(id);
+        }
+        Err(err) => panel.status = format!("Invalid days per move: {err}"),
+      }
+    }
+  });
+
+  ui.separator();
+  let mut changed = false;
+  for game in &panel.games {
+    let state = if game.is_over() {
+      format!("{} {}", game.outcome, game.reason)
+    } else {
+      let turn = if game.is_our_turn() { "your move" } else { "waiting" };
+      format!("{turn}, {} left", days_and_hours(game.time_left(now)))
+    };
+    let label = format!("{} - {}: {state}", game.white, game.black);
+    let shown = panel.shown.as_ref() == Some(&game.id);
+    if ui.selectable_label(shown, label).clicked() {
+      panel.shown = Some(game.id.clone());
+      changed = true;
+    }
+  }
+
+  ui.horizontal(|ui| {
+    ui.label("Move file: ");
+    ui.text_edit_singleline(&mut panel.move_file);
+    if ui.button("Import").clicked() {
+      let read = std::fs::read_to_string(panel.move_file.trim())
+        .map_err(anyhow::Error::from)
+        .and_then(|text| Correspondence::from_move_file(&text, now));
+      match read {
+        Ok(game) => {
+          let id = game.id.clone();
+          panel.receive(game, now);
+          panel.shown = Some(id);
+          changed = true;
+        }
+        Err(err) => panel.status = format!("Can't import: {err:#}"),
+      }
+    }
+  });
+
+  let Some(id) = panel.shown.clone() else {
+    ui.label(&panel.status);
+    return;
+  };
+  let Some(game) = panel.games.iter_mut().find(|game| game.id == id) else {
+    panel.shown = None;
+    return;
+  };
+  if ui.button("Export").clicked() {
+    let path = panel.move_file.trim();
+    panel.status = match std::fs::write(path, game.to_move_file()) {
+      Ok(()) => format!("Wrote {path} for {}.", game.opponent()),
+      Err(err) => format!("Can't write {path}: {err}"),
+    };
+  }
+  let mut played = false;
+  if game.is_our_turn() {
+    ui.horizontal(|ui| {
+      let field = ui.text_edit_singleline(&mut panel.input);
+      let entered =
+        field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+      if ui.button("Play").clicked() || entered {
+        let input = panel.input.trim();
+        let mv = san::parse_san(&game.position(), input)
+          .or_else(|_| Move::from_uci(input));
+        match mv.and_then(|mv| game.play(mv, now)) {
+          Ok(()) => played = true,
+          Err(err) => panel.status = format!("Can't play {input:?}: {err:#}"),
+        }
+        panel.input.clear();
+      }
+    });
+  } else if !game.is_over() {
+    ui.horizontal(|ui| {
+      ui.label("If: ");
+      ui.text_edit_singleline(&mut panel.conditional);
+      if ui.button("Add").clicked() {
+        let line = parse_line(&game.position(), &panel.conditional);
+        match line.and_then(|line| game.add_conditional(line)) {
+          Ok(()) => panel.conditional.clear(),
+          Err(err) => panel.status = format!("Can't add the line: {err:#}"),
+        }
+        changed = true;
+      }
+      if ui.button("Clear").clicked() {
+        game.clear_conditionals();
+        changed = true;
+      }
+    });
+    let position = game.position();
+    for line in game.conditionals() {
+      ui.label(san::line(&position, line));
+    }
+  }
+  if played || changed {
+    match GameTree::from_moves(game.start.clone(), &game.moves) {
+      Ok(loaded) => *tree = loaded,
+      Err(err) => log::error!("invalid correspondence game: {err}"),
+    }
+    tree.to_end();
+    panel.save(&id);
+  }
+  if played {
+    panel.send(&id);
+  }
+  ui.label(&panel.status);
+}
@@ -0,0 +1,189 @@
+//! The game database window: importing, searching and loading games.
+
+use std::path::Path;
+
+use crate::{
+  database::{Database, Query},
+  gametree::GameTree,
+  pgn::{self, Outcome},
+};
+
+/// Replaces the game shown, at the first position with the hash `at` if
+/// there is one.
+pub(super) fn load_game(
+  record: &mut pgn::Game,
+  tree: &mut GameTree,
+  game: anyhow::Result<pgn::Game>,
+  at: Option<u64>,
+) -> anyhow::Result<()> {
+  let game = game?;
+  let mut loaded = GameTree::from_moves(game.start.clone(), &game.moves)?;
+  if let Some(hash) = at {
+    let mut node = loaded.root();
+    for id in loaded.mainline() {
+      if loaded.node(node).position.hash() == hash {
+        break;
+      }
+      node = id;
+    }
+    loaded.go_to(node);
+  }
+  (*record, *tree) = (game, loaded);
+  Ok(())
+}
+
+/// State of the database window.
+pub struct DatabasePanel {
+  database: Option<Database>,
+  path: String,
+  import_path: String,
+  player: String,
+  event: String,
+  from_date: String,
+  to_date: String,
+  outcome: Option<Outcome>,
+  eco: String,
+  material: String,
+  /// Only games through the position shown.
+  at_position: bool,
+  /// Ids and summaries of the games found.
+  found: Vec<(u32, String)>,
+}
+
+impl Default for DatabasePanel {
+  fn default() -> Self {
+    Self {
+      database: None,
+      path: "games.chdb".into(),
+      import_path: String::new(),
+      player: String::new(),
+      event: String::new(),
+      from_date: String::new(),
+      to_date: String::new(),
+      outcome: None,
+      eco: String::new(),
+      material: String::new(),
+      at_position: false,
+      found: Vec::new(),
+    }
+  }
+}
+
+/// Games shown from a search, the rest is only counted.
+const MAX_FOUND: usize = 500;
+
+/// Opens a game database, imports PGN files into it and searches it by
+/// tags, by the position shown or by material. Clicking a game loads it.
+pub fn database_panel(
+  ui: &mut egui::Ui,
+  panel: &mut DatabasePanel,
+  record: &mut pgn::Game,
+  tree: &mut GameTree,
+) {
+  ui.horizontal(|ui| {
+    ui.label("Database: ");
+    ui.text_edit_singleline(&mut panel.path);
+    if ui.button("Open").clicked() {
+      match Database::open(Path::new(panel.path.trim())) {
+        Ok(database) => panel.database = Some(database),
+        Err(err) => log::error!("can't open the database: {err:#}"),
+      }
+      panel.found.clear();
+    }
+  });
+  let Some(database) = &mut panel.database else {
+    return;
+  };
+  ui.horizontal(|ui| {
+    ui.label("PGN file: ");
+    ui.text_edit_singleline(&mut panel.import_path);
+    if ui.button("Import").clicked() {
+      let imported = std::fs::read_to_string(panel.import_path.trim())
+        .map_err(anyhow::Error::from)
+        .and_then(|text| pgn::parse(&text))
+        .and_then(|games| database.import(&games));
+      if let Err(err) = imported {
+        log::error!("can't import {}: {err:#}", panel.import_path);
+      }
+    }
+  });
+  ui.label(format!("{} games", database.len()));
+
+  egui::Grid::new("database query").show(ui, |ui| {
+    for (label, field) in [
+      ("Player", &mut panel.player),
+      ("Event", &mut panel.event),
+      ("From", &mut panel.from_date),
+      ("To", &mut panel.to_date),
+      ("ECO", &mut panel.eco),
+      ("Material", &mut panel.material),
+    ] {
+      ui.label(label);
+      ui.text_edit_singleline(field);
+      ui.end_row();
+    }
+    ui.label("Result");
+    let shown = panel.outcome.map_or("any", Outcome::as_str);
+    egui::ComboBox::from_id_source("database result")
+      .selected_text(shown)
+      .show_ui(ui, |ui| {
+        ui.selectable_value(&mut panel.outcome, None, "any");
+        for outcome in [Outcome::WhiteWins, Outcome::Draw, Outcome::BlackWins] {
+          ui.selectable_value(
+            &mut panel.outcome,
+            Some(outcome),
+            outcome.as_str(),
+          );
+        }
+      });
+    ui.end_row();
+  });
+  ui.checkbox(&mut panel.at_position, "Through the position shown");
+
+  let hash = tree.position().hash();
+  if ui.button("Search").clicked() {
+    let text = |field: &String| {
+      Some(field.trim().to_string()).filter(|field| !field.is_empty())
+    };
+    let material = text(&panel.material).map(|material| material.parse());
+    match material.transpose() {
+      Ok(material) => {
+        let query = Query {
+          player: text(&panel.player),
+          event: text(&panel.event),
+          from_date: text(&panel.from_date),
+          to_date: text(&panel.to_date),
+          outcome: panel.outcome,
+          eco: text(&panel.eco),
+          position: panel.at_position.then_some(hash),
+          material,
+          ..Default::default()
+        };
+        panel.found = database
+          .search(&query)
+          .into_iter()
+          .map(|id| (id, database.summary(id).unwrap_or_default()))
+          .collect();
+      }
+      Err(err) => log::error!("{err:#}"),
+    }
+  }
+
+  if !panel.found.is_empty() {
+    ui.label(format!("{} found", panel.found.len()));
+  }
+  let mut load = None;
+  egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+    for (id, summary) in panel.found.iter().take(MAX_FOUND) {
+      if ui.link(summary).clicked() {
+        load = Some(*id);
+      }
+    }
+  });
+  if let Some(id) = load {
+    let at = panel.at_position.then_some(hash);
+    if let Err(err) = load_game(record, tree, database.game(id), at) {
+      log::error!("can't load game {id}: {err:#}");
+    }
+  }
+}
@@ -0,0 +1,162 @@
+//! The opening explorer window over an indexed collection of games.
+
+use std::path::Path;
+
+use crate::{
+  explorer::{self, Explorer, MoveStats},
+  gametree::GameTree,
+  pgn, san,
+};
+
+use super::database::load_game;
+
+/// Moves and games of a position, by its hash.
+type Shown = (u64, Vec<MoveStats>, Vec<(u32, String)>);
+
+/// State of the explorer window.
+#[derive(Default)]
+pub struct ExplorerPanel {
+  explorer: Option<Explorer>,
+  building: Option<std::sync::mpsc::Receiver<anyhow::Result<()>>>,
+  /// The last position looked up.
+  shown: Option<Shown>,
+  pgn_paths: String,
+  index_path: String,
+}
+
+/// Builds or opens an index of games, then lists the moves played from the
+/// shown position with their results, and the best rated games reaching
+/// it. Clicking a move plays it, clicking a game loads it.
+pub fn explorer_panel(
+  ui: &mut egui::Ui,
+  panel: &mut ExplorerPanel,
+  record: &mut pgn::Game,
+  tree: &mut GameTree,
+) {
+  if let Some(building) = &panel.building {
+    match building.try_recv() {
+      Ok(result) => {
+        panel.building = None;
+        match result.and_then(|()| Explorer::open(Path::new(&panel.index_path)))
+        {
+          Ok(explorer) => panel.explorer = Some(explorer),
+          Err(err) => log::error!("can't build the index: {err:#}"),
+        }
+        panel.shown = None;
+      }
+      Err(_) => {
+        ui.spinner();
+        ui.ctx().request_repaint();
+      }
+    }
+  }
+  ui.horizontal(|ui| {
+    ui.label("PGN files: ");
+    ui.text_edit_singleline(&mut panel.pgn_paths);
+  });
+  ui.horizontal(|ui| {
+    ui.label("Index: ");
+    ui.text_edit_singleline(&mut panel.index_path);
+    let idle = panel.building.is_none();
+    if ui.add_enabled(idle, egui::Button::new("Build")).clicked() {
+      let (tx, rx) = std::sync::mpsc::channel();
+      let paths = panel.pgn_paths.clone();
+      let index = panel.index_path.trim().to_string();
+      std::thread::spawn(move || {
+        let mut games = Vec::new();
+        let result = paths
+          .split(':')
+          .map(str::trim)
+          .filter(|path| !path.is_empty())
+          .try_for_each(|path| {
+            let text = std::fs::read_to_string(path)?;
+            games.extend(pgn::parse(&text)?);
+            anyhow::Ok(())
+          })
+          .and_then(|()| {
+            let options = explorer::BuildOptions::default();
+            explorer::build(&games, &options, Path::new(&index))
+          });
+        let _ = tx.send(result);
+      });
+      panel.building = Some(rx);
+    }
+    if ui.button("Open").clicked() {
+      match Explorer::open(Path::new(panel.index_path.trim())) {
+        Ok(explorer) => panel.explorer = Some(explorer),
+        Err(err) => log::error!("can't open the index: {err:#}"),
+      }
+      panel.shown = None;
+    }
+  });
+  let Some(explorer) = &panel.explorer else {
+    return;
+  };
+  ui.label(format!("{} games", explorer.games()));
+
+  let position = tree.position().clone();
+  let hash = position.hash();
+  if panel.shown.as_ref().map(|(shown, ..)| *shown) != Some(hash) {
+    let looked_up = explorer.moves(&position).and_then(|moves| {
+      let mut ids: Vec<u32> =
+        moves.iter().flat_map(|stats| stats.games.clone()).collect();
+      ids.sort();
+      ids.dedup();
+      let games = ids
+        .into_iter()
+        .map(|id| Ok((id, explorer.summary(id)?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+      Ok((moves, games))
+    });
+    match looked_up {
+      Ok((moves, games)) => panel.shown = Some((hash, moves, games)),
+      Err(err) => {
+        log::error!("explorer lookup failed: {err:#}");
+        panel.shown = Some((hash, Vec::new(), Vec::new()));
+      }
+    }
+  }
+  let Some((_, moves, games)) = &panel.shown else {
+    return;
+  };
+
+  let mut play = None;
+  egui::Grid::new("explorer moves").striped(true).show(ui, |ui| {
+    ui.strong("Move");
+    ui.strong("Games");
+    ui.strong("White / Draw / Black");
+    ui.strong("Rating");
+    ui.end_row();
+    for stats in moves {
+      if ui.link(san::to_san(&position, stats.mv)).clicked() {
+        play = Some(stats.mv);
+      }
+      ui.label(stats.total().to_string());
+      let [white, draws, black] = stats.percentages();
+      ui.label(format!("{white:.0}% / {draws:.0}% / {black:.0}%"));
+      let rating = stats.average_rating();
+      ui.label(rating.map_or("-".into(), |rating| rating.to_string()));
+      ui.end_row();
+    }
+  });
+  if moves.is_empty() {
+    ui.weak("No games reach this position.");
+  }
+
+  let mut load = None;
+  for (id, summary) in games {
+    if ui.link(summary).clicked() {
+      load = Some(*id);
+    }
+  }
+  if let Some(mv) = play {
+    if let Err(err) = tree.play(mv) {
+      log::error!("can't play {mv}: {err}");
+    }
+  }
+  if let Some(id) = load {
+    if let Err(err) = load_game(record, tree, explorer.game(id), Some(hash)) {
+      log::error!("can't load game {id}: {err:#}");
+    }
+  }
+}
@@ -0,0 +1,169 @@
+//! The window of a game played on this board, with the buttons for
+//! draw offers, resignation and the like that network games share.
+
+use crate::{
+  board::Color,
+  control::{self, Controls, Effect},
+  gametree::GameTree,
+  pgn::{self, Outcome},
+  protocol::Control,
+};
+
+pub(super) fn side(color: Color) -> &'static str {
+  color.fold("White", "Black")
+}
+
+pub(super) fn control_label(control: Control) -> &'static str {
+  match control {
+    Control::OfferDraw => "Offer draw",
+    Control::AcceptDraw => "Accept draw",
+    Control::DeclineDraw => "Decline draw",
+    Control::Resign => "Resign",
+    Control::AskTakeback => "Ask takeback",
+    Control::AcceptTakeback => "Accept takeback",
+    Control::DeclineTakeback => "Decline takeback",
+    Control::Abort => "Abort",
+    Control::ClaimDraw => "Claim draw",
+    Control::ClaimWin => "Claim win",
+  }
+}
+
+/// What a player did, for the status line.
+pub(super) fn control_news(
+  name: &str,
+  control: Control,
+) -> String {
+  match control {
+    Control::OfferDraw => format!("{name} offers a draw."),
+    Control::AcceptDraw => format!("{name} accepts the draw."),
+    Control::DeclineDraw => format!("{name} declines the draw."),
+    Control::Resign => format!("{name} resigns."),
+    Control::AskTakeback => format!("{name} asks for a takeback."),
+    Control::AcceptTakeback => format!("{name} accepts the takeback."),
+    Control::DeclineTakeback => format!("{name} declines the takeback."),
+    Control::Abort => format!("{name} aborts the game."),
+    Control::ClaimDraw => format!("{name} claims a draw."),
+    Control::ClaimWin => format!("{name} claims the game."),
+  }
+}
+
+/// A button for each control allowed, returning the one clicked.
+pub(super) fn control_buttons(
+  ui: &mut egui::Ui,
+  allowed: impl Fn(Control) -> bool,
+) -> Option<Control> {
+  let mut clicked = None;
+  ui.horizontal_wrapped(|ui| {
+    for control in Control::ALL.into_iter().filter(|&control| allowed(control))
+    {
+      if ui.button(control_label(control)).clicked() {
+        clicked = Some(control);
+      }
+    }
+  });
+  clicked
+}
+
+/// State of the local game window.
+#[derive(Default)]
+pub struct GamePanel {
+  white: String,
+  black: String,
+  /// The clock the players agreed on, kept over the board.
+  control: String,
+  controls: Controls,
+  /// How a control ended the game.
+  result: Option<(Outcome, String)>,
+  status: String,
+  /// Games that ended here, for the ratings.
+  pub(super) ended: Vec<pgn::Game>,
+  /// Whether the game shown is in `ended`.
+  recorded: bool,
+}
+
+/// Controls of a game both sides play on this board, the moves up to the
+/// one shown being the game. With the players named and a clock the
+/// game is rated once over.
+pub fn game_panel(
+  ui: &mut egui::Ui,
+  panel: &mut GamePanel,
+  record: &mut pgn::Game,
+  tree: &mut GameTree,
+) {
+  ui.horizontal(|ui| {
+    for (label, name) in
+      [("White: ", &mut panel.white), ("Black: ", &mut panel.black)]
+    {
+      ui.label(label);
+      ui.add(egui::TextEdit::singleline(name).desired_width(80.0));
+    }
+    ui.label("Clock: ");
+    ui.add(egui::TextEdit::singleline(&mut panel.control).desired_width(60.0));
+  });
+  let start = tree.start().clone();
+  let moves = tree.played();
+  let (position, history) = control::replay(&start, &moves);
+  let over = control::game_over(&position, &history)
+    .map(|(outcome, reason)| (outcome, reason.to_string()));
+  if let Some((outcome, reason)) = panel.result.clone().or(over) {
+    if !panel.recorded {
+      panel.recorded = true;
+      record.outcome = outcome;
+      record.set_tag("Termination", &reason);
+      for (tag, value) in [
+        ("White", &panel.white),
+        ("Black", &panel.black),
+        ("TimeControl", &panel.control),
+      ] {
+        if !value.trim().is_empty() {
+          record.set_tag(tag, value.trim());
+        }
+      }
+      if outcome != Outcome::Unknown {
+        panel.ended.push(pgn::Game { start, moves, ..record.clone() });
+      }
+    }
+    ui.label(format!("{outcome} by {reason}"));
+    ui.label(&panel.status);
+    if ui.button("New game").clicked() {
+      *tree = GameTree::default();
+      record.outcome = Outcome::Unknown;
+      record.tags.retain(|(name, _)| name != "Termination");
+      panel.controls = Controls::default();
+      (panel.result, panel.recorded) = (None, false);
+      panel.status.clear();
+    }
+    return;
+  }
+
+  let mut clicked = None;
+  ui.columns(2, |columns| {
+    for (ui, color) in columns.iter_mut().zip([Color::White, Color::Black]) {
+      ui.label(side(color));
+      let allowed =
+        |control| panel.controls.allows(color, control, &start, &moves, false);
+      if let Some(control) = control_buttons(ui, allowed) {
+        clicked = Some((color, control));
+      }
+    }
+  });
+  let Some((color, control)) = clicked else {
+    ui.label(&panel.status);
+    return;
+  };
+  panel.status = control_news(side(color), control);
+  match panel.controls.apply(color, control, &start, &moves, false) {
+    Ok(Effect::Offered | Effect::Declined) => {}
+    Ok(Effect::TakeBack(count)) => {
+      for _ in 0..count {
+        let current = tree.current();
+        tree.delete(current);
+      }
+    }
+    Ok(Effect::End(outcome, reason)) => {
+      panel.result = Some((outcome, reason.into()));
+    }
+    Err(err) => panel.status = format!("{err:#}"),
+  }
+  ui.label(&panel.status);
+}
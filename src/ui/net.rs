@@ -0,0 +1,207 @@
+//! The window of a peer-to-peer game over TCP.
+
+use crate::{
+  board::{Color, Move, Piece, Position, Square},
+  gametree::GameTree,
+  net::{self, NetGame},
+  premove::{self, Premoves},
+  san,
+};
+
+use super::game::{control_buttons, control_news, side};
+
+/// State of the network game window.
+pub struct NetPanel {
+  pub(super) game: Option<NetGame>,
+  address: String,
+  name: String,
+  color: Color,
+  status: String,
+  input: String,
+  premoves: Premoves,
+}
+
+impl Default for NetPanel {
+  fn default() -> Self {
+    Self {
+      game: None,
+      address: "127.0.0.1:7878".into(),
+      name: "Player".into(),
+      color: Color::White,
+      status: String::new(),
+      input: String::new(),
+      premoves: Premoves::default(),
+    }
+  }
+}
+
+impl NetPanel {
+  /// A piece dragged on the board: our move, or a premove while the
+  /// opponent thinks. False without a game.
+  pub fn drag(
+    &mut self,
+    from: Square,
+    to: Square,
+  ) -> bool {
+    let Some(game) = &mut self.game else {
+      return false;
+    };
+    let played = if game.is_our_turn() {
+      premove::dragged(game.position(), from, to)
+        .ok_or_else(|| anyhow::anyhow!("no move from {from} to {to}"))
+        .and_then(|mv| game.play(mv))
+    } else {
+      let color = game.color;
+      self.premoves.push(game.position(), color, from, to).map(drop)
+    };
+    if let Err(err) = played {
+      self.status = format!("{err:#}");
+    }
+    true
+  }
+
+  /// The pieces premoved in the game, where they go.
+  pub fn ghosts(&self) -> Vec<(Square, Piece)> {
+    let game = self.game.as_ref();
+    game.map_or_else(Vec::new, |game| self.premoves.ghosts(game.position()))
+  }
+
+  /// Returns whether there were premoves to cancel.
+  pub fn cancel_premoves(&mut self) -> bool {
+    let any = !self.premoves.is_empty();
+    self.premoves.clear();
+    any
+  }
+}
+
+/// Hosts or joins a game over the network and shows it on the board,
+/// sending the moves entered.
+pub fn net_panel(
+  ui: &mut egui::Ui,
+  panel: &mut NetPanel,
+  tree: &mut GameTree,
+) {
+  ui.horizontal(|ui| {
+    ui.label("Name: ");
+    ui.text_edit_singleline(&mut panel.name);
+  });
+  ui.horizontal(|ui| {
+    ui.label("Address: ");
+    ui.text_edit_singleline(&mut panel.address);
+  });
+  let Some(game) = &mut panel.game else {
+    ui.horizontal(|ui| {
+      for color in [Color::White, Color::Black] {
+        let name = color.fold("White", "Black");
+        ui.selectable_value(&mut panel.color, color, name);
+      }
+      if ui.button("Host").clicked() {
+        let address = panel.address.trim();
+        let start = Position::startpos();
+        match NetGame::host(address, &panel.name, panel.color, start) {
+          Ok(game) => {
+            panel.status = format!("Waiting on {address}.");
+            panel.game = Some(game);
+          }
+          Err(err) => panel.status = format!("Can't host: {err:#}"),
+        }
+      }
+      if ui.button("Join").clicked() {
+        match NetGame::join(panel.address.trim(), &panel.name) {
+          Ok(game) => {
+            panel.status = format!("Joined, playing {}.", side(game.color));
+            panel.game = Some(game);
+          }
+          Err(err) => panel.status = format!("Can't join: {err:#}"),
+        }
+      }
+    });
+    ui.label(&panel.status);
+    return;
+  };
+
+  let mut changed = false;
+  for event in game.poll() {
+    changed = true;
+    panel.status = match event {
+      net::Event::Connected { name } => format!("{name} is here."),
+      net::Event::Moved(_) => "Your move.".into(),
+      net::Event::Control(color, control) => control_news(side(color), control),
+      net::Event::Ended(outcome, reason) => format!("{outcome} by {reason}."),
+      net::Event::Synced => "Back in the game.".into(),
+      net::Event::Error(message) => format!("Refused: {message}"),
+      net::Event::Disconnected => "The connection was lost.".into(),
+    };
+  }
+  if game.is_our_turn() {
+    if let Some(mv) = panel.premoves.next(game.position()) {
+      changed |= game.play(mv).is_ok();
+    }
+  }
+  ui.ctx().request_repaint_after(std::time::Duration::from_millis(100));
+
+  ui.label(match game.opponent() {
+    Some(name) => format!("{} against {name}", side(game.color)),
+    None => format!("{}, nobody else here", side(game.color)),
+  });
+  let mut played = None;
+  ui.horizontal(|ui| {
+    let field = ui.text_edit_singleline(&mut panel.input);
+    let entered =
+      field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+    if ui.button("Play").clicked() || entered {
+      let input = panel.input.trim();
+      match san::parse_san(game.position(), input)
+        .or_else(|_| Move::from_uci(input))
+      {
+        Ok(mv) => played = Some(mv),
+        Err(err) => panel.status = format!("Can't play {input:?}: {err}"),
+      }
+      panel.input.clear();
+    }
+    if !game.is_host()
+      && !game.is_connected()
+      && ui.button("Reconnect").clicked()
+    {
+      match game.reconnect() {
+        Ok(()) => panel.status = "Back in the game.".into(),
+        Err(err) => panel.status = format!("Can't reconnect: {err:#}"),
+      }
+      changed = true;
+    }
+  });
+  if let Some(mv) = played {
+    match game.play(mv) {
+      Ok(()) => changed = true,
+      Err(err) => panel.status = format!("{err:#}"),
+    }
+  }
+  if let Some((outcome, reason)) = game.result() {
+    ui.label(format!("{outcome} by {reason}"));
+  }
+  if let Some((offer, by)) = game.offer() {
+    ui.label(control_news(side(by), offer));
+  }
+  if let Some(control) = control_buttons(ui, |control| game.allows(control)) {
+    match game.control(control) {
+      Ok(()) => {
+        panel.status = control_news(side(game.color), control);
+        changed = true;
+      }
+      Err(err) => panel.status = format!("{err:#}"),
+    }
+  }
+  ui.label(&panel.status);
+  if changed {
+    match GameTree::from_moves(game.start().clone(), game.moves()) {
+      Ok(loaded) => *tree = loaded,
+      Err(err) => log::error!("invalid network game: {err}"),
+    }
+    tree.to_end();
+  }
+  if ui.button("Leave").clicked() {
+    panel.game = None;
+    panel.premoves.clear();
+    panel.status = "Left the game.".into();
+  }
+}
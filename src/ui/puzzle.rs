@@ -0,0 +1,188 @@
+//! The puzzle trainer window.
+
+use std::path::Path;
+
+use crate::{
+  board::Move,
+  gametree::GameTree,
+  puzzle::{self, Attempt, Puzzle, Trainer, Verdict},
+  san,
+};
+
+/// State of the puzzle window.
+pub struct PuzzlePanel {
+  puzzles: Vec<Puzzle>,
+  themes: Vec<String>,
+  theme: Option<String>,
+  trainer: Trainer,
+  attempt: Option<Attempt>,
+  /// Whether the attempt counted towards the rating yet.
+  recorded: bool,
+  status: String,
+  csv_path: String,
+  rating_path: String,
+  input: String,
+}
+
+impl Default for PuzzlePanel {
+  fn default() -> Self {
+    Self {
+      puzzles: Vec::new(),
+      themes: Vec::new(),
+      theme: None,
+      trainer: Trainer::default(),
+      attempt: None,
+      recorded: false,
+      status: String::new(),
+      csv_path: String::new(),
+      rating_path: "puzzle-rating.txt".into(),
+      input: String::new(),
+    }
+  }
+}
+
+impl PuzzlePanel {
+  /// Rates the attempt once it is settled: solved without a slip, or
+  /// failed.
+  fn record(&mut self) {
+    let Some(attempt) = &self.attempt else {
+      return;
+    };
+    if self.recorded || !(attempt.is_solved() || attempt.has_failed()) {
+      return;
+    }
+    self.recorded = true;
+    self.trainer.record(&attempt.puzzle, !attempt.has_failed());
+    if let Err(err) = self.trainer.save(Path::new(&self.rating_path)) {
+      log::error!("can't save the puzzle rating: {err:#}");
+    }
+  }
+}
+
+/// Loads puzzles, picks the next one near the solver's rating in the
+/// chosen theme, shows it on the board and checks the moves entered,
+/// answering with the opponent's moves.
+pub fn puzzle_panel(
+  ui: &mut egui::Ui,
+  panel: &mut PuzzlePanel,
+  tree: &mut GameTree,
+) {
+  ui.horizontal(|ui| {
+    ui.label("Puzzles: ");
+    ui.text_edit_singleline(&mut panel.csv_path);
+    if ui.button("Load").clicked() {
+      let loaded = std::fs::read_to_string(panel.csv_path.trim())
+        .map_err(anyhow::Error::from)
+        .and_then(|text| puzzle::parse_csv(&text));
+      match loaded {
+        Ok(puzzles) => {
+          panel.themes = puzzle::themes(&puzzles);
+          panel.puzzles = puzzles;
+        }
+        Err(err) => log::error!("can't load puzzles: {err:#}"),
+      }
+      match Trainer::load(Path::new(&panel.rating_path)) {
+        Ok(trainer) => panel.trainer = trainer,
+        Err(err) => log::error!("can't load the puzzle rating: {err:#}"),
+      }
+    }
+  });
+  ui.horizontal(|ui| {
+    ui.label("Rating file: ");
+    ui.text_edit_singleline(&mut panel.rating_path);
+  });
+
+  let rating = panel.trainer.rating;
+  ui.label(format!(
+    "{} puzzles loaded. Rating {:.0} ± {:.0}, {} solved, {} failed",
+    panel.puzzles.len(),
+    rating.rating,
+    2.0 * rating.deviation,
+    panel.trainer.solved,
+    panel.trainer.failed,
+  ));
+
+  ui.horizontal(|ui| {
+    let shown = panel.theme.as_deref().unwrap_or("any theme");
+    egui::ComboBox::from_label("").selected_text(shown).show_ui(ui, |ui| {
+      ui.selectable_value(&mut panel.theme, None, "any theme");
+      for theme in &panel.themes {
+        ui.selectable_value(&mut panel.theme, Some(theme.clone()), theme);
+      }
+    });
+    if ui.button("Next puzzle").clicked() {
+      let next = panel.trainer.next(&panel.puzzles, panel.theme.as_deref());
+      match next.cloned() {
+        Some(puzzle) => {
+          *tree = GameTree::new(puzzle.start.clone());
+          if let Err(err) = tree.play(puzzle.moves[0]) {
+            log::error!("invalid puzzle {}: {err}", puzzle.id);
+          }
+          let side = puzzle.position().side_to_move();
+          panel.status = format!(
+            "{} to play, rated {:.0}",
+            side.fold("White", "Black"),
+            puzzle.rating.rating
+          );
+          panel.attempt = Some(Attempt::new(puzzle));
+          panel.recorded = false;
+        }
+        None => panel.status = "No puzzle left to try.".into(),
+      }
+    }
+  });
+
+  let Some(attempt) = &mut panel.attempt else {
+    return;
+  };
+  ui.label(&panel.status);
+  if attempt.is_solved() {
+    ui.hyperlink_to("Game", &attempt.puzzle.url);
+    return;
+  }
+  let mut played = None;
+  ui.horizontal(|ui| {
+    let field = ui.text_edit_singleline(&mut panel.input);
+    let entered =
+      field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+    if ui.button("Play").clicked() || entered {
+      let input = panel.input.trim();
+      match san::parse_san(attempt.position(), input)
+        .or_else(|_| Move::from_uci(input))
+      {
+        Ok(mv) => played = Some(mv),
+        Err(err) => log::warn!("can't play {input:?}: {err}"),
+      }
+      panel.input.clear();
+    }
+    if ui.button("Hint").clicked() {
+      if let Some(hint) = attempt.hint() {
+        panel.status = format!("Move the piece on {}.", hint.from);
+      }
+    }
+    if ui.button("Solution").clicked() {
+      played = attempt.give_up();
+    }
+  });
+
+  if let Some(mv) = played {
+    let position = attempt.position().clone();
+    let san = san::to_san(&position, mv);
+    match attempt.play(mv) {
+      Verdict::Correct { reply } => {
+        let _ = tree.play(mv);
+        let _ = tree.play(reply);
+        panel.status = format!("{san} is right, keep going.");
+      }
+      Verdict::Solved => {
+        let _ = tree.play(mv);
+        panel.status = match attempt.has_failed() {
+          false => format!("{san} solves it!"),
+          true => format!("{san} was the way."),
+        };
+      }
+      Verdict::Wrong => panel.status = format!("{san} is not it, try again."),
+    }
+  }
+  panel.record();
+}
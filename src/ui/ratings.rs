@@ -0,0 +1,182 @@
+//! The ratings window: leaderboards and the rating history of a player.
+
+use std::path::Path;
+
+use crate::{
+  database::Database,
+  glicko::Rating,
+  pgn,
+  ratings::{Category, Player, Ratings},
+};
+
+use super::{GamePanel, ServerPanel};
+
+/// State of the ratings window.
+pub struct RatingsPanel {
+  database: Option<Database>,
+  ratings: Ratings,
+  path: String,
+  ratings_path: String,
+  category: Category,
+  /// The player whose history is drawn.
+  shown: Option<String>,
+  status: String,
+}
+
+impl Default for RatingsPanel {
+  fn default() -> Self {
+    Self {
+      database: None,
+      ratings: Ratings::default(),
+      path: "rated.chdb".into(),
+      ratings_path: "ratings.txt".into(),
+      category: Category::Blitz,
+      shown: None,
+      status: String::new(),
+    }
+  }
+}
+
+impl RatingsPanel {
+  /// Stores games that ended and rates them, saving the ratings.
+  fn add(
+    &mut self,
+    games: &[pgn::Game],
+  ) -> anyhow::Result<()> {
+    let Some(database) = &mut self.database else {
+      return Ok(());
+    };
+    if !games.is_empty() {
+      database.import(games)?;
+    }
+    let rated = self.ratings.catch_up(database)?;
+    if rated > 0 {
+      self.status = format!("{rated} games rated.");
+    }
+    self.ratings.save(Path::new(self.ratings_path.trim()))
+  }
+}
+
+/// Rates the games played here and on servers, stored in a database of
+/// their own, and shows the leaderboard of each category with the rating
+/// history of a player.
+pub fn ratings_panel(
+  ui: &mut egui::Ui,
+  panel: &mut RatingsPanel,
+  game: &mut GamePanel,
+  server: &mut ServerPanel,
+) {
+  ui.horizontal(|ui| {
+    ui.label("Games: ");
+    ui.text_edit_singleline(&mut panel.path);
+  });
+  ui.horizontal(|ui| {
+    ui.label("Ratings: ");
+    ui.text_edit_singleline(&mut panel.ratings_path);
+    if ui.button("Open").clicked() {
+      let opened =
+        Database::open(Path::new(panel.path.trim())).and_then(|database| {
+          let ratings = Ratings::load(Path::new(panel.ratings_path.trim()))?;
+          Ok((database, ratings))
+        });
+      match opened {
+        Ok((database, ratings)) => {
+          (panel.database, panel.ratings) = (Some(database), ratings);
+          panel.status.clear();
+        }
+        Err(err) => panel.status = format!("Can't open: {err:#}"),
+      }
+    }
+  });
+  let Some(database) = &panel.database else {
+    ui.label(&panel.status);
+    return;
+  };
+  let recompute = ui
+    .horizontal(|ui| {
+      ui.label(format!("{} games stored.", database.len()));
+      ui.button("Recompute").clicked()
+    })
+    .inner;
+  if recompute {
+    match Ratings::recompute(database) {
+      Ok(ratings) => panel.ratings = ratings,
+      Err(err) => panel.status = format!("Can't recompute: {err:#}"),
+    }
+  }
+  // the games kept until a database takes them
+  let ended: Vec<pgn::Game> =
+    game.ended.drain(..).chain(server.ended.drain(..)).collect();
+  if recompute || !ended.is_empty() || panel.ratings.games() < database.len() {
+    if let Err(err) = panel.add(&ended) {
+      panel.status = format!("Can't rate: {err:#}");
+    }
+  }
+
+  ui.horizontal(|ui| {
+    for category in Category::ALL {
+      let label = category.as_str();
+      ui.selectable_value(&mut panel.category, category, label);
+    }
+  });
+  egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+    egui::Grid::new("leaderboard").striped(true).show(ui, |ui| {
+      let leaderboard = panel.ratings.leaderboard(panel.category);
+      for (rank, (name, player)) in leaderboard.into_iter().enumerate() {
+        ui.label(format!("{}.", rank + 1));
+        let shown = panel.shown.as_deref() == Some(name);
+        if ui.selectable_label(shown, name).clicked() {
+          panel.shown = Some(name.to_string());
+        }
+        let rating = player.rating();
+        let provisional = if player.is_provisional() { "?" } else { "" };
+        ui.label(format!("{:.0}{provisional}", rating.rating));
+        ui.label(format!("± {:.0}", rating.deviation));
+        ui.label(format!("{} games", player.games()));
+        ui.end_row();
+      }
+    });
+  });
+  let player = (panel.shown.as_deref())
+    .and_then(|name| panel.ratings.player(name, panel.category));
+  if let Some(player) = player {
+    rating_graph(ui, player);
+  }
+  ui.label(&panel.status);
+}
+
+/// The rating of a player from the start through each game.
+fn rating_graph(
+  ui: &mut egui::Ui,
+  player: &Player,
+) {
+  let ratings: Vec<f64> = std::iter::once(Rating::default())
+    .chain(player.history.iter().copied())
+    .map(|rating| rating.rating)
+    .collect();
+  let low = ratings.iter().copied().fold(f64::INFINITY, f64::min);
+  let high = ratings.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+  let size = egui::vec2(ui.available_width(), 100.0);
+  let (response, painter) = ui.allocate_painter(size, egui::Sense::hover());
+  let rect = response.rect;
+  painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+  let span = (high - low).max(1.0);
+  let steps = (ratings.len() - 1).max(1) as f32;
+  let points: Vec<egui::Pos2> = (ratings.iter().enumerate())
+    .map(|(game, &rating)| {
+      let x = rect.left() + rect.width() * game as f32 / steps;
+      let y = rect.bottom() - rect.height() * ((rating - low) / span) as f32;
+      egui::pos2(x, y)
+    })
+    .collect();
+  let stroke = egui::Stroke::new(1.5, ui.visuals().text_color());
+  painter.add(egui::Shape::line(points, stroke));
+  let font = egui::FontId::proportional(10.0);
+  let color = ui.visuals().weak_text_color();
+  for (pos, anchor, rating) in [
+    (rect.left_top(), egui::Align2::LEFT_TOP, high),
+    (rect.left_bottom(), egui::Align2::LEFT_BOTTOM, low),
+  ] {
+    painter.text(pos, anchor, format!("{rating:.0}"), font.clone(), color);
+  }
+}
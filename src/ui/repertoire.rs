@@ -0,0 +1,158 @@
+//! The repertoire trainer window.
+
+use std::path::Path;
+
+use crate::{
+  board::{Color, Move},
+  gametree::GameTree,
+  repertoire::{self, Drill, Repertoire, Schedule, Step},
+  san,
+};
+
+/// State of the repertoire trainer window.
+pub struct RepertoirePanel {
+  repertoire: Option<Repertoire>,
+  color: Color,
+  schedule: Schedule,
+  drill: Option<Drill>,
+  /// Whether the drill went into the schedule yet.
+  recorded: bool,
+  status: String,
+  pgn_path: String,
+  schedule_path: String,
+  input: String,
+}
+
+impl Default for RepertoirePanel {
+  fn default() -> Self {
+    Self {
+      repertoire: None,
+      color: Color::White,
+      schedule: Schedule::default(),
+      drill: None,
+      recorded: false,
+      status: String::new(),
+      pgn_path: String::new(),
+      schedule_path: "repertoire-schedule.txt".into(),
+      input: String::new(),
+    }
+  }
+}
+
+/// Loads a repertoire for one side, drills the lines due as the schedule
+/// says, playing the other side on the board, and schedules each line
+/// again by how well it went.
+pub fn repertoire_panel(
+  ui: &mut egui::Ui,
+  panel: &mut RepertoirePanel,
+  tree: &mut GameTree,
+) {
+  ui.horizontal(|ui| {
+    ui.label("Repertoire: ");
+    ui.text_edit_singleline(&mut panel.pgn_path);
+    for color in [Color::White, Color::Black] {
+      let name = color.fold("White", "Black");
+      ui.selectable_value(&mut panel.color, color, name);
+    }
+    if ui.button("Load").clicked() {
+      let loaded = std::fs::read_to_string(panel.pgn_path.trim())
+        .map_err(anyhow::Error::from)
+        .and_then(|text| Repertoire::from_pgn(panel.color, &text));
+      match loaded {
+        Ok(repertoire) => panel.repertoire = Some(repertoire),
+        Err(err) => log::error!("can't load the repertoire: {err:#}"),
+      }
+      match Schedule::load(Path::new(&panel.schedule_path)) {
+        Ok(schedule) => panel.schedule = schedule,
+        Err(err) => log::error!("can't load the schedule: {err:#}"),
+      }
+      panel.drill = None;
+    }
+  });
+  ui.horizontal(|ui| {
+    ui.label("Schedule file: ");
+    ui.text_edit_singleline(&mut panel.schedule_path);
+  });
+  let Some(repertoire) = &panel.repertoire else {
+    return;
+  };
+
+  let today = repertoire::today();
+  ui.horizontal(|ui| {
+    ui.label(format!(
+      "{} lines, {} due",
+      repertoire.lines.len(),
+      panel.schedule.due(repertoire, today)
+    ));
+    if ui.button("Next line").clicked() {
+      match panel.schedule.next(repertoire, today) {
+        Some(i) => {
+          let drill = Drill::new(repertoire.lines[i].clone(), repertoire.color);
+          match GameTree::from_moves(drill.line.start.clone(), drill.played()) {
+            Ok(loaded) => *tree = loaded,
+            Err(err) => log::error!("invalid line: {err}"),
+          }
+          tree.to_end();
+          panel.status = "Your move.".into();
+          panel.drill = Some(drill);
+          panel.recorded = false;
+        }
+        None => panel.status = "Nothing due today.".into(),
+      }
+    }
+  });
+
+  let Some(drill) = &mut panel.drill else {
+    return;
+  };
+  ui.label(&panel.status);
+  if drill.is_done() {
+    if !panel.recorded {
+      panel.recorded = true;
+      panel.schedule.record(&drill.line, drill.quality(), today);
+      let path = Path::new(&panel.schedule_path);
+      if let Err(err) = panel.schedule.save(path) {
+        log::error!("can't save the schedule: {err:#}");
+      }
+    }
+    return;
+  }
+  let mut played = None;
+  ui.horizontal(|ui| {
+    let field = ui.text_edit_singleline(&mut panel.input);
+    let entered =
+      field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+    if ui.button("Play").clicked() || entered {
+      let input = panel.input.trim();
+      match san::parse_san(drill.position(), input)
+        .or_else(|_| Move::from_uci(input))
+      {
+        Ok(mv) => played = Some(mv),
+        Err(err) => log::warn!("can't play {input:?}: {err}"),
+      }
+      panel.input.clear();
+    }
+    if ui.button("Show").clicked() {
+      played = drill.show();
+    }
+  });
+
+  if let Some(mv) = played {
+    let san = san::to_san(drill.position(), mv);
+    match drill.play(mv, repertoire) {
+      Step::Correct { reply } => {
+        let _ = tree.play(mv);
+        let _ = tree.play(reply);
+        panel.status = format!("{san} is right, your move.");
+      }
+      Step::Done => {
+        let _ = tree.play(mv);
+        panel.status = format!("{san}, end of the line.");
+      }
+      Step::Alternative => {
+        panel.status = format!("{san} is in the repertoire, but not this line.")
+      }
+      Step::Wrong => panel.status = format!("{san} is not in the repertoire."),
+    }
+  }
+}
@@ -0,0 +1,138 @@
+//! The game review window.
+
+use std::path::Path;
+
+use crate::{
+  analysis::Analyzer,
+  board::Color,
+  gametree::GameTree,
+  review::{self, Judgement, Reviewer},
+  search::Engine,
+};
+
+/// Starts a review of the main line with the engine of `engine_path`, or
+/// the built-in one, then shows each player's accuracy and mistakes and a
+/// graph of the evaluation. Clicking the graph shows that position.
+pub fn review_panel(
+  ui: &mut egui::Ui,
+  reviewer: &mut Reviewer,
+  tree: &mut GameTree,
+  engine_path: &str,
+) {
+  match reviewer.poll() {
+    Some(Ok(review)) => {
+      if let Err(err) = review.annotate(tree) {
+        log::error!("failed to annotate the game: {err:#}");
+      }
+    }
+    Some(Err(err)) => log::error!("review failed: {err:#}"),
+    None => {}
+  }
+
+  ui.horizontal(|ui| {
+    ui.label("Depth: ");
+    ui.add(egui::DragValue::new(&mut reviewer.depth).clamp_range(1..=30));
+    let idle = !reviewer.is_running();
+    if ui.add_enabled(idle, egui::Button::new("Review game")).clicked() {
+      let analyzer = match engine_path.trim() {
+        "" => Ok(Analyzer::BuiltIn(Engine::new())),
+        path => Analyzer::uci(Path::new(path)),
+      };
+      match analyzer {
+        Ok(analyzer) => reviewer.start(analyzer, tree),
+        Err(err) => log::error!("can't start the engine: {err:#}"),
+      }
+    }
+  });
+  if reviewer.is_running() {
+    let (done, total) = reviewer.progress();
+    let share = done as f32 / total.max(1) as f32;
+    ui.add(egui::ProgressBar::new(share).text(format!("{done}/{total}")));
+    ui.ctx().request_repaint();
+  }
+  let Some(review) = &reviewer.review else {
+    return;
+  };
+
+  egui::Grid::new("review summary").striped(true).show(ui, |ui| {
+    ui.label("");
+    ui.strong("Accuracy");
+    ui.strong("ACPL");
+    for judgement in Judgement::ALL {
+      ui.strong(judgement.name());
+    }
+    ui.end_row();
+    for color in [Color::White, Color::Black] {
+      ui.label(color.fold("White", "Black"));
+      let accuracy = review.accuracy(color);
+      ui.label(accuracy.map_or("-".into(), |a| format!("{a:.1}%")));
+      let acpl = review.average_cp_loss(color);
+      ui.label(acpl.map_or("-".into(), |acpl| format!("{acpl:.0}")));
+      for judgement in Judgement::ALL {
+        ui.label(review.count(color, judgement).to_string());
+      }
+      ui.end_row();
+    }
+  });
+
+  let size = egui::vec2(ui.available_width(), 80.0);
+  let (response, painter) = ui.allocate_painter(size, egui::Sense::click());
+  let rect = response.rect;
+  painter.rect_filled(rect, 0.0, egui::Color32::from_gray(40));
+  let plies = review.evals.len().max(2) - 1;
+  let x = |ply: usize| rect.left() + rect.width() * ply as f32 / plies as f32;
+  let y = |win: f64| rect.bottom() - rect.height() * win as f32 / 100.0;
+  let mut white_area: Vec<egui::Pos2> = vec![rect.left_bottom()];
+  white_area.extend(
+    review
+      .evals
+      .iter()
+      .enumerate()
+      .map(|(ply, eval)| egui::pos2(x(ply), y(eval.white_win))),
+  );
+  white_area.push(egui::pos2(x(review.evals.len() - 1), rect.bottom()));
+  // drawn as strips, the area is not convex
+  for pair in white_area[1..white_area.len() - 1].windows(2) {
+    let strip = vec![
+      pair[0],
+      pair[1],
+      egui::pos2(pair[1].x, rect.bottom()),
+      egui::pos2(pair[0].x, rect.bottom()),
+    ];
+    let fill = egui::Color32::from_gray(230);
+    painter.add(egui::Shape::convex_polygon(strip, fill, egui::Stroke::NONE));
+  }
+  let middle = egui::Stroke::new(1.0, egui::Color32::GRAY);
+  painter.hline(rect.x_range(), y(50.0), middle);
+  for (ply, mv) in review.moves.iter().enumerate() {
+    let color = match mv.judgement {
+      Some(Judgement::Inaccuracy) => egui::Color32::YELLOW,
+      Some(Judgement::Mistake) => egui::Color32::from_rgb(255, 140, 0),
+      Some(Judgement::Blunder) => egui::Color32::RED,
+      None => continue,
+    };
+    let point = egui::pos2(x(ply + 1), y(review.evals[ply + 1].white_win));
+    painter.circle_filled(point, 3.0, color);
+  }
+  if let Some(ply) = review::ply_of(tree, tree.current()) {
+    if ply < review.evals.len() {
+      let stroke = egui::Stroke::new(1.0, egui::Color32::LIGHT_BLUE);
+      painter.vline(x(ply), rect.y_range(), stroke);
+    }
+  }
+  if let Some(pos) = response.interact_pointer_pos() {
+    let share = (pos.x - rect.left()) / rect.width();
+    let ply = (share * plies as f32).round() as usize;
+    let ply = ply.min(review.moves.len());
+    // the review may be of an earlier main line, follow its moves
+    let mut node = tree.root();
+    for mv in &review.moves[..ply] {
+      let next = tree.node(node).children.iter().copied();
+      match next.clone().find(|&child| tree.node(child).mv == Some(mv.mv)) {
+        Some(child) => node = child,
+        None => break,
+      }
+    }
+    tree.go_to(node);
+  }
+}
@@ -0,0 +1,373 @@
+//! The game server window: lobby, seeks and the games played there.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+use crate::{
+  board::{Color, Move, Piece, Square},
+  client::{RemoteGame, ServerClient},
+  gametree::GameTree,
+  pgn::{self, Outcome},
+  premove::{self, Premoves},
+  protocol::Update,
+  san,
+  tournament::TimeControl,
+  transport::{Encoding, Transport},
+};
+
+use super::game::{control_buttons, control_news, side};
+
+/// State of the game server window.
+pub struct ServerPanel {
+  pub(super) client: Option<ServerClient>,
+  address: String,
+  name: String,
+  transport: Transport,
+  control: String,
+  color: Option<Color>,
+  opponent: String,
+  /// The game on the board.
+  pub(super) shown: Option<u32>,
+  input: String,
+  status: String,
+  premoves: Premoves,
+  /// The game and ply a premove was sent at, until the server plays it.
+  premoved: Option<(u32, usize)>,
+  /// Games played or watched that ended, for the ratings.
+  pub(super) ended: Vec<pgn::Game>,
+}
+
+impl Default for ServerPanel {
+  fn default() -> Self {
+    Self {
+      client: None,
+      address: "127.0.0.1:7879".into(),
+      name: "Player".into(),
+      transport: match cfg!(target_arch = "wasm32") {
+        true => Transport::WebSocket(Encoding::Binary),
+        false => Transport::Tcp,
+      },
+      control: "300+2".into(),
+      color: None,
+      opponent: String::new(),
+      shown: None,
+      input: String::new(),
+      status: String::new(),
+      premoves: Premoves::default(),
+      premoved: None,
+      ended: Vec::new(),
+    }
+  }
+}
+
+impl ServerPanel {
+  /// A piece dragged on the board: our move, or a premove while the
+  /// opponent thinks. False unless a game of ours is shown.
+  pub fn drag(
+    &mut self,
+    from: Square,
+    to: Square,
+  ) -> bool {
+    let Some(client) = &mut self.client else {
+      return false;
+    };
+    let Some(game) = self.shown.and_then(|id| client.games.get(&id)) else {
+      return false;
+    };
+    let Some(color) = game.color.filter(|_| game.result.is_none()) else {
+      return false;
+    };
+    let id = game.info.id;
+    let played = if game.is_our_turn() {
+      premove::dragged(game.position(), from, to)
+        .ok_or_else(|| anyhow::anyhow!("no move from {from} to {to}"))
+        .and_then(|mv| client.play(id, mv))
+    } else {
+      self.premoves.push(game.position(), color, from, to).map(drop)
+    };
+    if let Err(err) = played {
+      self.status = format!("{err:#}");
+    }
+    true
+  }
+
+  /// The pieces premoved in the game shown, where they go.
+  pub fn ghosts(&self) -> Vec<(Square, Piece)> {
+    let client = self.client.as_ref();
+    let game =
+      client.zip(self.shown).and_then(|(client, id)| client.games.get(&id));
+    game.map_or_else(Vec::new, |game| self.premoves.ghosts(game.position()))
+  }
+
+  /// Returns whether there were premoves to cancel.
+  pub fn cancel_premoves(&mut self) -> bool {
+    let any = !self.premoves.is_empty();
+    self.premoves.clear();
+    any
+  }
+}
+
+/// The PGN of a game played or watched on a server.
+fn remote_record(
+  server: &str,
+  game: &RemoteGame,
+) -> pgn::Game {
+  let mut record =
+    game.info.record(server, game.start.clone(), game.moves.clone());
+  if let Some((outcome, reason)) = &game.result {
+    record.outcome = *outcome;
+    record.set_tag("Termination", reason);
+  }
+  record
+}
+
+/// Connects to a game server, lists its seeks and games, and shows a game
+/// played or watched there on the board with its clocks.
+pub fn server_panel(
+  ui: &mut egui::Ui,
+  panel: &mut ServerPanel,
+  tree: &mut GameTree,
+) {
+  let Some(client) = &mut panel.client else {
+    ui.horizontal(|ui| {
+      ui.label("Name: ");
+      ui.text_edit_singleline(&mut panel.name);
+    });
+    ui.horizontal(|ui| {
+      // a page has no sockets but WebSockets
+      if !cfg!(target_arch = "wasm32") {
+        ui.selectable_value(&mut panel.transport, Transport::Tcp, "TCP");
+      }
+      for encoding in Encoding::ALL {
+        let label = format!("WebSocket {encoding}");
+        let transport = Transport::WebSocket(encoding);
+        ui.selectable_value(&mut panel.transport, transport, label);
+      }
+    });
+    ui.horizontal(|ui| {
+      ui.label("Address: ");
+      ui.text_edit_singleline(&mut panel.address);
+      if ui.button("Connect").clicked() {
+        let address = panel.address.trim();
+        match ServerClient::connect(address, panel.name.trim(), panel.transport)
+        {
+          Ok(client) if client.server.is_empty() => {
+            panel.status = format!("Connecting to {address}.");
+            panel.client = Some(client);
+          }
+          Ok(client) => {
+            panel.status = format!("Connected to {}.", client.server);
+            panel.client = Some(client);
+          }
+          Err(err) => panel.status = format!("Can't connect: {err:#}"),
+        }
+      }
+    });
+    ui.label(&panel.status);
+    return;
+  };
+
+  let mut changed = false;
+  for update in client.poll() {
+    match update {
+      Update::Game { info, .. } => {
+        changed |= panel.shown.is_none_or(|shown| shown == info.id);
+        panel.shown.get_or_insert(info.id);
+      }
+      Update::Move { game, .. } => changed |= panel.shown == Some(game),
+      Update::End { game, outcome, reason } => {
+        let remote = client.games.get(&game);
+        if let Some(remote) = remote.filter(|_| outcome != Outcome::Unknown) {
+          panel.ended.push(remote_record(&client.server, remote));
+        }
+        if panel.shown == Some(game) {
+          panel.status = format!("{outcome} by {reason}.");
+        }
+      }
+      Update::Control { game, color, control, .. }
+        if panel.shown == Some(game) =>
+      {
+        panel.status = control_news(side(color), control);
+      }
+      Update::Gone { game, color } if panel.shown == Some(game) => {
+        panel.status = format!("{} left.", side(color));
+      }
+      Update::Back { game, color } if panel.shown == Some(game) => {
+        panel.status = format!("{} is back.", side(color));
+      }
+      Update::Hello { name, .. } => {
+        panel.status = format!("Connected to {name}.")
+      }
+      Update::Error { message } => panel.status = format!("Refused: {message}"),
+      _ => {}
+    }
+  }
+  if !client.is_connected() {
+    panel.status = "The connection was lost.".into();
+    panel.client = None;
+    panel.premoves.clear();
+    return;
+  }
+  // a premove goes the moment the turn comes, once
+  match panel.shown.and_then(|id| client.games.get(&id)) {
+    Some(game) if game.result.is_some() => panel.premoves.clear(),
+    Some(game) if game.is_our_turn() => {
+      let at = (game.info.id, game.moves.len());
+      if panel.premoved != Some(at) {
+        if let Some(mv) = panel.premoves.next(game.position()) {
+          panel.premoved = Some(at);
+          if let Err(err) = client.play(at.0, mv) {
+            panel.status = format!("{err:#}");
+          }
+        }
+      }
+    }
+    _ => {}
+  }
+  ui.ctx().request_repaint_after(std::time::Duration::from_millis(100));
+  ui.label(format!("{} on {}", client.name, client.server));
+
+  let mut request = None;
+  ui.horizontal(|ui| {
+    ui.label("Clock: ");
+    ui.add(egui::TextEdit::singleline(&mut panel.control).desired_width(60.0));
+    ui.selectable_value(&mut panel.color, None, "Any");
+    for color in [Color::White, Color::Black] {
+      ui.selectable_value(&mut panel.color, Some(color), side(color));
+    }
+  });
+  ui.horizontal(|ui| {
+    let seek = ui.button("Seek").clicked();
+    ui.text_edit_singleline(&mut panel.opponent);
+    let challenge = ui.button("Challenge").clicked();
+    if seek || challenge {
+      request = Some(match panel.control.trim().parse() {
+        Ok(control @ TimeControl::Clock { .. }) if seek => {
+          client.seek(control, panel.color)
+        }
+        Ok(control @ TimeControl::Clock { .. }) => {
+          client.challenge(panel.opponent.trim(), control, panel.color)
+        }
+        Ok(_) => Err(anyhow::anyhow!("the clock is seconds+increment")),
+        Err(err) => Err(err),
+      });
+    }
+  });
+
+  ui.separator();
+  let mut seeks: Vec<_> = client.seeks.values().cloned().collect();
+  seeks.sort_by_key(|seek| seek.id);
+  for seek in seeks {
+    ui.horizontal(|ui| {
+      let color = seek.color.map_or("any", side);
+      let to = seek.to.as_ref().map_or(String::new(), |to| format!(" to {to}"));
+      ui.label(format!("{} {} {color}{to}", seek.from, seek.control));
+      let mine = seek.from == client.name;
+      if !mine && ui.button("Accept").clicked() {
+        request = Some(client.accept(seek.id));
+      }
+      let cancel = if mine { "Cancel" } else { "Decline" };
+      if (mine || seek.to.is_some()) && ui.button(cancel).clicked() {
+        request = Some(client.cancel(seek.id));
+      }
+    });
+  }
+  let listed: Vec<_> = client.listed.values().cloned().collect();
+  for info in listed {
+    ui.horizontal(|ui| {
+      ui.label(format!("{} - {} {}", info.white, info.black, info.control));
+      if !client.games.contains_key(&info.id) && ui.button("Watch").clicked() {
+        panel.shown = Some(info.id);
+        request = Some(client.watch(info.id));
+      }
+    });
+  }
+
+  ui.separator();
+  let ids: Vec<u32> = client.games.keys().copied().collect();
+  ui.horizontal_wrapped(|ui| {
+    for id in ids {
+      let game = &client.games[&id];
+      let label = format!("{} - {}", game.info.white, game.info.black);
+      if ui.selectable_label(panel.shown == Some(id), label).clicked() {
+        panel.shown = Some(id);
+        panel.premoves.clear();
+        changed = true;
+      }
+    }
+  });
+  let mut played = None;
+  let mut controlled = None;
+  let mut closed = None;
+  if let Some(game) = panel.shown.and_then(|id| client.games.get(&id)) {
+    let now = Instant::now();
+    for color in [Color::White, Color::Black] {
+      let clock = game.clock(color, now).as_secs();
+      let name = color.fold(&game.info.white, &game.info.black);
+      let gone = if game.away[color.index()] { " (gone)" } else { "" };
+      ui.label(format!("{name} {}:{:02}{gone}", clock / 60, clock % 60));
+    }
+    if let Some((outcome, reason)) = &game.result {
+      ui.label(format!("{outcome} by {reason}"));
+    }
+    if let Some((offer, by)) = game.offer() {
+      ui.label(control_news(side(by), offer));
+    }
+    if let Some(control) = control_buttons(ui, |control| game.allows(control)) {
+      controlled = Some((game.info.id, control));
+    }
+    ui.horizontal(|ui| {
+      if game.is_our_turn() {
+        let field = ui.text_edit_singleline(&mut panel.input);
+        let entered =
+          field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+        if ui.button("Play").clicked() || entered {
+          let input = panel.input.trim();
+          match san::parse_san(game.position(), input)
+            .or_else(|_| Move::from_uci(input))
+          {
+            Ok(mv) => played = Some((game.info.id, mv)),
+            Err(err) => panel.status = format!("Can't play {input:?}: {err}"),
+          }
+          panel.input.clear();
+        }
+      }
+      if (game.color.is_none() || game.result.is_some())
+        && ui.button("Close").clicked()
+      {
+        closed = Some(game.info.id);
+      }
+    });
+    if changed {
+      match GameTree::from_moves(game.start.clone(), &game.moves) {
+        Ok(loaded) => *tree = loaded,
+        Err(err) => log::error!("invalid server game: {err}"),
+      }
+      tree.to_end();
+    }
+  }
+  if let Some((game, mv)) = played {
+    request = Some(client.play(game, mv));
+  }
+  if let Some((game, control)) = controlled {
+    request = Some(client.control(game, control));
+  }
+  if let Some(game) = closed {
+    panel.shown = None;
+    panel.premoves.clear();
+    request = Some(client.close(game));
+  }
+  if let Some(Err(err)) = request {
+    panel.status = format!("{err:#}");
+  }
+  ui.label(&panel.status);
+  if ui.button("Leave").clicked() {
+    panel.client = None;
+    panel.shown = None;
+    panel.premoves.clear();
+    panel.status = "Left the server.".into();
+  }
+}
@@ -0,0 +1,198 @@
+//! The game tree window: moves, variations and annotations.
+
+use crate::{
+  board::{Color, Move},
+  eco,
+  gametree::{self, GameTree, NodeId},
+  pgn, san,
+};
+
+/// Glyphs offered as buttons for the shown move.
+const NAG_BUTTONS: [u8; 6] = [1, 2, 3, 4, 5, 6];
+
+/// Navigation and editing buttons, the moves with their variations, the
+/// annotations of the shown move, a field to enter new moves and the game
+/// as PGN. `record` holds the tags and result of the game.
+pub fn game_tree(
+  ui: &mut egui::Ui,
+  record: &mut pgn::Game,
+  tree: &mut GameTree,
+  input: &mut String,
+  pgn_text: &mut String,
+) {
+  ui.horizontal(|ui| {
+    if ui.button("|<").clicked() {
+      tree.to_start();
+    }
+    if ui.button("<").clicked() {
+      tree.back();
+    }
+    if ui.button(">").clicked() {
+      tree.forward();
+    }
+    if ui.button(">|").clicked() {
+      tree.to_end();
+    }
+    let current = tree.current();
+    let in_variation = !tree.is_mainline(current);
+    if ui.add_enabled(in_variation, egui::Button::new("Promote")).clicked() {
+      tree.promote(current);
+    }
+    let has_move = current != tree.root();
+    if ui.add_enabled(has_move, egui::Button::new("Delete")).clicked() {
+      tree.delete(current);
+    }
+  });
+
+  let mut clicked = None;
+  egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+    ui.horizontal_wrapped(|ui| {
+      let root = tree.root();
+      tree_line(ui, tree, root, true, true, &mut clicked);
+    });
+  });
+  if let Some(id) = clicked {
+    tree.go_to(id);
+  }
+  if let Some(opening) = eco::classify(tree.start(), &tree.played()) {
+    ui.label(format!("{} {}", opening.eco, opening.name));
+  }
+
+  let current = tree.current();
+  if current != tree.root() {
+    let annotations = tree.annotations_mut(current);
+    ui.horizontal(|ui| {
+      for nag in NAG_BUTTONS {
+        let on = annotations.nags.contains(&nag);
+        if ui.selectable_label(on, gametree::nag_text(nag)).clicked() {
+          if on {
+            annotations.nags.retain(|&other| other != nag);
+          } else {
+            // one move quality glyph at a time
+            annotations.nags.retain(|other| !NAG_BUTTONS.contains(other));
+            annotations.nags.insert(0, nag);
+          }
+        }
+      }
+      if let Some(clock) = annotations.clock {
+        ui.label(format!("clock {}", gametree::format_clock(clock)));
+      }
+    });
+  }
+  let annotations = tree.annotations_mut(current);
+  let marked =
+    !annotations.arrows.is_empty() || !annotations.highlights.is_empty();
+  if ui.add_enabled(marked, egui::Button::new("Clear marks")).clicked() {
+    annotations.arrows.clear();
+    annotations.highlights.clear();
+  }
+  ui.add(
+    egui::TextEdit::multiline(&mut tree.annotations_mut(current).comment)
+      .hint_text("Comment")
+      .desired_rows(2),
+  );
+
+  ui.horizontal(|ui| {
+    let field = ui.text_edit_singleline(input);
+    let entered =
+      field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+    if ui.button("Play").clicked() || entered {
+      let position = tree.position();
+      let mv = san::parse_san(position, input)
+        .or_else(|_| Move::from_uci(input.trim()));
+      match mv.and_then(|mv| tree.play(mv)) {
+        Ok(_) => input.clear(),
+        Err(err) => log::warn!("can't play {input:?}: {err}"),
+      }
+    }
+  });
+
+  ui.collapsing("PGN", |ui| {
+    ui.horizontal(|ui| {
+      if ui.button("Load").clicked() {
+        match pgn::parse_trees(pgn_text) {
+          Ok(games) => match games.into_iter().next() {
+            Some((game, loaded)) => (*record, *tree) = (game, loaded),
+            None => log::warn!("no game in the PGN text"),
+          },
+          Err(err) => log::warn!("can't load PGN: {err:#}"),
+        }
+      }
+      if ui.button("Export").clicked() {
+        let end = tree.mainline().last().copied().unwrap_or(tree.root());
+        record.moves = tree.moves_to(end);
+        eco::tag(record);
+        *pgn_text = record.to_pgn_with(tree);
+      }
+    });
+    egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+      ui.add(
+        egui::TextEdit::multiline(pgn_text)
+          .code_editor()
+          .desired_width(f32::INFINITY),
+      );
+    });
+  });
+}
+
+/// Labels for the line after `id` with its variations in parentheses,
+/// as the moves appear in PGN. Sets `clicked` to the move clicked on.
+fn tree_line(
+  ui: &mut egui::Ui,
+  tree: &GameTree,
+  id: NodeId,
+  mut numbered: bool,
+  mainline: bool,
+  clicked: &mut Option<NodeId>,
+) {
+  let mut node = id;
+  while let Some((&main, variations)) = tree.node(node).children.split_first() {
+    numbered = tree_move(ui, tree, main, numbered, mainline, clicked);
+    for &variation in variations {
+      ui.weak("(");
+      let numbered = tree_move(ui, tree, variation, true, false, clicked);
+      tree_line(ui, tree, variation, numbered, false, clicked);
+      ui.weak(")");
+    }
+    numbered |= !variations.is_empty();
+    node = main;
+  }
+}
+
+/// Label of one move with its number and glyphs, then its comment, main
+/// line moves in bold. Returns whether the next move needs its number
+/// repeated.
+fn tree_move(
+  ui: &mut egui::Ui,
+  tree: &GameTree,
+  id: NodeId,
+  numbered: bool,
+  mainline: bool,
+  clicked: &mut Option<NodeId>,
+) -> bool {
+  let node = tree.node(id);
+  let position = &tree.node(node.parent.unwrap()).position;
+  let number = position.fullmove_number();
+  if position.side_to_move() == Color::White {
+    ui.label(format!("{number}."));
+  } else if numbered {
+    ui.label(format!("{number}..."));
+  }
+  let mut text = san::to_san(position, node.mv.unwrap());
+  for &nag in &node.annotations.nags {
+    text.push_str(&gametree::nag_text(nag));
+  }
+  let label = if mainline {
+    egui::RichText::new(text).strong()
+  } else {
+    egui::RichText::new(text)
+  };
+  if ui.selectable_label(tree.current() == id, label).clicked() {
+    *clicked = Some(id);
+  }
+  let comment = &node.annotations.comment;
+  if !comment.is_empty() {
+    ui.weak(comment);
+  }
+  !comment.is_empty()
+}
@@ -0,0 +1,386 @@
+//! WebSocket connections (RFC 6455) over TCP, both ends of the handshake
+//! and the framing, enough to carry the game protocol to and from a
+//! browser. Messages are whole: fragments are put together on reading and
+//! never made on writing. Pings are answered while reading.
+
+use std::{
+  io::{BufRead, BufReader, Read, Write},
+  net::{Shutdown, TcpStream},
+  sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, bail, ensure};
+
+/// Appended to the client's key to prove the server read it.
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Messages larger than this are refused.
+const MAX_MESSAGE: usize = 1 << 20;
+
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xa;
+
+pub use crate::transport::Data;
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+  let mut h: [u32; 5] =
+    [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+  let mut message = data.to_vec();
+  message.push(0x80);
+  while message.len() % 64 != 56 {
+    message.push(0);
+  }
+  message.extend((data.len() as u64 * 8).to_be_bytes());
+  for block in message.chunks(64) {
+    let mut w = [0u32; 80];
+    for (i, word) in block.chunks(4).enumerate() {
+      w[i] = u32::from_be_bytes(word.try_into().unwrap());
+    }
+    for i in 16..80 {
+      w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e] = h;
+    for (i, &word) in w.iter().enumerate() {
+      let (f, k) = match i {
+        0..=19 => ((b & c) | (!b & d), 0x5a827999),
+        20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+        40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+        _ => (b ^ c ^ d, 0xca62c1d6),
+      };
+      let temp = a
+        .rotate_left(5)
+        .wrapping_add(f)
+        .wrapping_add(e)
+        .wrapping_add(k)
+        .wrapping_add(word);
+      (e, d, c, b, a) = (d, c, b.rotate_left(30), a, temp);
+    }
+    for (h, x) in h.iter_mut().zip([a, b, c, d, e]) {
+      *h = h.wrapping_add(x);
+    }
+  }
+  let mut digest = [0; 20];
+  for (bytes, word) in digest.chunks_mut(4).zip(h) {
+    bytes.copy_from_slice(&word.to_be_bytes());
+  }
+  digest
+}
+
+fn base64(data: &[u8]) -> String {
+  const ALPHABET: &[u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+  let mut encoded = String::new();
+  for chunk in data.chunks(3) {
+    let n = chunk
+      .iter()
+      .enumerate()
+      .fold(0u32, |n, (i, &byte)| n | (byte as u32) << (16 - 8 * i));
+    for i in 0..4 {
+      if i <= chunk.len() {
+        encoded.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+      } else {
+        encoded.push('=');
+      }
+    }
+  }
+  encoded
+}
+
+/// The `Sec-WebSocket-Accept` answering a `Sec-WebSocket-Key`.
+pub fn accept_key(key: &str) -> String {
+  base64(&sha1(format!("{key}{GUID}").as_bytes()))
+}
+
+/// Reads an HTTP head: the first line and the headers, names lowercased.
+fn read_head(
+  reader: &mut impl BufRead
+) -> anyhow::Result<(String, Vec<(String, String)>)> {
+  let mut lines = Vec::new();
+  loop {
+    let mut line = String::new();
+    ensure!(reader.read_line(&mut line)? > 0, "connection closed in handshake");
+    let line = line.trim_end().to_string();
+    if line.is_empty() {
+      break;
+    }
+    ensure!(lines.len() < 100, "handshake too long");
+    lines.push(line);
+  }
+  let mut lines = lines.into_iter();
+  let first = lines.next().ok_or_else(|| anyhow!("empty handshake"))?;
+  let headers = lines
+    .filter_map(|line| {
+      let (name, value) = line.split_once(':')?;
+      Some((name.trim().to_ascii_lowercase(), value.trim().to_string()))
+    })
+    .collect();
+  Ok((first, headers))
+}
+
+fn header<'a>(
+  headers: &'a [(String, String)],
+  name: &str,
+) -> Option<&'a str> {
+  headers
+    .iter()
+    .find(|(header, _)| header == name)
+    .map(|(_, value)| value.as_str())
+}
+
+/// Whether a comma separated header value lists `token`.
+fn lists(
+  value: Option<&str>,
+  token: &str,
+) -> bool {
+  value.is_some_and(|value| {
+    value.split(',').any(|item| item.trim().eq_ignore_ascii_case(token))
+  })
+}
+
+/// The writing half, shared with the reading half for answering pings.
+#[derive(Clone)]
+pub struct Sender {
+  stream: Arc<Mutex<TcpStream>>,
+  /// Clients mask what they send, servers don't.
+  masked: bool,
+}
+
+impl Sender {
+  fn frame(
+    &self,
+    opcode: u8,
+    payload: &[u8],
+  ) -> anyhow::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    let mask_bit = if self.masked { 0x80 } else { 0 };
+    match payload.len() {
+      len @ 0..=125 => frame.push(mask_bit | len as u8),
+      len @ 126..=0xffff => {
+        frame.push(mask_bit | 126);
+        frame.extend((len as u16).to_be_bytes());
+      }
+      len => {
+        frame.push(mask_bit | 127);
+        frame.extend((len as u64).to_be_bytes());
+      }
+    }
+    if self.masked {
+      let mask: [u8; 4] = fastrand::u32(..).to_be_bytes();
+      frame.extend(mask);
+      frame.extend(payload.iter().zip(mask.iter().cycle()).map(|(b, m)| b ^ m));
+    } else {
+      frame.extend(payload);
+    }
+    let mut stream =
+      self.stream.lock().map_err(|_| anyhow!("writer poisoned"))?;
+    stream.write_all(&frame)?;
+    stream.flush()?;
+    Ok(())
+  }
+
+  pub fn send(
+    &self,
+    data: &Data,
+  ) -> anyhow::Result<()> {
+    match data {
+      Data::Text(text) => self.frame(TEXT, text.as_bytes()),
+      Data::Binary(bytes) => self.frame(BINARY, bytes),
+    }
+  }
+
+  /// Sends a normal close frame and shuts the socket down both ways,
+  /// which also ends the [`Receiver`] of this connection.
+  pub fn close(&self) {
+    let _ = self.frame(CLOSE, &1000u16.to_be_bytes());
+    if let Ok(stream) = self.stream.lock() {
+      let _ = stream.shutdown(Shutdown::Both);
+    }
+  }
+}
+
+pub struct Receiver {
+  reader: BufReader<TcpStream>,
+  sender: Sender,
+}
+
+impl Receiver {
+  /// Reads frames until a whole message is in, answering pings on the
+  /// way; `None` on a close frame or the end of the stream.
+  pub fn recv(&mut self) -> anyhow::Result<Option<Data>> {
+    let mut message: Option<(u8, Vec<u8>)> = None;
+    loop {
+      let mut head = [0; 2];
+      match self.reader.read_exact(&mut head) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+          return Ok(None)
+        }
+        Err(err) => return Err(err.into()),
+      }
+      let fin = head[0] & 0x80 != 0;
+      let opcode = head[0] & 0x0f;
+      let masked = head[1] & 0x80 != 0;
+      ensure!(
+        masked != self.sender.masked,
+        "frames from a {} must {}be masked",
+        if self.sender.masked { "server" } else { "client" },
+        if self.sender.masked { "not " } else { "" }
+      );
+      let len = match head[1] & 0x7f {
+        126 => {
+          let mut len = [0; 2];
+          self.reader.read_exact(&mut len)?;
+          u16::from_be_bytes(len) as u64
+        }
+        127 => {
+          let mut len = [0; 8];
+          self.reader.read_exact(&mut len)?;
+          u64::from_be_bytes(len)
+        }
+        len => len as u64,
+      };
+      // control frames come whole and short, RFC 6455 section 5.5
+      if opcode >= CLOSE {
+        ensure!(fin, "fragmented control frame");
+        ensure!(len <= 125, "control frame of {len} bytes");
+      }
+      let sofar = message.as_ref().map_or(0, |(_, data)| data.len());
+      ensure!(
+        len <= (MAX_MESSAGE - sofar) as u64,
+        "message over {MAX_MESSAGE} bytes"
+      );
+      let mut mask = [0; 4];
+      if masked {
+        self.reader.read_exact(&mut mask)?;
+      }
+      let mut payload = vec![0; len as usize];
+      self.reader.read_exact(&mut payload)?;
+      if masked {
+        for (byte, m) in payload.iter_mut().zip(mask.iter().cycle()) {
+          *byte ^= m;
+        }
+      }
+
+      match opcode {
+        PING => self.sender.frame(PONG, &payload)?,
+        PONG => {}
+        CLOSE => {
+          self.sender.close();
+          return Ok(None);
+        }
+        CONTINUATION => {
+          let (_, data) = message
+            .as_mut()
+            .ok_or_else(|| anyhow!("continuation of nothing"))?;
+          data.extend(payload);
+        }
+        TEXT | BINARY => {
+          ensure!(message.is_none(), "new message inside a fragmented one");
+          message = Some((opcode, payload));
+        }
+        opcode => bail!("unknown opcode {opcode:#x}"),
+      }
+      if fin && opcode < CLOSE {
+        let Some((opcode, data)) = message.take() else {
+          continue;
+        };
+        return Ok(Some(match opcode {
+          TEXT => Data::Text(String::from_utf8(data)?),
+          _ => Data::Binary(data),
+        }));
+      }
+    }
+  }
+}
+
+fn split(
+  reader: BufReader<TcpStream>,
+  masked: bool,
+) -> anyhow::Result<(Sender, Receiver)> {
+  let stream = reader.get_ref().try_clone()?;
+  let sender = Sender { stream: Arc::new(Mutex::new(stream)), masked };
+  Ok((sender.clone(), Receiver { reader, sender }))
+}
+
+/// Takes the handshake of a client asking for one of `protocols`,
+/// returning the connection and the protocol agreed on, if any.
+pub fn accept(
+  stream: TcpStream,
+  protocols: &[&str],
+) -> anyhow::Result<(Sender, Receiver, Option<String>)> {
+  let mut writer = stream.try_clone()?;
+  let mut reader = BufReader::new(stream);
+  let (request, headers) = read_head(&mut reader)?;
+  let key = header(&headers, "sec-websocket-key");
+  let upgrade = request.starts_with("GET ")
+    && lists(header(&headers, "upgrade"), "websocket")
+    && lists(header(&headers, "connection"), "upgrade")
+    && header(&headers, "sec-websocket-version") == Some("13");
+  let Some(key) = key.filter(|_| upgrade) else {
+    write!(
+      writer,
+      "HTTP/1.1 400 Bad Request\r\nSec-WebSocket-Version: 13\r\n\
+       Content-Length: 0\r\n\r\n"
+    )?;
+    bail!("not a WebSocket handshake: {request}");
+  };
+  let protocol = header(&headers, "sec-websocket-protocol").and_then(|asked| {
+    asked
+      .split(',')
+      .map(str::trim)
+      .find(|asked| protocols.contains(asked))
+      .map(String::from)
+  });
+  let mut response = format!(
+    "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+     Connection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n",
+    accept_key(key)
+  );
+  if let Some(protocol) = &protocol {
+    response += &format!("Sec-WebSocket-Protocol: {protocol}\r\n");
+  }
+  response += "\r\n";
+  writer.write_all(response.as_bytes())?;
+  let (sender, receiver) = split(reader, false)?;
+  Ok((sender, receiver, protocol))
+}
+
+/// Opens a connection to `host` over `stream` asking for `protocol`,
+/// which the server must agree to.
+pub fn connect(
+  stream: TcpStream,
+  host: &str,
+  path: &str,
+  protocol: Option<&str>,
+) -> anyhow::Result<(Sender, Receiver)> {
+  let key = base64(&fastrand::u128(..).to_be_bytes());
+  let mut request = format!(
+    "GET {path} HTTP/1.1\r\nHost: {host}\r\nUpgrade: websocket\r\n\
+     Connection: Upgrade\r\nSec-WebSocket-Key: {key}\r\n\
+     Sec-WebSocket-Version: 13\r\n"
+  );
+  if let Some(protocol) = protocol {
+    request += &format!("Sec-WebSocket-Protocol: {protocol}\r\n");
+  }
+  request += "\r\n";
+  let mut writer = stream.try_clone()?;
+  writer.write_all(request.as_bytes())?;
+  let mut reader = BufReader::new(stream);
+  let (status, headers) = read_head(&mut reader)?;
+  ensure!(
+    status.split(' ').nth(1) == Some("101"),
+    "the server refused the WebSocket: {status}"
+  );
+  ensure!(
+    header(&headers, "sec-websocket-accept") == Some(&accept_key(&key)),
+    "the server answered another handshake"
+  );
+  if let Some(protocol) = protocol {
+    let agreed = header(&headers, "sec-websocket-protocol");
+    ensure!(agreed == Some(protocol), "the server does not speak {protocol}");
+  }
+  split(reader, true)
+}
@@ -0,0 +1,98 @@
+use chess::{
+  analysis::{self, Analysis},
+  board::{Color, Move, Position},
+  san,
+  search::{Engine, Limits, Score, SearchEvent},
+  uci,
+};
+
+#[test]
+fn test_san_round_trip() {
+  let mut position = Position::startpos();
+  let moves = ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Bxc6", "dxc6", "O-O"];
+  for text in moves {
+    let mv = san::parse_san(&position, text).unwrap();
+    assert_eq!(san::to_san(&position, mv), text);
+    position.play(mv);
+  }
+}
+
+#[test]
+fn test_san_disambiguation_and_mate() {
+  let fen = "7k/8/8/8/8/8/6R1/R5K1 w - - 0 1";
+  let position = Position::from_fen(fen).unwrap();
+  let mv = san::parse_san(&position, "Rga2").unwrap();
+  assert_eq!(mv, Move::from_uci("g2a2").unwrap());
+  assert_eq!(san::to_san(&position, mv), "Rga2");
+  let mate = Move::from_uci("a1a8").unwrap();
+  assert_eq!(san::to_san(&position, mate), "Ra8+");
+  assert!(san::parse_san(&position, "R2").is_err());
+}
+
+#[test]
+fn test_line_numbers() {
+  let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+  let position = Position::from_fen(fen).unwrap();
+  let moves = ["e7e5", "g1f3"].map(|mv| Move::from_uci(mv).unwrap());
+  assert_eq!(san::line(&position, &moves), "1... e5 2. Nf3");
+}
+
+#[test]
+fn test_parse_info() {
+  let line = "info depth 12 seldepth 18 multipv 2 score mate -3 nodes 4000 \
+              nps 2000 hashfull 5 time 2000 pv e2e4 e7e5";
+  let info = uci::parse_info(line).unwrap();
+  assert_eq!(info.depth, 12);
+  assert_eq!(info.multipv, 2);
+  assert_eq!(info.score, Score::Mate(-3));
+  assert_eq!(info.nodes, 4000);
+  assert_eq!(info.pv.len(), 2);
+  assert!(uci::parse_info("info string hello").is_none());
+}
+
+#[test]
+fn test_multi_pv_lines_are_distinct() {
+  let mut engine = Engine::new();
+  engine.set_multi_pv(3);
+  let events = engine.start(Position::startpos(), Vec::new(), Limits::depth(4));
+  let mut firsts = [None; 3];
+  for event in events {
+    match event {
+      SearchEvent::Info(info) => {
+        firsts[info.multipv - 1] = info.pv.first().copied()
+      }
+      SearchEvent::Done(result) => {
+        assert_eq!(result.best_move, firsts[0]);
+        break;
+      }
+    }
+  }
+  let firsts: Vec<Move> = firsts.into_iter().map(Option::unwrap).collect();
+  assert_ne!(firsts[0], firsts[1]);
+  assert_ne!(firsts[0], firsts[2]);
+  assert_ne!(firsts[1], firsts[2]);
+}
+
+#[test]
+fn test_scores_for_white() {
+  let black_winning = analysis::white_pov(Score::Cp(150), Color::Black);
+  assert_eq!(black_winning, Score::Cp(-150));
+  assert_eq!(analysis::format_score(black_winning), "-1.50");
+  assert_eq!(analysis::format_score(Score::Mate(3)), "M3");
+  assert_eq!(analysis::format_score(Score::Mate(-2)), "-M2");
+  assert_eq!(analysis::white_share(Score::Cp(0)), 0.5);
+  assert!(analysis::white_share(Score::Cp(300)) > 0.8);
+}
+
+#[test]
+fn test_analysis_follows_position() {
+  let mut analysis = Analysis::default();
+  let start = Position::startpos();
+  let e4 = [Move::from_uci("e2e4").unwrap()];
+  analysis.set_enabled(true, &start, &[]).unwrap();
+  assert!(analysis.is_searching());
+  analysis.follow(&start, &e4).unwrap();
+  assert_eq!(analysis.position(), &start.played(e4[0]));
+  analysis.set_enabled(false, &start, &e4).unwrap();
+  assert!(analysis.lines().is_empty());
+}
@@ -0,0 +1,75 @@
+use chess::board::{Color, Move, Position};
+
+fn perft(
+  fen: &str,
+  depth: u32,
+) -> u64 {
+  Position::from_fen(fen).unwrap().perft(depth)
+}
+
+#[test]
+fn test_perft() {
+  // reference numbers from https://www.chessprogramming.org/Perft_Results
+  let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+  assert_eq!(perft(start, 4), 197_281);
+  let kiwipete =
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+  assert_eq!(perft(kiwipete, 3), 97_862);
+  let endgame = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+  assert_eq!(perft(endgame, 5), 674_624);
+  let promotions =
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+  assert_eq!(perft(promotions, 4), 422_333);
+  let talkchess = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+  assert_eq!(perft(talkchess, 3), 62_379);
+}
+
+#[test]
+fn test_fen_round_trip() {
+  let fens = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+  ];
+  for fen in fens {
+    assert_eq!(Position::from_fen(fen).unwrap().to_fen(), fen);
+  }
+}
+
+#[test]
+fn test_mating_material() {
+  let can_mate =
+    |fen: &str, color| Position::from_fen(fen).unwrap().can_mate(color);
+  // bare king, and a lone knight against queens only
+  assert!(!can_mate("8/8/8/4k3/8/8/8/4K2Q w - - 0 1", Color::Black));
+  assert!(!can_mate("8/8/8/4k3/8/8/8/4K1nQ w - - 0 1", Color::Black));
+  // a knight with something to block its king's flight
+  assert!(can_mate("8/8/8/4k3/8/8/8/4K1nR w - - 0 1", Color::Black));
+  assert!(!can_mate("8/8/8/4k3/8/8/8/4K3 w - - 0 1", Color::White));
+  assert!(can_mate("8/8/8/4k3/8/8/8/4KQ2 w - - 0 1", Color::White));
+  // bishops of one shade need a knight, pawn or other bishop to mate
+  assert!(!can_mate("8/8/8/4k3/8/8/8/2b1K2R w - - 0 1", Color::Black));
+  assert!(can_mate("8/8/8/4k3/8/8/8/2b1KB2 w - - 0 1", Color::Black));
+  assert!(!can_mate("8/8/8/4k3/8/8/8/2b1K1B1 w - - 0 1", Color::Black));
+  assert!(can_mate("8/8/8/4k3/8/8/8/1bb1K3 w - - 0 1", Color::Black));
+}
+
+#[test]
+fn test_polyglot_hash() {
+  // reference keys from the Polyglot book format description
+  let mut position = Position::startpos();
+  assert_eq!(position.hash(), 0x463b96181691fc9c);
+  let keys = [
+    ("e2e4", 0x823c9b50fd114196),
+    ("d7d5", 0x0756b94461c50fb0),
+    ("e4e5", 0x662fafb965db29d4),
+    ("f7f5", 0x22a48b5a8e47ff78),
+    ("e1e2", 0x652a607ca3f242c1),
+    ("e8f7", 0x00fdd303c946bdd9),
+  ];
+  for (uci, key) in keys {
+    position.play(Move::from_uci(uci).unwrap());
+    assert_eq!(position.hash(), key, "after {uci}");
+    assert_eq!(Position::from_fen(&position.to_fen()).unwrap().hash(), key);
+  }
+}
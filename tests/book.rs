@@ -0,0 +1,88 @@
+use chess::{
+  board::{Move, Position},
+  book::{self, Book, BuildOptions, Selection},
+  pgn::{self, Outcome},
+  search::{Engine, Limits},
+};
+
+const GAMES: &str = r#"
+[Event "first"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 {a comment} Nc6 (2... d6 3. d4) 3. Bc4 Bc5 4. O-O Nf6 $1 1-0
+
+[Event "second"]
+[Result "1/2-1/2"]
+
+1.e4 e5 2.Nf3 Nf6 1/2-1/2
+
+[Event "third"]
+[Result "0-1"]
+
+1. d4 d5 0-1
+"#;
+
+#[test]
+fn test_pgn_main_line() {
+  let games = pgn::parse(GAMES).unwrap();
+  assert_eq!(games.len(), 3);
+  assert_eq!(games[0].tag("Event"), Some("first"));
+  assert_eq!(games[0].moves.len(), 8);
+  assert_eq!(games[0].moves[6], Move::from_uci("e1g1").unwrap());
+  assert_eq!(games[1].outcome, Outcome::Draw);
+  assert_eq!(games[2].outcome, Outcome::BlackWins);
+
+  let again = pgn::parse(&games[0].to_pgn()).unwrap();
+  assert_eq!(again[0].moves, games[0].moves);
+  assert_eq!(again[0].tags, games[0].tags);
+}
+
+#[test]
+fn test_build_weights() {
+  let games = pgn::parse(GAMES).unwrap();
+  let book = Book::build(&games, &BuildOptions::default());
+  let start = Position::startpos();
+  // white won once with e4 and drew once, d4 lost
+  let moves = book.moves(&start);
+  assert_eq!(moves, vec![(Move::from_uci("e2e4").unwrap(), 3)]);
+
+  let options = BuildOptions { min_games: 2, ..Default::default() };
+  let book = Book::build(&games, &options);
+  let mut position = start.clone();
+  for mv in ["e2e4", "e7e5", "g1f3"] {
+    position.play(Move::from_uci(mv).unwrap());
+  }
+  // Nc6 and Nf6 were played once each
+  assert!(book.moves(&position).is_empty());
+}
+
+#[test]
+fn test_castling_round_trip() {
+  let games = pgn::parse(GAMES).unwrap();
+  let book = Book::build(&games, &BuildOptions::default());
+  let book = Book::from_bytes(&book.to_bytes()).unwrap();
+  let game = &games[0];
+  let mut position = game.start.clone();
+  for &mv in &game.moves[..6] {
+    position.play(mv);
+  }
+  let castles = Move::from_uci("e1g1").unwrap();
+  assert_eq!(book.pick(&position, Selection::Best), Some(castles));
+  // the raw entry is the king taking its rook: e1h1 is 4 << 6 | 7
+  let bytes = book.to_bytes();
+  let key = book::key(&position).to_be_bytes();
+  let entry = bytes.chunks_exact(16).find(|entry| entry[..8] == key).unwrap();
+  assert_eq!(u16::from_be_bytes([entry[8], entry[9]]), 4 << 6 | 7);
+}
+
+#[test]
+fn test_engine_plays_from_book() {
+  let games = pgn::parse(GAMES).unwrap();
+  let book = Book::build(&games, &BuildOptions::default());
+  let mut engine = Engine::new();
+  engine.set_book(Some((book, Selection::Weighted)));
+  let result =
+    engine.search(Position::startpos(), Vec::new(), Limits::depth(1));
+  assert_eq!(result.best_move, Some(Move::from_uci("e2e4").unwrap()));
+  assert!(result.info.is_none());
+}
@@ -0,0 +1,170 @@
+use std::{
+  thread,
+  time::{Duration, Instant},
+};
+
+use chess::{
+  board::{Move, Position},
+  broadcast::{Broadcast, Viewer},
+  gametree::GameTree,
+  pgn::{Game, Outcome},
+  protocol::{Relay, VERSION},
+};
+
+fn mv(uci: &str) -> Move {
+  Move::from_uci(uci).unwrap()
+}
+
+#[test]
+fn test_relay_round_trip() {
+  let messages = [
+    Relay::Hello { version: VERSION, name: "Club championship".into() },
+    Relay::Tag { name: "White".into(), value: "Ann Smith".into() },
+    Relay::Start {
+      start: Position::from_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap(),
+    },
+    Relay::Move {
+      ply: 0,
+      mv: mv("e2e4"),
+      nags: Vec::new(),
+      comment: "".into(),
+    },
+    Relay::Move {
+      ply: 1,
+      mv: mv("c7c5"),
+      nags: vec![1, 14],
+      comment: "[%clk 0:29:52] The Sicilian!".into(),
+    },
+    Relay::Annotate { ply: 1, nags: vec![2], comment: String::new() },
+    Relay::Truncate { ply: 1 },
+    Relay::Result { outcome: Outcome::WhiteWins },
+    Relay::Bye,
+  ];
+  for message in messages {
+    assert_eq!(message.to_string().parse::<Relay>().unwrap(), message);
+  }
+  assert!("move 0 e2e4 x".parse::<Relay>().is_err());
+  assert!("result maybe".parse::<Relay>().is_err());
+}
+
+/// Publishes and polls until the viewer has caught up with `tree`.
+fn catch_up(
+  broadcast: &mut Broadcast,
+  viewer: &mut Viewer,
+  tree: &GameTree,
+  record: &Game,
+) {
+  let wanted: Vec<_> = tree
+    .mainline()
+    .into_iter()
+    .map(|id| tree.node(id).annotations.clone())
+    .collect();
+  let deadline = Instant::now() + Duration::from_secs(5);
+  while Instant::now() < deadline {
+    broadcast.poll();
+    broadcast.publish(tree, record);
+    viewer.poll();
+    let seen = viewer.tree();
+    let annotations: Vec<_> = seen
+      .mainline()
+      .into_iter()
+      .map(|id| seen.node(id).annotations.clone())
+      .collect();
+    if viewer.record().moves == tree.moves_to(*tree.mainline().last().unwrap())
+      && annotations == wanted
+      && viewer.outcome() == record.outcome
+    {
+      return;
+    }
+    thread::sleep(Duration::from_millis(5));
+  }
+  panic!("the viewer is at {:?}", viewer.record().moves);
+}
+
+fn game() -> (GameTree, Game) {
+  let mut tree = GameTree::new(Position::startpos());
+  for uci in ["e2e4", "c7c5", "g1f3"] {
+    tree.play(mv(uci)).unwrap();
+  }
+  let first = tree.mainline()[0];
+  tree.annotations_mut(first).add_comment("[%clk 0:29:40]");
+  let mut record = Game::default();
+  record.set_tag("White", "Ann");
+  record.set_tag("Black", "Bob");
+  (tree, record)
+}
+
+#[test]
+fn test_viewers_follow_the_game() {
+  let (mut tree, mut record) = game();
+  let mut broadcast = Broadcast::host("127.0.0.1:0", "Club").unwrap();
+  let addr = broadcast.local_addr().unwrap();
+  broadcast.publish(&tree, &record);
+
+  // a late joiner gets the game so far with the annotations
+  let mut ann = Viewer::connect(addr).unwrap();
+  catch_up(&mut broadcast, &mut ann, &tree, &record);
+  assert_eq!(ann.name, "Club");
+  assert_eq!(ann.tag("Black"), Some("Bob"));
+  let first = ann.tree().mainline()[0];
+  let clock = ann.tree().node(first).annotations.clock;
+  assert_eq!(clock, Some(Duration::from_secs(29 * 60 + 40)));
+
+  let mut bob = Viewer::connect(addr).unwrap();
+  catch_up(&mut broadcast, &mut bob, &tree, &record);
+  assert_eq!(broadcast.viewers(), 2);
+
+  // the host takes a move back and plays another, with a comment
+  let last = tree.mainline()[2];
+  tree.delete(last);
+  let reply = tree.play(mv("b1c3")).unwrap();
+  tree.annotations_mut(reply).add_comment("Closed.");
+  tree.annotations_mut(reply).nags.push(3);
+  let second = tree.mainline()[1];
+  tree.annotations_mut(second).nags.push(2);
+  for viewer in [&mut ann, &mut bob] {
+    catch_up(&mut broadcast, viewer, &tree, &record);
+  }
+  assert_eq!(ann.record().moves, [mv("e2e4"), mv("c7c5"), mv("b1c3")]);
+
+  // analysing on a copy leaves the broadcast alone
+  let mut analysis = ann.tree().clone();
+  analysis.play(mv("d7d6")).unwrap();
+  assert!(!ann.poll());
+  assert_eq!(ann.tree().mainline().len(), 3);
+
+  record.outcome = Outcome::Draw;
+  catch_up(&mut broadcast, &mut bob, &tree, &record);
+  assert_eq!(bob.outcome(), Outcome::Draw);
+
+  // a viewer who left is dropped once a message to them fails
+  drop(bob);
+  let deadline = Instant::now() + Duration::from_secs(5);
+  while broadcast.viewers() > 1 && Instant::now() < deadline {
+    tree.annotations_mut(reply).nags.push(3);
+    broadcast.publish(&tree, &record);
+    thread::sleep(Duration::from_millis(5));
+  }
+  assert_eq!(broadcast.viewers(), 1);
+
+  drop(broadcast);
+  let deadline = Instant::now() + Duration::from_secs(5);
+  while ann.is_connected() && Instant::now() < deadline {
+    ann.poll();
+    thread::sleep(Duration::from_millis(5));
+  }
+  assert!(!ann.is_connected());
+}
+
+#[test]
+fn test_a_new_start_replaces_the_game() {
+  let (tree, record) = game();
+  let mut broadcast = Broadcast::host("127.0.0.1:0", "Club").unwrap();
+  let mut viewer = Viewer::connect(broadcast.local_addr().unwrap()).unwrap();
+  catch_up(&mut broadcast, &mut viewer, &tree, &record);
+
+  let start = Position::from_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+  let tree = GameTree::from_moves(start.clone(), &[mv("a7a8q")]).unwrap();
+  catch_up(&mut broadcast, &mut viewer, &tree, &record);
+  assert_eq!(*viewer.tree().start(), start);
+}
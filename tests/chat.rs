@@ -0,0 +1,56 @@
+use std::time::{Duration, Instant};
+
+use chess::{
+  chat::{self, Filter, RateLimit, WordList, MAX_LEN},
+  pgn,
+  protocol::{ChatLine, Room},
+};
+
+#[test]
+fn test_lines_are_cleaned_and_filtered() {
+  assert_eq!(chat::clean("  good\tluck,\r\nhave  fun "), "good luck, have fun");
+  assert_eq!(chat::clean(&"a".repeat(500)).len(), MAX_LEN);
+
+  let words = WordList::new(["darn", "Heck"]);
+  let checked = words.check("Ann", "Darn! What the heck, darned pawn.");
+  assert_eq!(checked.unwrap(), "****! What the ****, darned pawn.");
+  assert_eq!(words.check("Ann", "").unwrap(), "");
+}
+
+#[test]
+fn test_the_rate_limit_forgets_old_lines() {
+  let mut limit = RateLimit::new(2, Duration::from_secs(10));
+  let start = Instant::now();
+  limit.check("Ann", start).unwrap();
+  limit.check("Ann", start + Duration::from_secs(4)).unwrap();
+  assert!(limit.check("Ann", start + Duration::from_secs(9)).is_err());
+  limit.check("Bob", start + Duration::from_secs(9)).unwrap();
+  // the first line is out of the window, the refused one did not count
+  limit.check("Ann", start + Duration::from_secs(10)).unwrap();
+  assert!(limit.check("Ann", start + Duration::from_secs(11)).is_err());
+}
+
+#[test]
+fn test_the_chat_is_saved_with_the_game() {
+  let lines = [
+    ChatLine {
+      ply: 0,
+      room: Room::Players,
+      from: "Ann".into(),
+      text: "good luck".into(),
+    },
+    ChatLine {
+      ply: 12,
+      room: Room::Spectators,
+      from: "Eve".into(),
+      text: "she said \"resign\" \\o/".into(),
+    },
+  ];
+  let mut game = pgn::Game::default();
+  game.set_tag("White", "Ann");
+  chat::save(&lines[..1], &mut game);
+  chat::save(&lines, &mut game);
+  let read = pgn::parse(&game.to_pgn()).unwrap().pop().unwrap();
+  assert_eq!(chat::load(&read), lines);
+  assert_eq!(read.tag("White"), Some("Ann"));
+}
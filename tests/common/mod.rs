@@ -0,0 +1,59 @@
+//! Helpers shared by the networking tests.
+
+use std::{
+  fmt::Debug,
+  thread,
+  time::{Duration, Instant},
+};
+
+use chess::{
+  board::Move,
+  client::ServerClient,
+  net::{Event, NetGame},
+  protocol::Update,
+};
+
+pub fn mv(uci: &str) -> Move {
+  Move::from_uci(uci).unwrap()
+}
+
+/// A connection handing out what arrived since it was last asked.
+pub trait Poll {
+  type Item: Debug;
+
+  fn poll(&mut self) -> Vec<Self::Item>;
+}
+
+impl Poll for ServerClient {
+  type Item = Update;
+
+  fn poll(&mut self) -> Vec<Update> {
+    ServerClient::poll(self)
+  }
+}
+
+impl Poll for NetGame {
+  type Item = Event;
+
+  fn poll(&mut self) -> Vec<Event> {
+    NetGame::poll(self)
+  }
+}
+
+/// Polls until something matching `wanted` arrives and returns all that
+/// did, failing after a few seconds.
+pub fn wait_for<P: Poll>(
+  source: &mut P,
+  wanted: impl Fn(&P::Item) -> bool,
+) -> Vec<P::Item> {
+  let deadline = Instant::now() + Duration::from_secs(5);
+  let mut seen = Vec::new();
+  while Instant::now() < deadline {
+    seen.extend(source.poll());
+    if seen.iter().any(&wanted) {
+      return seen;
+    }
+    thread::sleep(Duration::from_millis(5));
+  }
+  panic!("timed out, got {seen:?}");
+}
@@ -0,0 +1,137 @@
+use chess::{
+  board::{Color, Move, Position},
+  control::{self, Controls, Effect},
+  pgn::Outcome,
+  protocol::Control,
+};
+
+fn line(ucis: &str) -> Vec<Move> {
+  ucis.split(' ').map(|uci| Move::from_uci(uci).unwrap()).collect()
+}
+
+#[test]
+fn test_offers_stand_until_answered_or_a_move() {
+  let start = Position::startpos();
+  let moves = line("e2e4 e7e5");
+  let mut controls = Controls::default();
+  let apply = |controls: &mut Controls, color, control, moves: &[Move]| {
+    controls.apply(color, control, &start, moves, false)
+  };
+
+  assert!(
+    apply(&mut controls, Color::Black, Control::AcceptDraw, &moves).is_err()
+  );
+  assert_eq!(
+    apply(&mut controls, Color::White, Control::OfferDraw, &moves).unwrap(),
+    Effect::Offered
+  );
+  assert_eq!(controls.offer(2), Some((Control::OfferDraw, Color::White)));
+  // one offer at a time, and not to be answered by who made it
+  assert!(
+    apply(&mut controls, Color::White, Control::AskTakeback, &moves).is_err()
+  );
+  assert!(
+    apply(&mut controls, Color::White, Control::AcceptDraw, &moves).is_err()
+  );
+  assert_eq!(
+    apply(&mut controls, Color::Black, Control::DeclineDraw, &moves).unwrap(),
+    Effect::Declined
+  );
+  assert_eq!(controls.offer(2), None);
+
+  // a move lets the offer lapse
+  apply(&mut controls, Color::White, Control::OfferDraw, &moves).unwrap();
+  let moves = line("e2e4 e7e5 g1f3");
+  assert_eq!(controls.offer(3), None);
+  assert!(
+    apply(&mut controls, Color::Black, Control::AcceptDraw, &moves).is_err()
+  );
+
+  // offering back accepts
+  apply(&mut controls, Color::White, Control::OfferDraw, &moves).unwrap();
+  assert_eq!(
+    apply(&mut controls, Color::Black, Control::OfferDraw, &moves).unwrap(),
+    Effect::End(Outcome::Draw, "agreement")
+  );
+}
+
+#[test]
+fn test_takebacks_undo_the_asking_side_move() {
+  let start = Position::startpos();
+  let mut controls = Controls::default();
+  let asked = |controls: &mut Controls, color, moves: &[Move]| {
+    controls.apply(color, Control::AskTakeback, &start, moves, false)?;
+    controls.apply(!color, Control::AcceptTakeback, &start, moves, false)
+  };
+  // on the opponent's move only ours goes, on ours their reply too
+  let moves = line("e2e4 e7e5 g1f3");
+  assert_eq!(
+    asked(&mut controls, Color::White, &moves).unwrap(),
+    Effect::TakeBack(1)
+  );
+  assert_eq!(
+    asked(&mut controls, Color::Black, &moves).unwrap(),
+    Effect::TakeBack(2)
+  );
+  assert!(asked(&mut controls, Color::Black, &line("e2e4")).is_err());
+  assert!(asked(&mut controls, Color::White, &[]).is_err());
+  // black starts this one
+  let start = Position::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 1").unwrap();
+  let moves = line("e8d8");
+  let asked =
+    controls.apply(Color::Black, Control::AskTakeback, &start, &moves, false);
+  assert_eq!(asked.unwrap(), Effect::Offered);
+}
+
+#[test]
+fn test_ending_a_game() {
+  let start = Position::startpos();
+  let mut controls = Controls::default();
+  let mut apply = |color, control, moves: &[Move], away| {
+    controls.apply(color, control, &start, moves, away)
+  };
+  assert_eq!(
+    apply(Color::Black, Control::Resign, &[], false).unwrap(),
+    Effect::End(Outcome::WhiteWins, "resignation")
+  );
+  assert_eq!(
+    apply(Color::Black, Control::Abort, &line("e2e4"), false).unwrap(),
+    Effect::End(Outcome::Unknown, "aborted")
+  );
+  assert!(
+    apply(Color::White, Control::Abort, &line("e2e4 e7e5"), false).is_err()
+  );
+  assert!(apply(Color::White, Control::ClaimWin, &[], false).is_err());
+  assert_eq!(
+    apply(Color::White, Control::ClaimWin, &[], true).unwrap(),
+    Effect::End(Outcome::WhiteWins, "abandonment")
+  );
+
+  // the knights dance back to the start twice over
+  let dance = "g1f3 g8f6 f3g1 f6g8";
+  let twice = line(&format!("{dance} {dance}"));
+  assert!(apply(Color::White, Control::ClaimDraw, &twice[..7], false).is_err());
+  assert_eq!(
+    apply(Color::White, Control::ClaimDraw, &twice, false).unwrap(),
+    Effect::End(Outcome::Draw, "threefold repetition")
+  );
+}
+
+#[test]
+fn test_games_between_people_end_at_five_repetitions() {
+  let start = Position::startpos();
+  let dance = line("g1f3 g8f6 f3g1 f6g8");
+  let mut moves = Vec::new();
+  for times in 1..=4 {
+    moves.extend(&dance);
+    let (position, history) = control::replay(&start, &moves);
+    let over = control::game_over(&position, &history);
+    assert_eq!(over.is_some(), times == 4, "{times}");
+  }
+  let fifty = Position::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 100 80").unwrap();
+  assert_eq!(
+    control::claimable(&fifty, &[fifty.hash()]),
+    Some("fifty-move rule")
+  );
+  assert_eq!(control::game_over(&fifty, &[fifty.hash()]), None);
+}
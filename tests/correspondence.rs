@@ -0,0 +1,159 @@
+mod common;
+
+use std::time::Duration;
+
+use chess::{
+  board::{Color, Move},
+  client::ServerClient,
+  correspondence::{self, Correspondence},
+  pgn::Outcome,
+  protocol::Update,
+  server,
+  transport::Transport,
+};
+
+use common::{mv, wait_for};
+
+const DAY: u64 = 86400;
+
+fn line(ucis: &str) -> Vec<Move> {
+  ucis.split(' ').map(mv).collect()
+}
+
+/// The game as the other side gets it from a move file.
+fn sent(game: &Correspondence) -> Correspondence {
+  Correspondence::from_move_file(&game.to_move_file(), 0).unwrap()
+}
+
+#[test]
+fn test_move_files_and_conditional_moves() {
+  let mut ann = Correspondence::new("Ann", "Bob", Color::White, 3, 1000);
+  ann.play(mv("e2e4"), 1000).unwrap();
+  assert!(!ann.is_our_turn());
+  assert!(ann.play(mv("d2d4"), 1000).is_err());
+
+  let mut bob =
+    Correspondence::from_move_file(&ann.to_move_file(), 2000).unwrap();
+  assert_eq!((bob.color, bob.opponent()), (Color::Black, "Ann"));
+  assert_eq!((bob.id.as_str(), bob.days), ("Ann-Bob-1000", 3));
+  bob.play(mv("c7c5"), 3000).unwrap();
+  assert_eq!(ann.receive(&sent(&bob), 4000).unwrap(), None);
+  assert_eq!(ann.since, 4000);
+  ann.play(mv("g1f3"), 5000).unwrap();
+  bob.receive(&sent(&ann), 6000).unwrap();
+
+  // Ann prepares for the Najdorf and the Classical
+  ann.add_conditional(line("d7d6 d2d4 c5d4 f3d4")).unwrap();
+  ann.add_conditional(line("b8c6 d2d4")).unwrap();
+  let other = ann.add_conditional(line("d7d6 b1c3"));
+  assert!(other.unwrap_err().to_string().contains("already answered"));
+  assert!(ann.add_conditional(line("d7d6")).is_err());
+  assert!(ann.add_conditional(line("d7d6 e1g1")).is_err());
+
+  bob.play(mv("d7d6"), 7000).unwrap();
+  assert_eq!(ann.receive(&sent(&bob), 8000).unwrap(), Some(mv("d2d4")));
+  assert_eq!(ann.conditionals(), [line("c5d4 f3d4")]);
+  assert!(!ann.is_our_turn());
+
+  // the reply goes back like any move
+  bob.receive(&sent(&ann), 9000).unwrap();
+  bob.play(mv("g8f6"), 9000).unwrap();
+  assert_eq!(ann.receive(&sent(&bob), 9500).unwrap(), None);
+  assert!(ann.conditionals().is_empty());
+  assert!(ann.is_our_turn());
+
+  // a game that went elsewhere is refused
+  let mut stray = sent(&ann);
+  stray.moves[1] = mv("e7e5");
+  assert!(ann.receive(&stray, 9500).is_err());
+  let mut other = sent(&ann);
+  other.id = "Ann-Eve-1".into();
+  assert!(ann.receive(&other, 9500).is_err());
+}
+
+#[test]
+fn test_the_clock_runs_in_days() {
+  let mut game = Correspondence::new("Ann", "Bob", Color::Black, 2, 0);
+  assert_eq!(game.time_left(DAY / 2), Duration::from_secs(3 * DAY / 2));
+  assert!(!game.check_time(2 * DAY));
+  assert!(game.check_time(2 * DAY + 1));
+  assert_eq!(
+    (game.outcome, game.reason.as_str()),
+    (Outcome::BlackWins, "time")
+  );
+  assert!(game.add_conditional(line("e2e4 e7e5")).is_err());
+}
+
+#[test]
+fn test_games_are_saved_and_read_back() {
+  let dir = std::env::temp_dir().join("chess-correspondence-test");
+  let _ = std::fs::remove_dir_all(&dir);
+  assert!(correspondence::load(&dir).unwrap().is_empty());
+
+  let mut waiting = Correspondence::new("Ann", "Bob", Color::White, 5, 100);
+  waiting.play(mv("d2d4"), 200).unwrap();
+  waiting.add_conditional(line("d7d5 c2c4 e7e6 b1c3")).unwrap();
+  waiting.add_conditional(line("g8f6 c2c4")).unwrap();
+  let mut moving = Correspondence::new("Ann", "Eve", Color::White, 1, 300);
+  moving.play(mv("f2f3"), 300).unwrap();
+  moving.receive(&moving_reply(&moving), 400).unwrap();
+  let mut over = Correspondence::new("Ann", "Dan", Color::Black, 1, 0);
+  over.check_time(2 * DAY);
+  for game in [&waiting, &moving, &over] {
+    game.save(&dir).unwrap();
+  }
+
+  let loaded = correspondence::load(&dir).unwrap();
+  assert_eq!(loaded, [moving, waiting, over]);
+  // the move file keeps our conditional moves to ourselves
+  assert!(!loaded[1].to_move_file().contains("Conditional"));
+  std::fs::remove_dir_all(&dir).unwrap();
+}
+
+fn moving_reply(game: &Correspondence) -> Correspondence {
+  let mut reply = sent(game);
+  reply.play(mv("e7e5"), 0).unwrap();
+  reply
+}
+
+#[test]
+fn test_the_server_keeps_posts_for_players_away() {
+  let addr = server::spawn("127.0.0.1:0", "test").unwrap();
+  let mut ann = ServerClient::connect(addr, "Ann", Transport::Tcp).unwrap();
+  let mut game = Correspondence::new("Ann", "Bob", Color::White, 3, 0);
+  game.play(mv("e2e4"), 0).unwrap();
+  ann.post(&game).unwrap();
+  // a later post of the game replaces the first
+  game.moves.clear();
+  game.play(mv("d2d4"), 0).unwrap();
+  ann.post(&game).unwrap();
+  // the server answers in order, so the posts are in once the seek is
+  ann.seek("60".parse().unwrap(), None).unwrap();
+  wait_for(&mut ann, |update| matches!(update, Update::Seek(_)));
+
+  let mut bob = ServerClient::connect(addr, "Bob", Transport::Tcp).unwrap();
+  let posted = |update: &Update| matches!(update, Update::Post { .. });
+  let post = wait_for(&mut bob, posted).into_iter().find(posted).unwrap();
+  let Update::Post { from, game: id, days, start, moves } = post else {
+    unreachable!();
+  };
+  assert_eq!(moves, [mv("d2d4")]);
+  let received =
+    Correspondence::received(&id, &from, "Bob", days, start, &moves, 0);
+  let mut received = received.unwrap();
+  assert_eq!((received.color, received.opponent()), (Color::Black, "Ann"));
+  assert_eq!(received.id, game.id);
+  received.play(mv("g8f6"), 0).unwrap();
+  bob.post(&received).unwrap();
+
+  let post = wait_for(&mut ann, posted).into_iter().find(posted).unwrap();
+  let Update::Post { from, game: id, days, start, moves } = post else {
+    unreachable!();
+  };
+  assert_eq!(from, "Bob");
+  let sent =
+    Correspondence::received(&id, &from, "Ann", days, start, &moves, 0);
+  assert_eq!(game.receive(&sent.unwrap(), 0).unwrap(), None);
+  assert_eq!(game.moves, [mv("d2d4"), mv("g8f6")]);
+  assert!(game.is_our_turn());
+}
@@ -0,0 +1,164 @@
+use chess::{
+  board::{Move, Position},
+  database::{Database, Material, MaterialRange, Query},
+  pgn::{self, Outcome},
+};
+
+fn mv(uci: &str) -> Move {
+  Move::from_uci(uci).unwrap()
+}
+
+const GAMES: &str = r#"[Event "Club Championship"]
+[Date "2021.03.14"]
+[White "Ann Smith"]
+[Black "Bob Jones"]
+[ECO "C44"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 Nc6 3. d4 exd4 4. Nxd4 Nxd4 5. Qxd4 1-0
+
+[Event "Open"]
+[Date "2022.07.01"]
+[White "Bob Jones"]
+[Black "Cid Brown"]
+[ECO "B20"]
+[Result "1/2-1/2"]
+
+1. e4 c5 2. Nf3 Nc6 3. d4 cxd4 4. Nxd4 1/2-1/2
+
+[Event "Open"]
+[Date "????.??.??"]
+[White "Dan"]
+[Black "Ann Smith"]
+[SetUp "1"]
+[FEN "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"]
+[Result "0-1"]
+
+1. Kd2 Kd7 0-1
+"#;
+
+#[test]
+fn test_import_search_and_reopen() {
+  let path = std::env::temp_dir().join("chess-database-test.chdb");
+  let _ = std::fs::remove_file(&path);
+  let games = pgn::parse(GAMES).unwrap();
+  let mut database = Database::open(&path).unwrap();
+  assert!(database.is_empty());
+  assert_eq!(database.import(&games[..2]).unwrap(), 0);
+  drop(database);
+  let mut database = Database::open(&path).unwrap();
+  assert_eq!(database.import(&games[2..]).unwrap(), 2);
+  drop(database);
+  let database = Database::open(&path).unwrap();
+  assert_eq!(database.len(), 3);
+
+  let search = |query: Query| database.search(&query);
+  let text = |text: &str| Some(text.to_string());
+  assert_eq!(search(Query::default()), vec![0, 1, 2]);
+  assert_eq!(
+    search(Query { player: text("ann"), ..Default::default() }),
+    [0, 2]
+  );
+  assert_eq!(search(Query { white: text("Bob"), ..Default::default() }), [1]);
+  assert_eq!(
+    search(Query { event: text("open"), ..Default::default() }),
+    [1, 2]
+  );
+  let dates = Query {
+    from_date: text("2021.06"),
+    to_date: text("2022"),
+    ..Default::default()
+  };
+  assert_eq!(search(dates), [1]);
+  let draws = Query { outcome: Some(Outcome::Draw), ..Default::default() };
+  assert_eq!(search(draws), [1]);
+  assert_eq!(search(Query { eco: text("c4"), ..Default::default() }), [0]);
+
+  // both games open 1. e4, only the first goes on 1...e5
+  let mut position = Position::startpos();
+  position.play(mv("e2e4"));
+  let key = Some(position.hash());
+  assert_eq!(search(Query { position: key, ..Default::default() }), [0, 1]);
+  position.play(mv("e7e5"));
+  let key = Some(position.hash());
+  assert_eq!(search(Query { position: key, ..Default::default() }), [0]);
+
+  // after 4...Nxd4 white is a knight down until the queen takes back
+  let material: Material = "KQRRBBNPPPPPPPkqrrbbnnppppppp".parse().unwrap();
+  let query = Query { material: Some(material.into()), ..Default::default() };
+  assert_eq!(search(query), [0]);
+  let pawn: Material = "KPk".parse().unwrap();
+  assert_eq!(pawn.to_string(), "KPk");
+  let pawn = Some(pawn.into());
+  assert_eq!(search(Query { material: pawn, ..Default::default() }), [2]);
+  let material = |text: &str| {
+    let material = Some(text.parse().unwrap());
+    search(Query { material, ..Default::default() })
+  };
+  assert_eq!(material("Kk+"), [0, 1, 2]);
+  assert_eq!(material("KNkn+"), [0, 1]);
+  // a knight down with a pawn traded or not
+  let down = "KQRRBBNPPPPPPPkqrrbbnnppppppp..KQRRBBNPPPPPPPPkqrrbbnnpppppppp";
+  assert_eq!(material(down), [0]);
+  assert_eq!(material("Kk..KPk"), [2]);
+  assert!("KPk..Kk".parse::<MaterialRange>().is_err());
+
+  let game = database.game(2).unwrap();
+  assert_eq!(game.start, games[2].start);
+  assert_eq!(game.moves, games[2].moves);
+  assert_eq!(game.outcome, Outcome::BlackWins);
+  assert_eq!(database.game(0).unwrap().moves, games[0].moves);
+  assert_eq!(
+    database.summary(1).unwrap(),
+    "Bob Jones - Cid Brown 1/2-1/2, Open 2022"
+  );
+  assert!(database.game(3).is_err());
+  std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_stays_smaller_than_the_pgn() {
+  let path = std::env::temp_dir().join("chess-database-small.chdb");
+  let _ = std::fs::remove_file(&path);
+  let games = pgn::parse(GAMES).unwrap();
+  let mut database = Database::open(&path).unwrap();
+  for game in &games {
+    database.import(std::slice::from_ref(game)).unwrap();
+  }
+  assert!(std::fs::metadata(&path).unwrap().len() < GAMES.len() as u64);
+
+  // games imported one at a time are indexed as if imported together
+  let mut position = Position::startpos();
+  position.play(mv("e2e4"));
+  let query = Query { position: Some(position.hash()), ..Default::default() };
+  assert_eq!(database.search(&query), [0, 1]);
+  let pawn = Some("KPk".parse().unwrap());
+  assert_eq!(
+    database.search(&Query { material: pawn, ..Default::default() }),
+    [2]
+  );
+  std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_rejects_other_files() {
+  let path = std::env::temp_dir().join("chess-database-other.chdb");
+  std::fs::write(&path, "[Event \"?\"]").unwrap();
+  assert!(Database::open(&path).is_err());
+  std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_rejects_oversized_games() {
+  let path = std::env::temp_dir().join("chess-database-oversized.chdb");
+  let _ = std::fs::remove_file(&path);
+  let mut database = Database::open(&path).unwrap();
+  let mut games = pgn::parse(GAMES).unwrap();
+  games[1].tags.push(("Annotator".to_string(), "x".repeat(70_000)));
+  let err = database.import(&games).unwrap_err().to_string();
+  assert_eq!(err, "game 1: a tag of 70000 bytes is too long");
+  drop(database);
+  // nothing of the batch was written
+  assert!(Database::open(&path).unwrap().is_empty());
+  std::fs::remove_file(&path).unwrap();
+}
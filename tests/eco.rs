@@ -0,0 +1,72 @@
+use std::collections::HashSet;
+
+use chess::{
+  board::{Move, Position},
+  eco, pgn, san,
+};
+
+fn line(sans: &str) -> Vec<Move> {
+  let mut position = Position::startpos();
+  sans
+    .split_whitespace()
+    .map(|word| {
+      let mv = san::parse_san(&position, word).unwrap();
+      position.play(mv);
+      mv
+    })
+    .collect()
+}
+
+#[test]
+fn test_table_parses() {
+  assert!(eco::len() > 500);
+  assert_eq!(eco::lookup(&Position::startpos()), None);
+}
+
+#[test]
+fn test_classifies_by_last_known_position() {
+  let start = Position::startpos();
+  let scotch =
+    eco::classify(&start, &line("e4 e5 Nf3 Nc6 d4 exd4 Nxd4 Bc5 Be3")).unwrap();
+  assert_eq!((scotch.eco, scotch.name), ("C45", "Scotch Game"));
+  let sicilian = eco::classify(&start, &line("e4 c5 a3")).unwrap();
+  assert_eq!(sicilian.name, "Sicilian Defense");
+  assert_eq!(eco::classify(&start, &[]), None);
+}
+
+#[test]
+fn test_recognizes_transpositions() {
+  let start = Position::startpos();
+  let indian = eco::classify(&start, &line("d4 Nf6 c4 e6 Nc3 d5")).unwrap();
+  assert_eq!(indian.eco, "D35");
+  let english = eco::classify(&start, &line("c4 e6 Nc3 d5 d4 Nf6")).unwrap();
+  assert_eq!(english, indian);
+}
+
+#[test]
+fn test_covers_every_code() {
+  let table = include_str!("../src/eco.tsv");
+  let codes: HashSet<&str> =
+    table.lines().skip(1).filter_map(|line| line.split('\t').next()).collect();
+  for volume in ['A', 'B', 'C', 'D', 'E'] {
+    for n in 0..100 {
+      let code = format!("{volume}{n:02}");
+      assert!(codes.contains(code.as_str()), "{code} is missing");
+    }
+  }
+}
+
+#[test]
+fn test_tags_games() {
+  let mut game = pgn::parse("1. e4 e5 2. Nf3 Nc6 3. Bb5 *").unwrap().remove(0);
+  eco::tag(&mut game);
+  assert_eq!(game.tag("ECO"), Some("C60"));
+  assert_eq!(game.tag("Opening"), Some("Ruy Lopez"));
+  assert!(game.to_pgn().contains("[Opening \"Ruy Lopez\"]"));
+
+  let mut game =
+    pgn::parse("1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 *").unwrap().remove(0);
+  eco::tag(&mut game);
+  assert_eq!(game.tag("ECO"), Some("C70"));
+  assert_eq!(game.tag("Opening"), Some("Ruy Lopez: Morphy Defense"));
+}
@@ -0,0 +1,8 @@
+#!/bin/sh
+# A UCI engine that quits as soon as it is asked to search.
+while read -r command; do
+  case "$command" in
+    uci) echo "id name quitter"; echo uciok ;;
+    go*) exit 1 ;;
+  esac
+done
@@ -0,0 +1,91 @@
+use chess::{
+  board::{Move, Position},
+  explorer::{self, BuildOptions, Explorer},
+  pgn,
+};
+
+fn mv(uci: &str) -> Move {
+  Move::from_uci(uci).unwrap()
+}
+
+const GAMES: &str = r#"[Event "Club"]
+[White "Ann"]
+[Black "Bob"]
+[WhiteElo "2000"]
+[BlackElo "1800"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 Nc6 1-0
+
+[Event "Club"]
+[White "Cid"]
+[Black "Dan"]
+[WhiteElo "2400"]
+[BlackElo "2400"]
+[Result "1/2-1/2"]
+
+1. e4 c5 2. Nf3 1/2-1/2
+
+[Event "Open"]
+[White "Eve"]
+[Black "Fay"]
+[Result "0-1"]
+
+1. Nf3 Nc6 2. e4 e5 0-1
+"#;
+
+#[test]
+fn test_moves_games_and_transpositions() {
+  let games = pgn::parse(GAMES).unwrap();
+  let path = std::env::temp_dir().join("chess-explorer-test.idx");
+  explorer::build(&games, &BuildOptions::default(), &path).unwrap();
+  let explorer = Explorer::open(&path).unwrap();
+  assert_eq!(explorer.games(), 3);
+
+  let start = Position::default();
+  let moves = explorer.moves(&start).unwrap();
+  assert_eq!(moves.len(), 2);
+  let e4 = &moves[0];
+  assert_eq!(e4.mv, mv("e2e4"));
+  assert_eq!((e4.white, e4.draws, e4.black), (1, 1, 0));
+  assert_eq!(e4.percentages(), [50.0, 50.0, 0.0]);
+  assert_eq!(e4.average_rating(), Some(2150));
+  // best rated first
+  assert_eq!(e4.games, vec![1, 0]);
+  let nf3 = &moves[1];
+  assert_eq!((nf3.total(), nf3.average_rating()), (1, None));
+
+  // 1. e4 e5 2. Nf3 Nc6 and 1. Nf3 Nc6 2. e4 e5 meet
+  let mut position = start.clone();
+  for uci in ["e2e4", "e7e5", "g1f3", "b8c6"] {
+    position.play(mv(uci));
+  }
+  assert_eq!(explorer.moves(&position).unwrap(), vec![]);
+  position = start.played(mv("e2e4")).played(mv("e7e5"));
+  let moves = explorer.moves(&position).unwrap();
+  assert_eq!(moves.len(), 1);
+  assert_eq!(moves[0].games, vec![0]);
+  position = start.played(mv("g1f3")).played(mv("b8c6")).played(mv("e2e4"));
+  let e5 = &explorer.moves(&position).unwrap()[0];
+  assert_eq!((e5.mv, e5.games.clone()), (mv("e7e5"), vec![2]));
+
+  assert_eq!(explorer.summary(0).unwrap(), "Ann (2000) - Bob (1800) 1-0, Club");
+  let game = explorer.game(2).unwrap();
+  assert_eq!(game.tag("White"), Some("Eve"));
+  assert_eq!(game.moves, games[2].moves);
+  assert!(explorer.game(3).is_err());
+  std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_limits_plies_and_rejects_other_files() {
+  let games = pgn::parse(GAMES).unwrap();
+  let path = std::env::temp_dir().join("chess-explorer-short.idx");
+  explorer::build(&games, &BuildOptions { max_ply: 1 }, &path).unwrap();
+  let explorer = Explorer::open(&path).unwrap();
+  let after = Position::default().played(mv("e2e4"));
+  assert!(explorer.moves(&after).unwrap().is_empty());
+  std::fs::write(&path, "not an index at all").unwrap();
+  assert!(Explorer::open(&path).is_err());
+  std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,188 @@
+use std::time::Duration;
+
+use chess::{
+  board::{Move, Position},
+  gametree::{Annotations, ArrowMark, GameTree, MarkColor, SquareMark},
+  pgn,
+};
+
+fn mv(uci: &str) -> Move {
+  Move::from_uci(uci).unwrap()
+}
+
+const ANNOTATED: &str = r#"[Event "Annotated"]
+[Result "1-0"]
+
+{A short game.} 1. e4 $1 {[%clk 0:05:00]} e5 (1... c5!? {Sicilian} 2. Nf3
+(2. c3 d5) d6) (1... e6 2. d4) 2. Nf3?! {[%csl Gd4,Re5][%cal Gf3e5] hits e5}
+Nc6 3. Bb5 $14 1-0
+"#;
+
+#[test]
+fn test_pgn_variations_and_annotations() {
+  let games = pgn::parse_trees(ANNOTATED).unwrap();
+  let (game, tree) = &games[0];
+  assert_eq!(game.outcome, pgn::Outcome::WhiteWins);
+  assert_eq!(game.moves.len(), 5);
+  assert_eq!(tree.current(), tree.root());
+  assert_eq!(tree.node(tree.root()).annotations.comment, "A short game.");
+
+  let mainline = tree.mainline();
+  let e4 = tree.node(mainline[0]);
+  assert_eq!(e4.annotations.nags, [1]);
+  assert_eq!(e4.annotations.clock, Some(Duration::from_secs(300)));
+  assert_eq!(e4.children.len(), 3);
+
+  let c5 = tree.node(e4.children[1]);
+  assert_eq!(c5.mv, Some(mv("c7c5")));
+  assert_eq!(c5.annotations.nags, [5]);
+  assert_eq!(c5.annotations.comment, "Sicilian");
+  assert_eq!(c5.children.len(), 2);
+  assert_eq!(tree.node(c5.children[1]).mv, Some(mv("c2c3")));
+
+  let knight = &tree.node(mainline[2]).annotations;
+  assert_eq!(knight.nags, [6]);
+  assert_eq!(knight.comment, "hits e5");
+  assert_eq!(
+    knight.highlights,
+    [
+      SquareMark { square: "d4".parse().unwrap(), color: MarkColor::Green },
+      SquareMark { square: "e5".parse().unwrap(), color: MarkColor::Red },
+    ]
+  );
+  assert_eq!(
+    knight.arrows,
+    [ArrowMark {
+      from: "f3".parse().unwrap(),
+      to: "e5".parse().unwrap(),
+      color: MarkColor::Green,
+    }]
+  );
+  assert_eq!(tree.node(mainline[4]).annotations.nags, [14]);
+  // a glyph alone is worth keeping
+  assert!(Annotations::default().is_empty());
+  assert!(!Annotations { nags: vec![14], ..Default::default() }.is_empty());
+
+  // the main line reading ignores all of it
+  let plain = pgn::parse(ANNOTATED).unwrap();
+  assert_eq!(plain[0].moves, game.moves);
+}
+
+#[test]
+fn test_pgn_tree_round_trip() {
+  let games = pgn::parse_trees(ANNOTATED).unwrap();
+  let (game, tree) = &games[0];
+  let text = game.to_pgn_with(tree);
+  let movetext = text.replace('\n', " ");
+  assert!(movetext.contains(
+    "{A short game.} 1. e4! {[%clk 0:05:00]} 1... e5 (1... c5!? {Sicilian}"
+  ));
+  assert!(movetext.contains("2. Nf3 (2. c3 d5) 2... d6)"));
+  assert!(movetext.contains("(1... e6 2. d4) 2. Nf3?!"));
+  assert!(movetext.contains("{[%csl Gd4,Re5] [%cal Gf3e5] hits e5}"));
+  assert!(movetext.contains("3. Bb5 $14 1-0"));
+  assert!(text.lines().all(|line| line.len() <= 80));
+
+  let again = pgn::parse_trees(&text).unwrap();
+  let (_, reread) = &again[0];
+  assert_eq!(reread.mainline().len(), tree.mainline().len());
+  for (a, b) in reread.mainline().into_iter().zip(tree.mainline()) {
+    assert_eq!(reread.node(a).mv, tree.node(b).mv);
+    assert_eq!(reread.node(a).annotations, tree.node(b).annotations);
+    assert_eq!(reread.node(a).children.len(), tree.node(b).children.len());
+  }
+  assert_eq!(game.to_pgn_with(reread), text);
+}
+
+#[test]
+fn test_playing_alternatives_adds_variations() {
+  let mut tree = GameTree::default();
+  let e4 = tree.play(mv("e2e4")).unwrap();
+  let e5 = tree.play(mv("e7e5")).unwrap();
+  tree.back();
+  let c5 = tree.play(mv("c7c5")).unwrap();
+  assert_eq!(tree.node(e4).children, [e5, c5]);
+  assert!(!tree.is_mainline(c5));
+
+  // playing a move that is there already just steps into it
+  tree.back();
+  assert_eq!(tree.play(mv("e7e5")).unwrap(), e5);
+  assert_eq!(tree.node(e4).children.len(), 2);
+  assert!(tree.play(mv("e2e4")).is_err());
+
+  assert!(tree.switch_variation(1));
+  assert_eq!(tree.current(), c5);
+  assert!(!tree.switch_variation(1));
+  assert!(tree.switch_variation(-1));
+  assert_eq!(tree.current(), e5);
+
+  assert!(tree.to_start());
+  assert!(!tree.to_start());
+  assert!(!tree.back());
+  assert!(tree.to_end());
+  assert!(!tree.to_end());
+  assert!(!tree.forward());
+  assert_eq!(tree.played(), [mv("e2e4"), mv("e7e5")]);
+}
+
+#[test]
+fn test_promote_and_delete() {
+  let mut tree = GameTree::from_moves(
+    Position::startpos(),
+    &[mv("e2e4"), mv("e7e5"), mv("g1f3")],
+  )
+  .unwrap();
+  let e4 = tree.mainline()[0];
+  tree.go_to(e4);
+  let c5 = tree.play(mv("c7c5")).unwrap();
+  let nf3 = tree.play(mv("g1f3")).unwrap();
+  tree.back();
+  tree.back();
+  let e6 = tree.play(mv("e7e6")).unwrap();
+
+  // promoting from deep inside a variation moves the whole variation
+  assert!(tree.promote(nf3));
+  assert_eq!(tree.node(e4).children[0], c5);
+  assert!(tree.is_mainline(nf3));
+  assert!(!tree.promote(nf3));
+
+  tree.make_mainline(e6);
+  assert_eq!(tree.node(e4).children[0], e6);
+  assert_eq!(tree.node(e4).children.len(), 3);
+
+  tree.go_to(nf3);
+  tree.delete(c5);
+  assert_eq!(tree.current(), e4);
+  assert_eq!(tree.node(e4).children.len(), 2);
+  tree.delete(tree.root());
+  assert_eq!(tree.mainline().len(), 2);
+}
+
+#[test]
+fn test_marks_toggle_and_persist() {
+  let mut tree = GameTree::default();
+  tree.play(mv("e2e4")).unwrap();
+  let square = |name: &str| name.parse().unwrap();
+  let annotations = tree.annotations_mut(tree.current());
+  let arrow = |color| ArrowMark { from: square("g1"), to: square("f3"), color };
+  annotations.toggle_arrow(arrow(MarkColor::Green));
+  annotations.toggle_arrow(arrow(MarkColor::Red));
+  assert_eq!(annotations.arrows, [arrow(MarkColor::Red)]);
+  let circle = SquareMark { square: square("e4"), color: MarkColor::Blue };
+  annotations.toggle_highlight(circle);
+  annotations.toggle_highlight(circle);
+  assert!(annotations.highlights.is_empty());
+  annotations.toggle_highlight(circle);
+  annotations.toggle_arrow(ArrowMark {
+    from: square("d7"),
+    to: square("d5"),
+    color: MarkColor::Yellow,
+  });
+
+  let text = pgn::Game::default().to_pgn_with(&tree);
+  assert!(text.contains("1. e4 {[%csl Be4] [%cal Rg1f3,Yd7d5]} *"));
+  let games = pgn::parse_trees(&text).unwrap();
+  let (_, reread) = &games[0];
+  let node = reread.node(reread.mainline()[0]);
+  assert_eq!(node.annotations, tree.node(tree.current()).annotations);
+}
@@ -0,0 +1,345 @@
+use std::{
+  io::{BufRead, BufReader, Read, Write},
+  net::{SocketAddr, TcpListener, TcpStream},
+  sync::{Arc, Mutex},
+  thread,
+  time::Duration,
+};
+
+use chess::{
+  board::{Color, Move, Position},
+  lichess::{Api, ChatLine, Client, Event, GameEvent, Room},
+};
+
+const TOKEN: &str = "secret";
+/// Black's replies to the scholar's mate.
+const REPLIES: [&str; 3] = ["e7e5", "b8c6", "g8f6"];
+
+#[derive(Default)]
+struct Game {
+  moves: Vec<String>,
+  chat: Vec<(String, String)>,
+  accepted: bool,
+}
+
+impl Game {
+  fn status(&self) -> (&'static str, Option<&'static str>) {
+    let mut position = Position::startpos();
+    for mv in &self.moves {
+      position.play(Move::from_uci(mv).unwrap());
+    }
+    if position.legal_moves().is_empty() {
+      ("mate", Some("white"))
+    } else {
+      ("started", None)
+    }
+  }
+
+  fn state(&self) -> String {
+    let (status, winner) = self.status();
+    let mut state = serde_json::json!({
+      "type": "gameState",
+      "moves": self.moves.join(" "),
+      "wtime": 300000 - 1000 * self.moves.len(),
+      "btime": 300000,
+      "winc": 2000,
+      "binc": 2000,
+      "status": status,
+    });
+    if let Some(winner) = winner {
+      state["winner"] = winner.into();
+    }
+    state.to_string()
+  }
+}
+
+type Shared = Arc<Mutex<Game>>;
+
+fn respond(
+  stream: &mut TcpStream,
+  status: &str,
+  body: &str,
+) {
+  write!(
+    stream,
+    "HTTP/1.1 {status}\r\nContent-Type: application/json\r\n\
+     Content-Length: {}\r\n\r\n{body}",
+    body.len()
+  )
+  .unwrap();
+}
+
+fn start_stream(stream: &mut TcpStream) {
+  write!(
+    stream,
+    "HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\n\
+     Transfer-Encoding: chunked\r\n\r\n"
+  )
+  .unwrap();
+}
+
+/// Sends a line of the stream as its own chunk, split in two to make the
+/// client put them together.
+fn chunk(
+  stream: &mut TcpStream,
+  line: &str,
+) -> std::io::Result<()> {
+  let line = format!("{line}\n");
+  let (a, b) = line.split_at(line.len() / 2);
+  // an empty chunk would end the body
+  for part in [a, b].into_iter().filter(|part| !part.is_empty()) {
+    write!(stream, "{:x}\r\n{part}\r\n", part.len())?;
+  }
+  stream.flush()
+}
+
+fn end_stream(stream: &mut TcpStream) {
+  let _ = write!(stream, "0\r\n\r\n");
+}
+
+fn event_stream(
+  stream: &mut TcpStream,
+  game: &Shared,
+) {
+  start_stream(stream);
+  let challenge = serde_json::json!({
+    "type": "challenge",
+    "challenge": {
+      "id": "c1",
+      "challenger": {"id": "bob", "name": "Bob", "rating": 1500},
+      "variant": {"key": "standard"},
+      "rated": false,
+      "timeControl": {"type": "clock", "limit": 300, "increment": 2},
+      "color": "black",
+    },
+  });
+  chunk(stream, &challenge.to_string()).unwrap();
+  chunk(stream, r#"{"type":"somethingNew"}"#).unwrap();
+  while !game.lock().unwrap().accepted {
+    chunk(stream, "").unwrap();
+    thread::sleep(Duration::from_millis(5));
+  }
+  chunk(stream, r#"{"type":"gameStart","game":{"gameId":"g1"}}"#).unwrap();
+  end_stream(stream);
+}
+
+fn game_stream(
+  stream: &mut TcpStream,
+  game: &Shared,
+) {
+  start_stream(stream);
+  let state = game.lock().unwrap().state();
+  let full = serde_json::json!({
+    "type": "gameFull",
+    "id": "g1",
+    "white": {"id": "ann", "name": "Ann"},
+    "black": {"id": "bob", "name": "Bob"},
+    "clock": {"initial": 300000, "increment": 2000},
+    "initialFen": "startpos",
+    "state": serde_json::from_str::<serde_json::Value>(&state).unwrap(),
+  });
+  chunk(stream, &full.to_string()).unwrap();
+  let (mut moves, mut chat) = (0, 0);
+  loop {
+    let lines = {
+      let game = game.lock().unwrap();
+      let mut lines = Vec::new();
+      for (room, text) in &game.chat[chat..] {
+        let line = serde_json::json!({
+          "type": "chatLine",
+          "username": "Ann",
+          "text": text,
+          "room": room,
+        });
+        lines.push(line.to_string());
+      }
+      chat = game.chat.len();
+      if game.moves.len() > moves {
+        moves = game.moves.len();
+        lines.push(game.state());
+      }
+      if game.status().0 != "started" {
+        for line in lines {
+          let _ = chunk(stream, &line);
+        }
+        return end_stream(stream);
+      }
+      lines
+    };
+    for line in lines {
+      if chunk(stream, &line).is_err() {
+        return;
+      }
+    }
+    thread::sleep(Duration::from_millis(5));
+  }
+}
+
+fn handle(
+  mut stream: TcpStream,
+  game: &Shared,
+) {
+  let mut reader = BufReader::new(stream.try_clone().unwrap());
+  let mut request = String::new();
+  reader.read_line(&mut request).unwrap();
+  let (mut length, mut authorized) = (0, false);
+  loop {
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let line = line.trim().to_ascii_lowercase();
+    if line.is_empty() {
+      break;
+    }
+    if let Some(value) = line.strip_prefix("content-length:") {
+      length = value.trim().parse().unwrap();
+    }
+    authorized |= line == format!("authorization: bearer {TOKEN}");
+  }
+  let mut body = vec![0; length];
+  reader.read_exact(&mut body).unwrap();
+  let body = String::from_utf8(body).unwrap();
+  if !authorized {
+    return respond(
+      &mut stream,
+      "401 Unauthorized",
+      r#"{"error":"No such token"}"#,
+    );
+  }
+
+  let mut words = request.split(' ');
+  let (method, path) = (words.next().unwrap(), words.next().unwrap());
+  let parts: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+  match (method, parts.as_slice()) {
+    ("GET", ["api", "account"]) => {
+      respond(&mut stream, "200 OK", r#"{"id":"ann","username":"Ann"}"#)
+    }
+    ("GET", ["api", "stream", "event"]) => event_stream(&mut stream, game),
+    ("POST", ["api", "challenge", "c1", "accept"]) => {
+      game.lock().unwrap().accepted = true;
+      respond(&mut stream, "200 OK", r#"{"ok":true}"#)
+    }
+    ("GET", ["api", "board", "game", "stream", "g1"]) => {
+      game_stream(&mut stream, game)
+    }
+    ("POST", ["api", "board", "game", "g1", "move", uci]) => {
+      let mut game = game.lock().unwrap();
+      let ours =
+        game.moves.len().is_multiple_of(2) && game.status().0 == "started";
+      if !ours {
+        let error = r#"{"error":"Not your turn, or game already over"}"#;
+        return respond(&mut stream, "400 Bad Request", error);
+      }
+      game.moves.push(uci.to_string());
+      if let Some(reply) = REPLIES.get(game.moves.len() / 2) {
+        game.moves.push(reply.to_string());
+      }
+      respond(&mut stream, "200 OK", r#"{"ok":true}"#)
+    }
+    ("POST", ["api", "board", "game", "g1", "chat"]) => {
+      let mut room = String::new();
+      let mut text = String::new();
+      for field in body.split('&') {
+        let (key, value) = field.split_once('=').unwrap();
+        let value = value.replace('+', " ").replace("%21", "!");
+        match key {
+          "room" => room = value,
+          "text" => text = value,
+          _ => {}
+        }
+      }
+      game.lock().unwrap().chat.push((room, text));
+      respond(&mut stream, "200 OK", r#"{"ok":true}"#)
+    }
+    _ => respond(&mut stream, "404 Not Found", r#"{"error":"Not found"}"#),
+  }
+}
+
+/// A stand-in for the site on loopback, where Bob challenges Ann and
+/// walks into the scholar's mate.
+fn mock() -> SocketAddr {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  let game = Shared::default();
+  thread::spawn(move || {
+    for stream in listener.incoming() {
+      let game = game.clone();
+      thread::spawn(move || handle(stream.unwrap(), &game));
+    }
+  });
+  addr
+}
+
+fn mv(uci: &str) -> Move {
+  Move::from_uci(uci).unwrap()
+}
+
+#[test]
+fn test_base_urls() {
+  assert!(Client::new("http://localhost:8080", TOKEN, Api::Board).is_ok());
+  assert!(Client::new("http://localhost/lichess/", TOKEN, Api::Bot).is_ok());
+  assert!(Client::new("https://lichess.org", TOKEN, Api::Bot).is_err());
+  assert!(Client::new("lichess.org", TOKEN, Api::Bot).is_err());
+}
+
+#[test]
+fn test_accept_a_challenge_and_play_it_out() {
+  let addr = mock();
+  let client =
+    Client::new(&format!("http://{addr}"), TOKEN, Api::Board).unwrap();
+  let stranger = Client::new(&format!("http://{addr}/"), "guess", Api::Board);
+  let refused = stranger.unwrap().account().unwrap_err().to_string();
+  assert!(refused.contains("401 No such token"), "{refused}");
+  let account = client.account().unwrap();
+  assert_eq!(account.username, "Ann");
+
+  let mut events = client.events().unwrap();
+  let Event::Challenge(challenge) = events.next().unwrap().unwrap() else {
+    panic!("no challenge");
+  };
+  assert_eq!(challenge.challenger, "Bob");
+  assert_eq!(challenge.color, Some(Color::Black));
+  assert_eq!(challenge.clock, Some("300+2".parse().unwrap()));
+  client.accept_challenge(&challenge.id).unwrap();
+  let start = events.next().unwrap().unwrap();
+  assert_eq!(start, Event::GameStart { id: "g1".into() });
+  assert!(events.next().is_none());
+
+  let mut game = client.game("g1").unwrap();
+  let Some(Ok(GameEvent::Full(full))) = game.next() else {
+    panic!("no game");
+  };
+  assert_eq!(full.color_of(&account.username), Some(Color::White));
+  assert_eq!(full.clock, Some("300+2".parse().unwrap()));
+  client.chat("g1", Room::Player, "good luck!").unwrap();
+  let Some(Ok(GameEvent::Chat(line))) = game.next() else {
+    panic!("no chat");
+  };
+  assert_eq!(
+    line,
+    ChatLine {
+      username: "Ann".into(),
+      text: "good luck!".into(),
+      room: Room::Player
+    }
+  );
+
+  let mut last = full.state;
+  for ours in ["e2e4", "f1c4", "d1h5", "h5f7"] {
+    client.play("g1", mv(ours)).unwrap();
+    let Some(Ok(GameEvent::State(state))) = game.next() else {
+      panic!("no state after {ours}");
+    };
+    assert_eq!(
+      state.moves.len(),
+      last.moves.len() + 2 - state.is_over() as usize
+    );
+    last = state;
+  }
+  assert!(last.is_over());
+  assert_eq!((last.status.as_str(), last.winner), ("mate", Some(Color::White)));
+  assert!(last.position(&full.start).unwrap().legal_moves().is_empty());
+  assert!(game.next().is_none());
+
+  let late = client.play("g1", mv("a2a3")).unwrap_err().to_string();
+  assert!(late.contains("Not your turn"), "{late}");
+}
@@ -0,0 +1,248 @@
+mod common;
+
+use std::{
+  io::{BufReader, Write},
+  net::TcpStream,
+  thread,
+  time::{Duration, Instant},
+};
+
+use chess::{
+  board::{Color, Position},
+  net::{Event, NetGame},
+  pgn::Outcome,
+  protocol::{read_message, Control, Message, VERSION},
+};
+
+use common::{mv, wait_for};
+
+/// Joins from another thread while the host takes the player in.
+fn join(host: &mut NetGame) -> NetGame {
+  let addr = host.local_addr().unwrap();
+  let client = thread::spawn(move || NetGame::join(addr, "Bob").unwrap());
+  wait_for(host, |event| matches!(event, Event::Connected { .. }));
+  client.join().unwrap()
+}
+
+fn host() -> NetGame {
+  NetGame::host("127.0.0.1:0", "Ann", Color::White, Position::startpos())
+    .unwrap()
+}
+
+#[test]
+fn test_messages_round_trip() {
+  let messages = [
+    Message::Hello { version: VERSION, name: "Ann Smith".into() },
+    Message::Sync {
+      color: Color::Black,
+      start: Position::startpos(),
+      moves: vec![mv("e2e4"), mv("e7e5")],
+    },
+    Message::Sync {
+      color: Color::White,
+      start: Position::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap(),
+      moves: Vec::new(),
+    },
+    Message::Move { ply: 3, mv: mv("e7e8q") },
+    Message::Control {
+      ply: 3,
+      color: Color::White,
+      control: Control::OfferDraw,
+    },
+    Message::End { outcome: Outcome::Unknown, reason: "aborted".into() },
+    Message::Chat { ply: 0, text: "good luck".into() },
+    Message::Error { message: "no such game".into() },
+    Message::Bye,
+  ];
+  for message in messages {
+    let line = message.to_string();
+    assert_eq!(line.parse::<Message>().unwrap(), message, "{line}");
+  }
+  assert!("move 1".parse::<Message>().is_err());
+  assert!("launch".parse::<Message>().is_err());
+}
+
+#[test]
+fn test_long_lines_are_refused() {
+  let text = format!("bye\n\nchat 0 {}\n", "a".repeat(2 << 20));
+  let mut reader = text.as_bytes();
+  assert_eq!(read_message(&mut reader).unwrap(), Some(Message::Bye));
+  let err = read_message(&mut reader).unwrap_err();
+  assert!(err.to_string().contains("line over"), "{err}");
+}
+
+#[test]
+fn test_play_over_loopback() {
+  let mut host = host();
+  let mut client = join(&mut host);
+  assert_eq!(client.color, Color::Black);
+  assert_eq!(client.opponent(), Some("Ann"));
+  assert_eq!(host.opponent(), Some("Bob"));
+
+  assert!(client.play(mv("e7e5")).is_err());
+  assert!(host.play(mv("e2e5")).is_err());
+  host.play(mv("e2e4")).unwrap();
+  wait_for(&mut client, |event| *event == Event::Moved(mv("e2e4")));
+  client.play(mv("e7e5")).unwrap();
+  wait_for(&mut host, |event| *event == Event::Moved(mv("e7e5")));
+  assert_eq!(host.moves(), client.moves());
+  assert_eq!(host.position(), client.position());
+
+  host.say("well played").unwrap();
+  let deadline = Instant::now() + Duration::from_secs(5);
+  while client.chat().is_empty() && Instant::now() < deadline {
+    client.poll();
+    thread::sleep(Duration::from_millis(5));
+  }
+  assert_eq!(client.chat(), host.chat());
+  assert_eq!(
+    (client.chat()[0].ply, client.chat()[0].from.as_str()),
+    (2, "Ann")
+  );
+
+  client.leave();
+  wait_for(&mut host, |event| *event == Event::Disconnected);
+}
+
+#[test]
+fn test_reconnecting_resyncs() {
+  let mut host = host();
+  let mut client = join(&mut host);
+  host.play(mv("d2d4")).unwrap();
+  wait_for(&mut client, |event| *event == Event::Moved(mv("d2d4")));
+  client.play(mv("d7d5")).unwrap();
+  wait_for(&mut host, |event| *event == Event::Moved(mv("d7d5")));
+
+  client.leave();
+  wait_for(&mut host, |event| *event == Event::Disconnected);
+  // the host moves on while the other player is away
+  host.play(mv("c2c4")).unwrap();
+  assert!(!host.is_connected());
+
+  let reconnect = thread::spawn(move || {
+    client.reconnect().unwrap();
+    client
+  });
+  wait_for(&mut host, |event| matches!(event, Event::Connected { .. }));
+  let client = reconnect.join().unwrap();
+  assert_eq!(client.moves(), [mv("d2d4"), mv("d7d5"), mv("c2c4")]);
+  assert!(client.is_our_turn());
+}
+
+#[test]
+fn test_refuses_other_versions_and_resyncs_stray_moves() {
+  let mut host = host();
+  let addr = host.local_addr().unwrap();
+
+  let mut stream = TcpStream::connect(addr).unwrap();
+  writeln!(stream, "hello {} Eve", VERSION + 1).unwrap();
+  let events = wait_for(&mut host, |event| matches!(event, Event::Error(_)));
+  assert!(!events.iter().any(|event| matches!(event, Event::Connected { .. })));
+  let mut reader = BufReader::new(stream);
+  let hello = read_message(&mut reader).unwrap().unwrap();
+  assert!(matches!(hello, Message::Hello { .. }));
+  let refused = read_message(&mut reader).unwrap().unwrap();
+  assert!(matches!(refused, Message::Error { .. }), "{refused}");
+
+  let mut stream = TcpStream::connect(addr).unwrap();
+  writeln!(stream, "hello {VERSION} Eve").unwrap();
+  wait_for(&mut host, |event| matches!(event, Event::Connected { .. }));
+  host.play(mv("e2e4")).unwrap();
+  // a move from a game that went elsewhere gets the game back
+  writeln!(stream, "move 5 e7e5").unwrap();
+  let reader = thread::spawn(move || {
+    let mut reader = BufReader::new(stream);
+    (0..4)
+      .map(|_| read_message(&mut reader).unwrap().unwrap())
+      .collect::<Vec<_>>()
+  });
+  while !reader.is_finished() {
+    host.poll();
+    thread::sleep(Duration::from_millis(5));
+  }
+  let messages = reader.join().unwrap();
+  assert_eq!(
+    messages[1],
+    Message::Sync {
+      color: Color::Black,
+      start: Position::startpos(),
+      moves: Vec::new()
+    }
+  );
+  assert_eq!(messages[2], Message::Move { ply: 0, mv: mv("e2e4") });
+  assert_eq!(
+    messages[3],
+    Message::Sync {
+      color: Color::Black,
+      start: Position::startpos(),
+      moves: vec![mv("e2e4")]
+    }
+  );
+}
+
+#[test]
+fn test_controls_go_through_the_host() {
+  let mut host = host();
+  let mut client = join(&mut host);
+  host.play(mv("e2e4")).unwrap();
+  wait_for(&mut client, |event| *event == Event::Moved(mv("e2e4")));
+  client.play(mv("e7e5")).unwrap();
+  wait_for(&mut host, |event| *event == Event::Moved(mv("e7e5")));
+
+  // the joining side's takeback stands once the host sends it back
+  client.control(Control::AskTakeback).unwrap();
+  assert_eq!(client.offer(), None);
+  let asked = Event::Control(Color::Black, Control::AskTakeback);
+  wait_for(&mut host, |event| *event == asked);
+  wait_for(&mut client, |event| *event == asked);
+  assert_eq!(client.offer(), Some((Control::AskTakeback, Color::Black)));
+  host.control(Control::AcceptTakeback).unwrap();
+  assert_eq!(host.moves(), [mv("e2e4")]);
+  wait_for(&mut client, |event| *event == Event::Synced);
+  assert_eq!(client.moves(), [mv("e2e4")]);
+  assert!(client.is_our_turn());
+
+  host.control(Control::OfferDraw).unwrap();
+  let offered = Event::Control(Color::White, Control::OfferDraw);
+  wait_for(&mut client, |event| *event == offered);
+  assert!(client.allows(Control::DeclineDraw));
+  assert!(!client.allows(Control::AcceptTakeback));
+  client.control(Control::DeclineDraw).unwrap();
+  let declined = Event::Control(Color::Black, Control::DeclineDraw);
+  wait_for(&mut host, |event| *event == declined);
+  assert_eq!(host.offer(), None);
+
+  client.control(Control::Resign).unwrap();
+  wait_for(&mut host, |event| matches!(event, Event::Ended(..)));
+  wait_for(&mut client, |event| matches!(event, Event::Ended(..)));
+  let resigned = (Outcome::WhiteWins, "resignation".to_string());
+  assert_eq!(client.result(), Some(&resigned));
+  assert_eq!(host.result(), Some(&resigned));
+  assert!(host.play(mv("d2d4")).is_err());
+}
+
+#[test]
+fn test_the_game_of_a_player_who_left_can_be_claimed() {
+  let mut host = host();
+  let mut client = join(&mut host);
+  host.play(mv("e2e4")).unwrap();
+  wait_for(&mut client, |event| *event == Event::Moved(mv("e2e4")));
+  assert!(!host.allows(Control::ClaimWin));
+
+  client.leave();
+  wait_for(&mut host, |event| *event == Event::Disconnected);
+  host.control(Control::ClaimWin).unwrap();
+  let claimed = (Outcome::WhiteWins, "abandonment".to_string());
+  assert_eq!(host.result(), Some(&claimed));
+
+  // coming back shows how it ended
+  let reconnect = thread::spawn(move || {
+    client.reconnect().unwrap();
+    client
+  });
+  wait_for(&mut host, |event| matches!(event, Event::Connected { .. }));
+  let mut client = reconnect.join().unwrap();
+  wait_for(&mut client, |event| matches!(event, Event::Ended(..)));
+  assert_eq!(client.result(), Some(&claimed));
+  assert!(!client.is_our_turn());
+}
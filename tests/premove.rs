@@ -0,0 +1,96 @@
+use chess::{
+  board::{Color, Move, Piece, PieceKind, Position, Square},
+  premove::{self, Premoves},
+};
+
+fn sq(name: &str) -> Square {
+  name.parse().unwrap()
+}
+
+fn mv(uci: &str) -> Move {
+  Move::from_uci(uci).unwrap()
+}
+
+fn after(ucis: &[&str]) -> Position {
+  let mut position = Position::startpos();
+  for uci in ucis {
+    position.play(mv(uci));
+  }
+  position
+}
+
+#[test]
+fn test_premoves_are_checked_on_the_board_they_leave() {
+  // black to move, white premoves
+  let position = after(&["e2e4"]);
+  let mut premoves = Premoves::default();
+  // the pawn may take on an empty square, in case something comes there
+  assert!(premoves.push(&position, Color::White, sq("d2"), sq("e3")).is_ok());
+  assert!(premoves.push(&position, Color::White, sq("e4"), sq("d5")).is_ok());
+  assert!(premoves.push(&position, Color::White, sq("e7"), sq("e5")).is_err());
+  assert!(premoves.push(&position, Color::White, sq("g1"), sq("e2")).is_ok());
+  // the knight is on e2 on the imagined board, the bishop can't land there
+  assert!(premoves.push(&position, Color::White, sq("f1"), sq("e2")).is_err());
+  assert!(premoves.push(&position, Color::White, sq("d5"), sq("d6")).is_ok());
+  assert!(premoves.push(&position, Color::White, sq("d1"), sq("e4")).is_err());
+  assert_eq!(
+    premoves.moves(),
+    [mv("d2e3"), mv("e4d5"), mv("g1e2"), mv("d5d6")]
+  );
+}
+
+#[test]
+fn test_pawns_premove_to_a_queen() {
+  let position = Position::from_fen("4k3/1P6/8/8/8/8/8/4K3 b - - 0 1").unwrap();
+  let mut premoves = Premoves::default();
+  let queen = premoves.push(&position, Color::White, sq("b7"), sq("b8"));
+  assert_eq!(queen.unwrap().promotion, Some(PieceKind::Queen));
+  // the new queen moves on
+  assert!(premoves.push(&position, Color::White, sq("b8"), sq("h2")).is_ok());
+
+  let white = Position::from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+  let dragged = premove::dragged(&white, sq("b7"), sq("b8")).unwrap();
+  assert_eq!(dragged.promotion, Some(PieceKind::Queen));
+  assert_eq!(premove::dragged(&white, sq("b7"), sq("c8")), None);
+}
+
+#[test]
+fn test_premoves_play_until_one_is_illegal() {
+  let mut premoves = Premoves::default();
+  let position = after(&["e2e4"]);
+  premoves.push(&position, Color::White, sq("e4"), sq("d5")).unwrap();
+  premoves.push(&position, Color::White, sq("g1"), sq("f3")).unwrap();
+  premoves.push(&position, Color::White, sq("f1"), sq("c4")).unwrap();
+
+  // black went d5, the capture is on
+  let position = after(&["e2e4", "d7d5"]);
+  assert_eq!(premoves.next(&position), Some(mv("e4d5")));
+  let position = after(&["e2e4", "d7d5", "e4d5", "d8d5"]);
+  assert_eq!(premoves.next(&position), Some(mv("g1f3")));
+  // black blocked the bishop, the rest is dropped
+  let position = after(&["e2e4", "d7d5", "e4d5", "d8d5", "g1f3", "d5d3"]);
+  assert_eq!(premoves.next(&position), None);
+  assert!(premoves.is_empty());
+}
+
+#[test]
+fn test_castling_premoves_take_the_rook_along() {
+  let position =
+    Position::from_fen("r3k2r/p7/8/8/8/8/P7/R3K2R b KQkq - 0 1").unwrap();
+  let mut premoves = Premoves::default();
+  premoves.push(&position, Color::White, sq("e1"), sq("g1")).unwrap();
+  // the rook is on f1 now and h1 is empty
+  assert!(premoves.push(&position, Color::White, sq("h1"), sq("h5")).is_err());
+  assert!(premoves.push(&position, Color::White, sq("g1"), sq("f1")).is_err());
+  premoves.push(&position, Color::White, sq("f1"), sq("f7")).unwrap();
+  let king = Piece::new(Color::White, PieceKind::King);
+  let rook = Piece::new(Color::White, PieceKind::Rook);
+  assert_eq!(premoves.ghosts(&position), [(sq("g1"), king), (sq("f7"), rook)]);
+
+  // the other way the rook lands on d1
+  let mut premoves = Premoves::default();
+  premoves.push(&position, Color::White, sq("e1"), sq("c1")).unwrap();
+  assert!(premoves.push(&position, Color::White, sq("a1"), sq("a3")).is_err());
+  premoves.push(&position, Color::White, sq("d1"), sq("d8")).unwrap();
+  assert_eq!(premoves.ghosts(&position), [(sq("c1"), king), (sq("d8"), rook)]);
+}
@@ -0,0 +1,98 @@
+use chess::{
+  board::Move,
+  glicko::{self, Rating},
+  puzzle::{self, Attempt, Trainer, Verdict},
+};
+
+fn mv(uci: &str) -> Move {
+  Move::from_uci(uci).unwrap()
+}
+
+// back rank mate with a choice of rooks, and a knight fork
+const PUZZLES: &str = "\
+PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,GameUrl,OpeningTags
+00001,6k1/5ppp/8/8/8/8/5PPP/R2R2K1 b - - 0 1,g8h8 a1a8,900,80,95,1000,mate mateIn1 backRankMate short,https://lichess.org/x,
+00002,8/8/8/8/8/8/k7/4K2R w - - 0 1,e1f1 a2b2,1400,75,90,500,endgame short,https://lichess.org/y,
+00003,4k3/8/8/3q4/8/8/8/2N1K3 b - - 0 1,e8e7 c1b3 d5d1 e1d1,1800,90,80,200,fork,https://lichess.org/z,
+";
+
+#[test]
+fn test_glicko2_paper_example() {
+  let player = Rating::new(1500.0, 200.0);
+  let games = [
+    (Rating::new(1400.0, 30.0), 1.0),
+    (Rating::new(1550.0, 100.0), 0.0),
+    (Rating::new(1700.0, 300.0), 0.0),
+  ];
+  let updated = player.updated(&games, 0.5);
+  assert!((updated.rating - 1464.06).abs() < 0.01, "{updated:?}");
+  assert!((updated.deviation - 151.52).abs() < 0.01, "{updated:?}");
+  assert!((updated.volatility - 0.059996).abs() < 1e-6, "{updated:?}");
+
+  let idle = player.updated(&[], 0.5);
+  assert_eq!(idle.rating, 1500.0);
+  assert!(idle.deviation > 200.0);
+}
+
+#[test]
+fn test_csv_and_themes() {
+  let puzzles = puzzle::parse_csv(PUZZLES).unwrap();
+  assert_eq!(puzzles.len(), 3);
+  assert_eq!(puzzles[0].id, "00001");
+  assert_eq!(puzzles[0].rating.rating, 900.0);
+  assert!(puzzles[0].has_theme("backRankMate"));
+  assert_eq!(puzzles[2].moves.len(), 4);
+  assert_eq!(
+    puzzle::themes(&puzzles),
+    ["backRankMate", "endgame", "fork", "mate", "mateIn1", "short"]
+  );
+  assert!(
+    puzzle::parse_csv("1,8/8/8/8/8/8/8/K6k w - - 0 1,a1a3,1,1,1,1,x").is_err()
+  );
+}
+
+#[test]
+fn test_alternative_mates_are_accepted() {
+  let puzzles = puzzle::parse_csv(PUZZLES).unwrap();
+  let mut attempt = Attempt::new(puzzles[0].clone());
+  assert_eq!(attempt.play(mv("d1d8")), Verdict::Solved);
+  assert!(attempt.is_solved());
+  assert!(!attempt.has_failed());
+}
+
+#[test]
+fn test_replies_and_wrong_moves() {
+  let puzzles = puzzle::parse_csv(PUZZLES).unwrap();
+  let mut attempt = Attempt::new(puzzles[2].clone());
+  // illegal moves are typos, they do not fail the puzzle
+  assert_eq!(attempt.play(mv("c1c3")), Verdict::Wrong);
+  assert!(!attempt.has_failed());
+  assert_eq!(attempt.play(mv("c1b3")), Verdict::Correct { reply: mv("d5d1") });
+  assert_eq!(attempt.play(mv("e1f2")), Verdict::Wrong);
+  assert!(attempt.has_failed());
+  assert_eq!(attempt.hint(), Some(mv("e1d1")));
+  assert_eq!(attempt.play(mv("e1d1")), Verdict::Solved);
+}
+
+#[test]
+fn test_trainer_picks_by_rating_and_theme() {
+  let puzzles = puzzle::parse_csv(PUZZLES).unwrap();
+  let mut trainer = Trainer::default();
+  assert_eq!(trainer.next(&puzzles, None).unwrap().id, "00002");
+  assert_eq!(trainer.next(&puzzles, Some("mate")).unwrap().id, "00001");
+  assert!(trainer.next(&puzzles, Some("pin")).is_none());
+
+  trainer.record(&puzzles[1], true);
+  assert!(trainer.rating.rating > 1500.0);
+  assert!(trainer.rating.deviation < 350.0);
+  assert_eq!(trainer.next(&puzzles, None).unwrap().id, "00003");
+  let before = trainer.rating.rating;
+  trainer.record(&puzzles[2], false);
+  assert!(trainer.rating.rating < before);
+
+  let restored = Trainer::from_text(&trainer.to_text()).unwrap();
+  assert_eq!(restored.rating, trainer.rating);
+  assert_eq!((restored.solved, restored.failed), (1, 1));
+  assert_eq!(restored.attempted, trainer.attempted);
+  assert_eq!(glicko::TAU, 0.5);
+}
@@ -0,0 +1,99 @@
+use chess::{
+  database::Database,
+  glicko::Rating,
+  pgn,
+  ratings::{Category, Ratings},
+};
+
+fn game(
+  white: &str,
+  black: &str,
+  tags: &str,
+  result: &str,
+) -> String {
+  format!(
+    "[White \"{white}\"]\n[Black \"{black}\"]\n{tags}[Result \"{result}\"]\n\n\
+     1. e4 e5 {result}\n\n"
+  )
+}
+
+fn games() -> Vec<pgn::Game> {
+  let blitz = "[TimeControl \"180+2\"]\n";
+  let text = [
+    game("Ann", "Bob", blitz, "1-0"),
+    game("Bob", "Cid", blitz, "1/2-1/2"),
+    game("Cid", "Ann", blitz, "0-1"),
+    // not rated: casual, unfinished, unnamed, no clock
+    game("Bob", "Ann", "[TimeControl \"180+2\"]\n[Rated \"false\"]\n", "1-0"),
+    game("Bob", "Ann", blitz, "*"),
+    game("?", "Ann", blitz, "0-1"),
+    game("Bob", "Ann", "", "1-0"),
+    game("Bob", "Ann", "[DaysPerMove \"3\"]\n", "1-0"),
+  ]
+  .concat();
+  pgn::parse(&text).unwrap()
+}
+
+#[test]
+fn test_categories() {
+  let of = |control: &str| Category::of(&control.parse().unwrap());
+  assert_eq!(of("120+1"), Some(Category::Bullet));
+  assert_eq!(of("120+2"), Some(Category::Blitz));
+  assert_eq!(of("300"), Some(Category::Blitz));
+  assert_eq!(of("600+5"), Some(Category::Rapid));
+  assert_eq!(of("1800"), Some(Category::Classical));
+  assert_eq!(of("depth=8"), None);
+  for category in Category::ALL {
+    assert_eq!(category.to_string().parse::<Category>().unwrap(), category);
+  }
+  let games = games();
+  assert_eq!(Category::of_game(&games[0]), Some(Category::Blitz));
+  assert_eq!(Category::of_game(&games[6]), None);
+  assert_eq!(Category::of_game(&games[7]), Some(Category::Correspondence));
+}
+
+#[test]
+fn test_rate_a_database() {
+  let path = std::env::temp_dir().join("chess-ratings-test.chdb");
+  let _ = std::fs::remove_file(&path);
+  let games = games();
+  let mut database = Database::open(&path).unwrap();
+  database.import(&games[..1]).unwrap();
+
+  let mut ratings = Ratings::default();
+  assert_eq!(ratings.catch_up(&database).unwrap(), 1);
+  let ann = ratings.rating("Ann", Category::Blitz);
+  assert!(ann.rating > 1500.0 && ann.deviation < 350.0);
+  assert!(ratings.rating("Bob", Category::Blitz).rating < 1500.0);
+  assert_eq!(ratings.rating("Cid", Category::Blitz), Rating::default());
+
+  database.import(&games[1..]).unwrap();
+  assert_eq!(ratings.catch_up(&database).unwrap(), 3);
+  assert_eq!(ratings.games(), games.len());
+  assert_eq!(ratings.catch_up(&database).unwrap(), 0);
+  assert_eq!(ratings, Ratings::recompute(&database).unwrap());
+  // rating the games as they are added comes to the same
+  let mut added = Ratings::default();
+  for game in &games {
+    added.add(game);
+  }
+  assert_eq!(added, ratings);
+
+  let leaderboard = ratings.leaderboard(Category::Blitz);
+  let names: Vec<&str> = leaderboard.iter().map(|(name, _)| *name).collect();
+  assert_eq!(names, ["Ann", "Bob", "Cid"]);
+  let (_, ann) = leaderboard[0];
+  assert_eq!(ann.games(), 2);
+  assert!(ann.is_provisional());
+  let correspondence = ratings.leaderboard(Category::Correspondence);
+  assert_eq!(correspondence[0].0, "Bob");
+  assert!(ratings.player("Ann", Category::Bullet).is_none());
+
+  let file = std::env::temp_dir().join("chess-ratings-test.txt");
+  ratings.save(&file).unwrap();
+  assert_eq!(Ratings::load(&file).unwrap(), ratings);
+  assert_eq!(Ratings::from_text(&ratings.to_text()).unwrap(), ratings);
+  std::fs::remove_file(&file).unwrap();
+  assert_eq!(Ratings::load(&file).unwrap(), Ratings::default());
+  std::fs::remove_file(&path).unwrap();
+}
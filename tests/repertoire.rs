@@ -0,0 +1,91 @@
+use chess::{
+  board::{Color, Move},
+  repertoire::{Card, Drill, Repertoire, Schedule, Step},
+};
+
+fn mv(uci: &str) -> Move {
+  Move::from_uci(uci).unwrap()
+}
+
+// as black against 1. e4 and 1. d4, the e4 line with a sideline
+const REPERTOIRE: &str = "\
+1. e4 c5 2. Nf3 (2. c3 d5) 2... d6 3. d4 *
+
+1. d4 Nf6 2. c4 e6 *
+";
+
+#[test]
+fn test_lines_end_with_own_moves() {
+  let repertoire = Repertoire::from_pgn(Color::Black, REPERTOIRE).unwrap();
+  let keys: Vec<String> =
+    repertoire.lines.iter().map(|line| line.key()).collect();
+  assert_eq!(
+    keys,
+    ["e2e4 c7c5 g1f3 d7d6", "e2e4 c7c5 c2c3 d7d5", "d2d4 g8f6 c2c4 e7e6"]
+  );
+  let after_e4 = repertoire.lines[0].start.played(mv("e2e4"));
+  assert_eq!(repertoire.moves_at(&after_e4), [mv("c7c5")]);
+
+  let white = Repertoire::from_pgn(Color::White, REPERTOIRE).unwrap();
+  assert_eq!(white.lines[0].key(), "e2e4 c7c5 g1f3 d7d6 d2d4");
+  assert_eq!(white.lines[1].key(), "e2e4 c7c5 c2c3");
+  assert!(Repertoire::from_pgn(Color::Black, "1. e4 *").is_err());
+}
+
+#[test]
+fn test_drills_play_the_other_side() {
+  let repertoire = Repertoire::from_pgn(Color::Black, REPERTOIRE).unwrap();
+  let mut drill = Drill::new(repertoire.lines[1].clone(), Color::Black);
+  assert_eq!(drill.played(), [mv("e2e4")]);
+  assert_eq!(drill.play(mv("e7e5"), &repertoire), Step::Wrong);
+  assert_eq!(
+    drill.play(mv("c7c5"), &repertoire),
+    Step::Correct { reply: mv("c2c3") }
+  );
+  assert_eq!(drill.play(mv("d7d5"), &repertoire), Step::Done);
+  assert!(drill.is_done());
+  assert_eq!(drill.quality(), 3);
+
+  // the other line's move is no mistake
+  let two = Repertoire::from_pgn(Color::Black, "1. e4 c5 (1... e5) *").unwrap();
+  let mut drill = Drill::new(two.lines[0].clone(), Color::Black);
+  assert_eq!(drill.play(mv("e7e5"), &two), Step::Alternative);
+  assert_eq!(drill.play(mv("c7c5"), &two), Step::Done);
+  assert_eq!(drill.quality(), 5);
+  let mut shown = Drill::new(repertoire.lines[2].clone(), Color::Black);
+  assert_eq!(shown.show(), Some(mv("g8f6")));
+  assert_eq!(shown.quality(), 0);
+}
+
+#[test]
+fn test_sm2_schedule() {
+  let mut card = Card::default();
+  card.review(5, 100);
+  assert_eq!((card.interval, card.due), (1, 101));
+  card.review(5, 101);
+  assert_eq!((card.interval, card.due), (6, 107));
+  card.review(4, 107);
+  assert_eq!(card.interval, 16);
+  assert!((card.ease - 2.7).abs() < 1e-9);
+  card.review(1, 123);
+  assert_eq!((card.interval, card.repetitions, card.due), (1, 0, 124));
+  assert!(card.ease < 2.7);
+
+  let repertoire = Repertoire::from_pgn(Color::Black, REPERTOIRE).unwrap();
+  let mut schedule = Schedule::default();
+  assert_eq!(schedule.due(&repertoire, 10), 3);
+  schedule.record(&repertoire.lines[0], 5, 10);
+  schedule.record(&repertoire.lines[1], 5, 8);
+  assert_eq!(schedule.due(&repertoire, 10), 2);
+  // overdue reviews before new lines
+  assert_eq!(schedule.next(&repertoire, 10), Some(1));
+  schedule.record(&repertoire.lines[1], 5, 10);
+  assert_eq!(schedule.next(&repertoire, 10), Some(2));
+  schedule.record(&repertoire.lines[2], 5, 10);
+  assert_eq!(schedule.next(&repertoire, 10), None);
+
+  let text = schedule.to_text();
+  let loaded = Schedule::from_text(&text).unwrap();
+  assert_eq!(loaded.cards, schedule.cards);
+  assert!(Schedule::from_text("1 2 3\tx").is_err());
+}
@@ -0,0 +1,65 @@
+use chess::{
+  analysis::Analyzer,
+  board::Color,
+  pgn,
+  review::{self, Judgement},
+  search::{Engine, Limits, Score},
+};
+
+const SCHOLARS_MATE: &str = "1. e4 e5 2. Bc4 Nc6 3. Qh5 Nf6 4. Qxf7# 1-0";
+
+#[test]
+fn test_judgements_and_accuracy() {
+  assert_eq!(Judgement::of(2.0), None);
+  assert_eq!(Judgement::of(7.0), Some(Judgement::Inaccuracy));
+  assert_eq!(Judgement::of(12.0), Some(Judgement::Mistake));
+  assert_eq!(Judgement::of(40.0), Some(Judgement::Blunder));
+  assert!((review::move_accuracy(0.0) - 100.0).abs() < 0.01);
+  assert!(review::move_accuracy(10.0) < review::move_accuracy(5.0));
+  assert_eq!(review::move_accuracy(100.0), 0.0);
+  assert_eq!(review::white_win(Score::Cp(0)), 50.0);
+  assert_eq!(review::white_win(Score::Mate(-2)), 0.0);
+  assert!(review::white_win(Score::Cp(300)) > 75.0);
+}
+
+#[test]
+fn test_review_finds_the_blunder() {
+  let (_, mut tree) = pgn::parse_trees(SCHOLARS_MATE).unwrap().remove(0);
+  let mut analyzer = Analyzer::BuiltIn(Engine::new());
+  let limits = Limits { depth: Some(4), ..Default::default() };
+  let mut progress = Vec::new();
+  let review = review::review(&mut analyzer, &tree, &limits, |done, total| {
+    progress.push((done, total))
+  })
+  .unwrap();
+  assert_eq!(progress.last(), Some(&(8, 8)));
+  assert_eq!(review.evals.len(), 8);
+  assert_eq!(review.moves.len(), 7);
+  // the game ends in mate, there is nothing left to search
+  assert_eq!(review.evals[7].score, None);
+  assert_eq!(review.evals[7].white_win, 100.0);
+
+  let nf6 = &review.moves[5];
+  assert_eq!(nf6.color, Color::Black);
+  assert_eq!(nf6.judgement, Some(Judgement::Blunder));
+  assert_eq!(review.count(Color::Black, Judgement::Blunder), 1);
+  assert_eq!(review.count(Color::White, Judgement::Blunder), 0);
+  assert!(review.accuracy(Color::White) > review.accuracy(Color::Black));
+
+  review.annotate(&mut tree).unwrap();
+  let node = tree.node(tree.mainline()[5]);
+  assert_eq!(node.annotations.nags, [4]);
+  assert!(node.annotations.comment.starts_with("Blunder."));
+  assert_eq!(node.annotations.eval, Some(Score::Mate(1)));
+  let parent = tree.node(node.parent.unwrap());
+  assert_eq!(parent.children.len(), 2);
+
+  // annotating again does not pile up
+  review.annotate(&mut tree).unwrap();
+  let text = pgn::Game::default().to_pgn_with(&tree);
+  assert_eq!(text.matches("Blunder.").count(), 1);
+  assert!(text.contains("3... Nf6?? {[%eval #1] Blunder."));
+  let (_, reread) = pgn::parse_trees(&text).unwrap().remove(0);
+  let reread = reread.node(reread.mainline()[5]);
+  assert_eq!(reread.annotations, tree.node(tree.mainline()[5]).annotations);
+}
@@ -0,0 +1,76 @@
+use chess::{
+  board::{Move, Position},
+  search::{Engine, Limits, Score},
+  uci,
+};
+
+fn search(
+  engine: &mut Engine,
+  fen: &str,
+  depth: u32,
+) -> (Option<Move>, Score, u64) {
+  let position = Position::from_fen(fen).unwrap();
+  let result = engine.search(position, Vec::new(), Limits::depth(depth));
+  let info = result.info.unwrap();
+  (result.best_move, info.score, info.nodes)
+}
+
+#[test]
+fn test_finds_mate() {
+  let mut engine = Engine::new();
+  // back rank mate
+  let fen = "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1";
+  let (best, score, _) = search(&mut engine, fen, 4);
+  assert_eq!(best, Some(Move::from_uci("d1d8").unwrap()));
+  assert_eq!(score, Score::Mate(1));
+}
+
+#[test]
+fn test_single_thread_is_deterministic() {
+  let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+  let mut engine = Engine::new();
+  let first = search(&mut engine, fen, 6);
+  engine.new_game();
+  let second = search(&mut engine, fen, 6);
+  assert_eq!(first, second);
+}
+
+#[test]
+fn test_threads_agree_on_mate() {
+  let mut engine = Engine::new();
+  engine.set_threads(4);
+  // the king on g6 guards Qg7#
+  let fen = "7k/5Q2/6K1/8/8/8/8/8 w - - 0 1";
+  let (_, score, _) = search(&mut engine, fen, 6);
+  assert_eq!(score, Score::Mate(1));
+  let fen = "r1b1k2r/ppppqppp/2n5/8/1bB5/5N2/PPPQ1PPP/RNB1K2R w KQkq - 0 1";
+  let position = Position::from_fen(fen).unwrap();
+  let result = engine.search(position.clone(), Vec::new(), Limits::depth(5));
+  assert!(position.is_legal(result.best_move.unwrap()));
+}
+
+#[test]
+fn test_uci_session() {
+  let input = "uci\nsetoption name Threads value 2\nisready\n\
+    position startpos moves e2e4 e7e5\ngo depth 3\nquit\n";
+  let output = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+  struct Shared(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+  impl std::io::Write for Shared {
+    fn write(
+      &mut self,
+      buf: &[u8],
+    ) -> std::io::Result<usize> {
+      self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+      Ok(())
+    }
+  }
+  uci::run(input.as_bytes(), Shared(output.clone())).unwrap();
+  let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+  assert!(output.contains("option name Threads type spin"));
+  assert!(output.contains("uciok"));
+  assert!(output.contains("readyok"));
+  assert!(output.lines().any(|line| line.starts_with("bestmove ")));
+}
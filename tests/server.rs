@@ -0,0 +1,400 @@
+mod common;
+
+use std::{
+  io::{BufReader, Write},
+  net::{SocketAddr, TcpListener, TcpStream},
+  thread,
+  time::{Duration, Instant},
+};
+
+use chess::{
+  board::{Color, Position},
+  chat::{RateLimit, WordList},
+  client::ServerClient,
+  database::Database,
+  pgn::Outcome,
+  protocol::{
+    read, ChatLine, Control, GameInfo, Request, Room, Seek, Update, VERSION,
+  },
+  ratings::{Category, Ratings},
+  server::{self, Server},
+  transport::Transport,
+};
+
+use common::{mv, wait_for};
+
+fn serve() -> SocketAddr {
+  server::spawn("127.0.0.1:0", "test").unwrap()
+}
+
+fn moved(uci: &str) -> impl Fn(&Update) -> bool {
+  let played = mv(uci);
+  move |update| matches!(update, Update::Move { mv, .. } if *mv == played)
+}
+
+/// Ann seeks as white, Bob accepts; returns the game id.
+fn start_game(
+  ann: &mut ServerClient,
+  bob: &mut ServerClient,
+  control: &str,
+) -> u32 {
+  ann.seek(control.parse().unwrap(), Some(Color::White)).unwrap();
+  wait_for(bob, |update| matches!(update, Update::Seek(_)));
+  let seek = *bob.seeks.keys().next().unwrap();
+  bob.accept(seek).unwrap();
+  let updates = wait_for(ann, |update| matches!(update, Update::Game { .. }));
+  let Some(Update::Game { info, .. }) =
+    updates.iter().find(|update| matches!(update, Update::Game { .. }))
+  else {
+    unreachable!()
+  };
+  wait_for(bob, |update| matches!(update, Update::Game { .. }));
+  info.id
+}
+
+#[test]
+fn test_messages_round_trip() {
+  let control = "300+2".parse().unwrap();
+  let requests = [
+    Request::Hello { version: VERSION, name: "Ann".into() },
+    Request::Seek { control, color: None },
+    Request::Challenge { to: "Bob".into(), control, color: Some(Color::Black) },
+    Request::Cancel { seek: 3 },
+    Request::Accept { seek: 3 },
+    Request::Move { game: 4, ply: 0, mv: mv("e2e4") },
+    Request::Control { game: 4, ply: 0, control: Control::Abort },
+    Request::Watch { game: 4 },
+    Request::Unwatch { game: 4 },
+    Request::Chat { game: 4, text: "good luck".into() },
+    Request::Bye,
+  ];
+  for request in requests {
+    let line = request.to_string();
+    assert_eq!(line.parse::<Request>().unwrap(), request, "{line}");
+  }
+  let info =
+    GameInfo { id: 4, white: "Ann".into(), black: "Bob".into(), control };
+  let clocks = [Duration::from_millis(299_500), Duration::from_secs(300)];
+  let updates = [
+    Update::Hello { version: VERSION, name: "server".into() },
+    Update::Seek(Seek {
+      id: 3,
+      from: "Ann".into(),
+      control,
+      color: Some(Color::White),
+      to: Some("Bob".into()),
+    }),
+    Update::Unseek { seek: 3 },
+    Update::Listed(info.clone()),
+    Update::Game {
+      info,
+      color: None,
+      start: Position::startpos(),
+      moves: vec![mv("e2e4")],
+      clocks,
+    },
+    Update::Move { game: 4, ply: 1, mv: mv("e7e5"), clocks },
+    Update::Control {
+      game: 4,
+      ply: 2,
+      color: Color::White,
+      control: Control::DeclineDraw,
+    },
+    Update::Gone { game: 4, color: Color::Black },
+    Update::Chat {
+      game: 4,
+      line: ChatLine {
+        ply: 2,
+        room: Room::Spectators,
+        from: "Eve".into(),
+        text: "the Petroff again".into(),
+      },
+    },
+    Update::End { game: 4, outcome: Outcome::Draw, reason: "stalemate".into() },
+    Update::Error { message: "no game 5".into() },
+  ];
+  for update in updates {
+    let line = update.to_string();
+    assert_eq!(line.parse::<Update>().unwrap(), update, "{line}");
+  }
+  assert!("seek 300 red".parse::<Request>().is_err());
+  assert!("seek depth=5 any".parse::<Request>().is_err());
+}
+
+#[test]
+fn test_seek_play_and_watch() {
+  let addr = serve();
+  let mut ann = ServerClient::connect(addr, "Ann", Transport::Tcp).unwrap();
+  let mut bob = ServerClient::connect(addr, "Bob", Transport::Tcp).unwrap();
+  assert_eq!(bob.server, "test");
+  assert!(ServerClient::connect(addr, "Ann", Transport::Tcp).is_err());
+  assert!(ServerClient::connect(addr, "Ann Smith", Transport::Tcp).is_err());
+
+  let game = start_game(&mut ann, &mut bob, "300+2");
+  assert!(ann.seeks.is_empty() && bob.seeks.is_empty());
+  assert_eq!(ann.games[&game].color, Some(Color::White));
+  assert_eq!(bob.games[&game].color, Some(Color::Black));
+  assert!(bob.play(game, mv("e7e5")).is_err());
+
+  let mut eve = ServerClient::connect(addr, "Eve", Transport::Tcp).unwrap();
+  wait_for(&mut eve, |update| matches!(update, Update::Listed(_)));
+  eve.watch(game).unwrap();
+  wait_for(&mut eve, |update| matches!(update, Update::Game { .. }));
+  assert_eq!(eve.games[&game].color, None);
+
+  ann.play(game, mv("e2e4")).unwrap();
+  wait_for(&mut bob, |update| matches!(update, Update::Move { .. }));
+  wait_for(&mut eve, |update| matches!(update, Update::Move { .. }));
+  let clock = bob.games[&game].clock(Color::White, Instant::now());
+  assert!(clock > Duration::from_secs(300), "{clock:?}");
+  assert_eq!(eve.games[&game].moves, [mv("e2e4")]);
+
+  // a move the server refuses comes back with the game
+  bob.send(&Request::Move { game, ply: 1, mv: mv("e7e4") }).unwrap();
+  let updates =
+    wait_for(&mut bob, |update| matches!(update, Update::Game { .. }));
+  assert!(updates.iter().any(|update| matches!(update, Update::Error { .. })));
+  assert_eq!(bob.games[&game].moves, [mv("e2e4")]);
+
+  wait_for(&mut ann, moved("e2e4"));
+  bob.play(game, mv("f7f6")).unwrap();
+  wait_for(&mut ann, moved("f7f6"));
+  ann.play(game, mv("d2d4")).unwrap();
+  wait_for(&mut bob, moved("d2d4"));
+  bob.play(game, mv("g7g5")).unwrap();
+  wait_for(&mut ann, moved("g7g5"));
+  ann.play(game, mv("d1h5")).unwrap();
+  let updates =
+    wait_for(&mut eve, |update| matches!(update, Update::End { .. }));
+  assert!(updates.contains(&Update::End {
+    game,
+    outcome: Outcome::WhiteWins,
+    reason: "checkmate".into()
+  }));
+  wait_for(&mut bob, |update| matches!(update, Update::End { .. }));
+  assert_eq!(bob.games[&game].result.as_ref().unwrap().0, Outcome::WhiteWins);
+  assert!(bob.listed.is_empty());
+}
+
+#[test]
+fn test_flags_fall_on_the_server() {
+  let addr = serve();
+  let mut ann = ServerClient::connect(addr, "Ann", Transport::Tcp).unwrap();
+  let mut bob = ServerClient::connect(addr, "Bob", Transport::Tcp).unwrap();
+  let game = start_game(&mut ann, &mut bob, "0.3");
+  let updates =
+    wait_for(&mut bob, |update| matches!(update, Update::End { .. }));
+  assert!(updates.contains(&Update::End {
+    game,
+    outcome: Outcome::BlackWins,
+    reason: "time".into()
+  }));
+  wait_for(&mut ann, |update| matches!(update, Update::End { .. }));
+  assert!(ann.play(game, mv("e2e4")).is_err());
+}
+
+#[test]
+fn test_reconnecting_gets_the_games_back() {
+  let addr = serve();
+  let mut ann = ServerClient::connect(addr, "Ann", Transport::Tcp).unwrap();
+  let mut bob = ServerClient::connect(addr, "Bob", Transport::Tcp).unwrap();
+  let game = start_game(&mut ann, &mut bob, "300+0");
+  ann.play(game, mv("g1f3")).unwrap();
+  wait_for(&mut bob, |update| matches!(update, Update::Move { .. }));
+  drop(bob);
+
+  // the name is free once the server saw the goodbye
+  let deadline = Instant::now() + Duration::from_secs(5);
+  let mut bob = loop {
+    match ServerClient::connect(addr, "Bob", Transport::Tcp) {
+      Ok(bob) => break bob,
+      Err(_) if Instant::now() < deadline => {
+        thread::sleep(Duration::from_millis(10))
+      }
+      Err(err) => panic!("{err:#}"),
+    }
+  };
+  wait_for(&mut bob, |update| matches!(update, Update::Game { .. }));
+  let remote = &bob.games[&game];
+  assert_eq!(remote.color, Some(Color::Black));
+  assert_eq!(remote.moves, [mv("g1f3")]);
+  assert!(remote.is_our_turn());
+  assert!(
+    remote.clock(Color::Black, Instant::now()) < Duration::from_secs(300)
+  );
+}
+
+#[test]
+fn test_takebacks_draw_offers_and_claims() {
+  let addr = serve();
+  let mut ann = ServerClient::connect(addr, "Ann", Transport::Tcp).unwrap();
+  let mut bob = ServerClient::connect(addr, "Bob", Transport::Tcp).unwrap();
+  let game = start_game(&mut ann, &mut bob, "300+0");
+  assert!(ann.games[&game].allows(Control::Abort));
+  ann.play(game, mv("e2e4")).unwrap();
+  wait_for(&mut bob, moved("e2e4"));
+  bob.play(game, mv("e7e5")).unwrap();
+  wait_for(&mut ann, moved("e7e5"));
+  wait_for(&mut bob, moved("e7e5"));
+  assert!(!ann.games[&game].allows(Control::Abort));
+  assert!(ann.control(game, Control::AcceptDraw).is_err());
+
+  bob.control(game, Control::AskTakeback).unwrap();
+  wait_for(&mut ann, |update| matches!(update, Update::Control { .. }));
+  let offer = ann.games[&game].offer();
+  assert_eq!(offer, Some((Control::AskTakeback, Color::Black)));
+  ann.control(game, Control::AcceptTakeback).unwrap();
+  wait_for(&mut bob, |update| matches!(update, Update::Game { .. }));
+  wait_for(&mut ann, |update| matches!(update, Update::Game { .. }));
+  assert_eq!(bob.games[&game].moves, [mv("e2e4")]);
+  assert!(bob.games[&game].is_our_turn());
+
+  ann.control(game, Control::OfferDraw).unwrap();
+  let offered = |update: &Update| {
+    matches!(update, Update::Control { control: Control::OfferDraw, .. })
+  };
+  wait_for(&mut bob, offered);
+  bob.control(game, Control::AcceptDraw).unwrap();
+  wait_for(&mut ann, |update| matches!(update, Update::End { .. }));
+  let agreed = Some((Outcome::Draw, "agreement".to_string()));
+  assert_eq!(ann.games[&game].result, agreed);
+
+  // a player who left loses the game on a claim
+  let game = start_game(&mut ann, &mut bob, "300+0");
+  assert!(!ann.games[&game].allows(Control::ClaimWin));
+  drop(bob);
+  let gone = |update: &Update| matches!(update, Update::Gone { .. });
+  wait_for(&mut ann, gone);
+  assert_eq!(ann.games[&game].away, [false, true]);
+  ann.control(game, Control::ClaimWin).unwrap();
+  wait_for(&mut ann, |update| matches!(update, Update::End { .. }));
+  let claimed = Some((Outcome::WhiteWins, "abandonment".to_string()));
+  assert_eq!(ann.games[&game].result, claimed);
+}
+
+#[test]
+fn test_players_and_spectators_chat_apart() {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  let mut server = Server::new("test");
+  server.add_filter(WordList::new(["darn"]));
+  server.set_chat_limit(RateLimit::new(2, Duration::from_secs(60)));
+  thread::spawn(move || server.run(listener));
+  let mut ann = ServerClient::connect(addr, "Ann", Transport::Tcp).unwrap();
+  let mut bob = ServerClient::connect(addr, "Bob", Transport::Tcp).unwrap();
+  let game = start_game(&mut ann, &mut bob, "300+0");
+  let chatted = |update: &Update| matches!(update, Update::Chat { .. });
+
+  ann.say(game, "good luck,\n  have fun").unwrap();
+  wait_for(&mut bob, chatted);
+  ann.play(game, mv("e2e4")).unwrap();
+  wait_for(&mut bob, moved("e2e4"));
+  let mut eve = ServerClient::connect(addr, "Eve", Transport::Tcp).unwrap();
+  eve.watch(game).unwrap();
+  wait_for(&mut eve, |update| matches!(update, Update::Game { .. }));
+  eve.say(game, "Darn, the King's pawn!").unwrap();
+  wait_for(&mut eve, chatted);
+  // the spectators did not hear the players, nor the players them
+  let line = |ply, room, from: &str, text: &str| ChatLine {
+    ply,
+    room,
+    from: from.into(),
+    text: text.into(),
+  };
+  let heard = [line(1, Room::Spectators, "Eve", "****, the King's pawn!")];
+  assert_eq!(eve.games[&game].chat, heard);
+  bob.say(game, "thanks").unwrap();
+  wait_for(
+    &mut ann,
+    |update| matches!(update, Update::Chat { line, .. } if line.from == "Bob"),
+  );
+  let heard = [
+    line(0, Room::Players, "Ann", "good luck, have fun"),
+    line(1, Room::Players, "Bob", "thanks"),
+  ];
+  assert_eq!(ann.games[&game].chat, heard);
+
+  // the third line of Ann within the minute is refused
+  ann.say(game, "one more").unwrap();
+  ann.say(game, "and another").unwrap();
+  let refused =
+    wait_for(&mut ann, |update| matches!(update, Update::Error { .. }));
+  assert_eq!(refused.iter().filter(|update| chatted(update)).count(), 1);
+  assert!(ann.say(game, " \n ").is_err());
+
+  // a player back gets the chat of the players' room
+  drop(bob);
+  let deadline = Instant::now() + Duration::from_secs(5);
+  let mut bob = loop {
+    match ServerClient::connect(addr, "Bob", Transport::Tcp) {
+      Ok(bob) => break bob,
+      Err(_) if Instant::now() < deadline => {
+        thread::sleep(Duration::from_millis(10))
+      }
+      Err(err) => panic!("{err:#}"),
+    }
+  };
+  let last = |update: &Update| matches!(update, Update::Chat { line, .. } if line.text == "one more");
+  wait_for(&mut bob, last);
+  let mut heard = heard.to_vec();
+  heard.push(line(1, Room::Players, "Ann", "one more"));
+  assert_eq!(bob.games[&game].chat, heard);
+}
+
+#[test]
+fn test_requests_before_hello_are_refused() {
+  let addr = serve();
+  let mut stream = TcpStream::connect(addr).unwrap();
+  writeln!(stream, "seek 60+0 any").unwrap();
+  writeln!(stream, "hello {} Eve", VERSION + 1).unwrap();
+  let mut reader = BufReader::new(stream);
+  for _ in 0..2 {
+    let update: Update = read(&mut reader).unwrap().unwrap();
+    assert!(matches!(update, Update::Error { .. }), "{update}");
+  }
+  assert!(read::<Update>(&mut reader).unwrap().is_none());
+}
+
+#[test]
+fn test_games_are_kept_and_rated() {
+  let games = std::env::temp_dir().join("chess-server-games.chdb");
+  let ratings = std::env::temp_dir().join("chess-server-ratings.txt");
+  let _ = std::fs::remove_file(&games);
+  let _ = std::fs::remove_file(&ratings);
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  let mut server = Server::new("test");
+  server.keep_games(&games, &ratings).unwrap();
+  assert_eq!(Ratings::load(&ratings).unwrap().games(), 0);
+  thread::spawn(move || server.run(listener));
+
+  let mut ann = ServerClient::connect(addr, "Ann", Transport::Tcp).unwrap();
+  let mut bob = ServerClient::connect(addr, "Bob", Transport::Tcp).unwrap();
+  let game = start_game(&mut ann, &mut bob, "180+2");
+  ann.play(game, mv("e2e4")).unwrap();
+  wait_for(&mut bob, moved("e2e4"));
+  bob.control(game, Control::Resign).unwrap();
+  wait_for(&mut ann, |update| matches!(update, Update::End { .. }));
+
+  // the archive stores the game, then saves the ratings with it
+  let deadline = Instant::now() + Duration::from_secs(5);
+  let rated = loop {
+    let rated = Ratings::load(&ratings).unwrap();
+    if rated.games() == 1 || Instant::now() > deadline {
+      break rated;
+    }
+    thread::sleep(Duration::from_millis(10));
+  };
+  let database = Database::open(&games).unwrap();
+  assert_eq!(database.len(), 1);
+  let record = database.game(0).unwrap();
+  assert_eq!(record.moves, [mv("e2e4")]);
+  assert_eq!(record.outcome, Outcome::WhiteWins);
+  assert_eq!(record.tag("Termination"), Some("resignation"));
+  assert_eq!(rated.games(), 1);
+  assert!(rated.rating("Ann", Category::Blitz).rating > 1500.0);
+  assert!(rated.rating("Bob", Category::Blitz).rating < 1500.0);
+  assert_eq!(rated, Ratings::recompute(&database).unwrap());
+  std::fs::remove_file(&games).unwrap();
+  std::fs::remove_file(&ratings).unwrap();
+}
@@ -0,0 +1,96 @@
+use chess::{
+  pgn::{Game, Outcome},
+  sprt::{self, Params, Stats, Verdict},
+  tournament::{GameRecord, Pairing},
+};
+
+/// The two games of pair `pair`, engine 0 playing white first.
+fn pair(
+  pair: usize,
+  outcomes: [Outcome; 2],
+) -> [GameRecord; 2] {
+  let record = |white, black, outcome| GameRecord {
+    pairing: Pairing { round: 0, pair, white, black, opening: 0 },
+    game: Game { outcome, ..Default::default() },
+  };
+  [record(0, 1, outcomes[0]), record(1, 0, outcomes[1])]
+}
+
+#[test]
+fn test_bounds_and_conversions() {
+  let params: Params = "elo0=0,elo1=5".parse().unwrap();
+  let (lower, upper) = params.bounds();
+  assert!((lower + 2.944).abs() < 1e-3);
+  assert!((upper - 2.944).abs() < 1e-3);
+  assert!("elo0=5,elo1=0".parse::<Params>().is_err());
+  assert!("alpha=0".parse::<Params>().is_err());
+  assert!("beta=1".parse::<Params>().is_err());
+  assert!((sprt::score_to_elo(sprt::elo_to_score(35.0)) - 35.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_pentanomial_counts() {
+  let mut stats = Stats::default();
+  let [first, second] = pair(0, [Outcome::WhiteWins, Outcome::Draw]);
+  stats.add(&first, 0);
+  assert_eq!(stats.pairs(), 0);
+  stats.add(&second, 0);
+  for record in pair(1, [Outcome::BlackWins, Outcome::WhiteWins]) {
+    stats.add(&record, 0);
+  }
+  // 1.5 points in the first pair, none in the second
+  assert_eq!(stats.pentanomial, [1, 0, 0, 1, 0]);
+  assert_eq!((stats.wins, stats.draws, stats.losses), (1, 1, 2));
+  assert_eq!(stats.draw_ratio(), 0.25);
+}
+
+#[test]
+fn test_stronger_engine_passes() {
+  let params = Params::default();
+  let mut stats = Stats::default();
+  let mut verdict = None;
+  // pairs scoring 1.5, 1 and 1 points over and over: about +60 Elo
+  for i in 0..3000 {
+    let outcomes = match i % 3 {
+      0 => [Outcome::WhiteWins, Outcome::Draw],
+      1 => [Outcome::Draw, Outcome::Draw],
+      _ => [Outcome::WhiteWins, Outcome::WhiteWins],
+    };
+    for record in pair(i, outcomes) {
+      stats.add(&record, 0);
+    }
+    verdict = stats.verdict(&params);
+    if verdict.is_some() {
+      break;
+    }
+  }
+  assert_eq!(verdict, Some(Verdict::H1));
+  let (elo, error) = stats.elo().unwrap();
+  assert!((elo - 58.0).abs() < 2.0, "{elo}");
+  assert!(error > 0.0);
+  assert!(stats.los().unwrap() > 0.95);
+}
+
+#[test]
+fn test_equal_engines_fail() {
+  let params = Params::default();
+  let mut stats = Stats::default();
+  let mut verdict = None;
+  for i in 0..10_000 {
+    let outcomes = match i % 4 {
+      0 => [Outcome::WhiteWins, Outcome::WhiteWins],
+      1 => [Outcome::Draw, Outcome::Draw],
+      2 => [Outcome::BlackWins, Outcome::Draw],
+      _ => [Outcome::Draw, Outcome::BlackWins],
+    };
+    for record in pair(i, outcomes) {
+      stats.add(&record, 0);
+    }
+    verdict = stats.verdict(&params);
+    if verdict.is_some() {
+      break;
+    }
+  }
+  assert_eq!(verdict, Some(Verdict::H0));
+  assert!(stats.elo().unwrap().0.abs() < 5.0);
+}
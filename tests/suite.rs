@@ -0,0 +1,41 @@
+use chess::{
+  analysis::Analyzer,
+  board::Move,
+  epd,
+  search::{Engine, Limits},
+  suite::{self, Goal},
+};
+
+const SUITE: &str = r#"
+2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001";
+r1bq2rk/pp3pbp/2p1p1pQ/7P/3P4/2PB1N2/PP3PPR/2KR4 w - - bm Qxh7+; id "WAC.004";
+rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - am Ke2; id "avoid.001"; c0 "bongcloud";
+"#;
+
+#[test]
+fn test_goals() {
+  let records = epd::parse(SUITE);
+  let goal = Goal::of(&records[1]).unwrap();
+  assert_eq!(goal.best, [Move::from_uci("h6h7").unwrap()]);
+  let avoid = Goal::of(&records[2]).unwrap();
+  assert!(!avoid.is_solved_by(Move::from_uci("e1e2").unwrap()));
+  assert!(avoid.is_solved_by(Move::from_uci("g1f3").unwrap()));
+  assert_eq!(records[2].op("c0").unwrap(), ["bongcloud"]);
+}
+
+#[test]
+fn test_solves_suite() {
+  let records = epd::parse(SUITE);
+  let mut analyzer = Analyzer::BuiltIn(Engine::new());
+  let mut seen = Vec::new();
+  let results =
+    suite::run(&mut analyzer, &records, &Limits::depth(5), |epd, _| {
+      seen.push(epd.id().unwrap().to_string())
+    })
+    .unwrap();
+  assert_eq!(seen, ["WAC.001", "WAC.004", "avoid.001"]);
+  for result in &results {
+    assert!(result.solved, "{} failed with {:?}", result.id, result.best_move);
+    assert!(result.solved_after.unwrap() <= result.time);
+  }
+}
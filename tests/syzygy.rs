@@ -0,0 +1,241 @@
+#[path = "syzygy/generate.rs"]
+mod generate;
+
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+
+use chess::{
+  board::{king_attacks, Color, Move, Position},
+  search::{Engine, Limits, Score},
+  syzygy::{Dtz, Tablebase, Wdl},
+};
+
+/// KQvK, KRvK and KPvK, in both formats, written by `generate`.
+const TABLES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/syzygy");
+
+fn tablebase() -> Tablebase {
+  Tablebase::open(TABLES).unwrap()
+}
+
+fn position(fen: &str) -> Position {
+  Position::from_fen(fen).unwrap()
+}
+
+fn temp_dir(test: &str) -> PathBuf {
+  let name = format!("chess-syzygy-{}-{test}", std::process::id());
+  let dir = std::env::temp_dir().join(name);
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(&dir).unwrap();
+  dir
+}
+
+#[test]
+fn test_insufficient_material_is_drawn() {
+  let tablebase = Tablebase::default();
+  let position = position("8/8/3k4/8/8/2NK4/8/8 w - - 0 1");
+  assert_eq!(tablebase.probe_wdl(&position).unwrap(), Wdl::Draw);
+  assert_eq!(tablebase.probe_dtz(&position).unwrap(), Dtz(0));
+}
+
+#[test]
+fn test_probes_queen_and_rook_endings() {
+  let tablebase = tablebase();
+  assert_eq!(tablebase.max_pieces(), 3);
+  let wdl = |fen: &str| tablebase.probe_wdl(&position(fen)).unwrap();
+  let dtz = |fen: &str| tablebase.probe_dtz(&position(fen)).unwrap();
+
+  assert_eq!(dtz("k7/8/1K6/8/8/8/8/6Q1 w - - 0 1"), Dtz(1));
+  assert_eq!(wdl("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1"), Wdl::Loss);
+  // stalemate, and a queen left hanging next to the king
+  assert_eq!(wdl("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"), Wdl::Draw);
+  assert_eq!(wdl("8/8/8/8/8/8/Qk6/4K3 b - - 0 1"), Wdl::Draw);
+  assert_eq!(wdl("8/8/8/8/8/8/Q1k5/4K3 b - - 0 1"), Wdl::Loss);
+  // black's pieces are found in the same tables
+  assert_eq!(wdl("1r6/8/8/3k4/8/8/8/4K3 w - - 0 1"), Wdl::Loss);
+  assert_eq!(dtz("6q1/8/8/8/8/1k6/8/K7 b - - 0 1"), Dtz(1));
+
+  let err = tablebase.probe_wdl(&position("8/8/8/3k4/8/8/8/2B1K3 w - - 0 1"));
+  assert!(err.is_ok(), "one minor piece is a draw without a table");
+  let err = tablebase.probe_wdl(&position("8/8/8/3k4/8/8/8/1NN1K3 w - - 0 1"));
+  assert!(err.unwrap_err().to_string().contains("no tables for 4 pieces"));
+}
+
+#[test]
+fn test_best_moves_mate_the_bare_king() {
+  let tablebase = tablebase();
+  for fen in
+    ["8/8/8/4k3/8/8/8/R3K3 w - - 0 1", "8/8/8/3k4/8/8/8/1Q2K3 w - - 0 1"]
+  {
+    let mut position = position(fen);
+    let Dtz(mut dtz) = tablebase.probe_dtz(&position).unwrap();
+    // the longest mates take 16 moves with a rook, 10 with a queen
+    assert!(dtz > 0 && dtz <= 31, "{fen}: {dtz}");
+    while !position.is_checkmate() {
+      let best = tablebase.best_moves(&position).unwrap();
+      position.play(best[0]);
+      let Dtz(next) = tablebase.probe_dtz(&position).unwrap();
+      if !position.is_checkmate() {
+        assert_eq!(next.abs(), dtz.abs() - 1, "{}", position.to_fen());
+      }
+      dtz = next;
+    }
+  }
+}
+
+#[test]
+fn test_pawn_endings() {
+  let tablebase = tablebase();
+  let wdl = |fen: &str| tablebase.probe_wdl(&position(fen)).unwrap();
+  // whoever has to move gives the opposition away
+  assert_eq!(wdl("8/8/8/4k3/8/4K3/4P3/8 w - - 0 1"), Wdl::Draw);
+  assert_eq!(wdl("8/8/8/4k3/8/4K3/4P3/8 b - - 0 1"), Wdl::Loss);
+  assert_eq!(wdl("8/4p3/4k3/8/4K3/8/8/8 b - - 0 1"), Wdl::Draw);
+  assert_eq!(wdl("8/4p3/4k3/8/4K3/8/8/8 w - - 0 1"), Wdl::Loss);
+  // with the king on the sixth in front of the pawn it doesn't matter
+  assert_eq!(wdl("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"), Wdl::Win);
+  assert_eq!(wdl("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"), Wdl::Loss);
+  // the rook pawn can't get the king out of the corner
+  assert_eq!(wdl("k7/8/8/8/8/8/P7/K7 w - - 0 1"), Wdl::Draw);
+  assert_eq!(wdl("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1"), Wdl::Draw);
+
+  let position = position("3k4/8/3K4/4P3/8/8/8/8 w - - 0 1");
+  assert_eq!(tablebase.probe_dtz(&position).unwrap(), Dtz(1));
+  let best = tablebase.best_moves(&position).unwrap();
+  assert_eq!(best, [Move::from_uci("e5e6").unwrap()]);
+}
+
+#[test]
+fn test_search_uses_tables() {
+  let mut engine = Engine::new();
+  engine.set_tablebase(Some(Arc::new(tablebase())));
+
+  // taking the queen leaves a won ending the search can't see through
+  let position = position("4k3/8/8/8/8/8/3q4/3QK3 w - - 0 1");
+  let result = engine.search(position, Vec::new(), Limits::depth(1));
+  assert_eq!(result.best_move, Some(Move::from_uci("d1d2").unwrap()));
+  let Score::Cp(score) = result.info.unwrap().score else {
+    panic!("a tablebase win is no mate");
+  };
+  assert!(score > 20_000, "{score}");
+
+  // at the root only moves keeping the win are searched
+  let fen = "8/8/8/8/8/3k4/8/R3K3 w - - 0 1";
+  let position = Position::from_fen(fen).unwrap();
+  let best = tablebase().best_moves(&position).unwrap();
+  let result = engine.search(position, Vec::new(), Limits::depth(1));
+  assert!(best.contains(&result.best_move.unwrap()));
+}
+
+#[test]
+fn test_open_skips_bad_files() {
+  let dir = temp_dir("open");
+  fs::copy(format!("{TABLES}/KQvK.rtbw"), dir.join("KQvK.rtbw")).unwrap();
+  fs::write(dir.join("KRvK.rtbw"), b"junk").unwrap();
+  fs::write(dir.join("KXvK.rtbz"), [0xd7, 0x66, 0x0c, 0xa5]).unwrap();
+  fs::write(dir.join("README"), "not a table").unwrap();
+  let tablebase = Tablebase::open(dir.to_str().unwrap()).unwrap();
+  assert_eq!(tablebase.max_pieces(), 3);
+
+  let queen = position("8/8/3k4/8/8/2QK4/8/8 b - - 0 1");
+  assert_eq!(tablebase.probe_wdl(&queen).unwrap(), Wdl::Loss);
+  let err = tablebase.probe_dtz(&queen).unwrap_err().to_string();
+  assert_eq!(err, "missing KQvK.rtbz");
+  let rook = position("8/8/3k4/8/8/2RK4/8/8 w - - 0 1");
+  let err = tablebase.probe_wdl(&rook).unwrap_err().to_string();
+  assert_eq!(err, "missing table KRvK");
+
+  assert!(Tablebase::open(dir.join("missing").to_str().unwrap()).is_err());
+  fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_dtz_display() {
+  assert_eq!(Dtz(12).to_string(), "Win in 12");
+  assert_eq!(Dtz(0).to_string(), "Draw");
+  assert_eq!(Dtz(-7).to_string(), "Loss in 7");
+}
+
+#[test]
+fn test_agrees_with_the_longest_known_mates() {
+  let tablebase = tablebase();
+  // the queen mates in at most 10 moves, the rook in 16, from anywhere
+  // with white to move: 19 and 31 plies
+  let dtz = |fen: &str| tablebase.probe_dtz(&position(fen)).unwrap();
+  assert_eq!(dtz("7K/6Q1/8/8/8/3k4/8/8 w - - 0 1"), Dtz(19));
+  assert_eq!(dtz("7K/8/8/8/8/8/2k5/1R6 w - - 0 1"), Dtz(31));
+  for piece in ['Q', 'R'] {
+    let mut longest = 0;
+    // the black king in a1-d1-d4 stands for every square by symmetry
+    for king in [0, 1, 2, 3, 9, 10, 11, 18, 19, 27] {
+      for (white, other) in (0..64).flat_map(|a| (0..64).map(move |b| (a, b))) {
+        let mut board = ['.'; 64];
+        if [white, other].contains(&king) || white == other {
+          continue;
+        }
+        (board[king], board[white], board[other]) = ('k', 'K', piece);
+        let Some(position) = placed(&board, 'w') else {
+          continue;
+        };
+        let Dtz(dtz) = tablebase.probe_dtz(&position).unwrap();
+        assert!(dtz > 0, "{}", position.to_fen());
+        longest = longest.max(dtz);
+      }
+    }
+    assert_eq!(longest, if piece == 'Q' { 19 } else { 31 });
+  }
+}
+
+/// The position with the pieces on `board`, a1 first, if legal.
+fn placed(
+  board: &[char; 64],
+  stm: char,
+) -> Option<Position> {
+  let ranks: Vec<String> =
+    board.chunks(8).rev().map(|rank| rank.iter().collect::<String>()).collect();
+  let mut text = ranks.join("/");
+  for empty in (1..=8).rev() {
+    text = text.replace(&".".repeat(empty), &empty.to_string());
+  }
+  let position = Position::from_fen(&format!("{text} {stm} - - 0 1")).ok()?;
+  let [white, black] =
+    [Color::White, Color::Black].map(|color| position.king(color));
+  let apart = king_attacks(white) & 1 << black.index() == 0;
+  apart.then_some(position)
+}
+
+/// Minutes without optimizations:
+/// `cargo test --release --test syzygy -- --ignored`.
+#[test]
+#[ignore]
+fn test_fixtures_are_generated() {
+  let dir = temp_dir("generate");
+  let solved = generate::write_tables(&dir);
+  for name in generate::TABLES {
+    for suffix in ["rtbw", "rtbz"] {
+      let file = format!("{name}.{suffix}");
+      let written = fs::read(dir.join(&file)).unwrap();
+      let fixture = fs::read(Path::new(TABLES).join(&file)).unwrap();
+      // a changed generator leaves its tables there to copy over
+      assert!(written == fixture, "{file} differs from {}", dir.display());
+    }
+  }
+
+  // every position probes as solved
+  let tablebase = tablebase();
+  for solved in solved.values() {
+    for (position, wdl, dtz) in solved.iter() {
+      let fen = position.to_fen();
+      let expected = match wdl {
+        2 => Wdl::Win,
+        0 => Wdl::Draw,
+        _ => Wdl::Loss,
+      };
+      assert_eq!(tablebase.probe_wdl(position).unwrap(), expected, "{fen}");
+      assert_eq!(tablebase.probe_dtz(position).unwrap(), Dtz(dtz), "{fen}");
+    }
+  }
+  fs::remove_dir_all(&dir).unwrap();
+}
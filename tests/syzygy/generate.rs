@@ -0,0 +1,521 @@
+//! How the fixtures next to this file were made: KQvK, KRvK and KPvK
+//! solved backwards from the mates, every position with its result and
+//! its distance to a capture, pawn move or mate, then written in the
+//! Syzygy format as `Layout` places them. `test_fixtures_are_generated`
+//! writes them again and checks they didn't change.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use chess::{
+  board::{Color, PieceKind, Position},
+  syzygy::Layout,
+};
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+
+/// In the order solved, each table's promotions already known.
+pub const TABLES: [&str; 3] = ["KQvK", "KRvK", "KPvK"];
+
+/// Where a move leads.
+#[derive(Clone, Copy)]
+enum Child {
+  /// A position of the same table.
+  Inside(usize),
+  /// Another table's, by its result.
+  Outside(i8),
+}
+
+struct Edge {
+  child: Child,
+  /// A capture or pawn move, which resets the fifty-move count.
+  zeroing: bool,
+  mate: bool,
+}
+
+/// Every position of a table, with white the side named first.
+pub struct Solved {
+  pieces: Vec<(Color, PieceKind)>,
+  index: HashMap<u32, usize>,
+  positions: Vec<Position>,
+  /// -2 for a loss to 2 for a win, for the side to move.
+  wdl: Vec<i8>,
+  /// Plies to the zeroing move or mate, negative when losing.
+  dtz: Vec<i32>,
+}
+
+fn pieces(name: &str) -> Vec<(Color, PieceKind)> {
+  let (white, black) = name.split_once('v').unwrap();
+  let side = |color, part: &str| {
+    let kinds = part.chars().map(|c| PieceKind::from_char(c).unwrap());
+    kinds.map(move |kind| (color, kind)).collect::<Vec<_>>()
+  };
+  [side(Color::White, white), side(Color::Black, black)].concat()
+}
+
+/// The position's squares packed six bits apiece, then the side to move.
+fn key(
+  pieces: &[(Color, PieceKind)],
+  position: &Position,
+) -> Option<u32> {
+  if position.occupied().count_ones() as usize != pieces.len() {
+    return None;
+  }
+  let mut key = 0;
+  for (i, &(color, kind)) in pieces.iter().enumerate() {
+    let bits = position.pieces(color, kind);
+    if bits.count_ones() != 1 {
+      return None;
+    }
+    key |= bits.trailing_zeros() << (6 * i);
+  }
+  let stm = position.side_to_move().index() as u32;
+  Some(key | stm << (6 * pieces.len()))
+}
+
+fn fen(
+  pieces: &[(Color, PieceKind)],
+  squares: &[usize],
+  stm: Color,
+) -> String {
+  let mut board = [None; 64];
+  for (&(color, kind), &sq) in pieces.iter().zip(squares) {
+    board[sq] = Some(match color {
+      Color::White => kind.char(),
+      Color::Black => kind.char().to_ascii_lowercase(),
+    });
+  }
+  let ranks: Vec<String> = board
+    .chunks(8)
+    .rev()
+    .map(|rank| {
+      let mut text = String::new();
+      let mut empty = 0;
+      for square in rank {
+        match square {
+          None => empty += 1,
+          Some(c) => {
+            if empty > 0 {
+              text += &empty.to_string();
+              empty = 0;
+            }
+            text.push(*c);
+          }
+        }
+      }
+      if empty > 0 {
+        text += &empty.to_string();
+      }
+      text
+    })
+    .collect();
+  let stm = if stm == Color::White { "w" } else { "b" };
+  format!("{} {stm} - - 0 1", ranks.join("/"))
+}
+
+fn material_name(position: &Position) -> String {
+  let side = |color| -> String {
+    let kinds = PieceKind::ALL.into_iter().rev();
+    kinds
+      .flat_map(|kind| {
+        let count = position.pieces(color, kind).count_ones() as usize;
+        std::iter::repeat_n(kind.char(), count)
+      })
+      .collect()
+  };
+  format!("{}v{}", side(Color::White), side(Color::Black))
+}
+
+/// Every legal position of the table, kings apart and pawns off the
+/// back ranks.
+fn positions(pieces: &[(Color, PieceKind)]) -> Vec<Position> {
+  let mut positions = Vec::new();
+  for code in 0..64usize.pow(pieces.len() as u32) {
+    let squares: Vec<usize> =
+      (0..pieces.len()).map(|i| code >> (6 * i) & 63).collect();
+    let mut distinct = squares.clone();
+    distinct.sort_unstable();
+    distinct.dedup();
+    let pawn_on_edge = pieces.iter().zip(&squares).any(|(&(_, kind), &sq)| {
+      kind == PieceKind::Pawn && !(8..56).contains(&sq)
+    });
+    if distinct.len() < pieces.len() || pawn_on_edge {
+      continue;
+    }
+    for stm in [Color::White, Color::Black] {
+      let Ok(position) = Position::from_fen(&fen(pieces, &squares, stm)) else {
+        continue;
+      };
+      let [white, black] =
+        [Color::White, Color::Black].map(|color| position.king(color).index());
+      let apart = (white % 8).abs_diff(black % 8) > 1
+        || (white / 8).abs_diff(black / 8) > 1;
+      if apart {
+        positions.push(position);
+      }
+    }
+  }
+  positions
+}
+
+pub fn solve(
+  name: &str,
+  done: &HashMap<String, Solved>,
+) -> Solved {
+  let pieces = pieces(name);
+  let positions = positions(&pieces);
+  let index: HashMap<u32, usize> = positions
+    .iter()
+    .enumerate()
+    .map(|(i, position)| (key(&pieces, position).unwrap(), i))
+    .collect();
+
+  let edges: Vec<Vec<Edge>> = positions
+    .iter()
+    .map(|position| {
+      let moves = position.legal_moves().into_iter();
+      moves
+        .map(|mv| {
+          let pawn =
+            position.piece_at(mv.from).unwrap().kind == PieceKind::Pawn;
+          let zeroing = pawn || position.is_capture(mv);
+          let next = position.played(mv);
+          let child = match key(&pieces, &next) {
+            Some(key) => Child::Inside(index[&key]),
+            None => match done.get(&material_name(&next)) {
+              Some(solved) => {
+                let key = key(&solved.pieces, &next).unwrap();
+                Child::Outside(solved.wdl[solved.index[&key]])
+              }
+              None => {
+                assert!(next.is_insufficient_material(), "{}", next.to_fen());
+                Child::Outside(0)
+              }
+            },
+          };
+          Edge { child, zeroing, mate: next.is_checkmate() }
+        })
+        .collect()
+    })
+    .collect();
+
+  // mates and stalemates, then back from them until nothing changes
+  let mut wdl: Vec<Option<i8>> = positions
+    .iter()
+    .zip(&edges)
+    .map(|(position, edges)| match edges.is_empty() {
+      true => Some(if position.is_check() { -2 } else { 0 }),
+      false => None,
+    })
+    .collect();
+  loop {
+    let mut changed = false;
+    for i in 0..positions.len() {
+      if wdl[i].is_some() {
+        continue;
+      }
+      let values: Vec<Option<i8>> = edges[i]
+        .iter()
+        .map(|edge| match edge.child {
+          Child::Inside(j) => wdl[j],
+          Child::Outside(value) => Some(value),
+        })
+        .collect();
+      if values.contains(&Some(-2)) {
+        wdl[i] = Some(2);
+      } else if values.iter().all(|&value| value == Some(2)) {
+        wdl[i] = Some(-2);
+      } else {
+        continue;
+      }
+      changed = true;
+    }
+    if !changed {
+      break;
+    }
+  }
+  let wdl: Vec<i8> = wdl.into_iter().map(|value| value.unwrap_or(0)).collect();
+
+  // a win takes the fastest way to a zeroing move that keeps it, a loss
+  // the slowest, as Stockfish's probe_dtz counts them
+  let mut dtz: Vec<Option<i32>> =
+    wdl.iter().map(|&value| (value == 0).then_some(0)).collect();
+  for plies in 1.. {
+    assert!(plies < 300, "{name} doesn't converge");
+    let mut found = Vec::new();
+    for (i, edges) in edges.iter().enumerate() {
+      if dtz[i].is_some() {
+        continue;
+      }
+      if wdl[i] == 2 {
+        let wins = edges.iter().any(|edge| match edge.child {
+          Child::Outside(value) => value == -2 && plies == 1,
+          Child::Inside(j) if edge.zeroing || edge.mate => {
+            wdl[j] == -2 && plies == 1
+          }
+          Child::Inside(j) => wdl[j] == -2 && dtz[j] == Some(1 - plies),
+        });
+        if wins {
+          found.push((i, plies));
+        }
+        continue;
+      }
+      let longest = edges.iter().try_fold(1, |longest, edge| {
+        let plies = match edge.child {
+          Child::Inside(j) if !edge.zeroing => dtz[j]? + 1,
+          _ => 1,
+        };
+        Some(plies.max(longest))
+      });
+      if let Some(longest) = longest {
+        found.push((i, -longest));
+      }
+    }
+    for (i, plies) in found {
+      dtz[i] = Some(plies);
+    }
+    if dtz.iter().all(Option::is_some) {
+      break;
+    }
+  }
+  let dtz = dtz.into_iter().map(Option::unwrap).collect();
+  Solved { pieces, index, positions, wdl, dtz }
+}
+
+/// Values coded with canonical Huffman codes in blocks of 64 bytes, each
+/// symbol a single value: the sizes entry, sparse index, block lengths
+/// and blocks of one part.
+struct Compressed {
+  sizes: Vec<u8>,
+  sparse: Vec<u8>,
+  lengths: Vec<u8>,
+  data: Vec<u8>,
+}
+
+fn compress(
+  values: &[u16],
+  flags: u8,
+) -> Compressed {
+  let mut counts: HashMap<u16, usize> = HashMap::new();
+  for &value in values {
+    *counts.entry(value).or_default() += 1;
+  }
+  if counts.len() == 1 {
+    return Compressed {
+      sizes: vec![flags | 0x80, values[0] as u8],
+      sparse: Vec::new(),
+      lengths: Vec::new(),
+      data: Vec::new(),
+    };
+  }
+
+  let mut symbols: Vec<u16> = counts.keys().copied().collect();
+  symbols.sort_unstable();
+  let mut len: HashMap<u16, usize> = symbols.iter().map(|&s| (s, 0)).collect();
+  let mut nodes: Vec<(usize, Vec<u16>)> =
+    symbols.iter().map(|&s| (counts[&s], vec![s])).collect();
+  while nodes.len() > 1 {
+    nodes.sort_by_key(|node| std::cmp::Reverse(node.0));
+    let (a, b) = (nodes.pop().unwrap(), nodes.pop().unwrap());
+    for symbol in a.1.iter().chain(&b.1) {
+      *len.get_mut(symbol).unwrap() += 1;
+    }
+    nodes.push((a.0 + b.0, [a.1, b.1].concat()));
+  }
+  let max_len = *len.values().max().unwrap();
+  let min_len = *len.values().min().unwrap();
+  assert!(max_len <= 32);
+
+  // symbols are numbered longest codes first
+  let mut order = symbols;
+  order.sort_by_key(|s| (std::cmp::Reverse(len[s]), *s));
+  let id: HashMap<u16, usize> =
+    order.iter().enumerate().map(|(i, &s)| (s, i)).collect();
+  let lengths = max_len - min_len + 1;
+  let longer = |l: usize| order.iter().filter(|s| len[s] > l).count();
+  let lowest: Vec<usize> = (0..lengths).map(|i| longer(min_len + i)).collect();
+  let mut base = vec![0u64; lengths];
+  for i in (0..lengths - 1).rev() {
+    let of_len = lowest[i] - lowest[i + 1];
+    let sum = base[i + 1] + of_len as u64;
+    assert!(sum.is_multiple_of(2));
+    base[i] = sum / 2;
+  }
+  let code = |s: u16| {
+    let i = len[&s] - min_len;
+    (base[i] + (id[&s] - lowest[i]) as u64, len[&s])
+  };
+
+  let mut blocks: Vec<(Vec<u8>, usize)> = Vec::new();
+  let mut bits: Vec<bool> = Vec::new();
+  let mut count = 0;
+  let flush = |bits: &mut Vec<bool>, count, blocks: &mut Vec<(Vec<u8>, _)>| {
+    let mut bytes = vec![0u8; 64];
+    for (i, _) in bits.iter().enumerate().filter(|(_, &bit)| bit) {
+      bytes[i / 8] |= 0x80 >> (i % 8);
+    }
+    blocks.push((bytes, count));
+    bits.clear();
+  };
+  for &value in values {
+    let (code, len) = code(value);
+    if bits.len() + len > 512 {
+      flush(&mut bits, count, &mut blocks);
+      count = 0;
+    }
+    bits.extend((0..len).rev().map(|k| code >> k & 1 == 1));
+    count += 1;
+  }
+  flush(&mut bits, count, &mut blocks);
+
+  let starts: Vec<usize> = blocks
+    .iter()
+    .scan(0, |start, (_, count)| {
+      let first = *start;
+      *start += count;
+      Some(first)
+    })
+    .collect();
+  let span = 1024;
+  let mut sparse = Vec::new();
+  for k in 0..values.len().div_ceil(span) {
+    let middle = k * span + span / 2;
+    let block = starts.iter().rposition(|&start| start <= middle).unwrap();
+    sparse.extend((block as u32).to_le_bytes());
+    sparse.extend(((middle - starts[block]) as u16).to_le_bytes());
+  }
+  let lengths_bytes = blocks
+    .iter()
+    .flat_map(|(_, count)| ((count - 1) as u16).to_le_bytes())
+    .collect();
+  let data = blocks.iter().flat_map(|(bytes, _)| bytes.clone()).collect();
+
+  let mut sizes = vec![flags, 6, 10, 0];
+  sizes.extend((blocks.len() as u32).to_le_bytes());
+  sizes.extend([max_len as u8, min_len as u8]);
+  for &lowest in &lowest {
+    sizes.extend((lowest as u16).to_le_bytes());
+  }
+  sizes.extend((order.len() as u16).to_le_bytes());
+  for &symbol in &order {
+    // a leaf: no pair to expand into
+    sizes.extend([symbol as u8, (symbol >> 8) as u8 & 15 | 0xf0, 0xff]);
+  }
+  if order.len() % 2 == 1 {
+    sizes.push(0);
+  }
+  Compressed { sizes, sparse, lengths: lengths_bytes, data }
+}
+
+fn write_table(
+  name: &str,
+  solved: &Solved,
+  dtz: bool,
+  dir: &Path,
+) {
+  let has_pawns =
+    solved.pieces.iter().any(|&(_, kind)| kind == PieceKind::Pawn);
+  let files = if has_pawns { 4 } else { 1 };
+  let code = |(color, kind): (Color, PieceKind)| {
+    kind.index() as u8 + 1 + if color == Color::Black { 8 } else { 0 }
+  };
+  // pawns first, then the rest as named
+  let (mut order, rest): (Vec<_>, Vec<_>) =
+    solved.pieces.iter().partition(|&&(_, kind)| kind == PieceKind::Pawn);
+  order.extend(rest);
+
+  let mut header = Vec::from(if dtz { DTZ_MAGIC } else { WDL_MAGIC });
+  header.push(1 | (has_pawns as u8) << 1);
+  for _ in 0..files {
+    header.push(0);
+    header.extend(order.iter().map(|&&piece| code(piece) | code(piece) << 4));
+  }
+  if header.len() % 2 == 1 {
+    header.push(0);
+  }
+  let flags = if dtz { 4 | 8 } else { 0 };
+  let sides = if dtz { 1 } else { 2 };
+  let mut single = header.clone();
+  for _ in 0..files * sides {
+    single.extend([flags | 0x80, 0]);
+  }
+  single.extend([0; 64]);
+  let layout = Layout::new(name, single, dtz).unwrap();
+
+  let mut values: Vec<Vec<Vec<Option<u16>>>> = layout
+    .sizes()
+    .iter()
+    .map(|files| files.iter().map(|&size| vec![None; size as usize]).collect())
+    .collect();
+  for (i, position) in solved.positions.iter().enumerate() {
+    let Some((side, file, idx)) = layout.index(position).unwrap() else {
+      continue;
+    };
+    let value = match dtz {
+      true if solved.wdl[i] == 0 => continue,
+      true => (solved.dtz[i].abs() - 1) as u16,
+      false => (solved.wdl[i] + 2) as u16,
+    };
+    let slot = &mut values[side][file][idx as usize];
+    assert!(slot.is_none_or(|old| old == value), "{}", position.to_fen());
+    *slot = Some(value);
+  }
+
+  let mut parts = Vec::new();
+  for file in 0..files {
+    for side in values.iter().take(sides) {
+      let known = side[file].iter().flatten();
+      let mut counts: HashMap<u16, usize> = HashMap::new();
+      for &value in known {
+        *counts.entry(value).or_default() += 1;
+      }
+      // positions no probe reaches take the commonest value
+      let filler = counts
+        .iter()
+        .max_by_key(|&(value, count)| (*count, std::cmp::Reverse(*value)))
+        .map_or(0, |(value, _)| *value);
+      let filled: Vec<u16> =
+        side[file].iter().map(|value| value.unwrap_or(filler)).collect();
+      parts.push(compress(&filled, flags));
+    }
+  }
+  let mut bytes = header;
+  for part in &parts {
+    bytes.extend(&part.sizes);
+  }
+  if bytes.len() % 2 == 1 {
+    bytes.push(0);
+  }
+  for part in &parts {
+    bytes.extend(&part.sparse);
+  }
+  for part in &parts {
+    bytes.extend(&part.lengths);
+  }
+  for part in &parts {
+    bytes.resize(bytes.len().next_multiple_of(64), 0);
+    bytes.extend(&part.data);
+  }
+  let suffix = if dtz { "rtbz" } else { "rtbw" };
+  fs::write(dir.join(format!("{name}.{suffix}")), bytes).unwrap();
+}
+
+/// Solves and writes every table into `dir`, returning the solutions.
+pub fn write_tables(dir: &Path) -> HashMap<String, Solved> {
+  let mut done = HashMap::new();
+  for name in TABLES {
+    let solved = solve(name, &done);
+    write_table(name, &solved, false, dir);
+    write_table(name, &solved, true, dir);
+    done.insert(name.to_string(), solved);
+  }
+  done
+}
+
+impl Solved {
+  /// Each position with its result and distance.
+  pub fn iter(&self) -> impl Iterator<Item = (&Position, i8, i32)> {
+    let values = self.wdl.iter().zip(&self.dtz);
+    self.positions.iter().zip(values).map(|(p, (&wdl, &dtz))| (p, wdl, dtz))
+  }
+}
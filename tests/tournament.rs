@@ -0,0 +1,158 @@
+use std::{ops::ControlFlow, sync::Arc, time::Duration};
+
+use chess::{
+  analysis::Analyzer,
+  board::Position,
+  epd,
+  pgn::{self, Game, Outcome},
+  search::Engine,
+  syzygy::Tablebase,
+  tournament::{
+    self, Adjudication, EngineCommand, EngineConfig, Format, Opening, Settings,
+    TimeControl,
+  },
+};
+
+fn play(
+  white: &mut Analyzer,
+  fen: &str,
+  adjudication: &Adjudication,
+) -> Game {
+  let opening =
+    Opening { start: Position::from_fen(fen).unwrap(), moves: Vec::new() };
+  let mut black = Analyzer::BuiltIn(Engine::new());
+  let engines = [white, &mut black];
+  tournament::play_game(engines, &opening, TimeControl::Depth(1), adjudication)
+    .unwrap()
+}
+
+#[test]
+fn test_parse_settings() {
+  let engine: EngineConfig =
+    "cmd=/usr/bin/stockfish,option.Hash=64,option.Threads=2".parse().unwrap();
+  assert_eq!(engine.name, "stockfish");
+  assert_eq!(engine.command, EngineCommand::Uci("/usr/bin/stockfish".into()));
+  assert_eq!(engine.options.len(), 2);
+  assert!("name=x".parse::<EngineConfig>().is_err());
+
+  let clock = TimeControl::Clock {
+    base: Duration::from_secs(10),
+    increment: Duration::from_millis(100),
+  };
+  assert_eq!("10+0.1".parse::<TimeControl>().unwrap(), clock);
+  assert_eq!(clock.to_string(), "10+0.1");
+  assert_eq!("depth=6".parse::<TimeControl>().unwrap(), TimeControl::Depth(6));
+}
+
+#[test]
+fn test_epd_records() {
+  let text = r#"
+# comment
+r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - bm Bb5; id "test.001"; c0 "a; b";
+8/8/8/8/8/8/8/K1k5 b - - 12 40 id "full fen";
+8/8/8/8/8/8/8/K1k5 b - id "too few fields";
+rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - bm "unterminated;
+"#;
+  let records = epd::parse(text);
+  assert_eq!(records.len(), 2);
+  assert_eq!(records[0].id(), Some("test.001"));
+  assert_eq!(records[0].op("bm").unwrap(), ["Bb5"]);
+  assert_eq!(records[0].op("c0").unwrap(), ["a; b"]);
+  assert_eq!(records[1].position.halfmove_clock(), 12);
+  assert_eq!(records[1].position.fullmove_number(), 40);
+}
+
+#[test]
+fn test_schedule_alternates_colors() {
+  let settings =
+    Settings { format: Format::Gauntlet, rounds: 2, ..Default::default() };
+  let pairings = tournament::schedule(3, &settings);
+  assert_eq!(pairings.len(), 8);
+  assert!(pairings
+    .iter()
+    .all(|pairing| { pairing.white == 0 || pairing.black == 0 }));
+  for pair in pairings.chunks(2) {
+    assert_eq!(pair[0].white, pair[1].black);
+    assert_eq!(pair[0].pair, pair[1].pair);
+  }
+}
+
+#[test]
+fn test_builtin_against_uci_subprocess() {
+  let engines = [
+    "cmd=builtin,name=inside".parse().unwrap(),
+    EngineConfig {
+      name: "outside".into(),
+      command: EngineCommand::Uci(env!("CARGO_BIN_EXE_chess-uci").into()),
+      options: vec![("Hash".into(), "4".into())],
+    },
+  ];
+  let settings = Settings {
+    time_control: TimeControl::Depth(2),
+    adjudication: Adjudication {
+      resign_score: Some(500),
+      resign_moves: 2,
+      draw_score: Some(20),
+      draw_moves: 4,
+      draw_after: 20,
+      tablebase: None,
+    },
+    concurrency: 2,
+    ..Default::default()
+  };
+  let mut finished = 0;
+  let records = tournament::run(&engines, &settings, |_| {
+    finished += 1;
+    ControlFlow::Continue(())
+  })
+  .unwrap();
+  assert_eq!(records.len(), 2);
+  assert_eq!(finished, 2);
+  for record in &records {
+    let game = &record.game;
+    assert_ne!(game.outcome, Outcome::Unknown);
+    assert!(game.tag("Termination").is_some());
+    let again = pgn::parse(&game.to_pgn()).unwrap();
+    assert_eq!(again[0].moves, game.moves);
+    assert_eq!(again[0].outcome, game.outcome);
+  }
+  let standings = tournament::standings(&engines, &records);
+  let points: f64 = standings.iter().map(|standing| standing.points()).sum();
+  assert_eq!(points, 2.0);
+}
+
+#[test]
+fn test_tablebase_adjudication() {
+  let tables = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/syzygy");
+  let adjudication = Adjudication {
+    tablebase: Some(Arc::new(Tablebase::open(tables).unwrap())),
+    ..Default::default()
+  };
+  let mut white = Analyzer::BuiltIn(Engine::new());
+
+  let game = play(&mut white, "8/8/8/4k3/8/8/8/R3K3 w - - 0 1", &adjudication);
+  assert_eq!(game.outcome, Outcome::WhiteWins);
+  assert_eq!(game.tag("Termination"), Some("tablebase win"));
+  assert!(game.moves.is_empty());
+  let game = play(&mut white, "8/8/8/4k3/8/4K3/4P3/8 w - - 0 1", &adjudication);
+  assert_eq!(game.outcome, Outcome::Draw);
+  assert_eq!(game.tag("Termination"), Some("tablebase draw"));
+
+  // taking the queen gets into the tables
+  let fen = "4k3/8/8/8/8/8/3q4/3QK3 w - - 0 1";
+  let game = play(&mut white, fen, &adjudication);
+  assert_eq!(game.outcome, Outcome::WhiteWins);
+  assert_eq!(game.moves.len(), 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_engine_disconnect_loses() {
+  let quitter =
+    concat!(env!("CARGO_MANIFEST_DIR"), "/tests/engines/quitter.sh");
+  let mut white = Analyzer::uci(quitter.as_ref()).unwrap();
+  let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+  let game = play(&mut white, start, &Adjudication::default());
+  assert_eq!(game.outcome, Outcome::BlackWins);
+  assert_eq!(game.tag("Termination"), Some("engine disconnected"));
+}
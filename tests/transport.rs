@@ -0,0 +1,287 @@
+mod common;
+
+use std::{
+  io::{BufRead, BufReader, Write},
+  net::{TcpListener, TcpStream},
+  time::{Duration, Instant},
+};
+
+use chess::{
+  board::{Color, Position},
+  client::ServerClient,
+  pgn::Outcome,
+  protocol::{
+    ChatLine, Control, GameInfo, Request, Room, Seek, Update, VERSION,
+  },
+  server,
+  transport::{Encoding, Transport, Wire},
+  websocket::{self, Data},
+};
+
+use common::{mv, wait_for};
+
+fn requests() -> Vec<Request> {
+  let control = "180+2".parse().unwrap();
+  vec![
+    Request::Hello { version: VERSION, name: "Ann".into() },
+    Request::Seek { control, color: None },
+    Request::Challenge { to: "Bob".into(), control, color: Some(Color::Black) },
+    Request::Cancel { seek: 3 },
+    Request::Accept { seek: 3 },
+    Request::Move { game: 4, ply: 9, mv: mv("a7a8n") },
+    Request::Control { game: 4, ply: 10, control: Control::AskTakeback },
+    Request::Watch { game: 4 },
+    Request::Unwatch { game: 4 },
+    Request::Chat { game: 4, text: "good luck, have fun".into() },
+    Request::Post {
+      to: "Bob".into(),
+      game: "Ann-Bob-1".into(),
+      days: 3,
+      start: Position::startpos(),
+      moves: vec![mv("e2e4")],
+    },
+    Request::Bye,
+  ]
+}
+
+fn updates() -> Vec<Update> {
+  let control = "0.5+0.1".parse().unwrap();
+  let info =
+    GameInfo { id: 4, white: "Ann".into(), black: "Bob".into(), control };
+  let clocks = [Duration::from_millis(299_500), Duration::from_secs(300)];
+  vec![
+    Update::Hello { version: VERSION, name: "server".into() },
+    Update::Seek(Seek {
+      id: 3,
+      from: "Ann".into(),
+      control,
+      color: Some(Color::White),
+      to: Some("Bob".into()),
+    }),
+    Update::Seek(Seek {
+      id: 5,
+      from: "Eve".into(),
+      control,
+      color: None,
+      to: None,
+    }),
+    Update::Unseek { seek: 3 },
+    Update::Listed(info.clone()),
+    Update::Game {
+      info: info.clone(),
+      color: Some(Color::Black),
+      start: Position::startpos(),
+      moves: vec![mv("e2e4"), mv("e7e5"), mv("e1e2")],
+      clocks,
+    },
+    Update::Game {
+      info,
+      color: None,
+      start: Position::from_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap(),
+      moves: Vec::new(),
+      clocks,
+    },
+    Update::Move { game: 4, ply: 1, mv: mv("e7e5"), clocks },
+    Update::Control {
+      game: 4,
+      ply: 2,
+      color: Color::Black,
+      control: Control::ClaimWin,
+    },
+    Update::Gone { game: 4, color: Color::White },
+    Update::Back { game: 4, color: Color::White },
+    Update::End { game: 4, outcome: Outcome::Draw, reason: "stalemate".into() },
+    Update::Chat {
+      game: 4,
+      line: ChatLine {
+        ply: 0,
+        room: Room::Players,
+        from: "Ann".into(),
+        text: "good luck, have fun".into(),
+      },
+    },
+    Update::Chat {
+      game: 4,
+      line: ChatLine {
+        ply: 31,
+        room: Room::Spectators,
+        from: "Eve".into(),
+        text: "Rxf7!! wins".into(),
+      },
+    },
+    Update::Post {
+      from: "Ann".into(),
+      game: "Ann-Bob-1".into(),
+      days: 14,
+      start: Position::startpos(),
+      moves: Vec::new(),
+    },
+    Update::Error { message: "no game 5".into() },
+  ]
+}
+
+fn round_trip<M: Wire + PartialEq + std::fmt::Debug>(message: &M) {
+  let json = message.to_json();
+  assert_eq!(&M::from_json(&json).unwrap(), message, "{json}");
+  let bytes = message.to_binary();
+  assert_eq!(&M::from_binary(&bytes).unwrap(), message, "{message}");
+  assert!(M::from_binary(&bytes[..bytes.len() - 1]).is_err(), "{message}");
+}
+
+#[test]
+fn test_encodings_round_trip() {
+  requests().iter().for_each(round_trip);
+  updates().iter().for_each(round_trip);
+
+  // past what two bytes would count
+  let long = "x".repeat(70_000);
+  round_trip(&Update::Error { message: long.clone() });
+  round_trip(&Request::Move { game: 4, ply: 70_000, mv: mv("e2e4") });
+  round_trip(&Update::Chat {
+    game: 4,
+    line: ChatLine {
+      ply: 70_000,
+      room: Room::Players,
+      from: "Ann".into(),
+      text: long,
+    },
+  });
+  round_trip(&Request::Post {
+    to: "Bob".into(),
+    game: "Ann-Bob-1".into(),
+    days: 70_000,
+    start: Position::startpos(),
+    moves: vec![mv("g1f3"); 70_000],
+  });
+
+  let moved = Request::Move { game: 4, ply: 0, mv: mv("e2e4") };
+  assert_eq!(moved.to_binary().len(), 11);
+  assert_eq!(
+    moved.to_json(),
+    serde_json::json!({"type": "move", "game": 4, "ply": 0, "move": "e2e4"})
+  );
+  let json = serde_json::json!({"type": "seek", "control": "60"});
+  assert_eq!(
+    Request::from_json(&json).unwrap(),
+    Request::Seek { control: "60".parse().unwrap(), color: None }
+  );
+  assert!(Request::from_json(&serde_json::json!({"type": "launch"})).is_err());
+  assert!(Request::from_binary(&[9]).is_err());
+  for encoding in Encoding::ALL {
+    assert_eq!(encoding.to_string().parse::<Encoding>().unwrap(), encoding);
+  }
+}
+
+#[test]
+fn test_handshake() {
+  // the example of RFC 6455
+  assert_eq!(
+    websocket::accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+    "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+  );
+
+  let addr = server::spawn("127.0.0.1:0", "test").unwrap();
+  let mut stream = TcpStream::connect(addr).unwrap();
+  write!(stream, "GET / HTTP/1.1\r\nHost: test\r\n\r\n").unwrap();
+  let mut status = String::new();
+  BufReader::new(stream).read_line(&mut status).unwrap();
+  assert!(status.starts_with("HTTP/1.1 400"), "{status}");
+
+  // asking for no protocol gets JSON
+  let stream = TcpStream::connect(addr).unwrap();
+  let (sender, mut receiver) =
+    websocket::connect(stream, "test", "/", None).unwrap();
+  let hello = Request::Hello { version: VERSION, name: "Eve".into() };
+  sender.send(&Data::Text(hello.to_json().to_string())).unwrap();
+  let Some(Data::Text(text)) = receiver.recv().unwrap() else {
+    panic!("no answer");
+  };
+  let answer = Update::from_json(&serde_json::from_str(&text).unwrap());
+  assert_eq!(
+    answer.unwrap(),
+    Update::Hello { version: VERSION, name: "test".into() }
+  );
+  sender.close();
+}
+
+/// What the server side of a WebSocket makes of `frames`, sent by the
+/// client right after the handshake.
+fn receive(frames: &[u8]) -> anyhow::Result<Option<Data>> {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+  let (stream, _) = listener.accept().unwrap();
+  write!(
+    client,
+    "GET / HTTP/1.1\r\nHost: test\r\nUpgrade: websocket\r\n\
+     Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+     Sec-WebSocket-Version: 13\r\n\r\n"
+  )
+  .unwrap();
+  client.write_all(frames).unwrap();
+  let (_sender, mut receiver, _) = websocket::accept(stream, &[]).unwrap();
+  receiver.recv()
+}
+
+#[test]
+fn test_hostile_frames_are_refused() {
+  // masked with zeros, so the payloads read as they are
+  let text = [0x81, 0x82, 0, 0, 0, 0, b'h', b'i'];
+  assert_eq!(receive(&text).unwrap(), Some(Data::Text("hi".into())));
+  let mut ping = vec![0x89, 0x80, 0, 0, 0, 0];
+  ping.extend(text);
+  assert_eq!(receive(&ping).unwrap(), Some(Data::Text("hi".into())));
+
+  let mut huge = vec![0x82, 0x80 | 127];
+  huge.extend([0xff; 8]);
+  huge.extend([0; 4]);
+  let err = receive(&huge).unwrap_err();
+  assert!(err.to_string().contains("message over"), "{err}");
+
+  let mut long_ping = vec![0x89, 0x80 | 126, 0, 126, 0, 0, 0, 0];
+  long_ping.extend([0; 126]);
+  let err = receive(&long_ping).unwrap_err();
+  assert_eq!(err.to_string(), "control frame of 126 bytes");
+
+  let fragmented_ping = [0x09, 0x80, 0, 0, 0, 0];
+  let err = receive(&fragmented_ping).unwrap_err();
+  assert_eq!(err.to_string(), "fragmented control frame");
+}
+
+#[test]
+fn test_clients_on_every_transport_share_a_game() {
+  let addr = server::spawn("127.0.0.1:0", "test").unwrap();
+  let mut ann = ServerClient::connect(addr, "Ann", Transport::Tcp).unwrap();
+  let binary = Transport::WebSocket(Encoding::Binary);
+  let mut bob = ServerClient::connect(addr, "Bob", binary).unwrap();
+  let json = Transport::WebSocket(Encoding::Json);
+  let mut eve = ServerClient::connect(addr, "Eve", json).unwrap();
+  let text = Transport::WebSocket(Encoding::Text);
+  let mut dan = ServerClient::connect(addr, "Dan", text).unwrap();
+
+  bob.seek("300+3".parse().unwrap(), Some(Color::Black)).unwrap();
+  wait_for(&mut ann, |update| matches!(update, Update::Seek(_)));
+  let seek = *ann.seeks.keys().next().unwrap();
+  ann.accept(seek).unwrap();
+  wait_for(&mut bob, |update| matches!(update, Update::Game { .. }));
+  let game = *bob.games.keys().next().unwrap();
+  for watcher in [&mut eve, &mut dan] {
+    wait_for(watcher, |update| matches!(update, Update::Listed(_)));
+    watcher.watch(game).unwrap();
+    wait_for(watcher, |update| matches!(update, Update::Game { .. }));
+  }
+
+  wait_for(&mut ann, |update| matches!(update, Update::Game { .. }));
+  ann.play(game, mv("e2e4")).unwrap();
+  wait_for(&mut bob, |update| matches!(update, Update::Move { .. }));
+  bob.play(game, mv("c7c5")).unwrap();
+  for watcher in [&mut ann, &mut eve, &mut dan] {
+    let reply = mv("c7c5");
+    wait_for(
+      watcher,
+      |update| matches!(update, Update::Move { mv, .. } if *mv == reply),
+    );
+    assert_eq!(watcher.games[&game].moves, [mv("e2e4"), mv("c7c5")]);
+  }
+  let clock = eve.games[&game].clock(Color::White, Instant::now());
+  assert!(clock > Duration::from_secs(300), "{clock:?}");
+}